@@ -0,0 +1,239 @@
+// analysis/altitude.rs
+// Height above ground over the flight and per flight segment (a stretch above an airborne height,
+// allowing short dips), from a barometric altitude and optionally a GPS one. Ground level is the
+// altitude at the start of the log; the barometer gives the shape of the profile and the GPS, if
+// given, slowly corrects its drift.
+
+use super::energy::{REPORT_INTERVAL_US, SEGMENT_GAP_US};
+use super::{resample, FieldRef};
+use crate::errors::{Result, WallaceError};
+use crate::utils::time::MAX_TIMESTAMP_JUMP_US;
+use std::path::Path;
+
+/// Height (m) above which the vehicle counts as airborne, unless configured
+pub const DEFAULT_AIRBORNE_M: f64 = 2.0;
+/// Ground level is the median altitude over this long from the first sample
+const GROUND_WINDOW_US: u64 = 5_000_000;
+/// Time constant of the GPS correction of the barometric height; the barometer is trusted over
+/// shorter spans
+const GPS_TIME_CONSTANT_S: f64 = 30.0;
+
+/// `BARO[:GPS][:AIRBORNE_M]`, altitudes in metres, e.g. `BARO.Alt:GPS.Alt:3`
+#[derive(Debug, Clone)]
+pub struct AltitudeSpec {
+    pub baro: FieldRef,
+    pub gps: Option<FieldRef>,
+    pub airborne: f64,
+}
+
+impl AltitudeSpec {
+    pub fn parse(text: &str) -> Result<Self> {
+        let invalid = || {
+            WallaceError::InvalidArgument(format!(
+                "invalid altitude fields '{}': expected BARO[:GPS][:AIRBORNE_M], e.g. BARO.Alt:GPS.Alt",
+                text
+            ))
+        };
+        let mut parts = text.split(':');
+        let baro = FieldRef::parse(parts.next().unwrap_or_default())?;
+        let mut spec = AltitudeSpec {
+            baro,
+            gps: None,
+            airborne: DEFAULT_AIRBORNE_M,
+        };
+        let rest: Vec<&str> = parts.map(str::trim).collect();
+        let (gps, airborne) = match rest.as_slice() {
+            [] => (None, None),
+            [one] if one.parse::<f64>().is_ok() => (None, Some(*one)),
+            [one] => (Some(*one), None),
+            [gps, airborne] => (Some(*gps), Some(*airborne)),
+            _ => return Err(invalid()),
+        };
+        if let Some(gps) = gps {
+            spec.gps = Some(FieldRef::parse(gps)?);
+        }
+        if let Some(airborne) = airborne {
+            spec.airborne = airborne
+                .parse::<f64>()
+                .ok()
+                .filter(|m| m.is_finite())
+                .ok_or_else(invalid)?;
+        }
+        Ok(spec)
+    }
+
+    pub fn fields(&self) -> impl Iterator<Item = &FieldRef> {
+        std::iter::once(&self.baro).chain(&self.gps)
+    }
+}
+
+/// The profile at a point in time
+#[derive(Debug, Clone, Copy)]
+pub struct ProfileRow {
+    pub timestamp: u64,
+    /// Estimated height above the ground level at the start
+    pub agl: f64,
+    /// The GPS altitude as logged, usually above mean sea level
+    pub gps: Option<f64>,
+    /// Change of `agl` per second since the previous row
+    pub climb_rate: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AltitudeSegment {
+    pub start: u64,
+    pub end: u64,
+    pub max_agl: f64,
+    pub max_gps: Option<f64>,
+    pub max_climb: f64,
+    pub max_descent: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AltitudeReport {
+    /// One row per `REPORT_INTERVAL_US` plus the last sample
+    pub profile: Vec<ProfileRow>,
+    pub segments: Vec<AltitudeSegment>,
+    pub max_agl: f64,
+}
+
+/// Estimates the height above ground at each barometer sample and collects the profile and the
+/// flight segments. `None` without barometer samples.
+pub fn altitude_profile(
+    baro: &[(u64, f64)],
+    gps: Option<&[(u64, f64)]>,
+    airborne: f64,
+) -> Option<AltitudeReport> {
+    let mut baro = baro.to_vec();
+    baro.sort_by_key(|(ts, _)| *ts);
+    let ground = ground_level(&baro)?;
+    let times: Vec<u64> = baro.iter().map(|(ts, _)| *ts).collect();
+    let gps = gps
+        .map(|gps| {
+            let mut gps = gps.to_vec();
+            gps.sort_by_key(|(ts, _)| *ts);
+            gps
+        })
+        .filter(|gps| !gps.is_empty());
+    let gps_ground = gps.as_deref().and_then(ground_level);
+    let gps_values = gps.as_deref().map(|gps| resample(gps, &times));
+
+    let mut report = AltitudeReport::default();
+    let mut bias = 0.0;
+    let mut segment: Option<AltitudeSegment> = None;
+    let mut last_row: Option<ProfileRow> = None;
+    for (i, &(ts, value)) in baro.iter().enumerate() {
+        let relative = value - ground;
+        if let (Some(gps_values), Some(gps_ground)) = (&gps_values, gps_ground) {
+            let dt = if i == 0 { 0 } else { ts - times[i - 1] };
+            if dt <= MAX_TIMESTAMP_JUMP_US {
+                let dt = dt as f64 / 1e6;
+                let error = gps_values[i] - gps_ground - relative;
+                bias += (error - bias) * dt / (GPS_TIME_CONSTANT_S + dt);
+            }
+        }
+        let agl = relative + bias;
+        let gps_value = gps_values.as_ref().map(|values| values[i]);
+        report.max_agl = report.max_agl.max(agl);
+
+        let due = last_row.is_none_or(|row| ts >= row.timestamp + REPORT_INTERVAL_US);
+        if due || i + 1 == baro.len() {
+            let climb_rate = last_row
+                .filter(|row| ts > row.timestamp && ts - row.timestamp <= MAX_TIMESTAMP_JUMP_US)
+                .map(|row| (agl - row.agl) / ((ts - row.timestamp) as f64 / 1e6));
+            let row = ProfileRow {
+                timestamp: ts,
+                agl,
+                gps: gps_value,
+                climb_rate,
+            };
+            report.profile.push(row);
+            last_row = Some(row);
+            if let (Some(seg), Some(rate)) = (segment.as_mut(), climb_rate) {
+                seg.max_climb = seg.max_climb.max(rate);
+                seg.max_descent = seg.max_descent.max(-rate);
+            }
+        }
+
+        if agl > airborne {
+            let seg = segment.get_or_insert(AltitudeSegment {
+                start: ts,
+                end: ts,
+                max_agl: agl,
+                max_gps: gps_value,
+                max_climb: 0.0,
+                max_descent: 0.0,
+            });
+            seg.end = ts;
+            seg.max_agl = seg.max_agl.max(agl);
+            seg.max_gps = match (seg.max_gps, gps_value) {
+                (Some(max), Some(value)) => Some(max.max(value)),
+                (max, value) => max.or(value),
+            };
+        } else if segment.is_some_and(|seg| ts - seg.end > SEGMENT_GAP_US) {
+            report.segments.extend(segment.take());
+        }
+    }
+    report.segments.extend(segment);
+    Some(report)
+}
+
+// Median of the samples over the first `GROUND_WINDOW_US`
+fn ground_level(series: &[(u64, f64)]) -> Option<f64> {
+    let start = series.first()?.0;
+    let mut values: Vec<f64> = series
+        .iter()
+        .take_while(|(ts, _)| *ts <= start + GROUND_WINDOW_US)
+        .map(|(_, value)| *value)
+        .collect();
+    values.sort_by(f64::total_cmp);
+    Some(values[values.len() / 2])
+}
+
+/// Writes the profile to `profile_path` and one row per flight segment to `segments_path`
+pub fn write_altitude_report<P: AsRef<Path>>(
+    profile_path: P,
+    segments_path: P,
+    report: &AltitudeReport,
+) -> Result<()> {
+    let optional = |value: Option<f64>, decimals: usize| {
+        value.map_or_else(String::new, |v| format!("{:.*}", decimals, v))
+    };
+    let mut writer = csv::Writer::from_path(profile_path)?;
+    writer.write_record(["timestamp_us", "agl_m", "gps_alt_m", "climb_rate_m_s"])?;
+    for row in &report.profile {
+        writer.write_record([
+            row.timestamp.to_string(),
+            format!("{:.2}", row.agl),
+            optional(row.gps, 2),
+            optional(row.climb_rate, 3),
+        ])?;
+    }
+    writer.flush()?;
+
+    let mut writer = csv::Writer::from_path(segments_path)?;
+    writer.write_record([
+        "segment",
+        "start_us",
+        "end_us",
+        "duration_s",
+        "max_agl_m",
+        "max_gps_alt_m",
+        "max_climb_m_s",
+        "max_descent_m_s",
+    ])?;
+    for (i, seg) in report.segments.iter().enumerate() {
+        writer.write_record([
+            (i + 1).to_string(),
+            seg.start.to_string(),
+            seg.end.to_string(),
+            format!("{:.3}", (seg.end - seg.start) as f64 / 1e6),
+            format!("{:.2}", seg.max_agl),
+            optional(seg.max_gps, 2),
+            format!("{:.3}", seg.max_climb),
+            format!("{:.3}", seg.max_descent),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,65 @@
+// analysis/changes.rs
+// The moments a low-rate discrete field (flight mode, arm state, failsafe flags) takes a new
+// value, as a short event timeline instead of every identical row of the export.
+
+use super::FieldRef;
+use crate::errors::Result;
+use crate::parser::ParsedMessage;
+use std::path::Path;
+
+#[derive(Debug, Clone)]
+pub struct Change<'f> {
+    pub field: &'f FieldRef,
+    /// Timestamp (µs) of the message, if it has one
+    pub timestamp: Option<u64>,
+    /// None for the first value seen
+    pub previous: Option<String>,
+    pub value: String,
+}
+
+/// The first value of every field and each later message where it differs from the one before,
+/// in log order. Values are compared as decoded, so `1` and `1.0` differ.
+pub fn find_changes<'f>(fields: &'f [FieldRef], messages: &[ParsedMessage]) -> Vec<Change<'f>> {
+    let mut last: Vec<Option<&str>> = vec![None; fields.len()];
+    let mut changes = Vec::new();
+    for msg in messages {
+        for (field, last) in fields.iter().zip(last.iter_mut()) {
+            if field.message != msg.name {
+                continue;
+            }
+            let Some(value) = msg.field(&field.field) else {
+                continue;
+            };
+            if *last == Some(value) {
+                continue;
+            }
+            changes.push(Change {
+                field,
+                timestamp: msg.timestamp_us(),
+                previous: last.map(str::to_string),
+                value: value.to_string(),
+            });
+            *last = Some(value);
+        }
+    }
+    changes
+}
+
+/// Writes one row per change: timestamp (empty if the message has none), field, previous, value
+pub fn write_changes<P: AsRef<Path>>(path: P, changes: &[Change]) -> Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record(["timestamp_us", "field", "previous", "value"])?;
+    for change in changes {
+        writer.write_record([
+            change
+                .timestamp
+                .map(|ts| ts.to_string())
+                .unwrap_or_default(),
+            change.field.to_string(),
+            change.previous.clone().unwrap_or_default(),
+            change.value.clone(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,165 @@
+// analysis/correlate.rs
+// How well one signal tracks another, e.g. a setpoint and its response: both are resampled onto a
+// common time grid, then compared for correlation, lag and RMS error.
+
+use super::{resample, FieldRef};
+use crate::errors::{Result, WallaceError};
+use std::path::Path;
+
+/// Largest lag searched in either direction
+pub const MAX_LAG_US: u64 = 1_000_000;
+// Keeps the grid to a sane size when timestamps are dense or duplicated
+const MIN_STEP_US: u64 = 100;
+const MAX_SAMPLES: usize = 1_000_000;
+
+/// `REFERENCE:SIGNAL`, both `MESSAGE.FIELD`, e.g. `ATT.DesRoll:ATT.Roll`
+#[derive(Debug, Clone)]
+pub struct CorrelationSpec {
+    pub reference: FieldRef,
+    pub signal: FieldRef,
+}
+
+impl CorrelationSpec {
+    pub fn parse(text: &str) -> Result<Self> {
+        let (reference, signal) = text.split_once(':').ok_or_else(|| {
+            WallaceError::InvalidArgument(format!(
+                "invalid pair '{}': expected MESSAGE.FIELD:MESSAGE.FIELD",
+                text
+            ))
+        })?;
+        Ok(CorrelationSpec {
+            reference: FieldRef::parse(reference)?,
+            signal: FieldRef::parse(signal)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Correlation {
+    /// Points on the common grid
+    pub samples: usize,
+    /// Grid spacing in µs
+    pub step_us: u64,
+    /// Pearson correlation coefficient without lag
+    pub coefficient: f64,
+    /// Shift (µs) of the signal that correlates best with the reference; positive means the signal
+    /// follows the reference
+    pub lag_us: i64,
+    /// Correlation coefficient at that lag
+    pub lag_coefficient: f64,
+    /// Root mean square of signal minus reference, without lag
+    pub rms_error: f64,
+}
+
+/// Compares two series over the time span they share. The grid step is the finer of their median
+/// sample intervals. `None` if they do not overlap or too few points remain.
+pub fn correlate(reference: &[(u64, f64)], signal: &[(u64, f64)]) -> Option<Correlation> {
+    let (mut reference, mut signal) = (reference.to_vec(), signal.to_vec());
+    reference.sort_by_key(|(ts, _)| *ts);
+    signal.sort_by_key(|(ts, _)| *ts);
+    let start = reference.first()?.0.max(signal.first()?.0);
+    let end = reference.last()?.0.min(signal.last()?.0);
+    if end <= start {
+        return None;
+    }
+    let step = median_interval(&reference)
+        .min(median_interval(&signal))
+        .max(MIN_STEP_US)
+        .max((end - start) / MAX_SAMPLES as u64);
+    let times: Vec<u64> = (start..=end).step_by(step as usize).collect();
+    if times.len() < 3 {
+        return None;
+    }
+    let a = resample(&reference, &times);
+    let b = resample(&signal, &times);
+
+    let max_lag = ((MAX_LAG_US / step) as usize).min(times.len() / 4);
+    let mut best = (0i64, pearson(&a, &b));
+    for lag in 1..=max_lag {
+        for shift in [lag as i64, -(lag as i64)] {
+            let r = if shift > 0 {
+                pearson(&a[..a.len() - lag], &b[lag..])
+            } else {
+                pearson(&a[lag..], &b[..b.len() - lag])
+            };
+            if r > best.1 || best.1.is_nan() {
+                best = (shift, r);
+            }
+        }
+    }
+    let rms_error =
+        (a.iter().zip(&b).map(|(x, y)| (y - x).powi(2)).sum::<f64>() / a.len() as f64).sqrt();
+    Some(Correlation {
+        samples: times.len(),
+        step_us: step,
+        coefficient: pearson(&a, &b),
+        lag_us: best.0 * step as i64,
+        lag_coefficient: best.1,
+        rms_error,
+    })
+}
+
+fn median_interval(series: &[(u64, f64)]) -> u64 {
+    let mut intervals: Vec<u64> = series
+        .windows(2)
+        .map(|w| w[1].0.saturating_sub(w[0].0))
+        .filter(|d| *d > 0)
+        .collect();
+    intervals.sort_unstable();
+    intervals
+        .get(intervals.len() / 2)
+        .copied()
+        .unwrap_or(u64::MAX)
+}
+
+// NaN when either side is constant
+fn pearson(a: &[f64], b: &[f64]) -> f64 {
+    let n = a.len() as f64;
+    let mean_a = a.iter().sum::<f64>() / n;
+    let mean_b = b.iter().sum::<f64>() / n;
+    let (mut cov, mut var_a, mut var_b) = (0.0, 0.0, 0.0);
+    for (x, y) in a.iter().zip(b) {
+        cov += (x - mean_a) * (y - mean_b);
+        var_a += (x - mean_a).powi(2);
+        var_b += (y - mean_b).powi(2);
+    }
+    cov / (var_a * var_b).sqrt()
+}
+
+/// Writes one row per pair; pairs that could not be compared get empty values
+pub fn write_correlations<P: AsRef<Path>>(
+    path: P,
+    results: &[(&CorrelationSpec, Option<Correlation>)],
+) -> Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record([
+        "reference",
+        "signal",
+        "samples",
+        "step_us",
+        "correlation",
+        "lag_us",
+        "lag_correlation",
+        "rms_error",
+    ])?;
+    for (spec, result) in results {
+        let values = match result {
+            Some(c) => [
+                c.samples.to_string(),
+                c.step_us.to_string(),
+                c.coefficient.to_string(),
+                c.lag_us.to_string(),
+                c.lag_coefficient.to_string(),
+                c.rms_error.to_string(),
+            ],
+            None => Default::default(),
+        };
+        writer.write_record(
+            [spec.reference.to_string(), spec.signal.to_string()]
+                .into_iter()
+                .chain(values),
+        )?;
+    }
+    writer.flush()?;
+    Ok(())
+}
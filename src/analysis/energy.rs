@@ -0,0 +1,197 @@
+// analysis/energy.rs
+// Charge and energy drawn from the battery, integrated from voltage and current fields, over time
+// and per flight segment (a stretch where the current stays above an idle level, allowing short dips).
+
+use super::{resample, FieldRef};
+use crate::errors::{Result, WallaceError};
+use crate::utils::time::MAX_TIMESTAMP_JUMP_US;
+use std::path::Path;
+
+/// Current (A) above which the vehicle counts as flying, unless configured
+pub const DEFAULT_IDLE_CURRENT: f64 = 1.0;
+/// Dips below the idle current shorter than this do not end a flight segment
+pub const SEGMENT_GAP_US: u64 = 5_000_000;
+/// Spacing of the rows of the consumption time series
+pub const REPORT_INTERVAL_US: u64 = 1_000_000;
+
+/// `VOLTAGE:CURRENT[:IDLE_AMPS]`, e.g. `BATT.Volt:BATT.Curr:2.5`
+#[derive(Debug, Clone)]
+pub struct BatterySpec {
+    pub voltage: FieldRef,
+    pub current: FieldRef,
+    pub idle_current: f64,
+}
+
+impl BatterySpec {
+    pub fn parse(text: &str) -> Result<Self> {
+        let invalid = || {
+            WallaceError::InvalidArgument(format!(
+                "invalid battery fields '{}': expected VOLTAGE:CURRENT[:IDLE_AMPS], e.g. BATT.Volt:BATT.Curr",
+                text
+            ))
+        };
+        let parts: Vec<&str> = text.split(':').collect();
+        let idle_current = match parts.get(2) {
+            Some(amps) => amps
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|a| a.is_finite())
+                .ok_or_else(invalid)?,
+            None => DEFAULT_IDLE_CURRENT,
+        };
+        if parts.len() < 2 || parts.len() > 3 {
+            return Err(invalid());
+        }
+        Ok(BatterySpec {
+            voltage: FieldRef::parse(parts[0])?,
+            current: FieldRef::parse(parts[1])?,
+            idle_current,
+        })
+    }
+}
+
+/// Consumption up to a point in time
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Consumption {
+    pub timestamp: u64,
+    pub voltage: f64,
+    pub current: f64,
+    pub mah: f64,
+    pub wh: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Segment {
+    pub start: u64,
+    pub end: u64,
+    pub mah: f64,
+    pub wh: f64,
+    pub min_voltage: f64,
+    pub max_current: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BatteryReport {
+    /// Running totals, one row per `REPORT_INTERVAL_US` plus the last sample
+    pub timeline: Vec<Consumption>,
+    pub segments: Vec<Segment>,
+    pub total_mah: f64,
+    pub total_wh: f64,
+}
+
+/// Integrates current (A) and power (V·A) over the current samples with the trapezoidal rule;
+/// voltage is interpolated at their timestamps. Gaps longer than `MAX_TIMESTAMP_JUMP_US` are not
+/// integrated. `None` without voltage or current samples.
+pub fn integrate(
+    voltage: &[(u64, f64)],
+    current: &[(u64, f64)],
+    idle_current: f64,
+) -> Option<BatteryReport> {
+    let mut current = current.to_vec();
+    current.sort_by_key(|(ts, _)| *ts);
+    let mut voltage = voltage.to_vec();
+    voltage.sort_by_key(|(ts, _)| *ts);
+    if current.is_empty() || voltage.is_empty() {
+        return None;
+    }
+    let times: Vec<u64> = current.iter().map(|(ts, _)| *ts).collect();
+    let volts = resample(&voltage, &times);
+
+    let mut report = BatteryReport::default();
+    // The open segment, with the totals when it started
+    let mut segment: Option<(Segment, f64, f64)> = None;
+    let mut next_row = times[0];
+    for i in 0..times.len() {
+        let (ts, amps, v) = (times[i], current[i].1, volts[i]);
+        if i > 0 {
+            let dt_us = ts - times[i - 1];
+            if dt_us <= MAX_TIMESTAMP_JUMP_US {
+                let hours = dt_us as f64 / 3.6e9;
+                report.total_mah += (amps + current[i - 1].1) / 2.0 * hours * 1000.0;
+                report.total_wh += (amps * v + current[i - 1].1 * volts[i - 1]) / 2.0 * hours;
+            }
+        }
+        if amps > idle_current {
+            let (seg, start_mah, start_wh) = segment.get_or_insert((
+                Segment {
+                    start: ts,
+                    end: ts,
+                    mah: 0.0,
+                    wh: 0.0,
+                    min_voltage: v,
+                    max_current: amps,
+                },
+                report.total_mah,
+                report.total_wh,
+            ));
+            seg.end = ts;
+            seg.mah = report.total_mah - *start_mah;
+            seg.wh = report.total_wh - *start_wh;
+            seg.min_voltage = seg.min_voltage.min(v);
+            seg.max_current = seg.max_current.max(amps);
+        } else if segment.is_some_and(|(seg, _, _)| ts - seg.end > SEGMENT_GAP_US) {
+            report
+                .segments
+                .extend(segment.take().map(|(seg, _, _)| seg));
+        }
+        if ts >= next_row || i + 1 == times.len() {
+            report.timeline.push(Consumption {
+                timestamp: ts,
+                voltage: v,
+                current: amps,
+                mah: report.total_mah,
+                wh: report.total_wh,
+            });
+            next_row = ts + REPORT_INTERVAL_US;
+        }
+    }
+    report.segments.extend(segment.map(|(seg, _, _)| seg));
+    Some(report)
+}
+
+/// Writes the running totals to `timeline_path` and one row per flight segment to `segments_path`
+pub fn write_battery_report<P: AsRef<Path>>(
+    timeline_path: P,
+    segments_path: P,
+    report: &BatteryReport,
+) -> Result<()> {
+    let mut writer = csv::Writer::from_path(timeline_path)?;
+    writer.write_record(["timestamp_us", "voltage", "current", "mah", "wh"])?;
+    for row in &report.timeline {
+        writer.write_record([
+            row.timestamp.to_string(),
+            row.voltage.to_string(),
+            row.current.to_string(),
+            format!("{:.3}", row.mah),
+            format!("{:.4}", row.wh),
+        ])?;
+    }
+    writer.flush()?;
+
+    let mut writer = csv::Writer::from_path(segments_path)?;
+    writer.write_record([
+        "segment",
+        "start_us",
+        "end_us",
+        "duration_s",
+        "mah",
+        "wh",
+        "min_voltage",
+        "max_current",
+    ])?;
+    for (i, seg) in report.segments.iter().enumerate() {
+        writer.write_record([
+            (i + 1).to_string(),
+            seg.start.to_string(),
+            seg.end.to_string(),
+            format!("{:.3}", (seg.end - seg.start) as f64 / 1e6),
+            format!("{:.3}", seg.mah),
+            format!("{:.4}", seg.wh),
+            seg.min_voltage.to_string(),
+            seg.max_current.to_string(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
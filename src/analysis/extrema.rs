@@ -0,0 +1,125 @@
+// analysis/extrema.rs
+// Global minimum and maximum of a field, and every crossing of its thresholds, with their times.
+
+use super::FieldRef;
+use crate::errors::{Result, WallaceError};
+use std::path::Path;
+
+/// `MESSAGE.FIELD` with optional thresholds, e.g. `BATT.Volt:14.8:15.2`
+#[derive(Debug, Clone)]
+pub struct ExtremaSpec {
+    pub field: FieldRef,
+    pub thresholds: Vec<f64>,
+}
+
+impl ExtremaSpec {
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut parts = text.split(':');
+        let field = FieldRef::parse(parts.next().unwrap_or_default())?;
+        let thresholds = parts
+            .map(|t| {
+                t.trim()
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|t| t.is_finite())
+                    .ok_or_else(|| {
+                        WallaceError::InvalidArgument(format!(
+                            "invalid threshold '{}' in '{}': expected e.g. MESSAGE.FIELD:14.8",
+                            t, text
+                        ))
+                    })
+            })
+            .collect::<Result<_>>()?;
+        Ok(ExtremaSpec { field, thresholds })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EventKind {
+    Min,
+    Max,
+    /// The value went from at or below the threshold to above it
+    Rising(f64),
+    /// The value went from above the threshold to at or below it
+    Falling(f64),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Event {
+    pub kind: EventKind,
+    /// Timestamp (µs) of the first sample at the extremum or past the threshold
+    pub timestamp: u64,
+    pub value: f64,
+}
+
+/// The first global minimum and maximum of `series`, then every threshold crossing in time order
+pub fn find_events(series: &[(u64, f64)], thresholds: &[f64]) -> Vec<Event> {
+    let mut events = Vec::new();
+    let Some(&first) = series.first() else {
+        return events;
+    };
+    let (mut min, mut max) = (first, first);
+    for &(ts, value) in series {
+        if value < min.1 {
+            min = (ts, value);
+        }
+        if value > max.1 {
+            max = (ts, value);
+        }
+    }
+    events.push(Event {
+        kind: EventKind::Min,
+        timestamp: min.0,
+        value: min.1,
+    });
+    events.push(Event {
+        kind: EventKind::Max,
+        timestamp: max.0,
+        value: max.1,
+    });
+
+    let mut above: Vec<bool> = thresholds.iter().map(|t| first.1 > *t).collect();
+    for &(ts, value) in &series[1..] {
+        for (threshold, was_above) in thresholds.iter().zip(above.iter_mut()) {
+            let is_above = value > *threshold;
+            if is_above != *was_above {
+                events.push(Event {
+                    kind: if is_above {
+                        EventKind::Rising(*threshold)
+                    } else {
+                        EventKind::Falling(*threshold)
+                    },
+                    timestamp: ts,
+                    value,
+                });
+                *was_above = is_above;
+            }
+        }
+    }
+    events
+}
+
+/// Writes one row per event: field, event, threshold (empty for min/max), timestamp, value
+pub fn write_extrema<P: AsRef<Path>>(path: P, reports: &[(&FieldRef, Vec<Event>)]) -> Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record(["field", "event", "threshold", "timestamp_us", "value"])?;
+    for (field, events) in reports {
+        for event in events {
+            let (name, threshold) = match event.kind {
+                EventKind::Min => ("min", String::new()),
+                EventKind::Max => ("max", String::new()),
+                EventKind::Rising(t) => ("rising", t.to_string()),
+                EventKind::Falling(t) => ("falling", t.to_string()),
+            };
+            writer.write_record([
+                field.to_string(),
+                name.to_string(),
+                threshold,
+                event.timestamp.to_string(),
+                event.value.to_string(),
+            ])?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,271 @@
+// analysis/geo.rs
+// Positions from latitude and longitude fields, regions read from GeoJSON polygons to test them
+// against, and the distances covered between them.
+
+use super::FieldRef;
+use crate::errors::{Result, WallaceError};
+use crate::messages::registry::MessageRegistry;
+use crate::parser::ParsedMessage;
+use serde::Serialize;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+/// `LAT:LON[:SCALE]`, the values times SCALE (default 1) in degrees, e.g.
+/// `GPSData.lat:GPSData.lon:1e-7`. Both fields must be in the same message.
+#[derive(Debug, Clone)]
+pub struct PositionSpec {
+    pub lat: FieldRef,
+    pub lon: FieldRef,
+    pub scale: f64,
+}
+
+/// A position fix in degrees
+#[derive(Debug, Clone, Copy)]
+pub struct Fix {
+    pub timestamp: u64,
+    pub lat: f64,
+    pub lon: f64,
+}
+
+impl PositionSpec {
+    pub fn parse(text: &str) -> Result<Self> {
+        let invalid = || {
+            WallaceError::InvalidArgument(format!(
+                "invalid position fields '{}': expected LAT:LON[:SCALE], e.g. GPSData.lat:GPSData.lon:1e-7",
+                text
+            ))
+        };
+        let parts: Vec<&str> = text.split(':').collect();
+        if parts.len() < 2 || parts.len() > 3 {
+            return Err(invalid());
+        }
+        let scale = match parts.get(2) {
+            Some(scale) => scale
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|s| s.is_finite() && *s != 0.0)
+                .ok_or_else(invalid)?,
+            None => 1.0,
+        };
+        let spec = PositionSpec {
+            lat: FieldRef::parse(parts[0])?,
+            lon: FieldRef::parse(parts[1])?,
+            scale,
+        };
+        if spec.lat.message != spec.lon.message {
+            return Err(WallaceError::InvalidArgument(format!(
+                "position fields '{}' and '{}' are in different messages",
+                spec.lat, spec.lon
+            )));
+        }
+        Ok(spec)
+    }
+
+    pub fn check(&self, registry: &MessageRegistry) -> Result<()> {
+        self.lat.check(registry)?;
+        self.lon.check(registry)
+    }
+
+    /// Every timestamped fix in time order. 0,0 is what receivers log without a fix and is left
+    /// out, as are values outside the range of degrees.
+    pub fn fixes(&self, messages: &[ParsedMessage]) -> Vec<Fix> {
+        let value = |msg: &ParsedMessage, name: &str| {
+            msg.fields
+                .iter()
+                .find(|(field, _)| field == name)
+                .and_then(|(_, value)| value.parse::<f64>().ok())
+                .map(|v| v * self.scale)
+        };
+        let mut fixes: Vec<Fix> = messages
+            .iter()
+            .filter(|msg| msg.name == self.lat.message)
+            .filter_map(|msg| {
+                Some(Fix {
+                    timestamp: msg.timestamp_us()?,
+                    lat: value(msg, &self.lat.field)?,
+                    lon: value(msg, &self.lon.field)?,
+                })
+            })
+            .filter(|fix| fix.lat.abs() <= 90.0 && fix.lon.abs() <= 180.0)
+            .filter(|fix| fix.lat != 0.0 || fix.lon != 0.0)
+            .collect();
+        fixes.sort_by_key(|fix| fix.timestamp);
+        fixes
+    }
+}
+
+/// The polygons of a GeoJSON file: rings of (longitude, latitude), the first of each polygon its
+/// outline and any others its holes
+#[derive(Debug, Clone)]
+pub struct Region {
+    polygons: Vec<Vec<Vec<(f64, f64)>>>,
+}
+
+impl Region {
+    /// Reads every Polygon and MultiPolygon in a geometry, Feature or FeatureCollection
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let invalid = |reason: String| {
+            WallaceError::InvalidArgument(format!(
+                "invalid GeoJSON '{}': {}",
+                path.display(),
+                reason
+            ))
+        };
+        let json: Value =
+            serde_json::from_str(&fs::read_to_string(path)?).map_err(|e| invalid(e.to_string()))?;
+        let mut polygons = Vec::new();
+        collect_polygons(&json, &mut polygons).map_err(invalid)?;
+        if polygons.is_empty() {
+            return Err(invalid("no Polygon or MultiPolygon in it".to_string()));
+        }
+        Ok(Region { polygons })
+    }
+
+    /// Whether the point is inside a polygon outline and none of its holes
+    pub fn contains(&self, lat: f64, lon: f64) -> bool {
+        self.polygons.iter().any(|rings| {
+            let mut rings = rings.iter();
+            rings
+                .next()
+                .is_some_and(|outline| in_ring(outline, lon, lat))
+                && !rings.any(|hole| in_ring(hole, lon, lat))
+        })
+    }
+}
+
+fn collect_polygons(
+    json: &Value,
+    polygons: &mut Vec<Vec<Vec<(f64, f64)>>>,
+) -> std::result::Result<(), String> {
+    match json.get("type").and_then(Value::as_str) {
+        Some("FeatureCollection") => {
+            for feature in json
+                .get("features")
+                .and_then(Value::as_array)
+                .ok_or("a FeatureCollection without features")?
+            {
+                collect_polygons(feature, polygons)?;
+            }
+        }
+        Some("Feature") => {
+            if let Some(geometry) = json.get("geometry").filter(|g| !g.is_null()) {
+                collect_polygons(geometry, polygons)?;
+            }
+        }
+        Some("GeometryCollection") => {
+            for geometry in json
+                .get("geometries")
+                .and_then(Value::as_array)
+                .ok_or("a GeometryCollection without geometries")?
+            {
+                collect_polygons(geometry, polygons)?;
+            }
+        }
+        Some("Polygon") => polygons.push(polygon(coordinates(json)?)?),
+        Some("MultiPolygon") => {
+            for rings in coordinates(json)?
+                .as_array()
+                .ok_or("MultiPolygon coordinates are not an array")?
+            {
+                polygons.push(polygon(rings)?);
+            }
+        }
+        // Points and lines enclose nothing
+        Some(_) => {}
+        None => return Err("an object without a type".to_string()),
+    }
+    Ok(())
+}
+
+fn coordinates(json: &Value) -> std::result::Result<&Value, String> {
+    json.get("coordinates")
+        .ok_or_else(|| "a geometry without coordinates".to_string())
+}
+
+fn polygon(rings: &Value) -> std::result::Result<Vec<Vec<(f64, f64)>>, String> {
+    let invalid = || "polygon coordinates are not arrays of [longitude, latitude]".to_string();
+    rings
+        .as_array()
+        .ok_or_else(invalid)?
+        .iter()
+        .map(|ring| {
+            let ring = ring
+                .as_array()
+                .ok_or_else(invalid)?
+                .iter()
+                .map(|point| match point.as_array().map(Vec::as_slice) {
+                    Some([lon, lat, ..]) => lon.as_f64().zip(lat.as_f64()).ok_or_else(invalid),
+                    _ => Err(invalid()),
+                })
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            if ring.len() < 4 {
+                return Err("a polygon ring has fewer than 4 positions".to_string());
+            }
+            Ok(ring)
+        })
+        .collect()
+}
+
+// Even-odd rule: a ray from the point crosses the ring an odd number of times if it is inside.
+// Treats degrees as planar, which is exact enough for regions the size of an airfield.
+fn in_ring(ring: &[(f64, f64)], x: f64, y: f64) -> bool {
+    let mut inside = false;
+    // Rings should end where they start; the edge back to the start closes those that do not
+    for (&(x0, y0), &(x1, y1)) in ring.iter().zip(ring.iter().cycle().skip(1)) {
+        if (y0 > y) != (y1 > y) && x < x0 + (y - y0) * (x1 - x0) / (y1 - y0) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// Mean Earth radius (m) of the haversine distance
+const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+/// Great-circle distance in metres between two fixes
+pub fn distance(a: &Fix, b: &Fix) -> f64 {
+    let (lat_a, lat_b) = (a.lat.to_radians(), b.lat.to_radians());
+    let d_lat = lat_b - lat_a;
+    let d_lon = (b.lon - a.lon).to_radians();
+    let h = (d_lat / 2.0).sin().powi(2) + lat_a.cos() * lat_b.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_M * h.sqrt().min(1.0).asin()
+}
+
+/// Where the vehicle went, as recorded in `summary.json`
+#[derive(Debug, Clone, Serialize)]
+pub struct TrackStats {
+    pub fixes: usize,
+    /// Sum of the distances between consecutive fixes
+    pub distance_m: f64,
+    /// The first fix
+    pub launch: [f64; 2],
+    pub max_distance_from_launch_m: f64,
+    /// `[min_lat, min_lon, max_lat, max_lon]` in degrees
+    pub bounding_box: [f64; 4],
+}
+
+/// None without fixes
+pub fn track_stats(fixes: &[Fix]) -> Option<TrackStats> {
+    let launch = fixes.first()?;
+    let mut stats = TrackStats {
+        fixes: fixes.len(),
+        distance_m: 0.0,
+        launch: [launch.lat, launch.lon],
+        max_distance_from_launch_m: 0.0,
+        bounding_box: [launch.lat, launch.lon, launch.lat, launch.lon],
+    };
+    for (i, fix) in fixes.iter().enumerate().skip(1) {
+        stats.distance_m += distance(&fixes[i - 1], fix);
+        stats.max_distance_from_launch_m =
+            stats.max_distance_from_launch_m.max(distance(launch, fix));
+        let bbox = &mut stats.bounding_box;
+        bbox[0] = bbox[0].min(fix.lat);
+        bbox[1] = bbox[1].min(fix.lon);
+        bbox[2] = bbox[2].max(fix.lat);
+        bbox[3] = bbox[3].max(fix.lon);
+    }
+    Some(stats)
+}
@@ -0,0 +1,91 @@
+// analysis/histogram.rs
+// Distribution of a field's values in equal-width bins, e.g. vibration or current.
+
+use super::FieldRef;
+use crate::errors::{Result, WallaceError};
+use std::path::Path;
+
+pub const DEFAULT_BINS: usize = 20;
+
+/// `MESSAGE.FIELD` with an optional bin count, e.g. `BATT.Curr:50bins`
+#[derive(Debug, Clone)]
+pub struct HistogramSpec {
+    pub field: FieldRef,
+    pub bins: usize,
+}
+
+impl HistogramSpec {
+    pub fn parse(text: &str) -> Result<Self> {
+        let (field, bins) = match text.split_once(':') {
+            Some((field, bins)) => {
+                let count = bins.trim().trim_end_matches("bins");
+                let bins = count
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .ok_or_else(|| {
+                        WallaceError::InvalidArgument(format!(
+                            "invalid bin count in '{}': expected e.g. MESSAGE.FIELD:50bins",
+                            text
+                        ))
+                    })?;
+                (field, bins)
+            }
+            None => (text, DEFAULT_BINS),
+        };
+        Ok(HistogramSpec {
+            field: FieldRef::parse(field)?,
+            bins,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    pub min: f64,
+    pub max: f64,
+    /// Values per bin; every bin is `[lower, upper)` except the last, which includes `max`
+    pub counts: Vec<u64>,
+}
+
+impl Histogram {
+    /// Spreads `bins` bins evenly between the smallest and largest value. `None` without values.
+    pub fn compute(values: &[f64], bins: usize) -> Option<Self> {
+        let min = values.iter().copied().reduce(f64::min)?;
+        let max = values.iter().copied().reduce(f64::max)?;
+        let mut counts = vec![0u64; bins];
+        let width = (max - min) / bins as f64;
+        for value in values {
+            let bin = if width > 0.0 {
+                (((value - min) / width) as usize).min(bins - 1)
+            } else {
+                0
+            };
+            counts[bin] += 1;
+        }
+        Some(Histogram { min, max, counts })
+    }
+
+    /// Lower and upper edge of bin `i`
+    pub fn edges(&self, i: usize) -> (f64, f64) {
+        let width = (self.max - self.min) / self.counts.len() as f64;
+        let upper = if i + 1 == self.counts.len() {
+            self.max
+        } else {
+            self.min + width * (i + 1) as f64
+        };
+        (self.min + width * i as f64, upper)
+    }
+}
+
+/// Writes one row per bin: lower edge, upper edge, count
+pub fn write_histogram<P: AsRef<Path>>(path: P, histogram: &Histogram) -> Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record(["bin_start", "bin_end", "count"])?;
+    for (i, count) in histogram.counts.iter().enumerate() {
+        let (lower, upper) = histogram.edges(i);
+        writer.write_record([lower.to_string(), upper.to_string(), count.to_string()])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,139 @@
+// analysis/mod.rs
+// Post-flight analyses over the decoded messages, written as CSV reports next to the exports.
+
+pub mod altitude;
+pub mod changes;
+pub mod correlate;
+pub mod energy;
+pub mod extrema;
+pub mod geo;
+pub mod histogram;
+pub mod spectrum;
+
+use crate::errors::{Result, WallaceError};
+use crate::messages::registry::MessageRegistry;
+use crate::parser::{is_numeric_type, ParsedMessage};
+use std::fmt;
+
+/// `MESSAGE.FIELD`, a numeric signal in the log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldRef {
+    pub message: String,
+    pub field: String,
+}
+
+impl FieldRef {
+    pub fn parse(text: &str) -> Result<Self> {
+        match text.trim().split_once('.') {
+            Some((message, field)) if !message.is_empty() && !field.is_empty() => Ok(FieldRef {
+                message: message.to_string(),
+                field: field.to_string(),
+            }),
+            _ => Err(WallaceError::InvalidArgument(format!(
+                "invalid field '{}': expected MESSAGE.FIELD",
+                text
+            ))),
+        }
+    }
+
+    /// Fails unless some message of that name in the registry has a numeric field of that name
+    pub fn check(&self, registry: &MessageRegistry) -> Result<()> {
+        let found = registry
+            .values()
+            .filter(|def| def.name == self.message)
+            .flat_map(|def| def.fields.iter())
+            .any(|f| f.name == self.field && is_numeric_type(&f.r#type));
+        if found {
+            Ok(())
+        } else {
+            Err(WallaceError::InvalidArgument(format!(
+                "no numeric field '{}' in the registry",
+                self
+            )))
+        }
+    }
+
+    /// Fails unless some message of that name in the registry has a field of that name, of any
+    /// type, for uses that do not need a number such as modes and flags
+    pub fn check_exists(&self, registry: &MessageRegistry) -> Result<()> {
+        let found = registry
+            .values()
+            .filter(|def| def.name == self.message)
+            .flat_map(|def| def.fields.iter())
+            .any(|f| f.name == self.field);
+        if found {
+            Ok(())
+        } else {
+            Err(WallaceError::InvalidArgument(format!(
+                "no field '{}' in the registry",
+                self
+            )))
+        }
+    }
+
+    /// Every finite value of the field, in log order
+    pub fn values(&self, messages: &[ParsedMessage]) -> Vec<f64> {
+        self.samples(messages).map(|(_, value)| value).collect()
+    }
+
+    /// Timestamp (µs) and value of every message that carries both, in log order
+    pub fn series(&self, messages: &[ParsedMessage]) -> Vec<(u64, f64)> {
+        self.samples(messages)
+            .filter_map(|(ts, value)| Some((ts?, value)))
+            .collect()
+    }
+
+    fn samples<'m>(
+        &'m self,
+        messages: &'m [ParsedMessage],
+    ) -> impl Iterator<Item = (Option<u64>, f64)> + 'm {
+        messages
+            .iter()
+            .filter(move |msg| msg.name == self.message)
+            .filter_map(move |msg| {
+                let value = msg.field(&self.field)?.parse::<f64>().ok()?;
+                value.is_finite().then(|| (msg.timestamp_us(), value))
+            })
+    }
+
+    /// Name for report files, `MESSAGE.FIELD` with characters unsafe in file names replaced
+    pub fn file_stem(&self) -> String {
+        self.to_string()
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || "._-".contains(c) {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for FieldRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.message, self.field)
+    }
+}
+
+/// Linear interpolation of `series` (sorted by time) at each of `times`, holding the first and
+/// last value outside its span
+pub fn resample(series: &[(u64, f64)], times: &[u64]) -> Vec<f64> {
+    let mut i = 0;
+    times
+        .iter()
+        .map(|&t| {
+            while i + 1 < series.len() && series[i + 1].0 <= t {
+                i += 1;
+            }
+            let (t0, v0) = series[i];
+            match series.get(i + 1) {
+                Some(&(t1, v1)) if t1 > t0 && t > t0 => {
+                    v0 + (v1 - v0) * (t - t0) as f64 / (t1 - t0) as f64
+                }
+                _ => v0,
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,183 @@
+// analysis/spectrum.rs
+// Power spectral density of a signal by Welch's method: the signal is put on a uniform time grid,
+// cut into overlapping Hann-windowed segments, and the periodograms of the segments averaged.
+// The per-segment spectra also make a spectrogram, vibration over the course of the flight.
+
+use super::resample;
+use crate::errors::Result;
+use crate::report::png::write_rgb;
+use std::f64::consts::PI;
+use std::path::Path;
+
+pub struct Spectrum {
+    pub sample_rate: f64,
+    /// FFT length; the spectra have `window / 2 + 1` bins from 0 Hz to the Nyquist frequency
+    pub window: usize,
+    /// Averaged one-sided PSD, in squared units of the signal per Hz
+    pub psd: Vec<f64>,
+    /// The PSD of every segment, in time order
+    pub segments: Vec<Vec<f64>>,
+    /// Time (µs) from the start of one segment to the next
+    pub hop_us: f64,
+}
+
+impl Spectrum {
+    pub fn frequency(&self, bin: usize) -> f64 {
+        bin as f64 * self.sample_rate / self.window as f64
+    }
+
+    /// Frequency and PSD of the strongest bin above 0 Hz
+    pub fn peak(&self) -> Option<(f64, f64)> {
+        self.psd
+            .iter()
+            .enumerate()
+            .skip(1)
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(bin, &psd)| (self.frequency(bin), psd))
+    }
+}
+
+/// The spectrum of `series` (timestamp µs, value), sampled at the median interval of its
+/// timestamps. None if it is shorter than one window.
+pub fn welch(series: &[(u64, f64)], window: usize, overlap: f64) -> Option<Spectrum> {
+    let mut series = series.to_vec();
+    series.sort_by_key(|(ts, _)| *ts);
+    let mut intervals: Vec<u64> = series
+        .windows(2)
+        .map(|pair| pair[1].0 - pair[0].0)
+        .filter(|dt| *dt > 0)
+        .collect();
+    if intervals.is_empty() {
+        return None;
+    }
+    let mid = intervals.len() / 2;
+    let step_us = *intervals.select_nth_unstable(mid).1;
+    let start_us = series[0].0;
+    let count = ((series[series.len() - 1].0 - start_us) / step_us + 1) as usize;
+    if count < window {
+        return None;
+    }
+    let times: Vec<u64> = (0..count as u64).map(|i| start_us + i * step_us).collect();
+    let samples = resample(&series, &times);
+    let sample_rate = 1e6 / step_us as f64;
+
+    let hann: Vec<f64> = (0..window)
+        .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f64 / window as f64).cos())
+        .collect();
+    let power: f64 = hann.iter().map(|w| w * w).sum();
+    let hop = ((window as f64 * (1.0 - overlap)).round() as usize).max(1);
+    let bins = window / 2 + 1;
+    let mut segments = Vec::new();
+    let mut re = vec![0.0; window];
+    let mut im = vec![0.0; window];
+    for start in (0..=samples.len() - window).step_by(hop) {
+        let segment = &samples[start..start + window];
+        let mean = segment.iter().sum::<f64>() / window as f64;
+        for (i, value) in segment.iter().enumerate() {
+            re[i] = (value - mean) * hann[i];
+            im[i] = 0.0;
+        }
+        fft(&mut re, &mut im);
+        let psd: Vec<f64> = (0..bins)
+            .map(|k| {
+                // One-sided: every bin but 0 Hz and Nyquist also holds its negative frequency
+                let scale = if k == 0 || k == window / 2 { 1.0 } else { 2.0 };
+                scale * (re[k] * re[k] + im[k] * im[k]) / (sample_rate * power)
+            })
+            .collect();
+        segments.push(psd);
+    }
+    let mut psd = vec![0.0; bins];
+    for segment in &segments {
+        for (total, value) in psd.iter_mut().zip(segment) {
+            *total += value / segments.len() as f64;
+        }
+    }
+    Some(Spectrum {
+        sample_rate,
+        window,
+        psd,
+        segments,
+        hop_us: hop as f64 * step_us as f64,
+    })
+}
+
+// In-place iterative radix-2 FFT; the length must be a power of two
+fn fft(re: &mut [f64], im: &mut [f64]) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f64;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f64).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// Writes one row per frequency bin: frequency, PSD and PSD in dB
+pub fn write_spectrum<P: AsRef<Path>>(path: P, spectrum: &Spectrum) -> Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record(["frequency_hz", "psd", "psd_db"])?;
+    for (bin, psd) in spectrum.psd.iter().enumerate() {
+        writer.write_record([
+            spectrum.frequency(bin).to_string(),
+            psd.to_string(),
+            to_db(*psd).to_string(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// The range of a spectrogram below its strongest bin; anything weaker is drawn black
+const SPECTROGRAM_RANGE_DB: f64 = 80.0;
+
+/// Draws the segments as a PNG, one column per segment from left to right and one row per bin
+/// with 0 Hz at the bottom, from black through red and yellow to white as the power rises
+pub fn write_spectrogram<P: AsRef<Path>>(path: P, spectrum: &Spectrum) -> Result<()> {
+    let width = spectrum.segments.len();
+    let height = spectrum.window / 2 + 1;
+    let top = spectrum
+        .segments
+        .iter()
+        .flatten()
+        .map(|psd| to_db(*psd))
+        .fold(f64::NEG_INFINITY, f64::max);
+    let mut rgb = vec![0u8; width * height * 3];
+    for (x, segment) in spectrum.segments.iter().enumerate() {
+        for (bin, psd) in segment.iter().enumerate() {
+            let level = (1.0 - (top - to_db(*psd)) / SPECTROGRAM_RANGE_DB).clamp(0.0, 1.0);
+            let channel = |from: f64| ((level * 3.0 - from).clamp(0.0, 1.0) * 255.0) as u8;
+            let at = ((height - 1 - bin) * width + x) * 3;
+            rgb[at..at + 3].copy_from_slice(&[channel(0.0), channel(1.0), channel(2.0)]);
+        }
+    }
+    write_rgb(path, width as u32, height as u32, &rgb)
+}
+
+fn to_db(psd: f64) -> f64 {
+    10.0 * psd.max(1e-30).log10()
+}
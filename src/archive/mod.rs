@@ -0,0 +1,215 @@
+// archive/mod.rs
+// Bundles a finished output directory into a single artifact.
+
+use crate::errors::{Result, WallaceError};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    TarGz,
+}
+
+impl ArchiveFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "zip" => Some(ArchiveFormat::Zip),
+            "tar.gz" | "tgz" => Some(ArchiveFormat::TarGz),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::TarGz => "tar.gz",
+        }
+    }
+
+    /// The format of an archive, judged by its file name
+    pub fn of_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?;
+        ["zip", "tar.gz", "tgz"]
+            .into_iter()
+            .find(|ext| name.ends_with(&format!(".{}", ext)))
+            .and_then(ArchiveFormat::from_name)
+    }
+}
+
+/// Packs every file below `dir` into `<dir>.zip` or `<dir>.tar.gz`, placed next to
+/// the directory so the archive never ends up inside itself. Entries are stored
+/// relative to the directory name, e.g. `output/GPSData.csv`. Paths such as `.` are
+/// resolved first, so they are named and placed after the directory they stand for.
+pub fn archive_directory(dir: &Path, format: ArchiveFormat) -> Result<PathBuf> {
+    let dir = &fs::canonicalize(dir)?;
+    let dir_name = match dir.file_name() {
+        Some(name) => name
+            .to_str()
+            .ok_or_else(|| WallaceError::PathConversionError {
+                path: dir.to_path_buf(),
+            })?
+            .to_string(),
+        // Only the root has no name, and an archive of it could only go inside it
+        None => {
+            return Err(WallaceError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("cannot archive '{}' next to itself", dir.display()),
+            )))
+        }
+    };
+    let archive_path = dir.with_file_name(format!("{}.{}", dir_name, format.extension()));
+
+    let mut files = Vec::new();
+    collect_files(dir, &mut files)?;
+    files.sort(); // Stable entry order regardless of directory iteration order
+
+    let archive_file = File::create(&archive_path)?;
+    match format {
+        ArchiveFormat::Zip => {
+            let mut zip = ZipWriter::new(archive_file);
+            let options =
+                SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+            for file in &files {
+                let entry_name = entry_name(dir, &dir_name, file)?;
+                zip.start_file(entry_name, options)?;
+                io::copy(&mut File::open(file)?, &mut zip)?;
+            }
+            zip.finish()?;
+        }
+        ArchiveFormat::TarGz => {
+            let mut tar = tar::Builder::new(GzEncoder::new(archive_file, Compression::default()));
+            for file in &files {
+                let entry_name = entry_name(dir, &dir_name, file)?;
+                tar.append_path_with_name(file, entry_name)?;
+            }
+            tar.into_inner()?.finish()?;
+        }
+    }
+
+    Ok(archive_path)
+}
+
+/// Calls `visit` with the name and contents of every file in an archive written by
+/// `archive_directory`, in the order they are stored
+pub fn for_each_entry(
+    path: &Path,
+    format: ArchiveFormat,
+    mut visit: impl FnMut(&str, &mut dyn Read) -> Result<()>,
+) -> Result<()> {
+    match format {
+        ArchiveFormat::Zip => {
+            let mut zip = ZipArchive::new(File::open(path)?)?;
+            for i in 0..zip.len() {
+                let mut entry = zip.by_index(i)?;
+                if entry.is_file() {
+                    let name = entry.name().to_string();
+                    visit(&name, &mut entry)?;
+                }
+            }
+        }
+        ArchiveFormat::TarGz => {
+            let mut tar = tar::Archive::new(GzDecoder::new(File::open(path)?));
+            for entry in tar.entries()? {
+                let mut entry = entry?;
+                if entry.header().entry_type().is_file() {
+                    let name = entry.path()?.to_string_lossy().replace('\\', "/");
+                    visit(&name, &mut entry)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Every file below `dir`, recursively
+pub fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+// Archive entry names always use '/' so the artifact unpacks the same on every platform
+fn entry_name(dir: &Path, dir_name: &str, file: &Path) -> Result<String> {
+    let relative = file.strip_prefix(dir).unwrap_or(file);
+    let relative = relative
+        .to_str()
+        .ok_or_else(|| WallaceError::PathConversionError {
+            path: file.to_path_buf(),
+        })?;
+    Ok(format!("{}/{}", dir_name, relative.replace('\\', "/")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use zip::ZipArchive;
+
+    // An output directory `out` with a file at its top and one in a subdirectory
+    fn output_dir(test: &str) -> PathBuf {
+        let base =
+            std::env::temp_dir().join(format!("wallace-archive-{}-{}", std::process::id(), test));
+        fs::create_dir_all(base.join("out").join("sub")).unwrap();
+        fs::write(base.join("out").join("GPSData.csv"), "a\n1\n").unwrap();
+        fs::write(base.join("out").join("sub").join("IMU.csv"), "b\n2\n").unwrap();
+        base
+    }
+
+    fn zip_entries(path: &Path) -> Vec<String> {
+        let zip = ZipArchive::new(File::open(path).unwrap()).unwrap();
+        zip.file_names().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn entries_are_stored_below_the_directory_name() {
+        let base = output_dir("entries");
+        let zip = archive_directory(&base.join("out"), ArchiveFormat::Zip).unwrap();
+        assert_eq!(zip, fs::canonicalize(&base).unwrap().join("out.zip"));
+        assert_eq!(zip_entries(&zip), ["out/GPSData.csv", "out/sub/IMU.csv"]);
+
+        let tar_gz = archive_directory(&base.join("out"), ArchiveFormat::TarGz).unwrap();
+        assert_eq!(tar_gz.file_name().unwrap(), "out.tar.gz");
+        let mut tar = tar::Archive::new(GzDecoder::new(File::open(&tar_gz).unwrap()));
+        let names: Vec<String> = tar
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                entry
+                    .unwrap()
+                    .path()
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect();
+        assert_eq!(names, ["out/GPSData.csv", "out/sub/IMU.csv"]);
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn paths_without_a_name_are_archived_next_to_their_directory() {
+        let base = output_dir("unnamed");
+        // As `-o .` run inside `out` would give
+        let dir = base.join("out").join("sub").join("..");
+        let zip = archive_directory(&dir, ArchiveFormat::Zip).unwrap();
+        assert_eq!(zip, fs::canonicalize(&base).unwrap().join("out.zip"));
+        assert_eq!(zip_entries(&zip), ["out/GPSData.csv", "out/sub/IMU.csv"]);
+        assert!(!base.join("out").join("output.zip").exists());
+        fs::remove_dir_all(&base).unwrap();
+
+        assert!(archive_directory(Path::new("/"), ArchiveFormat::Zip).is_err());
+    }
+}
@@ -0,0 +1,225 @@
+// commands/annotate.rs
+// `annotate`: a hex dump of the records at a byte offset of a log, one line per field with its
+// type and decoded value beside its bytes, to see where a registry's layout stops matching the
+// data. Records are framed from the start of the log, as every other command reads them.
+
+use super::{existing_file, existing_input};
+use crate::errors::{Result, WallaceError};
+use crate::file_io::open_file;
+use crate::messages::firmware;
+use crate::messages::load_message_registry;
+use crate::messages::registry::{load_registry_for_firmware, MessageRegistry};
+use crate::parser::plan;
+use crate::parser::stream::RawRecord;
+use crate::parser::{field_layout_for, BytesFormat, MessageStream};
+use clap::{value_parser, Arg, ArgMatches, Command};
+use std::io::BufReader;
+
+// Bytes shown per line of the dump
+const BYTES_PER_LINE: usize = 16;
+// Characters of a decoded value shown, so that long text keeps to its line
+const MAX_VALUE_CHARS: usize = 60;
+
+pub fn subcommand() -> Command {
+    Command::new("annotate")
+        .about("Prints a hex dump of the records at a byte offset, with field boundaries and decoded values")
+        .arg(
+            Arg::new("input")
+                .short('i')
+                .long("input")
+                .value_name("FILE")
+                .help("Log to read; '-' for stdin")
+                .value_parser(existing_input)
+                .required(true),
+        )
+        .arg(
+            Arg::new("registry")
+                .short('r')
+                .long("registry")
+                .value_name("FILE")
+                .help("Sets the message registry JSON file path")
+                .value_parser(existing_file)
+                .default_value("messages.json"),
+        )
+        .arg(
+            Arg::new("offset")
+                .long("offset")
+                .value_name("BYTES")
+                .help("Byte offset in the log, counting the file header, in decimal or 0x hex; the dump starts at the record it falls in")
+                .value_parser(offset)
+                .default_value("0"),
+        )
+        .arg(
+            Arg::new("count")
+                .short('n')
+                .long("count")
+                .value_name("N")
+                .help("Records to print")
+                .value_parser(value_parser!(u64).range(1..))
+                .default_value("1"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let input = matches.get_one::<String>("input").unwrap(); // Required
+    let registry_path = matches.get_one::<String>("registry").unwrap(); // Has default
+    let offset = *matches.get_one::<u64>("offset").unwrap(); // Has default
+    let count = *matches.get_one::<u64>("count").unwrap(); // Has default
+    let mut registry = load_message_registry(registry_path)?;
+    if let Some(version) = firmware::detect(input, &registry)? {
+        println!("🏷️  Firmware {}, using its field layout", version);
+        registry = load_registry_for_firmware(registry_path, Some(&version))?;
+    }
+
+    let mut stream = MessageStream::new(BufReader::new(open_file(input)?), &registry)?;
+    let mut printed = 0;
+    while printed < count {
+        let Some(record) = stream.next_record()? else {
+            break;
+        };
+        if record.offset + 4 + record.payload.len() as u64 <= offset {
+            continue;
+        }
+        // Only the first record can hold the offset past its start
+        let mark = (printed == 0).then_some(offset);
+        print_record(&record, &registry, mark);
+        printed += 1;
+    }
+    if printed == 0 {
+        return Err(WallaceError::InvalidArgument(format!(
+            "offset {:#x} is past the last record of '{}', which ends at {:#x}",
+            offset, input, stream.bytes_read
+        )));
+    }
+    if printed < count {
+        println!(
+            "⚠️  The log ends after {} of the {} records asked for",
+            printed, count
+        );
+    }
+    Ok(())
+}
+
+/// A byte offset, e.g. `0x1A2B40` or `1715008`
+fn offset(text: &str) -> std::result::Result<u64, String> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| {
+        format!(
+            "invalid offset '{}': expected e.g. 0x1A2B40 or 1715008",
+            text
+        )
+    })
+}
+
+// The record's framing, then one line per field, or the bare payload if the type is unknown.
+// `mark` is the offset asked for, pointed out in the record holding it.
+fn print_record(record: &RawRecord, registry: &MessageRegistry, mark: Option<u64>) {
+    let payload = &record.payload;
+    let start = record.offset + 4;
+    let def = registry.get(&record.log_type.to_string());
+    println!(
+        "\n🔎 Record {} at {:#x}: log_type {} ({}), {} bytes",
+        record.index,
+        record.offset,
+        record.log_type,
+        def.map_or("not in the registry", |def| def.name.as_str()),
+        payload.len()
+    );
+    let holds = |from: u64, len: usize| mark.is_some_and(|m| m >= from && m < from + len as u64);
+    let mut framing = record.log_type.to_le_bytes().to_vec();
+    framing.extend((payload.len() as u16).to_le_bytes());
+    print_bytes(
+        record.offset,
+        &framing,
+        "type and length",
+        holds(record.offset, 4),
+    );
+
+    let Some(def) = def else {
+        print_bytes(start, payload, "payload", holds(start, payload.len()));
+        return;
+    };
+    let mut end = 0;
+    let mut warnings = Vec::new();
+    for span in field_layout_for(def, payload.len()) {
+        let field = span.field;
+        let label = format!("{} ({})", field.name, field.r#type);
+        let Some(range) = span.range() else {
+            // Nothing after a field of unknown size can be placed
+            println!("    {:>10}  {:<48}  {}: no known size", "", "", label);
+            break;
+        };
+        if range.start >= payload.len() {
+            println!("    {:>10}  {:<48}  {}: past the end", "", "", label);
+            continue;
+        }
+        let bytes = &payload[range.start..range.end.min(payload.len())];
+        let text = if bytes.len() < range.len() {
+            format!(
+                "{}: cut short, {} of {} bytes",
+                label,
+                bytes.len(),
+                range.len()
+            )
+        } else if span.is_skipped() {
+            format!("{}: skipped", label)
+        } else {
+            let value = plan::decode(
+                span.decoder,
+                field,
+                bytes,
+                BytesFormat::default(),
+                &mut warnings,
+            );
+            format!("{} = {}", label, shown(&value))
+        };
+        print_bytes(
+            start + range.start as u64,
+            bytes,
+            &text,
+            holds(start + range.start as u64, bytes.len()),
+        );
+        end = range.end.min(payload.len());
+    }
+    if end < payload.len() {
+        print_bytes(
+            start + end as u64,
+            &payload[end..],
+            &format!("{} bytes past the last field", payload.len() - end),
+            holds(start + end as u64, payload.len() - end),
+        );
+    }
+    for warning in warnings {
+        println!("    ⚠️  {}", warning);
+    }
+}
+
+// `bytes` from `offset` on in lines of 16, the label beside the first, `▶` if `marked`
+fn print_bytes(offset: u64, bytes: &[u8], label: &str, marked: bool) {
+    for (i, line) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+        let hex: Vec<String> = line.iter().map(|b| format!("{:02x}", b)).collect();
+        let (pointer, label) = match i {
+            0 => (if marked { "▶" } else { " " }, label),
+            _ => (" ", ""),
+        };
+        println!(
+            "  {} {:#010x}  {:<48}  {}",
+            pointer,
+            offset + (i * BYTES_PER_LINE) as u64,
+            hex.join(" "),
+            label
+        );
+    }
+}
+
+// `value` on one line, cut to MAX_VALUE_CHARS
+fn shown(value: &str) -> String {
+    let escaped: String = value.escape_debug().collect();
+    match escaped.char_indices().nth(MAX_VALUE_CHARS) {
+        Some((end, _)) => format!("{}…", &escaped[..end]),
+        None => escaped,
+    }
+}
@@ -0,0 +1,311 @@
+// commands/bench.rs
+// `bench`: decodes and exports a synthetic log built in memory from the registry and reports the
+// throughput, so a change to the code or the registry can be measured on the machine at hand.
+
+use super::selftest::random_payload;
+use super::{existing_file, format_parser};
+use crate::encode::{LogWriter, DEFAULT_HEADER};
+use crate::errors::{Result, WallaceError};
+use crate::export::{export_all, ExportOptions, OutputFormat};
+use crate::messages::load_message_registry;
+use crate::messages::registry::MessageDef;
+use crate::parser::bulk::{decode_columns, is_bulk_decodable};
+use crate::parser::{
+    extract_batches, extract_messages, field_size, BytesFormat, DecodeOptions, MessageSlices,
+    TIMESTAMP_FIELDS,
+};
+use crate::utils::group_by_type;
+use crate::utils::memory::{format_size, messages_size};
+use crate::utils::rng::SplitMix64;
+use clap::{value_parser, Arg, ArgMatches, Command};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Spacing of the synthetic timestamps, a 1 kHz log
+const TIMESTAMP_STEP_US: u64 = 1000;
+
+pub fn subcommand() -> Command {
+    Command::new("bench")
+        .about(
+            "Measures decode and export throughput on a synthetic log generated from the registry",
+        )
+        .arg(
+            Arg::new("registry")
+                .short('r')
+                .long("registry")
+                .value_name("FILE")
+                .help("Sets the message registry JSON file path")
+                .value_parser(existing_file)
+                .default_value("messages.json"),
+        )
+        .arg(
+            Arg::new("records")
+                .long("records")
+                .value_name("N")
+                .help("Records in the synthetic log, spread evenly over the message types")
+                .value_parser(value_parser!(u64).range(1..))
+                .default_value("200000"),
+        )
+        .arg(
+            Arg::new("types")
+                .long("types")
+                .value_name("NAME,...")
+                .help("Generates only these message types [default: every type of known size]")
+                .value_delimiter(','),
+        )
+        .arg(
+            Arg::new("format")
+                .short('f')
+                .long("format")
+                .value_name("FORMAT,...")
+                .help("Export formats to measure")
+                .value_parser(format_parser())
+                .value_delimiter(',')
+                .default_value("csv"),
+        )
+        .arg(
+            Arg::new("runs")
+                .long("runs")
+                .value_name("N")
+                .help("Times every step is run; the fastest run is reported")
+                .value_parser(value_parser!(u64).range(1..))
+                .default_value("3"),
+        )
+        .arg(
+            Arg::new("jobs")
+                .short('j')
+                .long("jobs")
+                .value_name("N")
+                .help("Writes up to N per-type export files at once [default: one per CPU]")
+                .value_parser(value_parser!(u64).range(1..)),
+        )
+        .arg(
+            Arg::new("bytes-format")
+                .long("bytes-format")
+                .value_name("FORMAT")
+                .help("Text form of byte-array fields")
+                .value_parser(["hex", "base64", "escaped"])
+                .default_value("hex"),
+        )
+        .arg(
+            Arg::new("seed")
+                .long("seed")
+                .value_name("INT")
+                .help("Seed for the payloads")
+                .value_parser(value_parser!(u64))
+                .default_value("0"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let registry = load_message_registry(matches.get_one::<String>("registry").unwrap())?; // Has default
+    let records = *matches.get_one::<u64>("records").unwrap(); // Has default
+    let runs = *matches.get_one::<u64>("runs").unwrap(); // Has default
+    let formats: Vec<OutputFormat> = matches
+        .get_many::<OutputFormat>("format")
+        .unwrap() // Has default
+        .copied()
+        .collect();
+    let options = DecodeOptions {
+        bytes: matches
+            .get_one::<String>("bytes-format")
+            .and_then(|name| BytesFormat::from_name(name))
+            .unwrap_or_default(), // Restricted to known names
+        ..Default::default()
+    };
+
+    // Types of known size only, FILE_CONTENTS runs to the end of its record
+    let mut defs: Vec<(u16, &MessageDef)> = registry
+        .iter()
+        .filter_map(|(log_type, def)| Some((log_type.parse().ok()?, def)))
+        .filter(|(_, def)| def.fields.iter().all(|f| field_size(f).is_some()))
+        .collect();
+    defs.sort_by_key(|(log_type, _)| *log_type);
+    if let Some(names) = matches.get_many::<String>("types") {
+        let names: Vec<&String> = names.collect();
+        if let Some(name) = names
+            .iter()
+            .find(|n| !defs.iter().any(|(_, d)| &&d.name == *n))
+        {
+            return Err(WallaceError::InvalidArgument(format!(
+                "no message type '{}' of known size in the registry",
+                name
+            )));
+        }
+        defs.retain(|(_, def)| names.contains(&&def.name));
+    }
+    if defs.is_empty() {
+        return Err(WallaceError::InvalidArgument(
+            "the registry has no message type of known size".to_string(),
+        ));
+    }
+
+    let started = Instant::now();
+    let log = synthetic_log(&defs, records, *matches.get_one::<u64>("seed").unwrap())?; // Has default
+    println!(
+        "🧪 Generated {} records of {} message types ({:.1} MB) in {:.2} s",
+        records,
+        defs.len(),
+        log.len() as f64 / 1e6,
+        started.elapsed().as_secs_f64()
+    );
+
+    let (decode, (messages, ..)) = fastest(runs, || {
+        extract_messages(&mut Cursor::new(&log), &registry, options)
+    })?;
+    report(
+        "decode",
+        decode,
+        messages.len(),
+        &format!("{:.1} MB/s", log.len() as f64 / 1e6 / decode.as_secs_f64()),
+    );
+
+    // Every type decoded into typed columns instead of messages of strings
+    let (columnar, (batches, _)) = fastest(runs, || {
+        extract_batches(&mut Cursor::new(&log), &registry, options)
+    })?;
+    let columnar_size: u64 = batches.values().map(|batch| batch.memory_size()).sum();
+    report(
+        "decode columns",
+        columnar,
+        batches.values().map(|batch| batch.len()).sum(),
+        &format!(
+            "{} in memory, {} as messages",
+            format_size(columnar_size),
+            format_size(messages_size(&messages))
+        ),
+    );
+
+    // Types whose fields are all fixed-width scalars, framed in place and decoded into columns
+    let bulk_types: Vec<u16> = defs
+        .iter()
+        .filter(|(_, def)| is_bulk_decodable(def))
+        .map(|(log_type, _)| *log_type)
+        .collect();
+    if !bulk_types.is_empty() {
+        let (bulk, bulk_records) = fastest(runs, || {
+            let mut payloads: BTreeMap<u16, Vec<&[u8]>> =
+                bulk_types.iter().map(|t| (*t, Vec::new())).collect();
+            for record in MessageSlices::new(&log, &registry)? {
+                let record = record?;
+                if let Some(payloads) = payloads.get_mut(&record.log_type) {
+                    payloads.push(record.payload);
+                }
+            }
+            let mut records = 0;
+            for (log_type, payloads) in &payloads {
+                let def = &registry[&log_type.to_string()];
+                if decode_columns(def, payloads).is_some() {
+                    records += payloads.len();
+                }
+            }
+            Ok(records)
+        })?;
+        report(
+            "decode bulk",
+            bulk,
+            bulk_records,
+            &format!("{} of {} types", bulk_types.len(), defs.len()),
+        );
+    }
+
+    let (group, grouped) = fastest(runs, || Ok(group_by_type(&messages)))?;
+    report("group", group, messages.len(), "");
+
+    let export_options = ExportOptions {
+        jobs: matches
+            .get_one::<u64>("jobs")
+            .map(|&jobs| jobs as usize)
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get())),
+        bytes: options.bytes,
+        ..Default::default()
+    };
+    let output_dir = std::env::temp_dir().join(format!("wallace-bench-{}", std::process::id()));
+    for format in formats {
+        let (export, ()) = fastest(runs, || {
+            // A fresh directory per run, so no run appends to or replaces the files of another
+            if output_dir.exists() {
+                fs::remove_dir_all(&output_dir)?;
+            }
+            fs::create_dir_all(&output_dir)?;
+            export_all(&output_dir, &grouped, format, &registry, &export_options)
+        })?;
+        let bytes = dir_size(&output_dir)?;
+        fs::remove_dir_all(&output_dir)?;
+        report(
+            &format!("export {}", format.extension()),
+            export,
+            messages.len(),
+            &format!("{:.1} MB written", bytes as f64 / 1e6),
+        );
+    }
+    Ok(())
+}
+
+// Round-robin over the types, with timestamps rising by `TIMESTAMP_STEP_US`
+fn synthetic_log(defs: &[(u16, &MessageDef)], records: u64, seed: u64) -> Result<Vec<u8>> {
+    let mut rng = SplitMix64(seed);
+    let mut writer = LogWriter::new(Vec::new(), DEFAULT_HEADER)?;
+    for i in 0..records {
+        let (log_type, def) = defs[(i % defs.len() as u64) as usize];
+        let mut payload = random_payload(def, &mut rng);
+        let timestamp = (i + 1) * TIMESTAMP_STEP_US;
+        if def
+            .fields
+            .first()
+            .is_some_and(|f| f.r#type == "Q" && TIMESTAMP_FIELDS.contains(&f.name.as_str()))
+        {
+            payload[..8].copy_from_slice(&timestamp.to_le_bytes());
+        }
+        writer.write_record(log_type, &payload)?;
+    }
+    writer.finish()
+}
+
+// Runs `step` `runs` times and returns the shortest time with the result of that run
+fn fastest<T>(runs: u64, mut step: impl FnMut() -> Result<T>) -> Result<(Duration, T)> {
+    let mut best: Option<(Duration, T)> = None;
+    for _ in 0..runs {
+        let started = Instant::now();
+        let result = step()?;
+        let elapsed = started.elapsed();
+        if best.as_ref().is_none_or(|(time, _)| elapsed < *time) {
+            best = Some((elapsed, result));
+        }
+    }
+    Ok(best.unwrap()) // At least one run
+}
+
+fn report(step: &str, time: Duration, records: usize, extra: &str) {
+    let rate = records as f64 / time.as_secs_f64();
+    let extra = if extra.is_empty() {
+        String::new()
+    } else {
+        format!(", {}", extra)
+    };
+    println!(
+        "⏱️  {:<14} {:>9.3} s  {:>12.0} records/s{}",
+        step,
+        time.as_secs_f64(),
+        rate,
+        extra
+    );
+}
+
+fn dir_size(dir: &Path) -> Result<u64> {
+    let mut total = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        total += if metadata.is_dir() {
+            dir_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(total)
+}
@@ -0,0 +1,222 @@
+// commands/cat.rs
+// `cat`: concatenates binary logs into one, keeping a single file header.
+
+use super::{existing_file, existing_input, DEFAULT_REGISTRY};
+use crate::encode::LogWriter;
+use crate::errors::{Result, WallaceError};
+use crate::file_io::open_file;
+use crate::messages::{load_message_registry, MessageRegistry};
+use crate::parser::stream::RawRecord;
+use crate::parser::{field_offset, MessageStream, TIMESTAMP_FIELDS};
+use crate::utils::time::MAX_TIMESTAMP_JUMP_US;
+use byteorder::{ByteOrder, LittleEndian};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+pub fn subcommand() -> Command {
+    Command::new("cat")
+        .about("Concatenates binary logs, validating and de-duplicating their headers")
+        .arg(
+            Arg::new("inputs")
+                .value_name("FILE")
+                .help("Logs to concatenate, in order")
+                .num_args(1..)
+                .value_parser(existing_input)
+                .required(true),
+        )
+        .arg(
+            Arg::new("registry")
+                .short('r')
+                .long("registry")
+                .value_name("FILE")
+                .help("Sets the message registry JSON file path, only read with --monotonic [default: messages.json]")
+                .value_parser(existing_file),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_name("FILE")
+                .help("Combined binary log to write")
+                .required(true),
+        )
+        .arg(
+            Arg::new("monotonic")
+                .long("monotonic")
+                .help("Offsets the timestamps of each log that starts before the previous one ended, e.g. after a reboot")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("force")
+                .long("force")
+                .help("Concatenates even if the file headers differ, keeping the first")
+                .action(ArgAction::SetTrue),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let monotonic = matches.get_flag("monotonic");
+    // Defaulted here rather than by clap, since only --monotonic reads a field; plain
+    // concatenation copies records unread and works without a registry
+    let registry = match matches.get_one::<String>("registry") {
+        Some(path) => load_message_registry(path)?,
+        None if !monotonic => MessageRegistry::new(),
+        None if Path::new(DEFAULT_REGISTRY).is_file() => load_message_registry(DEFAULT_REGISTRY)?,
+        None => {
+            return Err(WallaceError::InvalidArgument(format!(
+                "--monotonic reads timestamps with the registry, and '{}' does not exist (set one with --registry)",
+                DEFAULT_REGISTRY
+            )))
+        }
+    };
+    let output_path = matches.get_one::<String>("output").unwrap(); // Required
+    let inputs: Vec<&str> = matches
+        .get_many::<String>("inputs")
+        .unwrap()
+        .map(String::as_str)
+        .collect(); // Required
+    let mut timestamps = Timestamps::new(&registry);
+
+    // Work out each log's offset up front, since the first timestamp of a log is not
+    // necessarily its earliest
+    let mut offsets = vec![0u64; inputs.len()];
+    if monotonic {
+        let mut previous_end: Option<u64> = None;
+        for (i, input) in inputs.iter().enumerate() {
+            let (start, end) = match timestamps.range(input)? {
+                Some(range) => range,
+                None => continue,
+            };
+            if let Some(previous_end) = previous_end {
+                if start <= previous_end {
+                    offsets[i] = previous_end + 1 - start;
+                }
+            }
+            previous_end = Some(end + offsets[i]);
+        }
+    }
+
+    let mut writer = None;
+    let mut first_header = None;
+    for (input, &offset) in inputs.iter().zip(&offsets) {
+        let mut stream = MessageStream::new(BufReader::new(open_file(input)?), &registry)?;
+        match first_header {
+            None => first_header = Some(stream.header),
+            Some(header) if header != stream.header && !matches.get_flag("force") => {
+                return Err(WallaceError::InvalidArgument(format!(
+                    "'{}' has header {} but '{}' has {} (use --force to concatenate anyway)",
+                    input, stream.header, inputs[0], header
+                )));
+            }
+            Some(_) => {}
+        }
+        let writer = match &mut writer {
+            Some(writer) => writer,
+            None => writer.insert(LogWriter::new(
+                BufWriter::new(File::create(output_path)?),
+                stream.header,
+            )?),
+        };
+
+        while let Some(mut record) = stream.next_record()? {
+            if offset > 0 {
+                timestamps.shift(&mut record, offset);
+            }
+            writer.write_record(record.log_type, &record.payload)?;
+        }
+        if offset > 0 {
+            println!("⏱️  Shifted timestamps of '{}' by {} µs", input, offset);
+        }
+    }
+
+    if let Some(writer) = writer {
+        let records = writer.records_written();
+        writer.finish()?;
+        println!(
+            "✅ Wrote {} records from {} logs to '{}'",
+            records,
+            inputs.len(),
+            output_path
+        );
+    }
+    Ok(())
+}
+
+// Reads and rewrites record timestamps in place
+pub(super) struct Timestamps<'a> {
+    registry: &'a MessageRegistry,
+    // Timestamp location (offset, type) per log type, None if it has none
+    locations: HashMap<u16, Option<(usize, String)>>,
+}
+
+impl<'a> Timestamps<'a> {
+    pub(super) fn new(registry: &'a MessageRegistry) -> Self {
+        Timestamps {
+            registry,
+            locations: HashMap::new(),
+        }
+    }
+
+    // Earliest and latest timestamp of a log, ignoring values that jump far outside
+    // the range seen so far
+    fn range(&mut self, input: &str) -> Result<Option<(u64, u64)>> {
+        let registry = self.registry;
+        let mut stream = MessageStream::new(BufReader::new(open_file(input)?), registry)?;
+        let mut range: Option<(u64, u64)> = None;
+        while let Some(record) = stream.next_record()? {
+            let ts = match self.read(&record) {
+                Some(ts) => ts,
+                None => continue,
+            };
+            range = match range {
+                None => Some((ts, ts)),
+                Some((lo, hi))
+                    if ts + MAX_TIMESTAMP_JUMP_US < lo || ts > hi + MAX_TIMESTAMP_JUMP_US =>
+                {
+                    Some((lo, hi))
+                }
+                Some((lo, hi)) => Some((lo.min(ts), hi.max(ts))),
+            };
+        }
+        Ok(range)
+    }
+
+    fn location(&mut self, log_type: u16) -> Option<(usize, String)> {
+        let registry = self.registry;
+        self.locations
+            .entry(log_type)
+            .or_insert_with(|| {
+                let def = registry.get(&log_type.to_string())?;
+                TIMESTAMP_FIELDS.iter().find_map(|name| {
+                    let field = def.fields.iter().find(|f| f.name == *name)?;
+                    Some((field_offset(def, name)?, field.r#type.clone()))
+                })
+            })
+            .clone()
+    }
+
+    pub(super) fn read(&mut self, record: &RawRecord) -> Option<u64> {
+        let (offset, type_str) = self.location(record.log_type)?;
+        let bytes = record.payload.get(offset..)?;
+        match type_str.as_str() {
+            "Q" | "q" if bytes.len() >= 8 => Some(LittleEndian::read_u64(bytes)),
+            "I" | "i" if bytes.len() >= 4 => Some(LittleEndian::read_u32(bytes) as u64),
+            _ => None,
+        }
+    }
+
+    fn shift(&mut self, record: &mut RawRecord, offset: u64) {
+        let ts = match self.read(record) {
+            Some(ts) => ts + offset,
+            None => return,
+        };
+        let (at, type_str) = self.location(record.log_type).unwrap(); // Read succeeded
+        match type_str.as_str() {
+            "Q" | "q" => LittleEndian::write_u64(&mut record.payload[at..], ts),
+            _ => LittleEndian::write_u32(&mut record.payload[at..], ts as u32),
+        }
+    }
+}
@@ -0,0 +1,33 @@
+// commands/completions.rs
+// Prints a shell completion script for the whole command line.
+
+use crate::errors::Result;
+use clap::{Arg, ArgMatches, Command};
+use clap_complete::{generate, Shell};
+use std::io::{ErrorKind, Write};
+
+pub fn subcommand() -> Command {
+    Command::new("completions")
+        .about("Prints a completion script, e.g. `wallace_rs completions bash > /etc/bash_completion.d/wallace_rs`")
+        .arg(
+            Arg::new("shell")
+                .value_name("SHELL")
+                .help("Shell to generate completions for")
+                .value_parser(clap::value_parser!(Shell))
+                .required(true),
+        )
+}
+
+/// Writes the completions for `cli` to stdout
+pub fn run(matches: &ArgMatches, cli: &mut Command) -> Result<()> {
+    let shell = *matches.get_one::<Shell>("shell").unwrap(); // Required
+
+    // Generated into memory first, since the generator panics on write errors
+    let mut script = Vec::new();
+    generate(shell, cli, env!("CARGO_BIN_NAME"), &mut script);
+    match std::io::stdout().write_all(&script) {
+        // The consumer (e.g. `head`) closing the pipe early is not an error
+        Err(e) if e.kind() == ErrorKind::BrokenPipe => Ok(()),
+        other => Ok(other?),
+    }
+}
@@ -0,0 +1,58 @@
+// commands/index.rs
+// `index`: scans a log once and writes the sidecar index used by `--use-index`.
+
+use super::existing_file;
+use crate::errors::Result;
+use crate::index::{index_path, LogIndex};
+use crate::messages::load_message_registry;
+use clap::{Arg, ArgMatches, Command};
+use std::path::{Path, PathBuf};
+
+pub fn subcommand() -> Command {
+    Command::new("index")
+        .about("Writes a sidecar index of record offsets and timestamps for fast selective reads")
+        .arg(
+            Arg::new("input")
+                .short('i')
+                .long("input")
+                .value_name("FILE")
+                .help("Sets the input log file path (uncompressed)")
+                .value_parser(existing_file)
+                .required(true),
+        )
+        .arg(
+            Arg::new("registry")
+                .short('r')
+                .long("registry")
+                .value_name("FILE")
+                .help("Sets the message registry JSON file path")
+                .value_parser(existing_file)
+                .default_value("messages.json"),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_name("FILE")
+                .help("Index file to write [default: <input>.idx]"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let registry = load_message_registry(matches.get_one::<String>("registry").unwrap())?; // Has default
+    let input = Path::new(matches.get_one::<String>("input").unwrap()); // Required
+    let output = matches
+        .get_one::<String>("output")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| index_path(input));
+
+    let index = LogIndex::build(input, &registry)?;
+    index.save(&output)?;
+    println!(
+        "✅ Indexed {} records of {} types into '{}'",
+        index.record_count(),
+        index.types.len(),
+        output.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,399 @@
+// commands/merge.rs
+// `merge`: interleaves logs that overlap in time, e.g. from a primary and a backup recorder,
+// into one log in timestamp order, dropping the records they both hold. With `--vehicles` the
+// logs are of different vehicles instead: every row is kept, tagged with its source and put on
+// the common UTC clock, and the result is written as one export.
+
+use super::cat::Timestamps;
+use super::{existing_file, existing_path, format_parser};
+use crate::encode::input::{is_export, read_exports};
+use crate::encode::{encode_fields, LogWriter, DEFAULT_HEADER};
+use crate::errors::{Result, WallaceError};
+use crate::export::{export_all, ExportOptions, OutputFormat};
+use crate::file_io::open_file;
+use crate::messages::load_message_registry;
+use crate::messages::registry::{FieldDef, MessageRegistry};
+use crate::parser::stream::RawRecord;
+use crate::parser::{extract_messages, DecodeOptions, MessageStream, ParsedMessage};
+use crate::utils::group_by_type;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::thread;
+
+/// Columns put in front of every row by `--vehicles`
+const SOURCE_COLUMN: &str = "source";
+const UTC_COLUMN: &str = "utc_us";
+
+pub fn subcommand() -> Command {
+    Command::new("merge")
+        .about("Merges logs that overlap in time into one, in timestamp order, dropping records found in more than one")
+        .arg(
+            Arg::new("inputs")
+                .value_name("FILE")
+                .help("Logs to merge; CSV/NDJSON exports are encoded again first")
+                .num_args(2..)
+                .value_parser(existing_path)
+                .required(true),
+        )
+        .arg(
+            Arg::new("registry")
+                .short('r')
+                .long("registry")
+                .value_name("FILE")
+                .help("Sets the message registry JSON file path")
+                .value_parser(existing_file)
+                .default_value("messages.json"),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_name("FILE")
+                .help("Merged binary log to write, or with --vehicles the export directory")
+                .required(true),
+        )
+        .arg(
+            Arg::new("prefer")
+                .long("prefer")
+                .value_name("FILE")
+                .help("Log whose records win when logs disagree about a record [default: the first]"),
+        )
+        .arg(
+            Arg::new("force")
+                .long("force")
+                .help("Merges even if the file headers differ, keeping the preferred log's")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("vehicles")
+                .long("vehicles")
+                .help("Combines logs of different vehicles: keeps every row, adds source and utc_us columns and writes an export")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("format")
+                .short('f')
+                .long("format")
+                .value_name("FORMAT")
+                .help("Export format of --vehicles")
+                .value_parser(format_parser())
+                .default_value("csv")
+                .requires("vehicles"),
+        )
+        .arg(
+            Arg::new("source-tag")
+                .long("source-tag")
+                .value_name("KEY")
+                .help("Takes each source from this --tag column of the exports being combined [default: the file name]")
+                .requires("vehicles"),
+        )
+        .arg(
+            Arg::new("utc-field")
+                .long("utc-field")
+                .value_name("FIELD")
+                .help("Field holding UTC microseconds, read from the first record that has it to put each log's timestamps on UTC")
+                .default_value("time_utc_usec")
+                .requires("vehicles"),
+        )
+}
+
+// A record and where it came from
+struct Entry {
+    record: RawRecord,
+    /// Its own timestamp, or that of the last timestamped record before it in the same log
+    timestamp: u64,
+    timestamped: bool,
+    /// Position of the source among the logs, preferred log first; ties in time go to it
+    rank: usize,
+    hash: u64,
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let registry = load_message_registry(matches.get_one::<String>("registry").unwrap())?; // Has default
+    let output_path = matches.get_one::<String>("output").unwrap(); // Required
+    let mut inputs: Vec<&str> = matches
+        .get_many::<String>("inputs")
+        .unwrap()
+        .map(String::as_str)
+        .collect(); // Required
+    if let Some(preferred) = matches.get_one::<String>("prefer") {
+        let at = inputs.iter().position(|i| i == preferred).ok_or_else(|| {
+            WallaceError::InvalidArgument(format!(
+                "'--prefer {}' is not one of the logs being merged",
+                preferred
+            ))
+        })?;
+        let preferred = inputs.remove(at);
+        inputs.insert(0, preferred);
+    }
+    if matches.get_flag("vehicles") {
+        return combine_vehicles(matches, &inputs, &registry);
+    }
+
+    let mut timestamps = Timestamps::new(&registry);
+    let mut entries = Vec::new();
+    let mut header = None;
+    for (rank, input) in inputs.iter().enumerate() {
+        let (input_header, records) = read_records(input, &registry)?;
+        match header {
+            None => header = Some(input_header),
+            Some(first) if first != input_header && !matches.get_flag("force") => {
+                return Err(WallaceError::InvalidArgument(format!(
+                    "'{}' has header {} but '{}' has {} (use --force to merge anyway)",
+                    input, input_header, inputs[0], first
+                )));
+            }
+            Some(_) => {}
+        }
+        let mut last_timestamp = 0;
+        for record in records {
+            let own = timestamps.read(&record);
+            last_timestamp = own.unwrap_or(last_timestamp);
+            let mut hasher = DefaultHasher::new();
+            record.payload.hash(&mut hasher);
+            entries.push(Entry {
+                timestamp: last_timestamp,
+                timestamped: own.is_some(),
+                rank,
+                hash: hasher.finish(),
+                record,
+            });
+        }
+    }
+    let total = entries.len();
+
+    // The best-ranked log holding each exact record, and each timestamped (type, time) slot
+    let mut record_owner: HashMap<(u16, u64, u64), usize> = HashMap::new();
+    let mut slot_owner: HashMap<(u16, u64), usize> = HashMap::new();
+    let mut slot_hashes: HashMap<(u16, u64), HashSet<u64>> = HashMap::new();
+    for entry in &entries {
+        let key = (entry.record.log_type, entry.timestamp);
+        let owner = record_owner
+            .entry((key.0, key.1, entry.hash))
+            .or_insert(entry.rank);
+        *owner = (*owner).min(entry.rank);
+        if entry.timestamped {
+            let owner = slot_owner.entry(key).or_insert(entry.rank);
+            *owner = (*owner).min(entry.rank);
+            slot_hashes.entry(key).or_default().insert(entry.hash);
+        }
+    }
+
+    // Copies held by another log are dropped; where the logs hold different records for the same
+    // type and time, only the preferred log's are kept
+    let mut duplicates = 0;
+    let mut conflicts = 0;
+    entries.retain(|entry| {
+        let key = (entry.record.log_type, entry.timestamp);
+        if record_owner[&(key.0, key.1, entry.hash)] != entry.rank {
+            duplicates += 1;
+            return false;
+        }
+        if entry.timestamped && slot_owner[&key] != entry.rank && slot_hashes[&key].len() > 1 {
+            conflicts += 1;
+            return false;
+        }
+        true
+    });
+
+    // Interleave the logs by always taking the earliest next record, so that each log keeps its
+    // own record order even where its timestamps are not monotonic
+    let mut queues: Vec<VecDeque<Entry>> = inputs.iter().map(|_| VecDeque::new()).collect();
+    for entry in entries {
+        queues[entry.rank].push_back(entry);
+    }
+    let mut writer = LogWriter::new(
+        BufWriter::new(File::create(output_path)?),
+        header.unwrap_or_default(),
+    )?;
+    while let Some(next) = queues
+        .iter_mut()
+        .filter(|queue| !queue.is_empty())
+        .min_by_key(|queue| queue[0].timestamp)
+    {
+        let entry = next.pop_front().unwrap(); // Not empty
+        writer.write_record(entry.record.log_type, &entry.record.payload)?;
+    }
+    let records = writer.records_written();
+    writer.finish()?;
+    println!(
+        "🧹 Dropped {} records held by more than one log",
+        duplicates
+    );
+    if conflicts > 0 {
+        println!(
+            "⚠️  Dropped {} records that differ from '{}' at the same type and time",
+            conflicts, inputs[0]
+        );
+    }
+    println!(
+        "✅ Wrote {} of {} records from {} logs to '{}'",
+        records,
+        total,
+        inputs.len(),
+        output_path
+    );
+    Ok(())
+}
+
+// The file header and records of a log. Exports are encoded again, with the default header.
+fn read_records(input: &str, registry: &MessageRegistry) -> Result<(i32, Vec<RawRecord>)> {
+    if input != "-" && is_export(Path::new(input)) {
+        let mut records = Vec::new();
+        for (index, msg) in read_exports(Path::new(input), registry)?
+            .into_iter()
+            .enumerate()
+        {
+            let def = registry
+                .get(&msg.log_type.to_string())
+                .ok_or(WallaceError::UnknownMessageType(msg.log_type))?;
+            let payload =
+                encode_fields(&msg.fields, def).map_err(|reason| WallaceError::ParsingError {
+                    log_type: msg.log_type,
+                    name: def.name.clone(),
+                    reason,
+                })?;
+            records.push(RawRecord {
+                log_type: msg.log_type,
+                payload,
+                index: index as u64,
+                offset: 0,
+            });
+        }
+        return Ok((DEFAULT_HEADER, records));
+    }
+    let mut stream = MessageStream::new(BufReader::new(open_file(input)?), registry)?;
+    let mut records = Vec::new();
+    while let Some(record) = stream.next_record()? {
+        records.push(record);
+    }
+    Ok((stream.header, records))
+}
+
+// Writes the rows of every log as one export, each with the log it came from and its time on
+// the UTC clock, in UTC order. Rows of a log without a UTC time keep an empty utc_us and go last.
+fn combine_vehicles(
+    matches: &ArgMatches,
+    inputs: &[&str],
+    registry: &MessageRegistry,
+) -> Result<()> {
+    let output_path = matches.get_one::<String>("output").unwrap(); // Required
+    let format = *matches.get_one::<OutputFormat>("format").unwrap(); // Has default
+    let utc_field = matches.get_one::<String>("utc-field").unwrap(); // Has default
+    let source_tag = matches.get_one::<String>("source-tag");
+    if let Some(def) = registry.values().find(|def| {
+        def.fields
+            .iter()
+            .any(|f| f.name == SOURCE_COLUMN || f.name == UTC_COLUMN)
+    }) {
+        return Err(WallaceError::InvalidArgument(format!(
+            "'{}' has a field named like the '{}' or '{}' column --vehicles adds",
+            def.name, SOURCE_COLUMN, UTC_COLUMN
+        )));
+    }
+
+    let mut combined: Vec<(Option<u64>, ParsedMessage)> = Vec::new();
+    let mut longest_source = 1;
+    for input in inputs {
+        let messages = read_messages(input, registry)?;
+        let source = match source_tag {
+            Some(tag) => messages
+                .iter()
+                .find_map(|msg| msg.field(tag))
+                .map(str::to_string)
+                .ok_or_else(|| {
+                    WallaceError::InvalidArgument(format!(
+                        "'{}' has no '{}' column to take its source from",
+                        input, tag
+                    ))
+                })?,
+            None => Path::new(input)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_else(|| input.to_string()),
+        };
+        longest_source = longest_source.max(source.len());
+        // The UTC clock minus the boot clock, from the first record carrying both
+        let offset = messages.iter().find_map(|msg| {
+            let utc = msg
+                .field(utc_field)?
+                .parse::<u64>()
+                .ok()
+                .filter(|&utc| utc > 0)?;
+            Some(utc as i128 - msg.timestamp_us()? as i128)
+        });
+        if offset.is_none() {
+            println!(
+                "⚠️  '{}' has no '{}' to align on, its rows are left without {}",
+                input, utc_field, UTC_COLUMN
+            );
+        }
+        for mut msg in messages {
+            let utc = offset
+                .zip(msg.timestamp_us())
+                .map(|(offset, ts)| (ts as i128 + offset).max(0) as u64);
+            let columns = [
+                (SOURCE_COLUMN.to_string(), source.clone()),
+                (
+                    UTC_COLUMN.to_string(),
+                    utc.map(|utc| utc.to_string()).unwrap_or_default(),
+                ),
+            ];
+            msg.fields.splice(0..0, columns);
+            combined.push((utc, msg));
+        }
+    }
+    combined.sort_by_key(|(utc, _)| utc.unwrap_or(u64::MAX));
+    let messages: Vec<ParsedMessage> = combined.into_iter().map(|(_, msg)| msg).collect();
+
+    let mut registry = registry.clone();
+    for def in registry.values_mut() {
+        let columns = [
+            FieldDef {
+                name: SOURCE_COLUMN.to_string(),
+                r#type: format!("{}s", longest_source),
+                ..Default::default()
+            },
+            FieldDef {
+                name: UTC_COLUMN.to_string(),
+                r#type: "Q".to_string(),
+                ..Default::default()
+            },
+        ];
+        def.fields.splice(0..0, columns);
+    }
+    let output_dir = Path::new(output_path);
+    fs::create_dir_all(output_dir)?;
+    let options = ExportOptions {
+        jobs: thread::available_parallelism().map_or(1, |n| n.get()),
+        ..Default::default()
+    };
+    export_all(
+        output_dir,
+        &group_by_type(&messages),
+        format,
+        &registry,
+        &options,
+    )?;
+    println!(
+        "✅ Combined {} rows from {} vehicles into '{}'",
+        messages.len(),
+        inputs.len(),
+        output_path
+    );
+    Ok(())
+}
+
+// The decoded messages of a log or of exports
+fn read_messages(input: &str, registry: &MessageRegistry) -> Result<Vec<ParsedMessage>> {
+    if input != "-" && is_export(Path::new(input)) {
+        return read_exports(Path::new(input), registry);
+    }
+    let mut reader = BufReader::new(open_file(input)?);
+    Ok(extract_messages(&mut reader, registry, DecodeOptions::default())?.0)
+}
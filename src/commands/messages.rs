@@ -0,0 +1,230 @@
+// commands/messages.rs
+// `messages`: the text messages of a log, from the message types whose registry entry names a
+// `text` field, as one timeline in time order with their severities, on the terminal and in a
+// file. The first thing to read after an anomaly.
+
+use super::{existing_file, existing_input};
+use crate::errors::{Result, WallaceError};
+use crate::file_io::open_file;
+use crate::messages::load_message_registry;
+use crate::messages::registry::MessageDef;
+use crate::parser::{extract_messages, DecodeOptions};
+use clap::{Arg, ArgMatches, Command};
+use std::fs;
+use std::io::{BufReader, IsTerminal};
+
+pub fn subcommand() -> Command {
+    Command::new("messages")
+        .about("Prints the text messages of a log as a timeline, from the message types the registry flags with a text field")
+        .arg(
+            Arg::new("input")
+                .short('i')
+                .long("input")
+                .value_name("FILE")
+                .help("Sets the input log file path")
+                .value_parser(existing_input)
+                .required(true),
+        )
+        .arg(
+            Arg::new("registry")
+                .short('r')
+                .long("registry")
+                .value_name("FILE")
+                .help("Sets the message registry JSON file path")
+                .value_parser(existing_file)
+                .default_value("messages.json"),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_name("FILE")
+                .help("Text file the timeline is also written to")
+                .default_value("messages.txt"),
+        )
+        .arg(
+            Arg::new("min-severity")
+                .long("min-severity")
+                .value_name("LEVEL")
+                .help("Leaves out messages less severe than this; messages of unknown severity are always shown")
+                .value_parser(Severity::NAMES),
+        )
+}
+
+/// MAVLink severities, most severe first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Severity {
+    Emergency,
+    Alert,
+    Critical,
+    Error,
+    Warning,
+    Notice,
+    Info,
+    Debug,
+}
+
+impl Severity {
+    const ALL: [Severity; 8] = [
+        Severity::Emergency,
+        Severity::Alert,
+        Severity::Critical,
+        Severity::Error,
+        Severity::Warning,
+        Severity::Notice,
+        Severity::Info,
+        Severity::Debug,
+    ];
+    const NAMES: [&'static str; 8] = [
+        "emergency",
+        "alert",
+        "critical",
+        "error",
+        "warning",
+        "notice",
+        "info",
+        "debug",
+    ];
+
+    fn name(self) -> &'static str {
+        Self::NAMES[self as usize]
+    }
+
+    // A MAVLink number, or a name or its usual abbreviation such as ERR or WARN
+    fn parse(text: &str) -> Option<Self> {
+        let text = text.trim().to_ascii_lowercase();
+        if let Ok(number) = text.parse::<usize>() {
+            return Self::ALL.get(number).copied();
+        }
+        let text = text.strip_prefix("severity_").unwrap_or(&text);
+        let text = match text {
+            "emerg" | "fatal" => "emergency",
+            "crit" => "critical",
+            "err" => "error",
+            "warn" => "warning",
+            "information" => "info",
+            other => other,
+        };
+        Self::NAMES
+            .iter()
+            .position(|name| *name == text)
+            .map(|at| Self::ALL[at])
+    }
+
+    fn color(self) -> &'static str {
+        match self {
+            Severity::Emergency | Severity::Alert | Severity::Critical => "\x1b[1;31m",
+            Severity::Error => "\x1b[31m",
+            Severity::Warning => "\x1b[33m",
+            Severity::Notice => "\x1b[36m",
+            Severity::Info => "",
+            Severity::Debug => "\x1b[2m",
+        }
+    }
+}
+
+struct Line {
+    timestamp: Option<u64>,
+    severity: Option<Severity>,
+    source: String,
+    text: String,
+}
+
+impl Line {
+    fn render(&self, color: bool) -> String {
+        let time = match self.timestamp {
+            Some(ts) => format!("{:>12.3}s", ts as f64 / 1e6),
+            None => format!("{:>13}", "-"),
+        };
+        let severity = self
+            .severity
+            .map_or("?", Severity::name)
+            .to_ascii_uppercase();
+        let line = format!("{} {:<9} {}: {}", time, severity, self.source, self.text);
+        match self.severity.map(Severity::color) {
+            Some(code) if color && !code.is_empty() => format!("{}{}\x1b[0m", code, line),
+            _ => line,
+        }
+    }
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let registry = load_message_registry(matches.get_one::<String>("registry").unwrap())?; // Has default
+    let input_path = matches.get_one::<String>("input").unwrap(); // Required
+    let output_path = matches.get_one::<String>("output").unwrap(); // Has default
+    let min_severity = matches
+        .get_one::<String>("min-severity")
+        .and_then(|name| Severity::parse(name)); // Restricted to known names
+
+    let text_defs: Vec<&MessageDef> = registry.values().filter(|def| def.text.is_some()).collect();
+    if text_defs.is_empty() {
+        return Err(WallaceError::InvalidArgument(
+            "no message type in the registry names a 'text' field".to_string(),
+        ));
+    }
+    for def in &text_defs {
+        for field in def.text.iter().chain(&def.severity) {
+            if !def.fields.iter().any(|f| f.name == *field) {
+                return Err(WallaceError::InvalidArgument(format!(
+                    "'{}' names '{}' as its text or severity, but has no such field",
+                    def.name, field
+                )));
+            }
+        }
+    }
+
+    let mut reader = BufReader::new(open_file(input_path)?);
+    let (messages, ..) = extract_messages(&mut reader, &registry, DecodeOptions::default())?;
+    let mut lines = Vec::new();
+    for msg in &messages {
+        let Some(def) = text_defs.iter().find(|def| def.name == msg.name) else {
+            continue;
+        };
+        let text_field = def.text.as_deref().unwrap_or_default(); // Only defs with text are kept
+        let Some(text) = msg.field(text_field) else {
+            continue;
+        };
+        let severity = def.severity.as_deref().and_then(|name| {
+            let raw = msg.field(name)?;
+            let label = def
+                .fields
+                .iter()
+                .find(|f| f.name == name)
+                .and_then(|f| f.enum_labels.as_ref()?.get(raw));
+            Severity::parse(label.map_or(raw, String::as_str))
+        });
+        if severity.is_some_and(|severity| min_severity.is_some_and(|min| severity > min)) {
+            continue;
+        }
+        lines.push(Line {
+            timestamp: msg.timestamp_us(),
+            severity,
+            source: msg.name.clone(),
+            text: text.trim().to_string(),
+        });
+    }
+    // Stable, so messages without a timestamp stay in log order, ahead of the rest
+    lines.sort_by_key(|line| line.timestamp);
+
+    let color = std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+    let mut file = String::new();
+    for line in &lines {
+        println!("{}", line.render(color));
+        file.push_str(&line.render(false));
+        file.push('\n');
+    }
+    fs::write(output_path, file)?;
+    let serious = lines
+        .iter()
+        .filter(|line| line.severity.is_some_and(|s| s <= Severity::Error))
+        .count();
+    if serious > 0 {
+        println!("⚠️  {} messages of severity error or worse", serious);
+    }
+    println!(
+        "✅ Wrote {} text messages to '{}'",
+        lines.len(),
+        output_path
+    );
+    Ok(())
+}
@@ -0,0 +1,221 @@
+// commands/mod.rs
+// Subcommands of the command line tool, each with its own arguments and entry point.
+
+pub mod annotate;
+pub mod bench;
+pub mod cat;
+pub mod completions;
+pub mod encode;
+pub mod index;
+pub mod merge;
+pub mod messages;
+pub mod params;
+pub mod redact;
+pub mod repair;
+pub mod schema;
+pub mod selftest;
+pub mod spectrum;
+pub mod split;
+pub mod trim;
+pub mod validate;
+pub mod verify;
+
+use crate::errors::{Result, WallaceError};
+use crate::export::influx::parse_start_time;
+use crate::export::OutputFormat;
+use crate::messages::MessageRegistry;
+use crate::redact::{load_rules, Redactor, Rule};
+use crate::utils::memory::parse_size;
+use crate::utils::time::parse_duration;
+use clap::builder::{PossibleValue, PossibleValuesParser, TypedValueParser};
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Registry read when none is given with `--registry`
+pub const DEFAULT_REGISTRY: &str = "messages.json";
+
+pub fn subcommands() -> Vec<Command> {
+    vec![
+        encode::subcommand(),
+        trim::subcommand(),
+        repair::subcommand(),
+        redact::subcommand(),
+        cat::subcommand(),
+        merge::subcommand(),
+        split::subcommand(),
+        index::subcommand(),
+        messages::subcommand(),
+        params::subcommand(),
+        spectrum::subcommand(),
+        schema::subcommand(),
+        selftest::subcommand(),
+        validate::subcommand(),
+        annotate::subcommand(),
+        bench::subcommand(),
+        verify::subcommand(),
+        completions::subcommand(),
+    ]
+}
+
+/// Runs the subcommand `name`; `completions` is run by the caller, which owns the full command
+pub fn run(name: &str, matches: &ArgMatches) -> Result<()> {
+    match name {
+        "encode" => encode::run(matches),
+        "trim" => trim::run(matches),
+        "repair" => repair::run(matches),
+        "redact" => redact::run(matches),
+        "cat" => cat::run(matches),
+        "merge" => merge::run(matches),
+        "split" => split::run(matches),
+        "index" => index::run(matches),
+        "messages" => messages::run(matches),
+        "params" => params::run(matches),
+        "spectrum" => spectrum::run(matches),
+        "schema" => schema::run(matches),
+        "selftest" => selftest::run(matches),
+        "validate" => validate::run(matches),
+        "annotate" => annotate::run(matches),
+        "bench" => bench::run(matches),
+        "verify" => verify::run(matches),
+        _ => unreachable!("clap only accepts declared subcommands"),
+    }
+}
+
+/// Redaction options, shared by `redact` and the export path
+pub fn redaction_args() -> Vec<Arg> {
+    vec![
+        Arg::new("redact")
+            .long("redact")
+            .value_name("MESSAGE.FIELD=ACTION")
+            .help("Redacts a field: zero, fuzz:N, shift:N or scrub:KEY[,KEY]; '*' wildcards allowed; may be repeated")
+            .action(ArgAction::Append),
+        Arg::new("redact-rules")
+            .long("redact-rules")
+            .value_name("FILE")
+            .help("Reads redaction rules from a file, one per line")
+            .value_parser(existing_file),
+        Arg::new("seed")
+            .long("seed")
+            .value_name("INT")
+            .help("Seed for fuzz/shift and --reservoir, for reproducible output [default: random]")
+            .value_parser(value_parser!(u64)),
+    ]
+}
+
+/// Builds a redactor from `redaction_args`, or `None` when no rules were given
+pub fn redactor_from<'a>(
+    matches: &ArgMatches,
+    registry: &'a MessageRegistry,
+) -> Result<Option<Redactor<'a>>> {
+    let mut rules = match matches.get_one::<String>("redact-rules") {
+        Some(path) => load_rules(path)?,
+        None => Vec::new(),
+    };
+    for rule in matches.get_many::<String>("redact").into_iter().flatten() {
+        rules.push(Rule::parse(rule)?);
+    }
+    if rules.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(Redactor::new(rules, registry, seed(matches))))
+}
+
+/// `--seed`, or one from the clock
+pub fn seed(matches: &ArgMatches) -> u64 {
+    match matches.get_one::<u64>("seed") {
+        Some(seed) => *seed,
+        None => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default(),
+    }
+}
+
+// Value parsers, so that bad values are rejected before any work starts
+
+/// A file that must exist
+pub fn existing_file(path: &str) -> std::result::Result<String, String> {
+    if Path::new(path).is_file() {
+        Ok(path.to_string())
+    } else {
+        Err(format!("'{}' does not exist or is not a file", path))
+    }
+}
+
+/// An input log that must exist, or `-` for stdin
+pub fn existing_input(path: &str) -> std::result::Result<String, String> {
+    match path {
+        "-" => Ok(path.to_string()),
+        _ => existing_file(path),
+    }
+}
+
+/// A file or directory that must exist, or `-` for stdin
+pub fn existing_path(path: &str) -> std::result::Result<String, String> {
+    if path == "-" || Path::new(path).exists() {
+        Ok(path.to_string())
+    } else {
+        Err(format!("'{}' does not exist", path))
+    }
+}
+
+/// A duration such as `120s` or `10min`, in microseconds
+pub fn duration(text: &str) -> std::result::Result<u64, String> {
+    parse_duration(text).map_err(|e| match e {
+        WallaceError::InvalidArgument(message) => message,
+        other => other.to_string(),
+    })
+}
+
+/// A UTC time as RFC 3339 or Unix seconds, in microseconds since the epoch
+pub fn start_time(text: &str) -> std::result::Result<u64, String> {
+    parse_start_time(text).map_err(|e| match e {
+        WallaceError::InvalidArgument(message) => message,
+        other => other.to_string(),
+    })
+}
+
+/// A size such as `512M` or `4G`, in bytes
+pub fn byte_size(text: &str) -> std::result::Result<u64, String> {
+    parse_size(text).map_err(|e| match e {
+        WallaceError::InvalidArgument(message) => message,
+        other => other.to_string(),
+    })
+}
+
+/// A percentage such as `1%` or `0.5`, above 0 and up to 100
+pub fn percent(text: &str) -> std::result::Result<f64, String> {
+    match text.trim().trim_end_matches('%').parse::<f64>() {
+        Ok(percent) if percent > 0.0 && percent <= 100.0 => Ok(percent),
+        _ => Err(format!(
+            "invalid percentage '{}': expected a number above 0 and up to 100, e.g. 1%",
+            text
+        )),
+    }
+}
+
+/// One of the export formats, by name
+pub fn format_parser() -> impl TypedValueParser<Value = OutputFormat> {
+    PossibleValuesParser::new(format_values()).map(|name| OutputFormat::from_name(&name).unwrap())
+    // Restricted to known names
+}
+
+fn format_values() -> Vec<PossibleValue> {
+    vec![
+        PossibleValue::new("csv"),
+        PossibleValue::new("ndjson").alias("jsonl"),
+        PossibleValue::new("xlsx"),
+        PossibleValue::new("influx"),
+        PossibleValue::new("avro"),
+        PossibleValue::new("duckdb"),
+        PossibleValue::new("postgres"),
+    ]
+}
+
+/// Like `format_parser`, with `binary` (`None`) for writing binary logs
+pub fn format_or_binary_parser() -> impl TypedValueParser<Value = Option<OutputFormat>> {
+    let mut values = vec![PossibleValue::new("binary")];
+    values.extend(format_values());
+    PossibleValuesParser::new(values).map(|name| OutputFormat::from_name(&name))
+}
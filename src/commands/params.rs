@@ -0,0 +1,196 @@
+// commands/params.rs
+// `params`: rebuilds the parameter set of a log from the message types whose registry entry has
+// `param` fields, and lists every parameter that changed while the log was recorded.
+
+use super::{existing_file, existing_input};
+use crate::errors::{Result, WallaceError};
+use crate::file_io::open_file;
+use crate::messages::load_message_registry;
+use crate::messages::registry::MessageDef;
+use crate::parser::{extract_messages, DecodeOptions};
+use clap::{Arg, ArgMatches, Command};
+use std::collections::HashMap;
+use std::fs;
+use std::io::BufReader;
+use std::path::Path;
+
+pub fn subcommand() -> Command {
+    Command::new("params")
+        .about("Collates parameter records into params.csv and a .param file, reporting parameters changed during the log")
+        .arg(
+            Arg::new("input")
+                .short('i')
+                .long("input")
+                .value_name("FILE")
+                .help("Sets the input log file path")
+                .value_parser(existing_input)
+                .required(true),
+        )
+        .arg(
+            Arg::new("registry")
+                .short('r')
+                .long("registry")
+                .value_name("FILE")
+                .help("Sets the message registry JSON file path")
+                .value_parser(existing_file)
+                .default_value("messages.json"),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_name("DIR")
+                .help("Directory for params.csv, params.param and, if any, param_changes.csv")
+                .default_value("."),
+        )
+}
+
+// A parameter, in the order it was first seen
+struct Param {
+    name: String,
+    initial: String,
+    value: String,
+    changes: usize,
+}
+
+struct ParamChange {
+    timestamp: Option<u64>,
+    name: String,
+    old: String,
+    new: String,
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let registry = load_message_registry(matches.get_one::<String>("registry").unwrap())?; // Has default
+    let input_path = matches.get_one::<String>("input").unwrap(); // Required
+    let output_dir = Path::new(matches.get_one::<String>("output").unwrap()); // Has default
+
+    let param_defs: Vec<&MessageDef> = registry
+        .values()
+        .filter(|def| def.param.is_some())
+        .collect();
+    if param_defs.is_empty() {
+        return Err(WallaceError::InvalidArgument(
+            "no message type in the registry has 'param' fields".to_string(),
+        ));
+    }
+    for def in &param_defs {
+        let param = def.param.as_ref().unwrap(); // Only defs with param fields are kept
+        for field in [&param.name, &param.value]
+            .into_iter()
+            .chain(&param.previous)
+        {
+            if !def.fields.iter().any(|f| f.name == *field) {
+                return Err(WallaceError::InvalidArgument(format!(
+                    "'{}' names '{}' as a param field, but has no such field",
+                    def.name, field
+                )));
+            }
+        }
+    }
+
+    let mut reader = BufReader::new(open_file(input_path)?);
+    let (messages, ..) = extract_messages(&mut reader, &registry, DecodeOptions::default())?;
+    let mut params: Vec<Param> = Vec::new();
+    let mut by_name: HashMap<String, usize> = HashMap::new();
+    let mut changes = Vec::new();
+    for msg in &messages {
+        let Some(def) = param_defs.iter().find(|def| def.name == msg.name) else {
+            continue;
+        };
+        let fields = def.param.as_ref().unwrap(); // Only defs with param fields are kept
+        let (Some(name), Some(value)) = (msg.field(&fields.name), msg.field(&fields.value)) else {
+            continue;
+        };
+        // Records logged on a change say what the value was; otherwise it is the last one seen
+        let logged_previous = fields.previous.as_deref().and_then(|f| msg.field(f));
+        let (old, at) = match by_name.get(name) {
+            Some(&at) => (logged_previous.unwrap_or(&params[at].value).to_string(), at),
+            None => {
+                let initial = logged_previous.unwrap_or(value).to_string();
+                by_name.insert(name.to_string(), params.len());
+                params.push(Param {
+                    name: name.to_string(),
+                    initial: initial.clone(),
+                    value: initial.clone(),
+                    changes: 0,
+                });
+                (initial, params.len() - 1)
+            }
+        };
+        if old != value {
+            changes.push(ParamChange {
+                timestamp: msg.timestamp_us(),
+                name: name.to_string(),
+                old,
+                new: value.to_string(),
+            });
+            params[at].changes += 1;
+        }
+        params[at].value = value.to_string();
+    }
+    if params.is_empty() {
+        println!("⚠️  No parameter records in '{}'", input_path);
+        return Ok(());
+    }
+    // Names in order, then numeric ids in numeric order
+    params.sort_by(|a, b| {
+        (a.name.parse::<u64>().ok(), &a.name).cmp(&(b.name.parse::<u64>().ok(), &b.name))
+    });
+
+    fs::create_dir_all(output_dir)?;
+    let csv_path = output_dir.join("params.csv");
+    let mut writer = csv::Writer::from_path(&csv_path)?;
+    writer.write_record(["name", "value", "initial", "changes"])?;
+    for param in &params {
+        writer.write_record([
+            param.name.as_str(),
+            &param.value,
+            &param.initial,
+            &param.changes.to_string(),
+        ])?;
+    }
+    writer.flush()?;
+    // NAME,VALUE lines as ground stations load them, with the values at the end of the log
+    let param_path = output_dir.join("params.param");
+    let dump: String = params
+        .iter()
+        .map(|param| format!("{},{}\n", param.name, param.value))
+        .collect();
+    fs::write(&param_path, dump)?;
+    println!(
+        "✅ Wrote {} parameters to '{}' and '{}'",
+        params.len(),
+        csv_path.display(),
+        param_path.display()
+    );
+
+    if changes.is_empty() {
+        println!("✅ No parameter changed during the log");
+        return Ok(());
+    }
+    let changes_path = output_dir.join("param_changes.csv");
+    let mut writer = csv::Writer::from_path(&changes_path)?;
+    writer.write_record(["timestamp_us", "name", "old", "new"])?;
+    for change in &changes {
+        let time = change
+            .timestamp
+            .map(|ts| ts.to_string())
+            .unwrap_or_default();
+        writer.write_record([time.as_str(), &change.name, &change.old, &change.new])?;
+        let time = change
+            .timestamp
+            .map_or_else(|| "-".to_string(), |ts| format!("{:.3}s", ts as f64 / 1e6));
+        println!(
+            "🔧 {} {}: {} → {}",
+            time, change.name, change.old, change.new
+        );
+    }
+    writer.flush()?;
+    println!(
+        "⚠️  {} parameter changes during the log, written to '{}'",
+        changes.len(),
+        changes_path.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,84 @@
+// commands/repair.rs
+// `repair`: writes a cleaned copy of a damaged log plus a JSON report of what was removed.
+
+use super::{existing_file, existing_input};
+use crate::errors::Result;
+use crate::file_io::open_file;
+use crate::messages::load_message_registry;
+use crate::repair::repair_log;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::fs::File;
+use std::io::BufWriter;
+
+pub fn subcommand() -> Command {
+    Command::new("repair")
+        .about("Copies a log, dropping corrupted records and resynchronizing on the framing")
+        .arg(
+            Arg::new("input")
+                .short('i')
+                .long("input")
+                .value_name("FILE")
+                .help("Sets the input log file path")
+                .value_parser(existing_input)
+                .required(true),
+        )
+        .arg(
+            Arg::new("registry")
+                .short('r')
+                .long("registry")
+                .value_name("FILE")
+                .help("Sets the message registry JSON file path")
+                .value_parser(existing_file)
+                .default_value("messages.json"),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_name("FILE")
+                .help("Repaired binary log to write")
+                .required(true),
+        )
+        .arg(
+            Arg::new("report")
+                .long("report")
+                .value_name("FILE")
+                .help("Where to write the removed byte ranges [default: <output>.repair.json]"),
+        )
+        .arg(
+            Arg::new("keep-unknown")
+                .long("keep-unknown")
+                .help("Keeps well-framed records whose type is not in the registry")
+                .action(ArgAction::SetTrue),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let registry = load_message_registry(matches.get_one::<String>("registry").unwrap())?; // Has default
+    let output_path = matches.get_one::<String>("output").unwrap(); // Required
+    let report_path = matches
+        .get_one::<String>("report")
+        .cloned()
+        .unwrap_or_else(|| format!("{}.repair.json", output_path));
+
+    let reader = open_file(matches.get_one::<String>("input").unwrap())?;
+    let writer = BufWriter::new(File::create(output_path)?);
+    let report = repair_log(reader, writer, &registry, matches.get_flag("keep-unknown"))?;
+    serde_json::to_writer_pretty(BufWriter::new(File::create(&report_path)?), &report)?;
+
+    println!(
+        "✅ Kept {} records in '{}'",
+        report.records_kept, output_path
+    );
+    if report.removed.is_empty() {
+        println!("✅ No damage found");
+    } else {
+        println!(
+            "⚠️  Removed {} bytes in {} ranges, see '{}'",
+            report.bytes_removed,
+            report.removed.len(),
+            report_path
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,80 @@
+// commands/schema.rs
+// `schema`: writes the schema of the exports derived from a registry, so database tables and
+// validators can be kept in step with the log definitions.
+
+use super::existing_file;
+use crate::errors::Result;
+use crate::export::schema::{emit, SchemaKind};
+use crate::export::sql::SqlDialect;
+use crate::messages::load_message_registry;
+use clap::{Arg, ArgMatches, Command};
+use std::fs;
+use std::io::{ErrorKind, Write};
+
+pub fn subcommand() -> Command {
+    Command::new("schema")
+        .about("Writes the schema of the exports of a registry as JSON Schema, Arrow schemas or SQL DDL")
+        .arg(
+            Arg::new("registry")
+                .short('r')
+                .long("registry")
+                .value_name("FILE")
+                .help("Sets the message registry JSON file path")
+                .value_parser(existing_file)
+                .default_value("messages.json"),
+        )
+        .arg(
+            Arg::new("emit")
+                .long("emit")
+                .value_name("KIND")
+                .help("json-schema: NDJSON records; arrow: Arrow schemas per message type, in the Arrow JSON form; sql: CREATE TABLE statements")
+                .value_parser(["json-schema", "arrow", "sql"])
+                .required(true),
+        )
+        .arg(
+            Arg::new("dialect")
+                .long("dialect")
+                .value_name("DIALECT")
+                .help("SQL dialect of --emit sql")
+                .value_parser(["postgres", "duckdb"])
+                .default_value("postgres"),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_name("FILE")
+                .help("File to write [default: stdout]"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let registry = load_message_registry(matches.get_one::<String>("registry").unwrap())?; // Has default
+    let dialect = match matches.get_one::<String>("dialect").map(String::as_str) {
+        Some("duckdb") => SqlDialect::DuckDb,
+        _ => SqlDialect::Postgres,
+    };
+    let kind = match matches.get_one::<String>("emit").map(String::as_str) {
+        Some("json-schema") => SchemaKind::JsonSchema,
+        Some("arrow") => SchemaKind::Arrow,
+        _ => SchemaKind::Sql(dialect),
+    };
+    let schema = emit(&registry, kind);
+
+    match matches.get_one::<String>("output") {
+        Some(path) => {
+            fs::write(path, schema)?;
+            println!(
+                "✅ Wrote the {} schema to '{}'",
+                matches.get_one::<String>("emit").unwrap(), // Required
+                path
+            );
+            Ok(())
+        }
+        None => match std::io::stdout().write_all(schema.as_bytes()) {
+            // The consumer (e.g. `head`) closing the pipe early is not an error
+            Err(e) if e.kind() == ErrorKind::BrokenPipe => Ok(()),
+            other => Ok(other?),
+        },
+    }
+}
@@ -0,0 +1,271 @@
+// commands/selftest.rs
+// `selftest`: round-trips random payloads of every message type through decode and encode and
+// reports the fields where encoding does not give back the bytes that were decoded, so a registry
+// or a new field type can be checked before `encode`, `redact` or `merge` rely on it.
+
+use super::existing_file;
+use crate::encode::encode_fields;
+use crate::errors::{Result, WallaceError};
+use crate::messages::load_message_registry;
+use crate::messages::registry::{FieldDef, MessageDef, StringEncoding};
+use crate::parser::{field_size, parse_fields, DecodeOptions};
+use crate::utils::rng::SplitMix64;
+use clap::{value_parser, Arg, ArgMatches, Command};
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub fn subcommand() -> Command {
+    Command::new("selftest")
+        .about("Round-trips random payloads of every message type through decode and encode, reporting fields that do not survive")
+        .arg(
+            Arg::new("registry")
+                .short('r')
+                .long("registry")
+                .value_name("FILE")
+                .help("Sets the message registry JSON file path")
+                .value_parser(existing_file)
+                .default_value("messages.json"),
+        )
+        .arg(
+            Arg::new("iterations")
+                .long("iterations")
+                .value_name("N")
+                .help("Random payloads per message type")
+                .value_parser(value_parser!(usize))
+                .default_value("100"),
+        )
+        .arg(
+            Arg::new("seed")
+                .long("seed")
+                .value_name("INT")
+                .help("Seed for the payloads, to repeat a run [default: random]")
+                .value_parser(value_parser!(u64)),
+        )
+}
+
+// Why a payload did not survive, and the field it went wrong in if that is known
+struct Mismatch {
+    field: Option<String>,
+    problem: String,
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let registry = load_message_registry(matches.get_one::<String>("registry").unwrap())?; // Has default
+    let iterations = *matches.get_one::<usize>("iterations").unwrap(); // Has default
+    let seed = match matches.get_one::<u64>("seed") {
+        Some(seed) => *seed,
+        None => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default(),
+    };
+    let mut rng = SplitMix64(seed);
+
+    let mut defs: Vec<(u16, &MessageDef)> = registry
+        .iter()
+        .filter_map(|(log_type, def)| Some((log_type.parse().ok()?, def)))
+        .collect();
+    defs.sort_by_key(|(log_type, _)| *log_type);
+
+    // First example and count of each failing message and field
+    let mut failures: BTreeMap<(u16, String), (String, usize)> = BTreeMap::new();
+    for (log_type, def) in &defs {
+        let label = |field: Option<&str>| match field {
+            Some(field) => format!("{}.{}", def.name, field),
+            None => def.name.clone(),
+        };
+        if let Some(field) = def
+            .fields
+            .iter()
+            .find(|f| field_size(f).is_none() && !is_file_contents(f))
+        {
+            failures.insert(
+                (*log_type, label(Some(&field.name))),
+                (
+                    format!(
+                        "type '{}' has no known size, so it cannot be decoded",
+                        field.r#type
+                    ),
+                    iterations,
+                ),
+            );
+            continue;
+        }
+        if let Some(field) = def
+            .fields
+            .iter()
+            .enumerate()
+            .find(|(i, f)| !f.is_skipped() && def.fields[..*i].iter().any(|g| g.name == f.name))
+            .map(|(_, f)| f)
+        {
+            failures.insert(
+                (*log_type, label(Some(&field.name))),
+                (
+                    "the name is used by more than one field, so encoding cannot tell their values apart".to_string(),
+                    iterations,
+                ),
+            );
+            continue;
+        }
+        for _ in 0..iterations {
+            let payload = random_payload(def, &mut rng);
+            if let Some(mismatch) = round_trip(def, &payload) {
+                failures
+                    .entry((*log_type, label(mismatch.field.as_deref())))
+                    .or_insert((mismatch.problem, 0))
+                    .1 += 1;
+            }
+        }
+    }
+
+    for ((log_type, label), (problem, count)) in &failures {
+        println!(
+            "❌ {} ({}): {} of {} payloads, e.g. {}",
+            label, log_type, count, iterations, problem
+        );
+    }
+    if !failures.is_empty() {
+        return Err(WallaceError::InvalidArgument(format!(
+            "{} fields do not round-trip (--seed {} repeats this run)",
+            failures.len(),
+            seed
+        )));
+    }
+    println!(
+        "✅ All {} message types round-trip through decode and encode ({} random payloads each, seed {})",
+        defs.len(),
+        iterations,
+        seed
+    );
+    Ok(())
+}
+
+/// Decodes `payload`, encodes the fields again and compares the bytes
+fn round_trip(def: &MessageDef, payload: &[u8]) -> Option<Mismatch> {
+    let whole = |problem: String| Mismatch {
+        field: None,
+        problem,
+    };
+    let decoded = match parse_fields(payload, &def.fields, DecodeOptions::default()) {
+        Ok(decoded) => decoded,
+        Err(e) => return Some(whole(format!("decoding failed: {}", e))),
+    };
+    let encoded = match encode_fields(&decoded.fields, def) {
+        Ok(encoded) => encoded,
+        Err(reason) => return Some(whole(format!("encoding failed: {}", reason))),
+    };
+    if encoded == payload {
+        return None;
+    }
+
+    let at = payload
+        .iter()
+        .zip(&encoded)
+        .position(|(a, b)| a != b)
+        .unwrap_or_else(|| payload.len().min(encoded.len()));
+    let Some(field) = field_at(def, at) else {
+        return Some(whole(format!(
+            "encoding is {} bytes but the payload was {}",
+            encoded.len(),
+            payload.len()
+        )));
+    };
+    let again = parse_fields(&encoded, &def.fields, DecodeOptions::default())
+        .map(|parsed| parsed.fields)
+        .unwrap_or_default();
+    let value_in = |fields: &[(String, String)]| {
+        fields
+            .iter()
+            .find(|(name, _)| *name == field.name)
+            .map_or_else(
+                || "(missing)".to_string(),
+                |(_, value)| format!("'{}'", value),
+            )
+    };
+    Some(Mismatch {
+        field: Some(field.name.clone()),
+        problem: format!(
+            "{} '{}' decoded as {} but its encoding decodes as {}",
+            field.r#type,
+            field.name,
+            value_in(&decoded.fields),
+            value_in(&again)
+        ),
+    })
+}
+
+// The field holding byte `at` of the payload
+fn field_at(def: &MessageDef, at: usize) -> Option<&FieldDef> {
+    let mut offset = 0;
+    for field in &def.fields {
+        // Only the variable length FILE_CONTENTS has no size, and it runs to the end
+        let size = field_size(field).unwrap_or(usize::MAX - offset);
+        if at < offset + size {
+            return Some(field);
+        }
+        offset += size;
+    }
+    None
+}
+
+fn is_file_contents(field: &FieldDef) -> bool {
+    field.r#type == "c" && field.name == "FILE_CONTENTS"
+}
+
+// A payload of `def` holding values the logger could have written: finite floats, booleans as 0
+// or 1, and strings of printable ASCII followed by NULs. Padding is zero, as encoding writes it.
+pub(super) fn random_payload(def: &MessageDef, rng: &mut SplitMix64) -> Vec<u8> {
+    let mut payload = Vec::new();
+    for field in &def.fields {
+        let size = field_size(field).unwrap_or(0);
+        let start = payload.len();
+        match field.r#type.as_str() {
+            _ if field.is_skipped() => {}
+            "f" => loop {
+                let value = f32::from_bits(rng.next_u64() as u32);
+                if value.is_finite() {
+                    payload.extend_from_slice(&value.to_le_bytes());
+                    break;
+                }
+            },
+            "d" => loop {
+                let value = f64::from_bits(rng.next_u64());
+                if value.is_finite() {
+                    payload.extend_from_slice(&value.to_le_bytes());
+                    break;
+                }
+            },
+            "?" => payload.push((rng.next_u64() & 1) as u8),
+            "c" if is_file_contents(field) => {
+                let chars = (rng.next_u64() % 33) as usize;
+                push_text(&mut payload, field, chars, rng);
+            }
+            "c" => payload.push(printable(rng)),
+            t if t.chars().all(|c| c == 'c') || t.ends_with('s') => {
+                let unit = match field.encoding {
+                    StringEncoding::Utf16le => 2,
+                    _ => 1,
+                };
+                let chars = (rng.next_u64() % (size / unit + 1) as u64) as usize;
+                push_text(&mut payload, field, chars, rng);
+            }
+            _ => payload.extend((0..size).map(|_| rng.next_u64() as u8)),
+        }
+        payload.resize(start + size.max(payload.len() - start), 0);
+    }
+    payload
+}
+
+// Printable ASCII, which every string encoding represents
+fn push_text(payload: &mut Vec<u8>, field: &FieldDef, chars: usize, rng: &mut SplitMix64) {
+    for _ in 0..chars {
+        payload.push(printable(rng));
+        if field.encoding == StringEncoding::Utf16le {
+            payload.push(0);
+        }
+    }
+}
+
+fn printable(rng: &mut SplitMix64) -> u8 {
+    b' ' + (rng.next_u64() % 95) as u8
+}
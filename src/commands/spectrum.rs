@@ -0,0 +1,139 @@
+// commands/spectrum.rs
+// `spectrum`: the vibration spectrum of high-rate fields such as raw IMU axes, as PSD CSVs and
+// optionally spectrogram PNGs showing how it changes over the flight.
+
+use super::{existing_file, existing_input};
+use crate::analysis::spectrum::{welch, write_spectrogram, write_spectrum};
+use crate::analysis::FieldRef;
+use crate::errors::{Result, WallaceError};
+use crate::file_io::open_file;
+use crate::messages::load_message_registry;
+use crate::parser::{extract_messages, DecodeOptions};
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use std::fs;
+use std::io::BufReader;
+use std::path::Path;
+
+pub fn subcommand() -> Command {
+    Command::new("spectrum")
+        .about("Computes the power spectral density of high-rate fields, e.g. for vibration analysis")
+        .arg(
+            Arg::new("input")
+                .short('i')
+                .long("input")
+                .value_name("FILE")
+                .help("Sets the input log file path")
+                .value_parser(existing_input)
+                .required(true),
+        )
+        .arg(
+            Arg::new("registry")
+                .short('r')
+                .long("registry")
+                .value_name("FILE")
+                .help("Sets the message registry JSON file path")
+                .value_parser(existing_file)
+                .default_value("messages.json"),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_name("DIR")
+                .help("Directory for spectrum_<MESSAGE.FIELD>.csv and spectrogram_<MESSAGE.FIELD>.png")
+                .default_value("."),
+        )
+        .arg(
+            Arg::new("field")
+                .long("field")
+                .value_name("MESSAGE.FIELD,...")
+                .help("Fields to analyse, e.g. IMU.AccZ")
+                .value_delimiter(',')
+                .required(true),
+        )
+        .arg(
+            Arg::new("window")
+                .long("window")
+                .value_name("SAMPLES")
+                .help("Samples per FFT window, a power of two; the frequency resolution is the sample rate divided by it")
+                .value_parser(value_parser!(u64).range(16..=1 << 20))
+                .default_value("1024"),
+        )
+        .arg(
+            Arg::new("overlap")
+                .long("overlap")
+                .value_name("PERCENT")
+                .help("Overlap of consecutive windows")
+                .value_parser(value_parser!(u8).range(0..=90))
+                .default_value("50"),
+        )
+        .arg(
+            Arg::new("spectrogram")
+                .long("spectrogram")
+                .help("Also draws the spectrum of every window over time as a PNG")
+                .action(ArgAction::SetTrue),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let registry = load_message_registry(matches.get_one::<String>("registry").unwrap())?; // Has default
+    let input_path = matches.get_one::<String>("input").unwrap(); // Required
+    let output_dir = Path::new(matches.get_one::<String>("output").unwrap()); // Has default
+    let window = *matches.get_one::<u64>("window").unwrap() as usize; // Has default
+    let overlap = *matches.get_one::<u8>("overlap").unwrap() as f64 / 100.0; // Has default
+    if !window.is_power_of_two() {
+        return Err(WallaceError::InvalidArgument(format!(
+            "'--window {}' is not a power of two",
+            window
+        )));
+    }
+    let fields = matches
+        .get_many::<String>("field")
+        .unwrap() // Required
+        .map(|spec| FieldRef::parse(spec))
+        .collect::<Result<Vec<_>>>()?;
+    for field in &fields {
+        field.check(&registry)?;
+    }
+
+    let mut reader = BufReader::new(open_file(input_path)?);
+    let (messages, ..) = extract_messages(&mut reader, &registry, DecodeOptions::default())?;
+    fs::create_dir_all(output_dir)?;
+    for field in &fields {
+        let series = field.series(&messages);
+        let Some(spectrum) = welch(&series, window, overlap) else {
+            println!(
+                "⚠️  {} has {} timestamped values, fewer than one window of {}; no spectrum written",
+                field,
+                series.len(),
+                window
+            );
+            continue;
+        };
+        let path = output_dir.join(format!("spectrum_{}.csv", field.file_stem()));
+        write_spectrum(&path, &spectrum)?;
+        let peak = spectrum
+            .peak()
+            .map(|(hz, _)| format!(", strongest at {:.2} Hz", hz))
+            .unwrap_or_default();
+        println!(
+            "📊 Wrote the spectrum of {} ({:.1} Hz sampling, {} windows{}) to '{}'",
+            field,
+            spectrum.sample_rate,
+            spectrum.segments.len(),
+            peak,
+            path.display()
+        );
+        if matches.get_flag("spectrogram") {
+            let path = output_dir.join(format!("spectrogram_{}.png", field.file_stem()));
+            write_spectrogram(&path, &spectrum)?;
+            println!(
+                "🖼️  Wrote the spectrogram of {} ({:.1} s per column) to '{}'",
+                field,
+                spectrum.hop_us / 1e6,
+                path.display()
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,238 @@
+// commands/split.rs
+// `split`: cuts a log into fixed-length time chunks, as binary logs or per-chunk exports.
+
+use super::{duration, existing_file, existing_input, format_or_binary_parser, start_time};
+use crate::encode::LogWriter;
+use crate::errors::{Result, WallaceError};
+use crate::export::influx::require_start_time;
+use crate::export::{export_all, ExportOptions, OutputFormat};
+use crate::file_io::open_file;
+use crate::messages::{load_message_registry, MessageRegistry};
+use crate::parser::stream::RawRecord;
+use crate::parser::{MessageStream, ParsedMessage};
+use crate::utils::group_by_type;
+use crate::utils::time::MAX_TIMESTAMP_JUMP_US;
+use clap::{Arg, ArgMatches, Command};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+pub fn subcommand() -> Command {
+    Command::new("split")
+        .about("Splits a log into time-based chunks")
+        .arg(
+            Arg::new("input")
+                .short('i')
+                .long("input")
+                .value_name("FILE")
+                .help("Sets the input log file path")
+                .value_parser(existing_input)
+                .required(true),
+        )
+        .arg(
+            Arg::new("registry")
+                .short('r')
+                .long("registry")
+                .value_name("FILE")
+                .help("Sets the message registry JSON file path")
+                .value_parser(existing_file)
+                .default_value("messages.json"),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_name("DIR")
+                .help("Directory for the chunks, named <input>_000.dat, <input>_001.dat, ...")
+                .default_value("."),
+        )
+        .arg(
+            Arg::new("every")
+                .long("every")
+                .value_name("DURATION")
+                .help("Length of each chunk, e.g. 10min")
+                .value_parser(duration)
+                .required(true),
+        )
+        .arg(
+            Arg::new("format")
+                .short('f')
+                .long("format")
+                .value_name("FORMAT")
+                .help("Writes each chunk as binary log or as an export directory in this format")
+                .value_parser(format_or_binary_parser())
+                .default_value("binary"),
+        )
+        .arg(
+            Arg::new("start-time")
+                .long("start-time")
+                .value_name("TIME")
+                .help("UTC time the log's clock started, as RFC 3339 or Unix seconds; required with '--format influx'")
+                .value_parser(start_time),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let registry = load_message_registry(matches.get_one::<String>("registry").unwrap())?; // Has default
+    let input_path = matches.get_one::<String>("input").unwrap(); // Required
+    let every = *matches.get_one::<u64>("every").unwrap(); // Required
+    if every == 0 {
+        return Err(WallaceError::InvalidArgument(
+            "'--every' must be longer than zero".to_string(),
+        ));
+    }
+    let format = *matches.get_one::<Option<OutputFormat>>("format").unwrap(); // None is binary
+    let start_time = matches.get_one::<u64>("start-time").copied();
+    if format == Some(OutputFormat::Influx) {
+        require_start_time(start_time)?;
+    }
+    let output_dir = Path::new(matches.get_one::<String>("output").unwrap()); // Has default
+    fs::create_dir_all(output_dir)?;
+    let stem = Path::new(input_path)
+        .file_name()
+        .and_then(|s| s.to_str())
+        .map(|name| name.split('.').next().unwrap_or(name))
+        .filter(|stem| !stem.is_empty())
+        .unwrap_or("chunk")
+        .to_string();
+
+    let reader = BufReader::new(open_file(input_path)?);
+    let mut stream = MessageStream::new(reader, &registry)?;
+    let mut chunker = Chunker {
+        output_dir,
+        stem,
+        header: stream.header,
+        format,
+        start_time,
+        registry: &registry,
+        preamble: Vec::new(),
+        current: None,
+        chunks_written: 0,
+    };
+
+    // Records before the first timestamp (versions, serial numbers) are repeated at the
+    // start of every chunk so each one stays self-describing. Records without a timestamp
+    // of their own go with the chunk of the last timestamp seen. Chunks only move forward,
+    // slightly out of order records stay in the current chunk.
+    let mut first_ts = None;
+    let mut latest = 0;
+    while let Some(record) = stream.next_record()? {
+        let msg = stream.decode(&record)?;
+        if let Some(ts) = msg.as_ref().and_then(|m| m.timestamp_us()) {
+            let start = *first_ts.get_or_insert(ts);
+            let elapsed = ts.saturating_sub(start);
+            if elapsed > latest && elapsed - latest <= MAX_TIMESTAMP_JUMP_US {
+                latest = elapsed;
+            }
+            chunker.switch_to(latest / every)?;
+        }
+        chunker.push(record, msg)?;
+    }
+    chunker.finish()?;
+
+    println!(
+        "✅ Split '{}' into {} chunks in '{}'",
+        input_path,
+        chunker.chunks_written,
+        output_dir.display()
+    );
+    Ok(())
+}
+
+struct Chunk {
+    index: u64,
+    // Binary output, or the decoded messages for an export
+    writer: Option<LogWriter<BufWriter<File>>>,
+    messages: Vec<ParsedMessage>,
+}
+
+struct Chunker<'a> {
+    output_dir: &'a Path,
+    stem: String,
+    header: i32,
+    format: Option<OutputFormat>,
+    start_time: Option<u64>,
+    registry: &'a MessageRegistry,
+    preamble: Vec<(RawRecord, Option<ParsedMessage>)>,
+    current: Option<Chunk>,
+    chunks_written: usize,
+}
+
+impl Chunker<'_> {
+    fn chunk_path(&self, index: u64) -> PathBuf {
+        match self.format {
+            Some(_) => self.output_dir.join(format!("{}_{:03}", self.stem, index)),
+            None => self
+                .output_dir
+                .join(format!("{}_{:03}.dat", self.stem, index)),
+        }
+    }
+
+    // Starts chunk `index` unless it is already the current one
+    fn switch_to(&mut self, index: u64) -> Result<()> {
+        if self.current.as_ref().map(|c| c.index) == Some(index) {
+            return Ok(());
+        }
+        self.finish()?;
+        let mut chunk = Chunk {
+            index,
+            writer: None,
+            messages: Vec::new(),
+        };
+        if self.format.is_none() {
+            let file = File::create(self.chunk_path(index))?;
+            chunk.writer = Some(LogWriter::new(BufWriter::new(file), self.header)?);
+        }
+        self.current = Some(chunk);
+        for (record, msg) in self.preamble.clone() {
+            self.add(&record, msg)?;
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, record: RawRecord, msg: Option<ParsedMessage>) -> Result<()> {
+        if self.current.is_none() {
+            self.preamble.push((record, msg));
+            return Ok(());
+        }
+        self.add(&record, msg)
+    }
+
+    fn add(&mut self, record: &RawRecord, msg: Option<ParsedMessage>) -> Result<()> {
+        let chunk = self.current.as_mut().unwrap(); // Callers ensure a current chunk
+        match &mut chunk.writer {
+            Some(writer) => writer.write_record(record.log_type, &record.payload)?,
+            None => chunk.messages.extend(msg),
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        let chunk = match self.current.take() {
+            Some(chunk) => chunk,
+            None => return Ok(()),
+        };
+        let path = self.chunk_path(chunk.index);
+        match (chunk.writer, self.format) {
+            (Some(writer), _) => {
+                writer.finish()?;
+            }
+            (None, Some(format)) => {
+                fs::create_dir_all(&path)?;
+                export_all(
+                    &path,
+                    &group_by_type(&chunk.messages),
+                    format,
+                    self.registry,
+                    &ExportOptions {
+                        start_time: self.start_time,
+                        ..ExportOptions::default()
+                    },
+                )?;
+            }
+            (None, None) => {}
+        }
+        self.chunks_written += 1;
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+// commands/trim.rs
+// `trim`: copies the records inside a time window into a new, smaller binary log.
+
+use super::{duration, existing_file, existing_input};
+use crate::encode::LogWriter;
+use crate::errors::{Result, WallaceError};
+use crate::file_io::open_file;
+use crate::messages::load_message_registry;
+use crate::parser::MessageStream;
+use clap::{Arg, ArgMatches, Command};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+pub fn subcommand() -> Command {
+    Command::new("trim")
+        .about("Copies the records within a time window to a new binary log")
+        .arg(
+            Arg::new("input")
+                .short('i')
+                .long("input")
+                .value_name("FILE")
+                .help("Sets the input log file path")
+                .value_parser(existing_input)
+                .required(true),
+        )
+        .arg(
+            Arg::new("registry")
+                .short('r')
+                .long("registry")
+                .value_name("FILE")
+                .help("Sets the message registry JSON file path")
+                .value_parser(existing_file)
+                .default_value("messages.json"),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_name("FILE")
+                .help("Binary log to write")
+                .required(true),
+        )
+        .arg(
+            Arg::new("from")
+                .long("from")
+                .value_name("DURATION")
+                .help("Start of the window, relative to the first timestamp in the log (e.g. 120s)")
+                .value_parser(duration),
+        )
+        .arg(
+            Arg::new("to")
+                .long("to")
+                .value_name("DURATION")
+                .help("End of the window (exclusive), relative to the first timestamp in the log")
+                .value_parser(duration),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let registry = load_message_registry(matches.get_one::<String>("registry").unwrap())?; // Has default
+    let output_path = matches.get_one::<String>("output").unwrap(); // Required
+    let from = matches.get_one::<u64>("from").copied();
+    let to = matches.get_one::<u64>("to").copied();
+    if let (Some(from), Some(to)) = (from, to) {
+        if from >= to {
+            return Err(WallaceError::InvalidArgument(
+                "'--from' must be before '--to'".to_string(),
+            ));
+        }
+    }
+
+    let reader = BufReader::new(open_file(matches.get_one::<String>("input").unwrap())?);
+    let mut stream = MessageStream::new(reader, &registry)?;
+    let mut writer = LogWriter::new(BufWriter::new(File::create(output_path)?), stream.header)?;
+
+    // Records without a timestamp of their own (serial numbers, file contents, unknown types)
+    // belong to the last timestamp seen. Those before the first timestamp form the log preamble
+    // and are always kept, so the clip stays self-describing.
+    let mut first_ts = None;
+    let mut elapsed = None;
+    let mut total = 0usize;
+    while let Some(record) = stream.next_record()? {
+        total += 1;
+        if let Some(ts) = stream.decode(&record)?.and_then(|msg| msg.timestamp_us()) {
+            let start = *first_ts.get_or_insert(ts);
+            elapsed = Some(ts.saturating_sub(start));
+        }
+        let keep = match elapsed {
+            None => true,
+            Some(t) => from.is_none_or(|f| t >= f) && to.is_none_or(|e| t < e),
+        };
+        if keep {
+            writer.write_record(record.log_type, &record.payload)?;
+        }
+    }
+
+    let kept = writer.records_written();
+    writer.finish()?;
+    println!(
+        "✅ Wrote {} of {} records to '{}'",
+        kept, total, output_path
+    );
+    Ok(())
+}
@@ -0,0 +1,270 @@
+// commands/validate.rs
+// `validate`: checks a registry on its own for fields without a known size, field names used
+// twice and layout variants that are never used, and with `--against` checks its message sizes
+// against the record lengths of a log. For a type whose records have other lengths it names the
+// fields most likely responsible: the ones past the end of shorter records, ones that would
+// account for the difference if wider or narrower, or a missing `"packing": "natural"`.
+
+use super::existing_input;
+use crate::errors::{Result, WallaceError};
+use crate::file_io::open_file;
+use crate::messages::firmware;
+use crate::messages::load_message_registry;
+use crate::messages::registry::{
+    load_registry_for_firmware, natural_size, FieldDef, MessageDef, MessageRegistry, Packing,
+};
+use crate::parser::{field_size, MessageStream, RecordCount};
+use clap::{Arg, ArgMatches, Command};
+use std::collections::BTreeMap;
+use std::io::BufReader;
+
+// Fields named in one suggestion at most
+const MAX_SUSPECTS: usize = 5;
+
+pub fn subcommand() -> Command {
+    Command::new("validate")
+        .about("Checks a registry for fields without a size, repeated field names and unused variants, and with --against its message sizes against the record lengths of a log")
+        .arg(
+            Arg::new("registry")
+                .short('r')
+                .long("registry")
+                .value_name("FILE")
+                .help("Sets the message registry JSON file path")
+                .value_parser(super::existing_file)
+                .default_value("messages.json"),
+        )
+        .arg(
+            Arg::new("against")
+                .long("against")
+                .value_name("LOG")
+                .help("A log whose record lengths each message size must match, e.g. one from the firmware the registry is for; '-' for stdin")
+                .value_parser(existing_input),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let registry_path = matches.get_one::<String>("registry").unwrap(); // Has default
+    let against = matches.get_one::<String>("against").map(String::as_str);
+    let mut registry = load_message_registry(registry_path)?;
+    if let Some(log) = against.filter(|&log| log != "-") {
+        if let Some(version) = firmware::detect(log, &registry)? {
+            println!("🏷️  Firmware {}, using its field layout", version);
+            registry = load_registry_for_firmware(registry_path, Some(&version))?;
+        }
+    }
+    let mut defs: Vec<(u16, &MessageDef)> = registry
+        .iter()
+        .filter_map(|(log_type, def)| Some((log_type.parse().ok()?, def)))
+        .collect();
+    defs.sort_by_key(|(log_type, _)| *log_type);
+
+    let mut problems = 0;
+    for (log_type, def) in &defs {
+        for problem in layout_problems(def) {
+            println!("❌ {} ({}): {}", def.name, log_type, problem);
+            problems += 1;
+        }
+    }
+    if let Some(log) = against {
+        let counts = record_lengths(log, &registry)?;
+        for (log_type, def) in &defs {
+            let (Some(count), Some(sizes)) = (counts.get(log_type), def.payload_sizes()) else {
+                continue;
+            };
+            // Without fields a definition only names the type
+            if def.fields.is_empty() {
+                continue;
+            }
+            for (&length, records) in count.lengths.iter().filter(|(l, _)| !sizes.contains(l)) {
+                let sizes: Vec<String> = sizes.iter().map(usize::to_string).collect();
+                println!(
+                    "❌ {} ({}): {} records of {} bytes, the registry gives it {} bytes",
+                    def.name,
+                    log_type,
+                    records,
+                    length,
+                    sizes.join(" or ")
+                );
+                for suspect in suspects(def, length) {
+                    println!("   ↳ {}", suspect);
+                }
+                problems += 1;
+            }
+        }
+    }
+
+    if problems > 0 {
+        return Err(WallaceError::InvalidArgument(format!(
+            "{} problems in registry '{}'",
+            problems, registry_path
+        )));
+    }
+    match against {
+        Some(log) => println!(
+            "✅ All {} message types of '{}' are sound and match the record lengths of '{}'",
+            defs.len(),
+            registry_path,
+            log
+        ),
+        None => println!(
+            "✅ All {} message types of '{}' are sound",
+            defs.len(),
+            registry_path
+        ),
+    }
+    Ok(())
+}
+
+// What is wrong with a definition whatever the log
+fn layout_problems(def: &MessageDef) -> Vec<String> {
+    let mut problems = Vec::new();
+    for (i, field) in def.fields.iter().enumerate() {
+        if field_size(field).is_none() && !is_file_contents(field) {
+            problems.push(format!(
+                "field '{}' has type '{}', which has no known size",
+                field.name,
+                short_type(&field.r#type)
+            ));
+        }
+        if !field.is_skipped() && def.fields[..i].iter().any(|f| f.name == field.name) {
+            problems.push(format!(
+                "field name '{}' is used more than once",
+                field.name
+            ));
+        }
+    }
+    let size = def.decode_plan().payload_size();
+    if let Some(size) = size.filter(|&size| {
+        def.variants
+            .iter()
+            .any(|v| v.plan.payload_size() == Some(size))
+    }) {
+        problems.push(format!(
+            "a layout variant has the size of the fields, {} bytes, so it is never used",
+            size
+        ));
+    }
+    problems
+}
+
+// Long repeated types such as `ffff…` cut to their start
+fn short_type(type_str: &str) -> String {
+    match type_str.char_indices().nth(16) {
+        Some((end, _)) => format!("{}… ({} characters)", &type_str[..end], type_str.len()),
+        None => type_str.to_string(),
+    }
+}
+
+fn is_file_contents(field: &FieldDef) -> bool {
+    field.name == "FILE_CONTENTS" && field.r#type == "c"
+}
+
+// Records and payload lengths per log_type, read without decoding
+fn record_lengths(log: &str, registry: &MessageRegistry) -> Result<BTreeMap<u16, RecordCount>> {
+    let mut stream = MessageStream::new(BufReader::new(open_file(log)?), registry)?;
+    while stream.next_record()?.is_some() {}
+    Ok(stream.record_counts)
+}
+
+// The likely causes of records of `length` bytes, for a definition of fixed size
+fn suspects(def: &MessageDef, length: usize) -> Vec<String> {
+    let sized: Vec<(&FieldDef, usize)> = def
+        .fields
+        .iter()
+        .filter_map(|field| Some((field, field_size(field)?)))
+        .collect();
+    let expected: usize = sized.iter().map(|(_, size)| size).sum();
+    let mut suspects = Vec::new();
+    if def.packing == Packing::Packed && natural_size(&def.fields) == Some(length) {
+        suspects.push(format!(
+            "the fields take exactly {} bytes when aligned as a C compiler would: set \"packing\": \"natural\"",
+            length
+        ));
+    }
+    if length < expected {
+        let diff = expected - length;
+        let mut offset = 0;
+        let mut cut = None;
+        for (i, (field, size)) in sized.iter().enumerate() {
+            if offset + size > length {
+                cut = Some((i, field, offset));
+                break;
+            }
+            offset += size;
+        }
+        // Always found, as the fields take more than `length` bytes
+        let Some((i, field, offset)) = cut else {
+            return suspects;
+        };
+        let missing = names(sized[i..].iter().map(|(field, _)| *field));
+        if offset == length {
+            suspects.push(format!(
+                "the fields before '{}' take exactly {} bytes; {} may be missing from these records, e.g. added by a later firmware (give them a layout variant or a firmware_version gate)",
+                field.name, length, missing
+            ));
+            return suspects;
+        }
+        suspects.push(format!(
+            "the records end {} bytes into '{}', which starts at byte {}; {} do not fit",
+            length - offset,
+            field.name,
+            offset,
+            missing
+        ));
+        let narrower = sized
+            .iter()
+            .filter(|(field, size)| *size == 2 * diff || (is_array(field) && *size > diff));
+        push_fields(
+            &mut suspects,
+            narrower.map(|(field, _)| *field),
+            &format!("may be {} bytes narrower than declared", diff),
+        );
+    } else {
+        let diff = length - expected;
+        suspects.push(format!(
+            "{} bytes are left after the last field '{}': a field may be missing",
+            diff,
+            sized.last().map_or("", |(field, _)| field.name.as_str())
+        ));
+        let wider = sized
+            .iter()
+            .filter(|(field, size)| *size == diff || is_array(field));
+        push_fields(
+            &mut suspects,
+            wider.map(|(field, _)| *field),
+            &format!("may be {} bytes wider than declared", diff),
+        );
+    }
+    suspects
+}
+
+// Text and byte arrays, whose declared length is the likeliest to be off
+fn is_array(field: &FieldDef) -> bool {
+    let t = field.r#type.as_str();
+    (t.ends_with('s') && t.len() > 1)
+        || (t.len() > 1 && t.chars().all(|c| matches!(c, 'c' | 'B' | 'b')))
+}
+
+fn push_fields<'a>(
+    suspects: &mut Vec<String>,
+    fields: impl Iterator<Item = &'a FieldDef>,
+    what: &str,
+) {
+    let fields: Vec<&FieldDef> = fields.collect();
+    if !fields.is_empty() {
+        suspects.push(format!("{} {}", names(fields.into_iter()), what));
+    }
+}
+
+// `'A', 'B' and 2 more`
+fn names<'a>(fields: impl Iterator<Item = &'a FieldDef>) -> String {
+    let names: Vec<String> = fields.map(|field| format!("'{}'", field.name)).collect();
+    match names.len() {
+        n if n > MAX_SUSPECTS => format!(
+            "{} and {} more",
+            names[..MAX_SUSPECTS].join(", "),
+            n - MAX_SUSPECTS
+        ),
+        _ => names.join(", "),
+    }
+}
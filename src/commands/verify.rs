@@ -0,0 +1,183 @@
+// commands/verify.rs
+// `verify`: hashes exports again and compares them with their `.meta.json` sidecars, and the
+// input logs and registries with the hashes recorded there, to catch files that were edited,
+// truncated or rotted on disk since the export. Works on export directories, on the archives
+// `--archive` writes and on single sidecars.
+
+use super::existing_path;
+use crate::archive::{collect_files, for_each_entry, ArchiveFormat};
+use crate::errors::{Result, WallaceError};
+use crate::report::provenance::{sha256_file, sha256_reader, SidecarRecord, SIDECAR_SUFFIX};
+use clap::{Arg, ArgMatches, Command};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+pub fn subcommand() -> Command {
+    Command::new("verify")
+        .about("Checks exports against the hashes in their .meta.json sidecars, and the input logs and registries they name")
+        .arg(
+            Arg::new("paths")
+                .value_name("PATH")
+                .help("Export directories, .zip/.tar.gz archives of them, or single .meta.json sidecars")
+                .num_args(1..)
+                .value_parser(existing_path)
+                .required(true),
+        )
+}
+
+// The files of an export, by name relative to it, and its sidecars
+#[derive(Default)]
+struct Contents {
+    hashes: BTreeMap<String, String>,
+    sidecars: Vec<(String, SidecarRecord)>,
+    /// Sidecars that are not valid JSON any more, and why
+    unreadable: Vec<(String, String)>,
+}
+
+impl Contents {
+    fn add(&mut self, name: String, reader: &mut dyn Read) -> Result<()> {
+        if !name.ends_with(SIDECAR_SUFFIX) {
+            let hash = sha256_reader(reader)?;
+            self.hashes.insert(name, hash);
+            return Ok(());
+        }
+        match serde_json::from_reader(reader) {
+            Ok(record) => self.sidecars.push((name, record)),
+            Err(e) => self.unreadable.push((name, e.to_string())),
+        }
+        Ok(())
+    }
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let paths = matches.get_many::<String>("paths").unwrap(); // Required
+    let mut failures = 0;
+    let mut verified = 0;
+    // Inputs and registries named by the sidecars, with each hash recorded for them
+    let mut sources: BTreeMap<(&str, String), BTreeSet<String>> = BTreeMap::new();
+
+    for path in paths {
+        let contents = read_contents(Path::new(path))?;
+        for (name, reason) in &contents.unreadable {
+            println!("❌ {}: sidecar {} cannot be read: {}", path, name, reason);
+            failures += 1;
+        }
+        let mut checked = BTreeSet::new();
+        for (sidecar, record) in &contents.sidecars {
+            let dir = sidecar.rfind('/').map_or("", |at| &sidecar[..=at]);
+            let name = format!("{}{}", dir, record.file);
+            match contents.hashes.get(&name) {
+                None => {
+                    println!("❌ {}: {} is missing", path, name);
+                    failures += 1;
+                }
+                Some(hash) if *hash != record.sha256 => {
+                    println!(
+                        "❌ {}: {} has changed (sha256 {}, recorded {})",
+                        path, name, hash, record.sha256
+                    );
+                    failures += 1;
+                }
+                Some(_) => verified += 1,
+            }
+            checked.insert(name);
+            if let Some(hash) = &record.input_sha256 {
+                sources
+                    .entry(("input", record.input.clone()))
+                    .or_default()
+                    .insert(hash.clone());
+            }
+            sources
+                .entry(("registry", record.registry.clone()))
+                .or_default()
+                .insert(record.registry_sha256.clone());
+        }
+        let unchecked = contents
+            .hashes
+            .keys()
+            .filter(|name| !checked.contains(*name))
+            .count();
+        if contents.sidecars.is_empty() {
+            println!("⚠️  {}: no sidecars, nothing to check", path);
+        } else if unchecked > 0 {
+            println!(
+                "⚠️  {}: {} files have no sidecar and were not checked",
+                path, unchecked
+            );
+        }
+    }
+
+    // Sources are often archived elsewhere, so one that is gone is only reported
+    let mut source_hashes: HashMap<String, String> = HashMap::new();
+    let mut sources_verified = 0;
+    for ((kind, path), recorded) in &sources {
+        if !Path::new(path).is_file() {
+            println!("⚠️  {} '{}' not found, not checked", kind, path);
+            continue;
+        }
+        if !source_hashes.contains_key(path) {
+            source_hashes.insert(path.clone(), sha256_file(path)?);
+        }
+        let hash = &source_hashes[path];
+        for expected in recorded.iter().filter(|expected| *expected != hash) {
+            println!(
+                "❌ {} '{}' has changed since the export (sha256 {}, recorded {})",
+                kind, path, hash, expected
+            );
+            failures += 1;
+        }
+        if recorded.contains(hash) {
+            sources_verified += 1;
+        }
+    }
+
+    if failures > 0 {
+        return Err(WallaceError::InvalidArgument(format!(
+            "{} files are missing, changed or unreadable",
+            failures
+        )));
+    }
+    println!(
+        "✅ {} exports and {} inputs and registries match their recorded hashes",
+        verified, sources_verified
+    );
+    Ok(())
+}
+
+// Hashes every file of an export directory or archive, or the one file a sidecar describes
+fn read_contents(path: &Path) -> Result<Contents> {
+    let mut contents = Contents::default();
+    if path.is_dir() {
+        let mut files = Vec::new();
+        collect_files(path, &mut files)?;
+        for file in files {
+            let relative = file.strip_prefix(path).unwrap_or(&file);
+            let name = relative.to_string_lossy().replace('\\', "/");
+            contents.add(name, &mut BufReader::new(File::open(&file)?))?;
+        }
+    } else if let Some(format) = ArchiveFormat::of_path(path) {
+        for_each_entry(path, format, |name, entry| {
+            contents.add(name.to_string(), entry)
+        })?;
+    } else if let Some(name) = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .filter(|name| name.ends_with(SIDECAR_SUFFIX))
+    {
+        contents.add(name.to_string(), &mut BufReader::new(File::open(path)?))?;
+        let file = &name[..name.len() - SIDECAR_SUFFIX.len()];
+        let described = path.with_file_name(file);
+        if described.is_file() {
+            contents.add(file.to_string(), &mut File::open(described)?)?;
+        }
+    } else {
+        return Err(WallaceError::InvalidArgument(format!(
+            "cannot verify '{}': expected an export directory, a .zip/.tar.gz archive or a {} sidecar",
+            path.display(),
+            SIDECAR_SUFFIX
+        )));
+    }
+    Ok(contents)
+}
@@ -0,0 +1,286 @@
+// config/mod.rs
+// Project defaults from `wallace.toml`, applied as argument defaults so that flags given on the
+// command line still win. `[profile.NAME]` tables bundle the settings of one vehicle or log
+// variant and are layered over the top-level ones with `--profile NAME`. Every option can also be
+// set from a `WALLACE_*` environment variable, which beats the config but not the command line.
+
+use crate::errors::{Result, WallaceError};
+use clap::Command;
+use serde::Deserialize;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+/// Read from the working directory when `--config` is not given
+pub const CONFIG_FILE: &str = "wallace.toml";
+/// Prefix of the environment variables standing in for options, e.g. `WALLACE_REGISTRY`
+pub const ENV_PREFIX: &str = "WALLACE_";
+
+/// Contents of a config file with the selected profile applied; keys are named like the long
+/// options they stand for
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub registry: Option<String>,
+    pub registry_manifest: Option<String>,
+    pub output: Option<String>,
+    pub format: Option<String>,
+    pub missing_value: Option<String>,
+    pub locale: Option<String>,
+    pub decimal_separator: Option<String>,
+    pub digit_grouping: Option<String>,
+    pub convert: Option<String>,
+    pub transform: Option<String>,
+    pub transform_max_operations: Option<u64>,
+    pub archive: Option<String>,
+    pub columns: Vec<String>,
+    pub rate: Vec<String>,
+    pub smooth: Vec<String>,
+    pub tag: Vec<String>,
+    pub metadata: Option<bool>,
+    pub rows_per_file: Option<u64>,
+    pub provenance_header: Option<bool>,
+    pub keep_formulas: Option<bool>,
+    pub append: Option<bool>,
+    pub max_memory: Option<String>,
+    pub cache_decompressed: Option<String>,
+    pub incremental: Option<bool>,
+    pub max_records: Option<u64>,
+    pub parse_timeout: Option<u64>,
+    pub max_empty_run: Option<u64>,
+    pub filters: Filters,
+    pub analysis: Analysis,
+}
+
+/// Which records and fields are decoded
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Filters {
+    pub skip_field: Vec<String>,
+    #[serde(alias = "keep-padding")]
+    pub keep_skipped: Option<bool>,
+    pub partial: Option<String>,
+    pub lenient: Option<bool>,
+    pub bytes_format: Option<String>,
+    pub dedup: Option<bool>,
+    pub sample: Option<String>,
+    pub reservoir: Option<u64>,
+    pub use_index: Option<bool>,
+    /// Only with `use-index`, like the flags
+    pub types: Vec<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub geofilter: Option<String>,
+    pub geofilter_mode: Option<String>,
+    pub geofilter_position: Option<String>,
+}
+
+/// Reports written next to the exports
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Analysis {
+    pub histogram: Vec<String>,
+    pub extrema: Vec<String>,
+    pub changes: Vec<String>,
+    pub correlate: Vec<String>,
+    pub battery: Option<String>,
+    pub altitude: Option<String>,
+    pub track: Option<String>,
+}
+
+impl Config {
+    /// Reads `path`, with the keys of `[profile.NAME]` replacing the top-level ones if a profile
+    /// is given
+    pub fn load<P: AsRef<Path>>(path: P, profile: Option<&str>) -> Result<Self> {
+        let path = path.as_ref();
+        let invalid = |e: &dyn std::fmt::Display| {
+            WallaceError::InvalidArgument(format!("config '{}': {}", path.display(), e))
+        };
+        let text = fs::read_to_string(path).map_err(|e| invalid(&e))?;
+        let mut table: Table = toml::from_str(&text).map_err(|e| invalid(&e))?;
+        let profiles = match table.remove("profile") {
+            Some(Value::Table(profiles)) => profiles,
+            Some(_) => return Err(invalid(&"'profile' must be a table of profiles")),
+            None => Table::new(),
+        };
+        if let Some(name) = profile {
+            match profiles.get(name) {
+                Some(Value::Table(overrides)) => merge(&mut table, overrides.clone()),
+                Some(_) => return Err(invalid(&format!("profile '{}' must be a table", name))),
+                None => {
+                    let known: Vec<&str> = profiles.keys().map(String::as_str).collect();
+                    return Err(invalid(&format!(
+                        "no profile '{}' (available: {})",
+                        name,
+                        if known.is_empty() {
+                            "none".to_string()
+                        } else {
+                            known.join(", ")
+                        }
+                    )));
+                }
+            }
+        }
+        Value::Table(table).try_into().map_err(|e| invalid(&e))
+    }
+
+    /// Argument ids and the values the config gives them
+    fn defaults(&self) -> Vec<(&'static str, Vec<String>)> {
+        let single = |id, value: &Option<String>| value.clone().map(|v| (id, vec![v]));
+        let flag = |id, value: Option<bool>| value.map(|v| (id, vec![v.to_string()]));
+        let number = |id, value: Option<u64>| value.map(|v| (id, vec![v.to_string()]));
+        let list = |id, values: &Vec<String>| (!values.is_empty()).then(|| (id, values.clone()));
+        [
+            single("registry", &self.registry),
+            single("registry-manifest", &self.registry_manifest),
+            single("output", &self.output),
+            single("format", &self.format),
+            single("missing-value", &self.missing_value),
+            single("locale", &self.locale),
+            single("decimal-separator", &self.decimal_separator),
+            single("digit-grouping", &self.digit_grouping),
+            single("convert", &self.convert),
+            single("transform", &self.transform),
+            number("transform-max-operations", self.transform_max_operations),
+            single("archive", &self.archive),
+            list("columns", &self.columns),
+            list("rate", &self.rate),
+            list("smooth", &self.smooth),
+            list("tag", &self.tag),
+            flag("metadata", self.metadata),
+            number("rows-per-file", self.rows_per_file),
+            flag("provenance-header", self.provenance_header),
+            flag("keep-formulas", self.keep_formulas),
+            flag("append", self.append),
+            single("max-memory", &self.max_memory),
+            single("cache-decompressed", &self.cache_decompressed),
+            flag("incremental", self.incremental),
+            number("max-records", self.max_records),
+            number("parse-timeout", self.parse_timeout),
+            number("max-empty-run", self.max_empty_run),
+            list("skip-field", &self.filters.skip_field),
+            flag("keep-skipped", self.filters.keep_skipped),
+            single("partial", &self.filters.partial),
+            flag("lenient", self.filters.lenient),
+            single("bytes-format", &self.filters.bytes_format),
+            flag("dedup", self.filters.dedup),
+            single("sample", &self.filters.sample),
+            number("reservoir", self.filters.reservoir),
+            flag("use-index", self.filters.use_index),
+            list("types", &self.filters.types),
+            single("from", &self.filters.from),
+            single("to", &self.filters.to),
+            single("geofilter", &self.filters.geofilter),
+            single("geofilter-mode", &self.filters.geofilter_mode),
+            single("geofilter-position", &self.filters.geofilter_position),
+            list("histogram", &self.analysis.histogram),
+            list("extrema", &self.analysis.extrema),
+            list("changes", &self.analysis.changes),
+            list("correlate", &self.analysis.correlate),
+            single("battery", &self.analysis.battery),
+            single("altitude", &self.analysis.altitude),
+            single("track", &self.analysis.track),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    /// Makes the config values the defaults of `cli`. The registry also applies to every
+    /// subcommand that reads one; the rest only to the main command.
+    pub fn apply(&self, mut cli: Command) -> Command {
+        for (id, values) in self.defaults() {
+            cli = cli.mut_arg(id, |arg| arg.default_values(values.clone()));
+            if id != "registry" {
+                continue;
+            }
+            let names: Vec<String> = cli
+                .get_subcommands()
+                .filter(|sub| sub.get_arguments().any(|arg| arg.get_id() == id))
+                .map(|sub| sub.get_name().to_string())
+                .collect();
+            for name in names {
+                cli = cli.mut_subcommand(name, |sub| {
+                    sub.mut_arg(id, |arg| arg.default_values(values.clone()))
+                });
+            }
+        }
+        cli
+    }
+}
+
+// Profile keys replace top-level ones, except that tables such as `[filters]` are merged key by key
+fn merge(base: &mut Table, overrides: Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(overrides)) => merge(base, overrides),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Environment variable for the option with this id, e.g. `WALLACE_MISSING_VALUE`
+pub fn env_name(id: &str) -> String {
+    format!("{}{}", ENV_PREFIX, id.to_uppercase().replace('-', "_"))
+}
+
+/// Lets every option of the main command, and the registry of each subcommand, be set from the
+/// environment. `--help` lists the variables.
+pub fn with_env(cli: Command) -> Command {
+    let cli = cli.mut_args(|arg| match arg.get_long() {
+        Some(_) => {
+            let name = env_name(arg.get_id().as_str());
+            arg.env(name)
+        }
+        None => arg,
+    });
+    let names: Vec<String> = cli
+        .get_subcommands()
+        .filter(|sub| sub.get_arguments().any(|arg| arg.get_id() == "registry"))
+        .map(|sub| sub.get_name().to_string())
+        .collect();
+    names.into_iter().fold(cli, |cli, name| {
+        cli.mut_subcommand(name, |sub| {
+            sub.mut_arg("registry", |arg| arg.env(env_name("registry")))
+        })
+    })
+}
+
+/// The file named by `--config` or `WALLACE_CONFIG`, else `wallace.toml` in the working
+/// directory if there is one. Looked up before the arguments are parsed, since the config changes
+/// how they are parsed.
+pub fn locate(args: &[OsString]) -> Option<PathBuf> {
+    option_value(args, "config")
+        .map(PathBuf::from)
+        .or_else(|| Some(PathBuf::from(CONFIG_FILE)).filter(|path| path.is_file()))
+}
+
+/// The profile named by `--profile` or `WALLACE_PROFILE`
+pub fn profile(args: &[OsString]) -> Option<String> {
+    option_value(args, "profile")
+}
+
+// Value of a long option, as `--id VALUE` or `--id=VALUE`, else from its environment variable
+fn option_value(args: &[OsString], id: &str) -> Option<String> {
+    let name = format!("--{}", id);
+    let name = name.as_str();
+    let mut args = args.iter().map(|arg| arg.to_string_lossy());
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        }
+        if arg == name {
+            return args.next().map(|value| value.into_owned());
+        }
+        if let Some(value) = arg.strip_prefix(name).and_then(|v| v.strip_prefix('=')) {
+            return Some(value.to_string());
+        }
+    }
+    env::var(env_name(id))
+        .ok()
+        .filter(|value| !value.is_empty())
+}
@@ -0,0 +1,214 @@
+// encode/input.rs
+// Reads messages back from our own NDJSON and CSV exports so they can be re-encoded, or exported
+// and analysed again without the original log.
+
+use super::log_types_by_name;
+use crate::errors::{Result, WallaceError};
+use crate::export::columns::METADATA_FIELDS;
+use crate::messages::registry::MessageRegistry;
+use crate::parser::ParsedMessage;
+use crate::report::is_report_csv;
+use crate::utils::unescape_formula;
+use serde_json::value::RawValue;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+/// True if `path` holds exports rather than a binary log: a directory, or a CSV or NDJSON file
+pub fn is_export(path: &Path) -> bool {
+    path.is_dir()
+        || matches!(
+            path.extension().and_then(|e| e.to_str()),
+            Some("csv" | "ndjson" | "jsonl")
+        )
+}
+
+/// Reads the messages of an export file or directory, in log order as far as it can be restored:
+/// by record index when the exports have `--metadata` columns, else by timestamp for files split
+/// per type. A single NDJSON file is already in log order.
+pub fn read_exports(path: &Path, registry: &MessageRegistry) -> Result<Vec<ParsedMessage>> {
+    let mut messages = Vec::new();
+    let mut split = path.is_dir();
+    for file in export_files(path)? {
+        if file.extension().and_then(|e| e.to_str()) == Some("csv") {
+            split = true;
+            messages.extend(read_csv(&file, registry)?);
+        } else {
+            messages.extend(read_ndjson(BufReader::new(File::open(&file)?), registry)?);
+        }
+    }
+    // Records are never at offset 0, which is the file header, so 0 means no metadata
+    if messages.iter().all(|msg| msg.offset > 0) {
+        messages.sort_by_key(|msg| msg.index);
+    } else if split {
+        messages.sort_by_key(|msg| msg.timestamp_us().unwrap_or(0));
+    }
+    Ok(messages)
+}
+
+/// Reads `--format ndjson` output. Each line needs a `log_type` or a `message` name
+/// known to the registry; every other key is taken as a field value.
+pub fn read_ndjson<R: BufRead>(
+    reader: R,
+    registry: &MessageRegistry,
+) -> Result<Vec<ParsedMessage>> {
+    let by_name = log_types_by_name(registry);
+    let mut messages = Vec::new();
+    for (line_no, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let object = match serde_json::from_str::<Value>(&line)? {
+            Value::Object(object) => object,
+            _ => {
+                return Err(WallaceError::InvalidArgument(format!(
+                    "line {}: expected a JSON object",
+                    line_no + 1
+                )))
+            }
+        };
+        let log_type = match object.get("log_type").and_then(Value::as_u64) {
+            Some(id) => id as u16,
+            None => object
+                .get("message")
+                .and_then(Value::as_str)
+                .and_then(|name| by_name.get(name).copied())
+                .ok_or_else(|| {
+                    WallaceError::InvalidArgument(format!(
+                        "line {}: no known 'log_type' or 'message'",
+                        line_no + 1
+                    ))
+                })?,
+        };
+        // Numbers are taken as written, since parsing them would change how floats are spelled
+        let raw: BTreeMap<String, &RawValue> = serde_json::from_str(&line)?;
+        let fields = raw
+            .into_iter()
+            .filter(|(key, _)| !matches!(key.as_str(), "message" | "log_type"))
+            .map(|(key, val)| {
+                let text = match object.get(&key) {
+                    Some(Value::String(s)) => s.clone(),
+                    Some(Value::Null) => String::new(),
+                    _ => val.get().to_string(),
+                };
+                (key, text)
+            })
+            .collect();
+        messages.push(message_for(log_type, fields, registry)?);
+    }
+    Ok(messages)
+}
+
+/// Reads a `--format csv` file, whose file stem is the message name, followed by `_0001` etc. if
+/// the type was split with `--rows-per-file`. Text cells escaped against formulas are read as logged.
+pub fn read_csv(path: &Path, registry: &MessageRegistry) -> Result<Vec<ParsedMessage>> {
+    let name = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default();
+    let by_name = log_types_by_name(registry);
+    let unsplit = name
+        .rsplit_once('_')
+        .filter(|(_, part)| part.len() >= 4 && part.chars().all(|c| c.is_ascii_digit()))
+        .map_or(name, |(stem, _)| stem);
+    let log_type = by_name
+        .get(name)
+        .or_else(|| by_name.get(unsplit))
+        .copied()
+        .ok_or_else(|| {
+            WallaceError::InvalidArgument(format!(
+                "'{}': no message named '{}' in the registry",
+                path.display(),
+                name
+            ))
+        })?;
+    // Provenance comment lines may sit above the header
+    let mut reader = csv::ReaderBuilder::new()
+        .comment(Some(b'#'))
+        .from_path(path)?;
+    let headers = reader.headers()?.clone();
+    let mut messages = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let fields = headers
+            .iter()
+            .zip(record.iter())
+            .map(|(h, v)| (h.to_string(), unescape_formula(v).to_string()))
+            .collect();
+        messages.push(message_for(log_type, fields, registry)?);
+    }
+    Ok(messages)
+}
+
+fn message_for(
+    log_type: u16,
+    fields: Vec<(String, String)>,
+    registry: &MessageRegistry,
+) -> Result<ParsedMessage> {
+    let def = registry
+        .get(&log_type.to_string())
+        .ok_or(WallaceError::UnknownMessageType(log_type))?;
+    // Metadata columns from `--metadata` go back into the message, unless the definition has a
+    // field of the same name
+    let (metadata, fields): (Vec<_>, Vec<_>) = fields.into_iter().partition(|(name, _)| {
+        METADATA_FIELDS.iter().any(|(m, _)| m == name)
+            && !def.fields.iter().any(|f| &f.name == name)
+    });
+    // Back in registry order, which JSON objects do not keep; fields it lacks go last
+    let mut fields = fields;
+    fields.sort_by_key(|(name, _)| {
+        def.fields
+            .iter()
+            .position(|f| &f.name == name)
+            .unwrap_or(usize::MAX)
+    });
+    let mut msg = ParsedMessage {
+        log_type,
+        name: def.name.clone(),
+        fields,
+        ..Default::default()
+    };
+    for (name, value) in metadata {
+        match name.as_str() {
+            "record_index" => msg.index = value.parse().unwrap_or_default(),
+            "byte_offset" => msg.offset = value.parse().unwrap_or_default(),
+            "payload_length" => msg.payload_len = value.parse().unwrap_or_default(),
+            _ => {} // The timestamp is read from the fields
+        }
+    }
+    msg.timestamp = msg.timestamp_us();
+    Ok(msg)
+}
+
+/// A directory stands for the per-message CSV and NDJSON files directly inside it, without the
+/// reports
+pub fn export_files(path: &Path) -> Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files: Vec<PathBuf> = fs::read_dir(path)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| {
+            matches!(
+                p.extension().and_then(|e| e.to_str()),
+                Some("csv" | "ndjson" | "jsonl")
+            )
+        })
+        .filter(|p| {
+            !p.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(is_report_csv)
+        })
+        .collect();
+    files.sort();
+    if files.is_empty() {
+        return Err(WallaceError::InvalidArgument(format!(
+            "no CSV or NDJSON files in '{}'",
+            path.display()
+        )));
+    }
+    Ok(files)
+}
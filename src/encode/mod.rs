@@ -0,0 +1,341 @@
+// encode/mod.rs
+// Inverse of the parser: turns decoded messages back into the binary log framing.
+
+use crate::errors::{Result, WallaceError};
+use crate::messages::registry::{FieldDef, MessageDef, MessageRegistry};
+use crate::parser::text::encode_string;
+use crate::parser::{FixedPoint, ParsedMessage, Scaled};
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::collections::HashMap;
+use std::io::Write;
+
+pub mod input;
+
+/// Header value of the logs we have seen in the field, used when the source header is unknown
+pub const DEFAULT_HEADER: i32 = 10;
+
+/// Writes the file header followed by length-prefixed records
+pub struct LogWriter<W: Write> {
+    writer: W,
+    records: usize,
+}
+
+impl<W: Write> LogWriter<W> {
+    pub fn new(mut writer: W, header: i32) -> Result<Self> {
+        writer.write_i32::<LittleEndian>(header)?;
+        Ok(LogWriter { writer, records: 0 })
+    }
+
+    /// Writes one record with an already-encoded payload
+    pub fn write_record(&mut self, log_type: u16, payload: &[u8]) -> Result<()> {
+        let length = u16::try_from(payload.len()).map_err(|_| {
+            WallaceError::InvalidArgument(format!(
+                "payload of {} bytes for log_type {} exceeds the 65535 byte record limit",
+                payload.len(),
+                log_type
+            ))
+        })?;
+        self.writer.write_u16::<LittleEndian>(log_type)?;
+        self.writer.write_u16::<LittleEndian>(length)?;
+        self.writer.write_all(payload)?;
+        self.records += 1;
+        Ok(())
+    }
+
+    /// Encodes a decoded message against its registry definition and writes it
+    pub fn write_message(&mut self, msg: &ParsedMessage, registry: &MessageRegistry) -> Result<()> {
+        let def = registry
+            .get(&msg.log_type.to_string())
+            .ok_or(WallaceError::UnknownMessageType(msg.log_type))?;
+        let payload =
+            encode_fields(&msg.fields, def).map_err(|reason| WallaceError::ParsingError {
+                log_type: msg.log_type,
+                name: def.name.clone(),
+                reason,
+            })?;
+        self.write_record(msg.log_type, &payload)
+    }
+
+    pub fn records_written(&self) -> usize {
+        self.records
+    }
+
+    /// Flushes and returns the underlying writer
+    pub fn finish(mut self) -> Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Builds a payload from field values in the textual form produced by `parse_fields`.
+/// Skipped padding fields (TRASH, PADDING, RESERVED by default) and fields missing from `fields` are zero-filled.
+/// A name defined more than once takes its values in order, the n-th definition the n-th value.
+pub fn encode_fields(
+    fields: &[(String, String)],
+    def: &MessageDef,
+) -> std::result::Result<Vec<u8>, String> {
+    let mut occurrences: HashMap<&str, usize> = HashMap::new();
+    let mut payload = Vec::new();
+    for field in &def.fields {
+        let value = if field.is_skipped() {
+            None
+        } else {
+            // Same (name, occurrence) matching as the typed exporters' columns
+            let occurrence = occurrences.entry(field.name.as_str()).or_insert(0);
+            let value = fields
+                .iter()
+                .filter(|(name, _)| *name == field.name)
+                .nth(*occurrence)
+                .map(|(_, val)| val.as_str());
+            *occurrence += 1;
+            value
+        };
+        encode_field(&mut payload, field, value)?;
+    }
+    Ok(payload)
+}
+
+fn encode_field(
+    out: &mut Vec<u8>,
+    field: &FieldDef,
+    value: Option<&str>,
+) -> std::result::Result<(), String> {
+    let t = field.r#type.as_str();
+    let written = match t {
+        "Q" => out.write_u64::<LittleEndian>(parse_number(field, value)?),
+        "q" => out.write_i64::<LittleEndian>(parse_number(field, value)?),
+        "I" => out.write_u32::<LittleEndian>(parse_number(field, value)?),
+        "i" => out.write_i32::<LittleEndian>(parse_number(field, value)?),
+        "H" => out.write_u16::<LittleEndian>(parse_number(field, value)?),
+        "h" => out.write_i16::<LittleEndian>(parse_number(field, value)?),
+        "B" => out.write_u8(parse_number(field, value)?),
+        "b" => out.write_i8(parse_number(field, value)?),
+        "f" => out.write_f32::<LittleEndian>(parse_number(field, value)?),
+        "d" => out.write_f64::<LittleEndian>(parse_number(field, value)?),
+        "?" => out.write_u8(match value.map(str::trim) {
+            None | Some("") | Some("false") | Some("0") => 0,
+            Some("true") | Some("1") => 1,
+            _ => return Err(invalid_value(field, value)),
+        }),
+        "c" if field.name != "FILE_CONTENTS" => {
+            let mut chars = value.unwrap_or("").chars();
+            let byte = match (chars.next(), chars.next()) {
+                (None, _) => 0,
+                (Some(c), None) => u8::try_from(c).map_err(|_| invalid_value(field, value))?,
+                _ => return Err(invalid_value(field, value)),
+            };
+            out.write_u8(byte)
+        }
+        "u128" => out.write_u128::<LittleEndian>(parse_number(field, value)?),
+        "i128" => out.write_i128::<LittleEndian>(parse_number(field, value)?),
+        s if FixedPoint::parse(s).is_some() => {
+            let fixed = FixedPoint::parse(s).unwrap(); // Checked by the guard
+            let bytes = fixed
+                .encode(parse_number(field, value)?)
+                .ok_or_else(|| invalid_value(field, value))?;
+            out.extend_from_slice(&bytes);
+            Ok(())
+        }
+        s if Scaled::parse(s).is_some() => {
+            let scaled = Scaled::parse(s).unwrap(); // Checked by the guard
+            let raw = match value.map(str::trim) {
+                None | Some("") => 0,
+                Some(v) => scaled
+                    .raw_value(v)
+                    .ok_or_else(|| invalid_value(field, value))?,
+            };
+            out.write_i32::<LittleEndian>(raw)
+        }
+        // Variable length contents run to the end of the payload
+        "c" if field.name == "FILE_CONTENTS" => {
+            let bytes = encode_string(value.unwrap_or(""), field)
+                .ok_or_else(|| invalid_value(field, value))?;
+            out.extend_from_slice(&bytes);
+            Ok(())
+        }
+        // Fixed length strings are NUL padded
+        s if s.chars().all(|c| c == 'c') || s.ends_with('s') => {
+            let len = match s.strip_suffix('s') {
+                Some(count) => count.parse::<usize>().map_err(|_| {
+                    format!("invalid string type '{}' of field '{}'", s, field.name)
+                })?,
+                None => s.len(),
+            };
+            let bytes = encode_string(value.unwrap_or(""), field)
+                .ok_or_else(|| invalid_value(field, value))?;
+            if bytes.len() > len {
+                return Err(format!(
+                    "value for field '{}' is {} bytes, longer than its {} byte slot",
+                    field.name,
+                    bytes.len(),
+                    len
+                ));
+            }
+            out.extend_from_slice(&bytes);
+            out.resize(out.len() + len - bytes.len(), 0);
+            Ok(())
+        }
+        // Byte arrays are decoded as space separated hex
+        s if s.chars().all(|c| c == 'B') || s.chars().all(|c| c == 'b') => {
+            let bytes = value
+                .unwrap_or("")
+                .split_whitespace()
+                .map(|b| u8::from_str_radix(b, 16))
+                .collect::<std::result::Result<Vec<u8>, _>>()
+                .map_err(|_| invalid_value(field, value))?;
+            if bytes.len() > s.len() {
+                return Err(invalid_value(field, value));
+            }
+            out.extend_from_slice(&bytes);
+            out.resize(out.len() + s.len() - bytes.len(), 0);
+            Ok(())
+        }
+        _ => {
+            return Err(format!(
+                "cannot encode unsupported type '{}' of field '{}'",
+                t, field.name
+            ))
+        }
+    };
+    written.map_err(|e| e.to_string())
+}
+
+// Missing numeric values encode as zero
+fn parse_number<T: std::str::FromStr + Default>(
+    field: &FieldDef,
+    value: Option<&str>,
+) -> std::result::Result<T, String> {
+    match value.map(str::trim) {
+        None | Some("") => Ok(T::default()),
+        Some(v) => v.parse().map_err(|_| invalid_value(field, value)),
+    }
+}
+
+fn invalid_value(field: &FieldDef, value: Option<&str>) -> String {
+    format!(
+        "invalid value '{}' for field '{}' ({})",
+        value.unwrap_or(""),
+        field.name,
+        field.r#type
+    )
+}
+
+/// Maps message names to their registry id, for inputs that only carry the name
+pub fn log_types_by_name(registry: &MessageRegistry) -> HashMap<&str, u16> {
+    let mut by_name = HashMap::new();
+    for (id, def) in registry {
+        if let Ok(log_type) = id.parse::<u16>() {
+            // Keep the lowest id when a name is reused, so the choice is stable
+            let entry = by_name.entry(def.name.as_str()).or_insert(log_type);
+            *entry = (*entry).min(log_type);
+        }
+    }
+    by_name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode::input::read_ndjson;
+    use crate::export::ndjson::write_ndjson;
+    use crate::parser::{extract_messages, DecodeOptions};
+
+    const REGISTRY: &str = r#"{
+        "1": {"name": "Gps", "fields": [
+            {"name": "Timestamp", "type": "Q"},
+            {"name": "Lat", "type": "i"},
+            {"name": "Alt", "type": "f"},
+            {"name": "Fix", "type": "B"},
+            {"name": "TRASH", "type": "BBB"}
+        ]},
+        "2": {"name": "Status", "fields": [
+            {"name": "Timestamp", "type": "Q"},
+            {"name": "Text", "type": "8s"},
+            {"name": "Raw", "type": "BBBB"}
+        ]}
+    }"#;
+
+    fn record(log: &mut Vec<u8>, log_type: u16, payload: &[u8]) {
+        log.extend(log_type.to_le_bytes());
+        log.extend((payload.len() as u16).to_le_bytes());
+        log.extend(payload);
+    }
+
+    // A log with a record of each type and padding left zeroed, as the encoder writes it
+    fn sample_log() -> Vec<u8> {
+        let mut log = DEFAULT_HEADER.to_le_bytes().to_vec();
+        let mut gps = 1_000_u64.to_le_bytes().to_vec();
+        gps.extend(473_977_420_i32.to_le_bytes());
+        gps.extend(512.25_f32.to_le_bytes());
+        gps.extend([3, 0, 0, 0]);
+        record(&mut log, 1, &gps);
+        let mut status = 2_000_u64.to_le_bytes().to_vec();
+        status.extend(b"armed\0\0\0");
+        status.extend([0xde, 0xad, 0xbe, 0xef]);
+        record(&mut log, 2, &status);
+        record(&mut log, 1, &[0; 20]);
+        log
+    }
+
+    fn decode(log: &[u8], registry: &MessageRegistry) -> Vec<ParsedMessage> {
+        extract_messages(&mut &log[..], registry, DecodeOptions::default())
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn ndjson_export_encodes_back_to_the_same_log() {
+        let registry: MessageRegistry = serde_json::from_str(REGISTRY).unwrap();
+        let log = sample_log();
+        let messages = decode(&log, &registry);
+        assert_eq!(messages.len(), 3);
+
+        let mut ndjson = Vec::new();
+        write_ndjson(&mut ndjson, &messages, &registry).unwrap();
+        let read_back = read_ndjson(&ndjson[..], &registry).unwrap();
+        let mut writer = LogWriter::new(Vec::new(), DEFAULT_HEADER).unwrap();
+        for msg in &read_back {
+            writer.write_message(msg, &registry).unwrap();
+        }
+        let encoded = writer.finish().unwrap();
+
+        assert_eq!(encoded, log);
+        let decoded = decode(&encoded, &registry);
+        for (before, after) in messages.iter().zip(&decoded) {
+            assert_eq!(before.log_type, after.log_type);
+            assert_eq!(before.fields, after.fields);
+        }
+    }
+
+    #[test]
+    fn repeated_field_names_keep_their_own_values() {
+        let registry: MessageRegistry = serde_json::from_str(
+            r#"{"3": {"name": "Bat", "fields": [
+                {"name": "Timestamp", "type": "Q"},
+                {"name": "Volt", "type": "H"},
+                {"name": "PADDING", "type": "B"},
+                {"name": "Volt", "type": "H"}
+            ]}}"#,
+        )
+        .unwrap();
+        let mut log = DEFAULT_HEADER.to_le_bytes().to_vec();
+        let mut bat = 5_000_u64.to_le_bytes().to_vec();
+        bat.extend(11_900_u16.to_le_bytes());
+        bat.push(0);
+        bat.extend(12_100_u16.to_le_bytes());
+        record(&mut log, 3, &bat);
+
+        let messages = decode(&log, &registry);
+        let volts: Vec<&str> = messages[0]
+            .fields
+            .iter()
+            .filter(|(name, _)| name == "Volt")
+            .map(|(_, val)| val.as_str())
+            .collect();
+        assert_eq!(volts, ["11900", "12100"]);
+
+        let mut writer = LogWriter::new(Vec::new(), DEFAULT_HEADER).unwrap();
+        writer.write_message(&messages[0], &registry).unwrap();
+        assert_eq!(writer.finish().unwrap(), log);
+    }
+}
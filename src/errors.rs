@@ -11,6 +11,9 @@ pub enum WallaceError {
     #[error("CSV Error: {0}")]
     Csv(#[from] csv::Error),
 
+    #[error("Archive Error: {0}")]
+    Zip(#[from] zip::result::ZipError),
+
     #[error("Failed to parse message type {log_type} ({name}): {reason}")]
     ParsingError {
         log_type: u16,
@@ -18,13 +21,67 @@ pub enum WallaceError {
         reason: String,
     },
 
+    /// A failure to read or decode one record of a log, with where the record starts
+    #[error(
+        "Record {index} at byte {offset} ({offset:#x}){}: {source}",
+        record_type(.log_type, .name, .source)
+    )]
+    InRecord {
+        /// Position of the record among all records of the log, from 0
+        index: u64,
+        /// Byte offset of the record in the input, counting the file header
+        offset: u64,
+        /// The record's type, if it was read before the failure
+        log_type: Option<u16>,
+        /// The message name, if the type is in the registry
+        name: Option<String>,
+        source: Box<WallaceError>,
+    },
+
+    #[error("Parse limit exceeded after {records} records ({bytes} bytes): {reason}")]
+    LimitExceeded {
+        reason: String,
+        records: u64,
+        bytes: u64,
+    },
+
     #[error("Message type {0} not found in registry")]
     UnknownMessageType(u16),
 
+    #[error("Excel Error: {0}")]
+    Xlsx(#[from] rust_xlsxwriter::XlsxError),
+
+    #[error("HTTP Error: {0}")]
+    Http(String),
+
+    #[error("Sink Error: {0}")]
+    Sink(String),
+
+    #[error("Script Error: {0}")]
+    Script(String),
+
+    #[error("External tool error: {0}")]
+    ExternalTool(String),
+
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+
+    #[error("Unsupported input: {0}")]
+    UnsupportedInput(String),
+
     #[error("Failed to convert path to string: {path:?}")]
     PathConversionError { path: std::path::PathBuf },
     // Add more specific errors as needed
 }
 
+// The type of a failed record, unless the error names it already
+fn record_type(log_type: &Option<u16>, name: &Option<String>, source: &WallaceError) -> String {
+    match (log_type, name, source) {
+        (_, _, WallaceError::ParsingError { .. }) | (None, _, _) => String::new(),
+        (Some(log_type), Some(name), _) => format!(", {} (log_type {})", name, log_type),
+        (Some(log_type), None, _) => format!(", log_type {} (not in the registry)", log_type),
+    }
+}
+
 // Define a convenient Result type
 pub type Result<T> = std::result::Result<T, WallaceError>;
@@ -0,0 +1,349 @@
+// export/avro.rs
+// Apache Avro object container files, one per message type, with the schema derived from the registry.
+// See https://avro.apache.org/docs/current/specification/ for the container and encoding layout.
+
+use super::columns::{columns_for, Column};
+use crate::errors::Result;
+use crate::messages::registry::{MessageDef, MessageRegistry};
+use crate::parser::{BytesFormat, FixedPoint, ParsedMessage, Scaled};
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use serde_json::{json, Value};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+// Records per container block
+const BLOCK_RECORDS: usize = 4096;
+
+#[derive(Clone, Copy)]
+enum AvroType {
+    Int,
+    Long,
+    Float,
+    Double,
+    Boolean,
+    Bytes,
+    Text,
+}
+
+impl AvroType {
+    fn for_registry_type(type_str: &str) -> Self {
+        match type_str {
+            "i" | "H" | "h" | "B" | "b" => AvroType::Int,
+            // Avro has no unsigned types: u32 fits a long, u64 is stored as its two's complement bits
+            "I" | "Q" | "q" => AvroType::Long,
+            "f" => AvroType::Float,
+            "d" => AvroType::Double,
+            s if FixedPoint::parse(s).is_some() || Scaled::parse(s).is_some() => AvroType::Double,
+            "?" => AvroType::Boolean,
+            s if s.len() > 1 && (s.chars().all(|c| c == 'B') || s.chars().all(|c| c == 'b')) => {
+                AvroType::Bytes
+            }
+            _ => AvroType::Text,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            AvroType::Int => "int",
+            AvroType::Long => "long",
+            AvroType::Float => "float",
+            AvroType::Double => "double",
+            AvroType::Boolean => "boolean",
+            AvroType::Bytes => "bytes",
+            AvroType::Text => "string",
+        }
+    }
+}
+
+struct AvroField {
+    column: Column,
+    avro_type: AvroType,
+}
+
+pub fn export_to_avro(
+    path: &str,
+    messages: &[ParsedMessage],
+    registry: &MessageRegistry,
+    bytes: BytesFormat,
+) -> Result<()> {
+    let Some(first) = messages.first() else {
+        return Ok(());
+    };
+    let Some(def) = registry.get(&first.log_type.to_string()) else {
+        return Ok(());
+    };
+
+    let mut writer = AvroWriter::create(Path::new(path), def, bytes)?;
+    for msg in messages {
+        writer.push(msg)?;
+    }
+    let rows = writer.finish()?;
+    println!("✅ Wrote {} rows to '{}'", rows, path);
+    Ok(())
+}
+
+/// A container file written a record at a time, in blocks of `BLOCK_RECORDS`
+pub struct AvroWriter {
+    writer: BufWriter<File>,
+    fields: Vec<AvroField>,
+    sync: [u8; 16],
+    bytes: BytesFormat,
+    block: Vec<u8>,
+    block_records: usize,
+    rows: usize,
+}
+
+impl AvroWriter {
+    /// Creates `path` and writes the header with the schema of `def`
+    pub fn create(path: &Path, def: &MessageDef, bytes: BytesFormat) -> Result<Self> {
+        let (schema, fields) = build_schema(def);
+        let schema_text = schema.to_string();
+        // Derived from the schema so identical inputs produce byte-identical files
+        let sync = sync_marker(&schema_text);
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(b"Obj\x01")?;
+        let mut header = Vec::new();
+        write_long(&mut header, 2);
+        write_bytes(&mut header, b"avro.schema");
+        write_bytes(&mut header, schema_text.as_bytes());
+        write_bytes(&mut header, b"avro.codec");
+        write_bytes(&mut header, b"deflate");
+        write_long(&mut header, 0);
+        writer.write_all(&header)?;
+        writer.write_all(&sync)?;
+        Ok(AvroWriter {
+            writer,
+            fields,
+            sync,
+            bytes,
+            block: Vec::new(),
+            block_records: 0,
+            rows: 0,
+        })
+    }
+
+    pub fn push(&mut self, msg: &ParsedMessage) -> Result<()> {
+        encode_record(&mut self.block, msg, &self.fields, self.bytes);
+        self.block_records += 1;
+        self.rows += 1;
+        if self.block_records == BLOCK_RECORDS {
+            self.write_block()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the last block and flushes the file. Returns the rows written.
+    pub fn finish(mut self) -> Result<usize> {
+        if self.block_records > 0 {
+            self.write_block()?;
+        }
+        self.writer.flush()?;
+        Ok(self.rows)
+    }
+
+    fn write_block(&mut self) -> Result<()> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&self.block)?;
+        let compressed = encoder.finish()?;
+
+        let mut block_header = Vec::new();
+        write_long(&mut block_header, self.block_records as i64);
+        write_long(&mut block_header, compressed.len() as i64);
+        self.writer.write_all(&block_header)?;
+        self.writer.write_all(&compressed)?;
+        self.writer.write_all(&self.sync)?;
+        self.block.clear();
+        self.block_records = 0;
+        Ok(())
+    }
+}
+
+/// Joins files `export_to_avro` wrote for the same message definition into one at `path`. They
+/// share the schema and the sync marker, so the header of the first is followed by the data
+/// blocks of all of them. Returns the rows written.
+pub fn merge_avro(parts: &[PathBuf], path: &Path) -> Result<usize> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    let mut rows = 0;
+    for (i, part) in parts.iter().enumerate() {
+        let data = fs::read(part)?;
+        let invalid = || {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("'{}' is not an Avro container file", part.display()),
+            )
+        };
+        let header_len = header_len(&data).ok_or_else(invalid)?;
+        if i == 0 {
+            writer.write_all(&data[..header_len])?;
+        }
+        let mut pos = header_len;
+        while pos < data.len() {
+            let count = read_long(&data, &mut pos).ok_or_else(invalid)?;
+            let size = read_long(&data, &mut pos).ok_or_else(invalid)?;
+            pos += size as usize + 16;
+            rows += count as usize;
+        }
+        writer.write_all(&data[header_len..])?;
+    }
+    writer.flush()?;
+    Ok(rows)
+}
+
+// Length of the magic, the metadata map and the sync marker at the start of a container file
+fn header_len(data: &[u8]) -> Option<usize> {
+    if !data.starts_with(b"Obj\x01") {
+        return None;
+    }
+    let mut pos = 4;
+    loop {
+        let entries = read_long(data, &mut pos)?;
+        if entries == 0 {
+            break;
+        }
+        // A negative count is followed by the size of the block in bytes
+        if entries < 0 {
+            read_long(data, &mut pos)?;
+        }
+        for _ in 0..entries.unsigned_abs() * 2 {
+            let len = read_long(data, &mut pos)?;
+            pos += usize::try_from(len).ok()?;
+        }
+    }
+    let end = pos + 16;
+    (end <= data.len()).then_some(end)
+}
+
+// Reads a zig-zag encoded variable length integer, see `write_long`
+fn read_long(data: &[u8], pos: &mut usize) -> Option<i64> {
+    let mut v = 0u64;
+    let mut shift = 0;
+    loop {
+        let b = *data.get(*pos)?;
+        *pos += 1;
+        v |= ((b & 0x7F) as u64) << shift;
+        if b < 0x80 {
+            break;
+        }
+        shift += 7;
+        if shift > 63 {
+            return None;
+        }
+    }
+    Some((v >> 1) as i64 ^ -((v & 1) as i64))
+}
+
+/// Builds the record schema for a message definition. Every field is a `["null", T]` union
+/// because truncated records may stop before the end of the definition. Units and enum
+/// labels from the registry are carried as extra field attributes.
+fn build_schema(def: &MessageDef) -> (Value, Vec<AvroField>) {
+    let mut schema_fields = Vec::new();
+    let mut fields = Vec::new();
+
+    for column in columns_for(def, avro_name) {
+        let field = &def.fields[column.field_index];
+        let avro_type = AvroType::for_registry_type(&column.type_str);
+        let mut schema_field = json!({
+            "name": column.name,
+            "type": ["null", avro_type.name()],
+            "default": null,
+            "wallace_type": column.type_str,
+        });
+        if let Some(unit) = &field.unit {
+            schema_field["unit"] = json!(unit);
+        }
+        if let Some(labels) = &field.enum_labels {
+            schema_field["enum_labels"] = json!(labels);
+        }
+        schema_fields.push(schema_field);
+        fields.push(AvroField { column, avro_type });
+    }
+
+    let schema = json!({
+        "type": "record",
+        "name": avro_name(&def.name),
+        "namespace": "wallace",
+        "fields": schema_fields,
+    });
+    (schema, fields)
+}
+
+fn encode_record(out: &mut Vec<u8>, msg: &ParsedMessage, fields: &[AvroField], bytes: BytesFormat) {
+    for field in fields {
+        let encoded = field
+            .column
+            .value(msg)
+            .filter(|val| !msg.is_filled(val))
+            .and_then(|val| encode_value(val, field.avro_type, bytes));
+        match encoded {
+            Some(bytes) => {
+                write_long(out, 1); // union branch: value
+                out.extend_from_slice(&bytes);
+            }
+            None => write_long(out, 0), // union branch: null
+        }
+    }
+}
+
+fn encode_value(val: &str, avro_type: AvroType, bytes: BytesFormat) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    match avro_type {
+        AvroType::Int => write_long(&mut out, val.parse::<i32>().ok()? as i64),
+        AvroType::Long => {
+            let v = val
+                .parse::<i64>()
+                .ok()
+                .or_else(|| val.parse::<u64>().ok().map(|v| v as i64))?;
+            write_long(&mut out, v);
+        }
+        AvroType::Float => out.extend_from_slice(&val.parse::<f32>().ok()?.to_le_bytes()),
+        AvroType::Double => out.extend_from_slice(&val.parse::<f64>().ok()?.to_le_bytes()),
+        AvroType::Boolean => out.push(val.parse::<bool>().ok()? as u8),
+        // Byte arrays are decoded as text, in the form they were asked for
+        AvroType::Bytes => write_bytes(&mut out, &bytes.parse(val)?),
+        AvroType::Text => write_bytes(&mut out, val.as_bytes()),
+    }
+    Some(out)
+}
+
+// Zig-zag encoded variable length integer
+fn write_long(out: &mut Vec<u8>, n: i64) {
+    let mut v = ((n << 1) ^ (n >> 63)) as u64;
+    while v >= 0x80 {
+        out.push((v as u8 & 0x7F) | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_long(out, bytes.len() as i64);
+    out.extend_from_slice(bytes);
+}
+
+// Avro names must match [A-Za-z_][A-Za-z0-9_]*
+fn avro_name(name: &str) -> String {
+    let mut cleaned: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if cleaned.is_empty() || cleaned.starts_with(|c: char| c.is_ascii_digit()) {
+        cleaned.insert(0, '_');
+    }
+    cleaned
+}
+
+// 16 byte marker from two FNV-1a passes over the schema text
+fn sync_marker(schema: &str) -> [u8; 16] {
+    let fnv = |seed: u64| {
+        schema.bytes().fold(seed, |hash, b| {
+            (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01B3)
+        })
+    };
+    let mut marker = [0u8; 16];
+    marker[..8].copy_from_slice(&fnv(0xcbf2_9ce4_8422_2325).to_le_bytes());
+    marker[8..].copy_from_slice(&fnv(0x8422_2325_cbf2_9ce4).to_le_bytes());
+    marker
+}
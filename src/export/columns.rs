@@ -0,0 +1,386 @@
+// export/columns.rs
+// Column layout for typed exporters, derived from the registry definition of a message type,
+// the user's choice of columns and the optional record metadata columns.
+
+use crate::errors::{Result, WallaceError};
+use crate::messages::registry::{FieldDef, MessageDef, MessageRegistry};
+use crate::parser::{is_numeric_type, ParsedMessage};
+use std::collections::{BTreeMap, HashSet};
+
+pub struct Column {
+    /// Unique column name, with a numeric suffix when the registry repeats a field name
+    pub name: String,
+    /// Field name as it appears in the parsed message
+    pub source_name: String,
+    /// Which occurrence of `source_name` in the parsed fields this column reads
+    pub occurrence: usize,
+    /// Registry type code of the field
+    pub type_str: String,
+    /// Position of the field in the message definition
+    pub field_index: usize,
+}
+
+impl Column {
+    pub fn value<'a>(&self, msg: &'a ParsedMessage) -> Option<&'a str> {
+        msg.fields
+            .iter()
+            .filter(|(name, _)| *name == self.source_name)
+            .nth(self.occurrence)
+            .map(|(_, val)| val.as_str())
+    }
+}
+
+/// Builds one column per exported field of `def`, skipping the ignorable padding fields.
+/// `sanitize` maps a field name to a name valid for the target format.
+pub fn columns_for<F>(def: &MessageDef, sanitize: F) -> Vec<Column>
+where
+    F: Fn(&str) -> String,
+{
+    let mut used_names = HashSet::new();
+    let mut columns: Vec<Column> = Vec::new();
+
+    for (field_index, field) in def.fields.iter().enumerate() {
+        if field.is_skipped() {
+            continue;
+        }
+        let base = sanitize(&field.name);
+        let mut name = base.clone();
+        let mut n = 2;
+        while !used_names.insert(name.to_lowercase()) {
+            name = format!("{}_{}", base, n);
+            n += 1;
+        }
+        let occurrence = columns
+            .iter()
+            .filter(|c| c.source_name == field.name)
+            .count();
+        columns.push(Column {
+            name,
+            source_name: field.name.clone(),
+            occurrence,
+            type_str: field.r#type.clone(),
+            field_index,
+        });
+    }
+    columns
+}
+
+/// Column headers for formats that take their layout from the parsed messages rather than the
+/// registry: the union of the fields of every message in `messages`, in field order, so records
+/// whose decoding stopped early still line up. Each entry is a field name and which occurrence of
+/// that name in a message it stands for.
+pub fn union_headers(messages: &[ParsedMessage]) -> Vec<(String, usize)> {
+    let mut headers: Vec<(String, usize)> = Vec::new();
+    let mut previous: Option<&ParsedMessage> = None;
+    for msg in messages {
+        // Consecutive records of a type nearly always carry the same fields
+        if previous.is_some_and(|p| same_names(&p.fields, &msg.fields)) {
+            continue;
+        }
+        previous = Some(msg);
+        add_to_union(
+            &mut headers,
+            msg.fields.iter().map(|(name, _)| name.as_str()),
+        );
+    }
+    headers
+}
+
+/// Which of `headers` (see `union_headers`) hold numbers by the registry types of the fields of
+/// `messages`, all of one type
+pub fn numeric_columns(
+    messages: &[ParsedMessage],
+    headers: &[(String, usize)],
+    registry: &MessageRegistry,
+) -> Vec<bool> {
+    let def = messages
+        .first()
+        .and_then(|msg| registry.get(&msg.log_type.to_string()));
+    headers
+        .iter()
+        .map(|(field_name, _)| {
+            def.and_then(|d| d.fields.iter().find(|f| &f.name == field_name))
+                .map(|f| is_numeric_type(&f.r#type))
+                .unwrap_or(false)
+        })
+        .collect()
+}
+
+/// Adds the columns of one row to `headers` (see `union_headers`), each new one right after the
+/// column before it
+pub fn add_to_union<'a>(
+    headers: &mut Vec<(String, usize)>,
+    names: impl IntoIterator<Item = &'a str>,
+) {
+    let mut seen: Vec<&str> = Vec::new();
+    let mut insert_at = 0;
+    for name in names {
+        let occurrence = seen.iter().filter(|n| **n == name).count();
+        seen.push(name);
+        match headers
+            .iter()
+            .position(|(n, o)| n == name && *o == occurrence)
+        {
+            Some(pos) => insert_at = pos + 1,
+            None => {
+                headers.insert(insert_at, (name.to_string(), occurrence));
+                insert_at += 1;
+            }
+        }
+    }
+}
+
+/// The values of `msg` under `headers` (see `union_headers`), with `missing` for fields it lacks
+/// or that were lost to truncation
+pub fn row_values<'a>(
+    msg: &'a ParsedMessage,
+    headers: &[(String, usize)],
+    missing: &'a str,
+) -> Vec<&'a str> {
+    let complete = msg.fields.len() == headers.len()
+        && msg
+            .fields
+            .iter()
+            .zip(headers)
+            .all(|((n, _), (h, _))| n == h);
+    let value = |val: &'a String| {
+        if msg.is_filled(val) {
+            missing
+        } else {
+            val.as_str()
+        }
+    };
+    if complete {
+        return msg.fields.iter().map(|(_, val)| value(val)).collect();
+    }
+    headers
+        .iter()
+        .map(|(name, occurrence)| {
+            msg.fields
+                .iter()
+                .filter(|(n, _)| n == name)
+                .nth(*occurrence)
+                .map_or(missing, |(_, val)| value(val))
+        })
+        .collect()
+}
+
+/// `--columns MESSAGE=FIELD,FIELD,...`: the fields exported for a message type, in that order.
+/// Types without an entry are exported whole.
+#[derive(Debug, Clone, Default)]
+pub struct ColumnSelection(BTreeMap<String, Vec<String>>);
+
+impl ColumnSelection {
+    pub fn parse<'a>(specs: impl IntoIterator<Item = &'a str>) -> Result<Self> {
+        let mut selection = BTreeMap::new();
+        for spec in specs {
+            let invalid = || {
+                WallaceError::InvalidArgument(format!(
+                    "invalid columns '{}': expected MESSAGE=FIELD,FIELD,...",
+                    spec
+                ))
+            };
+            let (message, fields) = spec.split_once('=').ok_or_else(invalid)?;
+            let fields: Vec<String> = fields
+                .split(',')
+                .map(|f| f.trim().to_string())
+                .filter(|f| !f.is_empty())
+                .collect();
+            if message.trim().is_empty() || fields.is_empty() {
+                return Err(invalid());
+            }
+            selection.insert(message.trim().to_string(), fields);
+        }
+        Ok(ColumnSelection(selection))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Fails unless every message and field named is in the registry
+    pub fn check(&self, registry: &MessageRegistry) -> Result<()> {
+        for (message, fields) in &self.0 {
+            let def = registry
+                .values()
+                .find(|def| &def.name == message)
+                .ok_or_else(|| {
+                    WallaceError::InvalidArgument(format!(
+                        "--columns: no message '{}' in the registry",
+                        message
+                    ))
+                })?;
+            if let Some(field) = fields
+                .iter()
+                .find(|field| !def.fields.iter().any(|f| &f.name == *field))
+            {
+                return Err(WallaceError::InvalidArgument(format!(
+                    "--columns: message '{}' has no field '{}'",
+                    message, field
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// The groups with only the selected fields, in the selected order, and a registry whose
+    /// definitions list the same fields, for exporters that lay out columns from the registry
+    pub fn apply(
+        &self,
+        grouped: &BTreeMap<String, Vec<ParsedMessage>>,
+        registry: &MessageRegistry,
+    ) -> (BTreeMap<String, Vec<ParsedMessage>>, MessageRegistry) {
+        let mut grouped = grouped.clone();
+        for (message, fields) in &self.0 {
+            for msg in grouped.get_mut(message).into_iter().flatten() {
+                msg.fields = select(&msg.fields, fields, |(name, _)| name);
+            }
+        }
+        let mut registry = registry.clone();
+        for def in registry.values_mut() {
+            if let Some(fields) = self.0.get(&def.name) {
+                def.fields = select(&def.fields, fields, |f| &f.name);
+            }
+        }
+        (grouped, registry)
+    }
+}
+
+/// Columns added by `--metadata`, with the registry type they are exported as
+pub const METADATA_FIELDS: &[(&str, &str)] = &[
+    ("record_index", "Q"),
+    ("byte_offset", "Q"),
+    ("payload_length", "H"),
+    ("timestamp_us", "Q"),
+];
+
+/// Puts each record's index, byte offset, payload length and timestamp in front of its fields,
+/// and the matching fields in front of every definition. Messages without a timestamp leave it
+/// missing.
+pub fn add_metadata<'a>(
+    messages: impl IntoIterator<Item = &'a mut ParsedMessage>,
+    registry: &mut MessageRegistry,
+) {
+    for msg in messages {
+        let values = [
+            Some(msg.index.to_string()),
+            Some(msg.offset.to_string()),
+            Some(msg.payload_len.to_string()),
+            msg.timestamp.map(|ts| ts.to_string()),
+        ];
+        let metadata = METADATA_FIELDS
+            .iter()
+            .zip(values)
+            .filter_map(|((name, _), value)| Some((name.to_string(), value?)));
+        msg.fields.splice(0..0, metadata);
+    }
+    for def in registry.values_mut() {
+        let metadata = METADATA_FIELDS.iter().map(|(name, r#type)| FieldDef {
+            name: name.to_string(),
+            r#type: r#type.to_string(),
+            ..Default::default()
+        });
+        def.fields.splice(0..0, metadata);
+    }
+}
+
+/// `--tag KEY=VALUE`: constant columns put in front of every exported row, so rows from several
+/// vehicles or sessions stay attributable once merged
+#[derive(Debug, Clone, Default)]
+pub struct Tags(Vec<(String, String)>);
+
+impl Tags {
+    pub fn parse<'a>(specs: impl IntoIterator<Item = &'a str>) -> Result<Self> {
+        let mut tags = Tags::default();
+        for spec in specs {
+            let (key, value) = spec
+                .split_once('=')
+                .map(|(key, value)| (key.trim(), value.trim()))
+                .filter(|(key, _)| !key.is_empty())
+                .ok_or_else(|| {
+                    WallaceError::InvalidArgument(format!(
+                        "invalid tag '{}': expected KEY=VALUE",
+                        spec
+                    ))
+                })?;
+            tags.push(key, value)?;
+        }
+        Ok(tags)
+    }
+
+    /// Adds a tag after the others; fails if one has its key already
+    pub fn push(&mut self, key: &str, value: &str) -> Result<()> {
+        if self.0.iter().any(|(k, _)| k == key) {
+            return Err(WallaceError::InvalidArgument(format!(
+                "tag '{}' is given more than once",
+                key
+            )));
+        }
+        self.0.push((key.to_string(), value.to_string()));
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Fails if a tag has the name of a field or metadata column, which it would hide
+    pub fn check(&self, registry: &MessageRegistry) -> Result<()> {
+        for (key, _) in &self.0 {
+            if METADATA_FIELDS.iter().any(|(name, _)| name == key) {
+                return Err(WallaceError::InvalidArgument(format!(
+                    "tag '{}' has the name of a --metadata column",
+                    key
+                )));
+            }
+            if let Some(def) = registry
+                .values()
+                .find(|def| def.fields.iter().any(|f| &f.name == key))
+            {
+                return Err(WallaceError::InvalidArgument(format!(
+                    "tag '{}' has the name of a field of '{}'",
+                    key, def.name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Puts the tags in front of the fields of every message, and matching string fields in
+    /// front of every definition
+    pub fn apply<'a>(
+        &self,
+        messages: impl IntoIterator<Item = &'a mut ParsedMessage>,
+        registry: &mut MessageRegistry,
+    ) {
+        for msg in messages {
+            msg.fields.splice(0..0, self.0.iter().cloned());
+        }
+        for def in registry.values_mut() {
+            let tags = self.0.iter().map(|(key, value)| FieldDef {
+                name: key.clone(),
+                r#type: format!("{}s", value.len().max(1)),
+                ..Default::default()
+            });
+            def.fields.splice(0..0, tags);
+        }
+    }
+
+    pub fn to_map(&self) -> BTreeMap<String, String> {
+        self.0.iter().cloned().collect()
+    }
+}
+
+// Every item named in `names`, in that order; repeated names keep all their occurrences
+fn select<T: Clone>(items: &[T], names: &[String], name_of: impl Fn(&T) -> &String) -> Vec<T> {
+    let name_of = &name_of;
+    names
+        .iter()
+        .flat_map(|name| items.iter().filter(move |item| name_of(item) == name))
+        .cloned()
+        .collect()
+}
+
+fn same_names(a: &[(String, String)], b: &[(String, String)]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|((x, _), (y, _))| x == y)
+}
@@ -0,0 +1,248 @@
+// export/derived.rs
+// Columns computed from a field of the same message type and exported right after it: its rate
+// of change over the message timestamps, to spot spikes without another tool, and smoothed
+// versions, since raw high-rate traces are unreadable when plotted straight from the export.
+
+use crate::analysis::FieldRef;
+use crate::errors::{Result, WallaceError};
+use crate::messages::registry::{FieldDef, MessageRegistry};
+use crate::parser::ParsedMessage;
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Derivation {
+    /// Change per second since the previous message of the type
+    Rate,
+    /// Mean of the last this many values, the current one included
+    MovingAverage(usize),
+    /// First-order low-pass filter with this cutoff in Hz, over the actual time steps
+    LowPass(f64),
+}
+
+#[derive(Debug, Clone)]
+struct Derived {
+    field: FieldRef,
+    derivation: Derivation,
+}
+
+impl Derived {
+    fn name(&self) -> String {
+        match self.derivation {
+            Derivation::Rate => format!("d({})/dt", self.field.field),
+            Derivation::MovingAverage(window) => format!("{}_ma{}", self.field.field, window),
+            Derivation::LowPass(cutoff) => format!("{}_lp{}Hz", self.field.field, cutoff),
+        }
+    }
+}
+
+/// The derived columns asked for, in the order given
+#[derive(Debug, Clone, Default)]
+pub struct DerivedColumns(Vec<Derived>);
+
+impl DerivedColumns {
+    /// `--rate MESSAGE.FIELD`: a `d(FIELD)/dt` column in units per second
+    pub fn parse_rates<'a>(specs: impl IntoIterator<Item = &'a str>) -> Result<Self> {
+        let mut columns = DerivedColumns::default();
+        for spec in specs {
+            columns.push(FieldRef::parse(spec)?, Derivation::Rate)?;
+        }
+        Ok(columns)
+    }
+
+    /// `--smooth MESSAGE.FIELD:window=N` for a moving average of N values, named `FIELD_maN`, or
+    /// `MESSAGE.FIELD:cutoff=HZ` for a low-pass filter, named `FIELD_lpHZHz`
+    pub fn parse_smoothing<'a>(&mut self, specs: impl IntoIterator<Item = &'a str>) -> Result<()> {
+        for spec in specs {
+            let invalid = || {
+                WallaceError::InvalidArgument(format!(
+                    "invalid smoothing '{}': expected MESSAGE.FIELD:window=N or MESSAGE.FIELD:cutoff=HZ",
+                    spec
+                ))
+            };
+            let (field, filter) = spec.split_once(':').ok_or_else(invalid)?;
+            let derivation = match filter.trim().split_once('=').ok_or_else(invalid)? {
+                ("window", n) => match n.trim().parse::<usize>() {
+                    Ok(window) if window > 1 => Derivation::MovingAverage(window),
+                    _ => return Err(invalid()),
+                },
+                ("cutoff", hz) => match hz.trim().parse::<f64>() {
+                    Ok(cutoff) if cutoff.is_finite() && cutoff > 0.0 => Derivation::LowPass(cutoff),
+                    _ => return Err(invalid()),
+                },
+                _ => return Err(invalid()),
+            };
+            self.push(FieldRef::parse(field)?, derivation)?;
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, field: FieldRef, derivation: Derivation) -> Result<()> {
+        let derived = Derived { field, derivation };
+        if self
+            .0
+            .iter()
+            .any(|d| d.field.message == derived.field.message && d.name() == derived.name())
+        {
+            return Err(WallaceError::InvalidArgument(format!(
+                "'{}' of {} is asked for more than once",
+                derived.name(),
+                derived.field.message
+            )));
+        }
+        self.0.push(derived);
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Fails unless every source is a numeric field, or if a column would hide a field
+    pub fn check(&self, registry: &MessageRegistry) -> Result<()> {
+        for derived in &self.0 {
+            derived.field.check(registry)?;
+            let name = derived.name();
+            if registry
+                .values()
+                .filter(|def| def.name == derived.field.message)
+                .any(|def| def.fields.iter().any(|f| f.name == name))
+            {
+                return Err(WallaceError::InvalidArgument(format!(
+                    "'{}' already has a field named '{}'",
+                    derived.field.message, name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Puts a double field for every column after its source field
+    pub fn add_fields(&self, registry: &mut MessageRegistry) {
+        for (i, derived) in self.0.iter().enumerate() {
+            let earlier = self.earlier_names(i);
+            for def in registry
+                .values_mut()
+                .filter(|def| def.name == derived.field.message)
+            {
+                let Some(at) = def
+                    .fields
+                    .iter()
+                    .position(|f| f.name == derived.field.field)
+                else {
+                    continue;
+                };
+                let unit = def.fields[at]
+                    .unit
+                    .as_ref()
+                    .map(|unit| match derived.derivation {
+                        Derivation::Rate => format!("{}/s", unit),
+                        _ => unit.clone(),
+                    });
+                let at = after(&def.fields, at, |f| &f.name, &earlier);
+                def.fields.insert(
+                    at,
+                    FieldDef {
+                        name: derived.name(),
+                        r#type: "d".to_string(),
+                        unit,
+                        ..Default::default()
+                    },
+                );
+            }
+        }
+    }
+
+    /// Computes the columns in log order and puts them after their source fields. A value
+    /// that cannot be computed, e.g. the rate of the first message, is left missing. Returns
+    /// the number of values computed.
+    pub fn apply(&self, messages: &mut [ParsedMessage]) -> usize {
+        let mut computed = 0;
+        for (i, derived) in self.0.iter().enumerate() {
+            let name = derived.name();
+            let earlier = self.earlier_names(i);
+            let mut previous: Option<(u64, f64)> = None;
+            let mut window: VecDeque<f64> = VecDeque::new();
+            let mut sum = 0.0;
+            for msg in messages
+                .iter_mut()
+                .filter(|msg| msg.name == derived.field.message)
+            {
+                let Some(at) = msg
+                    .fields
+                    .iter()
+                    .position(|(name, _)| *name == derived.field.field)
+                else {
+                    continue;
+                };
+                let value = msg.fields[at]
+                    .1
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|v| v.is_finite());
+                let sample = msg.timestamp_us().zip(value);
+                let result = match derived.derivation {
+                    Derivation::Rate => match (previous, sample) {
+                        (Some((t0, v0)), Some((t1, v1))) if t1 > t0 => {
+                            Some((v1 - v0) / ((t1 - t0) as f64 / 1e6))
+                        }
+                        _ => None,
+                    },
+                    Derivation::MovingAverage(size) => value.map(|v| {
+                        window.push_back(v);
+                        sum += v;
+                        if window.len() > size {
+                            sum -= window.pop_front().unwrap(); // Longer than size
+                        }
+                        sum / window.len() as f64
+                    }),
+                    // The filtered value follows the input by the share of its time constant
+                    // that has passed since the previous sample
+                    Derivation::LowPass(cutoff) => match (previous, sample) {
+                        (Some((t0, y0)), Some((t1, v))) if t1 >= t0 => {
+                            let rc = 1.0 / (2.0 * std::f64::consts::PI * cutoff);
+                            let dt = (t1 - t0) as f64 / 1e6;
+                            Some(y0 + (v - y0) * dt / (rc + dt))
+                        }
+                        (_, Some((_, v))) => Some(v),
+                        _ => None,
+                    },
+                };
+                // The low-pass filter carries its own output forward, the rate the raw value
+                if let Some((ts, v)) = sample {
+                    previous = match derived.derivation {
+                        Derivation::LowPass(_) => Some((ts, result.unwrap_or(v))),
+                        _ => Some((ts, v)),
+                    };
+                }
+                if let Some(result) = result {
+                    let at = after(&msg.fields, at, |(name, _)| name, &earlier);
+                    msg.fields.insert(at, (name.clone(), result.to_string()));
+                    computed += 1;
+                }
+            }
+        }
+        computed
+    }
+
+    // Names of the columns of the same field that come before column `i`
+    fn earlier_names(&self, i: usize) -> Vec<String> {
+        self.0[..i]
+            .iter()
+            .filter(|d| d.field == self.0[i].field)
+            .map(Derived::name)
+            .collect()
+    }
+}
+
+// Where a column goes: after the source field at `at` and the columns of it already there, so the
+// columns of a field keep the order they were asked for in
+fn after<T>(items: &[T], at: usize, name_of: impl Fn(&T) -> &String, earlier: &[String]) -> usize {
+    let mut at = at + 1;
+    while items
+        .get(at)
+        .is_some_and(|item| earlier.contains(name_of(item)))
+    {
+        at += 1;
+    }
+    at
+}
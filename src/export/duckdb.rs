@@ -0,0 +1,125 @@
+// export/duckdb.rs
+// DuckDB database with one typed table per message type.
+// The database is built by the `duckdb` command line tool (override with WALLACE_DUCKDB),
+// loading staged CSV files written here with exactly the columns of the generated DDL.
+// With a flight to append, the database is kept and the flight is loaded in one transaction.
+
+use super::columns::{columns_for, Column};
+use super::sql::{create_table, quote_ident, quote_literal, Flight, SqlDialect};
+use crate::errors::{Result, WallaceError};
+use crate::messages::registry::MessageRegistry;
+use crate::parser::ParsedMessage;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+pub fn export_to_duckdb(
+    path: &str,
+    grouped: &BTreeMap<String, Vec<ParsedMessage>>,
+    registry: &MessageRegistry,
+    append: Option<&Flight>,
+) -> Result<()> {
+    let db_path = Path::new(path);
+    let staging_dir = db_path.with_extension("staging");
+    fs::create_dir_all(&staging_dir)?;
+
+    // Stop at the first failing statement instead of leaving a half-built database
+    let mut script = String::from(".bail on\n");
+    if let Some(flight) = append {
+        script.push_str("BEGIN TRANSACTION;\n");
+        let records = grouped.values().map(Vec::len).sum();
+        script.push_str(&flight.register(records));
+    }
+    for (name, messages) in grouped {
+        let Some(def) = messages
+            .first()
+            .and_then(|first| registry.get(&first.log_type.to_string()))
+        else {
+            continue;
+        };
+        let columns = columns_for(def, |n| n.to_string());
+        if columns.is_empty() {
+            continue; // Nothing but padding, no table to create
+        }
+        let csv_path = staging_dir.join(format!("{}.csv", name));
+        write_staging_csv(&csv_path, &columns, messages)?;
+
+        script.push_str(&create_table(name, &columns, SqlDialect::DuckDb));
+        script.push('\n');
+        script.push_str(&format!(
+            "COPY {} FROM {} (HEADER, NULLSTR '');\n",
+            quote_ident(name),
+            quote_literal(&csv_path.to_string_lossy())
+        ));
+    }
+
+    if append.is_some() {
+        script.push_str("COMMIT;\n");
+    } else if db_path.exists() {
+        // Start from an empty database so re-runs don't collide with existing tables
+        fs::remove_file(db_path)?;
+    }
+    let result = run_duckdb(db_path, &script);
+    fs::remove_dir_all(&staging_dir)?;
+    match (result, append) {
+        (Err(WallaceError::ExternalTool(e)), Some(flight)) if Flight::is_duplicate(&e) => {
+            println!(
+                "⏭️  '{}' is already in '{}', nothing appended",
+                flight.input, path
+            );
+            return Ok(());
+        }
+        (result, _) => result?,
+    }
+
+    match append {
+        Some(flight) => println!(
+            "✅ Appended '{}' to {} tables in '{}'",
+            flight.input,
+            grouped.len(),
+            path
+        ),
+        None => println!("✅ Wrote {} tables to '{}'", grouped.len(), path),
+    }
+    Ok(())
+}
+
+fn write_staging_csv(path: &Path, columns: &[Column], messages: &[ParsedMessage]) -> Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record(columns.iter().map(|c| c.name.as_str()))?;
+    for msg in messages {
+        writer.write_record(columns.iter().map(|c| c.value(msg).unwrap_or("")))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn run_duckdb(db_path: &Path, script: &str) -> Result<()> {
+    let binary = std::env::var("WALLACE_DUCKDB").unwrap_or_else(|_| "duckdb".to_string());
+    let mut child = Command::new(&binary)
+        .arg(db_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| {
+            WallaceError::ExternalTool(format!(
+                "could not run '{}' ({}); install the DuckDB CLI or set WALLACE_DUCKDB",
+                binary, e
+            ))
+        })?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(script.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(WallaceError::ExternalTool(format!(
+            "{} failed: {}",
+            binary,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
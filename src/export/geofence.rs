@@ -0,0 +1,132 @@
+// export/geofence.rs
+// `--geofilter`: whether each record was captured inside a region, from the last position fix at
+// or before it, either exported as a column or used to drop records, e.g. those logged while
+// taxiing inside the hangar.
+
+use crate::analysis::geo::{Fix, PositionSpec, Region};
+use crate::errors::{Result, WallaceError};
+use crate::messages::registry::{FieldDef, MessageRegistry};
+use crate::parser::ParsedMessage;
+use std::path::Path;
+
+/// Name of the column `mark` adds, 1 inside the region and 0 outside
+pub const GEOFENCE_FIELD: &str = "in_geofence";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeofilterMode {
+    /// Adds the `in_geofence` column to every record
+    Mark,
+    /// Keeps only the records captured inside the region
+    Inside,
+    /// Keeps only the records captured outside the region
+    Outside,
+}
+
+impl GeofilterMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "mark" => Some(GeofilterMode::Mark),
+            "inside" => Some(GeofilterMode::Inside),
+            "outside" => Some(GeofilterMode::Outside),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GeofilterStats {
+    pub inside: usize,
+    pub outside: usize,
+    /// Records without a timestamp or from before the first fix, which are always kept
+    pub unknown: usize,
+    pub dropped: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Geofilter {
+    pub region: Region,
+    pub position: PositionSpec,
+    pub mode: GeofilterMode,
+}
+
+impl Geofilter {
+    pub fn new<P: AsRef<Path>>(
+        path: P,
+        position: PositionSpec,
+        mode: GeofilterMode,
+    ) -> Result<Self> {
+        Ok(Geofilter {
+            region: Region::load(path)?,
+            position,
+            mode,
+        })
+    }
+
+    /// Fails unless the position fields are numeric, or if the column would hide a field
+    pub fn check(&self, registry: &MessageRegistry) -> Result<()> {
+        self.position.check(registry)?;
+        if self.mode == GeofilterMode::Mark {
+            if let Some(def) = registry
+                .values()
+                .find(|def| def.fields.iter().any(|f| f.name == GEOFENCE_FIELD))
+            {
+                return Err(WallaceError::InvalidArgument(format!(
+                    "'{}' already has a field named '{}'",
+                    def.name, GEOFENCE_FIELD
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Puts the column at the end of every definition when marking
+    pub fn add_fields(&self, registry: &mut MessageRegistry) {
+        if self.mode != GeofilterMode::Mark {
+            return;
+        }
+        for def in registry.values_mut() {
+            def.fields.push(FieldDef {
+                name: GEOFENCE_FIELD.to_string(),
+                r#type: "B".to_string(),
+                ..Default::default()
+            });
+        }
+    }
+
+    /// Marks or drops the records by where the vehicle was when they were captured
+    pub fn apply(&self, messages: &mut Vec<ParsedMessage>) -> GeofilterStats {
+        let fixes = self.position.fixes(messages);
+        let mut stats = GeofilterStats::default();
+        messages.retain_mut(|msg| {
+            let inside = msg
+                .timestamp_us()
+                .and_then(|ts| last_fix(&fixes, ts))
+                .map(|fix| self.region.contains(fix.lat, fix.lon));
+            match inside {
+                Some(true) => stats.inside += 1,
+                Some(false) => stats.outside += 1,
+                None => stats.unknown += 1,
+            }
+            let keep = match (self.mode, inside) {
+                (GeofilterMode::Mark, _) | (_, None) => true,
+                (GeofilterMode::Inside, Some(inside)) => inside,
+                (GeofilterMode::Outside, Some(inside)) => !inside,
+            };
+            if self.mode == GeofilterMode::Mark {
+                if let Some(inside) = inside {
+                    msg.fields
+                        .push((GEOFENCE_FIELD.to_string(), u8::from(inside).to_string()));
+                }
+            } else if !keep {
+                stats.dropped += 1;
+            }
+            keep
+        });
+        stats
+    }
+}
+
+fn last_fix(fixes: &[Fix], timestamp: u64) -> Option<&Fix> {
+    let after = fixes.partition_point(|fix| fix.timestamp <= timestamp);
+    after.checked_sub(1).map(|at| &fixes[at])
+}
@@ -0,0 +1,379 @@
+// export/influx.rs
+// InfluxDB line protocol: measurement = message name, fields = decoded fields, time = record timestamp.
+// Record timestamps count from boot, so every point time is anchored to the `--start-time` the
+// caller supplies (the UTC time the log's clock started) instead of landing in 1970. Records
+// without a timestamp are written without a time and stamped by the server.
+
+use crate::errors::{Result, WallaceError};
+use crate::messages::registry::MessageRegistry;
+use crate::net::http::{self, HttpUrl};
+use crate::parser::{FixedPoint, ParsedMessage, Scaled, TIMESTAMP_FIELDS};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+// Lines per HTTP write request, within InfluxDB's recommended batch size
+const WRITE_BATCH_LINES: usize = 5000;
+
+#[derive(Clone, Copy)]
+enum FieldKind {
+    Integer,
+    Float,
+    Boolean,
+    Text,
+}
+
+pub fn export_to_line_protocol(
+    path: &str,
+    grouped: &BTreeMap<String, Vec<ParsedMessage>>,
+    registry: &MessageRegistry,
+    start_us: u64,
+) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    let mut count = 0;
+    for_each_line(grouped, registry, start_us, |line| {
+        count += 1;
+        writeln!(writer, "{}", line)?;
+        Ok(())
+    })?;
+    writer.flush()?;
+    println!("✅ Wrote {} points to '{}'", count, path);
+    Ok(())
+}
+
+/// Posts all groups to an InfluxDB write endpoint, e.g. `http://host:8086/write?db=flights`
+/// (1.x) or `http://host:8086/api/v2/write?org=o&bucket=b` (2.x). Timestamps are sent in
+/// nanoseconds, so the URL must not override the default precision.
+pub fn write_to_influx(
+    url: &str,
+    token: Option<&str>,
+    grouped: &BTreeMap<String, Vec<ParsedMessage>>,
+    registry: &MessageRegistry,
+    start_us: u64,
+) -> Result<()> {
+    let url = HttpUrl::parse(url)?;
+    let auth = token.map(|t| format!("Token {}", t));
+    let mut headers = vec![("Content-Type", "text/plain; charset=utf-8")];
+    if let Some(auth) = &auth {
+        headers.push(("Authorization", auth.as_str()));
+    }
+
+    let mut batch = String::new();
+    let mut batch_lines = 0;
+    let mut total = 0;
+    for_each_line(grouped, registry, start_us, |line| {
+        batch.push_str(&line);
+        batch.push('\n');
+        batch_lines += 1;
+        if batch_lines == WRITE_BATCH_LINES {
+            http::post(&url, &headers, batch.as_bytes())?;
+            total += batch_lines;
+            batch.clear();
+            batch_lines = 0;
+        }
+        Ok(())
+    })?;
+    if batch_lines > 0 {
+        http::post(&url, &headers, batch.as_bytes())?;
+        total += batch_lines;
+    }
+    println!("✅ Wrote {} points to InfluxDB at {}", total, url.host);
+    Ok(())
+}
+
+// Groups are visited in name order so repeated exports produce identical files
+fn for_each_line<F>(
+    grouped: &BTreeMap<String, Vec<ParsedMessage>>,
+    registry: &MessageRegistry,
+    start_us: u64,
+    mut emit: F,
+) -> Result<()>
+where
+    F: FnMut(String) -> Result<()>,
+{
+    for messages in grouped.values() {
+        let Some(first) = messages.first() else {
+            continue;
+        };
+        let kinds = field_kinds(first, registry);
+        for msg in messages {
+            if let Some(line) = to_line(msg, &kinds, start_us) {
+                emit(line)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn field_kinds(msg: &ParsedMessage, registry: &MessageRegistry) -> HashMap<String, FieldKind> {
+    let mut kinds = HashMap::new();
+    if let Some(def) = registry.get(&msg.log_type.to_string()) {
+        for field in &def.fields {
+            let kind = match field.r#type.as_str() {
+                "Q" | "q" | "I" | "i" | "H" | "h" | "B" | "b" | "u128" | "i128" => {
+                    FieldKind::Integer
+                }
+                "f" | "d" => FieldKind::Float,
+                s if FixedPoint::parse(s).is_some() || Scaled::parse(s).is_some() => {
+                    FieldKind::Float
+                }
+                "?" => FieldKind::Boolean,
+                _ => FieldKind::Text,
+            };
+            kinds.insert(field.name.clone(), kind);
+        }
+    }
+    kinds
+}
+
+/// Builds one line protocol point, or None when no field has a representable value.
+fn to_line(
+    msg: &ParsedMessage,
+    kinds: &HashMap<String, FieldKind>,
+    start_us: u64,
+) -> Option<String> {
+    let timestamp = msg.timestamp_us();
+    let mut field_set = Vec::new();
+    for (name, val) in &msg.fields {
+        // The timestamp becomes the point time rather than a field
+        if timestamp.is_some() && TIMESTAMP_FIELDS.contains(&name.as_str()) {
+            continue;
+        }
+        // Line protocol has no null, fields lost to truncation are left out
+        if msg.is_filled(val) {
+            continue;
+        }
+        let kind = kinds.get(name).copied().unwrap_or(FieldKind::Text);
+        let value = match kind {
+            FieldKind::Integer => match val.parse::<i64>() {
+                Ok(v) => format!("{}i", v),
+                // u64 values beyond i64 range are kept as floats
+                Err(_) => match val.parse::<f64>() {
+                    Ok(v) => format!("{}", v),
+                    Err(_) => continue,
+                },
+            },
+            FieldKind::Float => match val.parse::<f64>() {
+                Ok(v) if v.is_finite() => format!("{}", v),
+                // NaN and infinities are not valid line protocol values
+                _ => continue,
+            },
+            FieldKind::Boolean => match val.parse::<bool>() {
+                Ok(v) => v.to_string(),
+                Err(_) => continue,
+            },
+            FieldKind::Text => format!("\"{}\"", escape_string(val)),
+        };
+        field_set.push(format!("{}={}", escape_key(name), value));
+    }
+    if field_set.is_empty() {
+        return None;
+    }
+
+    let mut line = format!("{} {}", escape_key(&msg.name), field_set.join(","));
+    if let Some(ts) = timestamp {
+        line.push_str(&format!(" {}", (start_us as u128 + ts as u128) * 1000));
+    }
+    Some(line)
+}
+
+/// Returns the start time given on the command line, which line protocol output cannot do without.
+pub fn require_start_time(start_us: Option<u64>) -> Result<u64> {
+    start_us.ok_or_else(|| {
+        WallaceError::InvalidArgument(
+            "InfluxDB output needs '--start-time', the UTC time the log's clock started"
+                .to_string(),
+        )
+    })
+}
+
+/// Parses a start time given either as RFC 3339 (`2024-05-01T12:30:00Z`, optionally with
+/// fractional seconds or a `+hh:mm` offset) or as Unix seconds, into microseconds since the epoch.
+pub fn parse_start_time(text: &str) -> Result<u64> {
+    let invalid = || {
+        WallaceError::InvalidArgument(format!(
+            "invalid start time '{}', expected e.g. 2024-05-01T12:30:00Z or Unix seconds",
+            text
+        ))
+    };
+    if !text.is_empty() && text.chars().all(|c| c.is_ascii_digit() || c == '.') {
+        let secs: f64 = text.parse().map_err(|_| invalid())?;
+        return Ok((secs * 1e6).round() as u64);
+    }
+
+    let num = |part: &str| {
+        if part.is_empty() || !part.chars().all(|c| c.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        part.parse::<i64>().map_err(|_| invalid())
+    };
+    let (date, time) = text.split_once(['T', 't', ' ']).ok_or_else(invalid)?;
+    let (clock, offset_secs) = match time.strip_suffix(['Z', 'z']) {
+        Some(clock) => (clock, 0),
+        None => {
+            let at = time.rfind(['+', '-']).ok_or_else(invalid)?;
+            let (clock, offset) = time.split_at(at);
+            let (hours, minutes) = offset[1..].split_once(':').ok_or_else(invalid)?;
+            let secs = num(hours)? * 3600 + num(minutes)? * 60;
+            (clock, if offset.starts_with('-') { -secs } else { secs })
+        }
+    };
+    let (whole, fraction) = clock.split_once('.').unwrap_or((clock, ""));
+
+    let date: Vec<&str> = date.split('-').collect();
+    let whole: Vec<&str> = whole.split(':').collect();
+    let ([year, month, day], [hour, minute, second]) = (&date[..], &whole[..]) else {
+        return Err(invalid());
+    };
+    let (year, month, day) = (num(year)?, num(month)?, num(day)?);
+    let (hour, minute, second) = (num(hour)?, num(minute)?, num(second)?);
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return Err(invalid());
+    }
+    // Digits beyond microsecond precision are dropped
+    let micros = if fraction.is_empty() {
+        0
+    } else {
+        num(fraction)?;
+        num(&format!("{:0<6}", &fraction[..fraction.len().min(6)]))?
+    };
+
+    let secs = days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second
+        - offset_secs;
+    if secs < 0 {
+        return Err(invalid());
+    }
+    Ok(secs as u64 * 1_000_000 + micros as u64)
+}
+
+// Days since 1970-01-01 in the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn escape_key(key: &str) -> String {
+    key.replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
+
+// Newlines would terminate the point, so they are escaped along with quotes and backslashes
+fn escape_string(val: &str) -> String {
+    val.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(fields: &[(&str, &str)]) -> ParsedMessage {
+        ParsedMessage {
+            log_type: 1,
+            name: "GPS".to_string(),
+            fields: fields
+                .iter()
+                .map(|(name, val)| (name.to_string(), val.to_string()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn kinds(fields: &[(&str, &str)]) -> HashMap<String, FieldKind> {
+        let fields: Vec<String> = fields
+            .iter()
+            .map(|(name, ty)| format!(r#"{{"name":"{}","type":"{}"}}"#, name, ty))
+            .collect();
+        let registry: MessageRegistry = serde_json::from_str(&format!(
+            r#"{{"1":{{"name":"GPS","fields":[{}]}}}}"#,
+            fields.join(",")
+        ))
+        .unwrap();
+        field_kinds(&message(&[]), &registry)
+    }
+
+    #[test]
+    fn keys_and_strings_are_escaped() {
+        let kinds = kinds(&[("Fix Type", "Z")]);
+        let mut msg = message(&[("Fix Type", "say \"hi\"\\\nbye"), ("a,b=c", "x")]);
+        msg.name = "My,Msg".to_string();
+        assert_eq!(
+            to_line(&msg, &kinds, 0).unwrap(),
+            r#"My\,Msg Fix\ Type="say \"hi\"\\\nbye",a\,b\=c="x""#
+        );
+    }
+
+    #[test]
+    fn fields_are_typed_from_the_registry() {
+        let kinds = kinds(&[
+            ("Sats", "B"),
+            ("Big", "Q"),
+            ("Alt", "f"),
+            ("Bad", "d"),
+            ("Name", "N"),
+        ]);
+        let msg = message(&[
+            ("Sats", "12"),
+            ("Big", "18446744073709551615"),
+            ("Alt", "1.5"),
+            ("Bad", "NaN"),
+            ("Name", "7"),
+            ("Unknown", "3"),
+        ]);
+        assert_eq!(
+            to_line(&msg, &kinds, 0).unwrap(),
+            r#"GPS Sats=12i,Big=18446744073709552000,Alt=1.5,Name="7",Unknown="3""#
+        );
+    }
+
+    #[test]
+    fn point_time_is_start_time_plus_log_time() {
+        let msg = message(&[("TimeUS", "2500000"), ("Sats", "9")]);
+        let kinds = kinds(&[("TimeUS", "Q"), ("Sats", "B")]);
+        let start = parse_start_time("2024-05-01T12:30:00Z").unwrap();
+        assert_eq!(
+            to_line(&msg, &kinds, start).unwrap(),
+            "GPS Sats=9i 1714566602500000000"
+        );
+        assert_eq!(
+            to_line(&message(&[("Sats", "9")]), &kinds, start).unwrap(),
+            "GPS Sats=9i"
+        );
+    }
+
+    #[test]
+    fn start_times_are_parsed_as_utc() {
+        let start = 1_714_566_600_000_000;
+        assert_eq!(parse_start_time("2024-05-01T12:30:00Z").unwrap(), start);
+        assert_eq!(
+            parse_start_time("2024-05-01T14:30:00+02:00").unwrap(),
+            start
+        );
+        assert_eq!(
+            parse_start_time("2024-05-01T12:30:00.25Z").unwrap(),
+            start + 250_000
+        );
+        assert_eq!(parse_start_time("1714566600").unwrap(), start);
+        assert_eq!(parse_start_time("1970-01-01T00:00:00Z").unwrap(), 0);
+        for bad in [
+            "",
+            "yesterday",
+            "2024-05-01",
+            "2024-13-01T00:00:00Z",
+            "2024-05-01T12:30Z",
+        ] {
+            assert!(parse_start_time(bad).is_err(), "{}", bad);
+        }
+    }
+}
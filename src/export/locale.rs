@@ -0,0 +1,130 @@
+// export/locale.rs
+// Numbers in CSV and xlsx exports the way spreadsheets set up for other locales read them: a
+// decimal comma and digit grouping, and for CSV the `;` delimiter that goes with a decimal comma.
+// Excel installs that expect a decimal comma read `1.5` as text or as a date, or drop the dot.
+
+use crate::errors::{Result, WallaceError};
+use std::borrow::Cow;
+
+/// Locale presets for `--locale`, with their decimal separator and digit grouping
+const LOCALES: &[(&str, char, Option<char>)] = &[
+    ("c", '.', None),
+    ("en", '.', Some(',')),
+    ("ch", '.', Some('\'')),
+    ("de", ',', Some('.')),
+    ("da", ',', Some('.')),
+    ("es", ',', Some('.')),
+    ("it", ',', Some('.')),
+    ("nl", ',', Some('.')),
+    ("pt", ',', Some('.')),
+    ("cs", ',', Some(NO_BREAK_SPACE)),
+    ("fi", ',', Some(NO_BREAK_SPACE)),
+    ("fr", ',', Some(NO_BREAK_SPACE)),
+    ("nb", ',', Some(NO_BREAK_SPACE)),
+    ("pl", ',', Some(NO_BREAK_SPACE)),
+    ("ru", ',', Some(NO_BREAK_SPACE)),
+    ("sv", ',', Some(NO_BREAK_SPACE)),
+];
+
+// Grouping with a plain space would let spreadsheets split the number in two
+const NO_BREAK_SPACE: char = '\u{a0}';
+
+/// How numeric values are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumberFormat {
+    pub decimal: char,
+    /// Put between groups of three integer digits, if at all
+    pub grouping: Option<char>,
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        NumberFormat {
+            decimal: '.',
+            grouping: None,
+        }
+    }
+}
+
+impl NumberFormat {
+    pub fn locale_names() -> impl Iterator<Item = &'static str> {
+        LOCALES.iter().map(|(name, _, _)| *name)
+    }
+
+    /// The format of a `--locale` preset
+    pub fn from_locale(name: &str) -> Option<Self> {
+        LOCALES
+            .iter()
+            .find(|(locale, _, _)| *locale == name)
+            .map(|&(_, decimal, grouping)| NumberFormat { decimal, grouping })
+    }
+
+    /// A separator named on the command line: the character itself, `space` or `none`
+    pub fn parse_separator(name: &str) -> Option<char> {
+        match name {
+            "none" => None,
+            "space" => Some(NO_BREAK_SPACE),
+            other => other.chars().next(),
+        }
+    }
+
+    pub fn check(&self) -> Result<()> {
+        if self.grouping == Some(self.decimal) {
+            return Err(WallaceError::InvalidArgument(format!(
+                "'{}' cannot separate both decimals and digit groups",
+                self.decimal
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn is_default(&self) -> bool {
+        *self == NumberFormat::default()
+    }
+
+    /// `;` with a decimal comma, as spreadsheets in those locales expect, else `,`
+    pub fn csv_delimiter(&self) -> u8 {
+        if self.decimal == ',' {
+            b';'
+        } else {
+            b','
+        }
+    }
+
+    /// `value` with this decimal separator and grouping if it is a plain decimal number such as
+    /// `-1234.5`; anything else, e.g. `NaN` or text, as it is
+    pub fn format<'a>(&self, value: &'a str) -> Cow<'a, str> {
+        if self.is_default() {
+            return Cow::Borrowed(value);
+        }
+        let (sign, unsigned) = match value.strip_prefix('-') {
+            Some(rest) => ("-", rest),
+            None => ("", value),
+        };
+        let (int, frac) = match unsigned.split_once('.') {
+            Some((int, frac)) => (int, Some(frac)),
+            None => (unsigned, None),
+        };
+        let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+        if !digits(int) || frac.is_some_and(|frac| !digits(frac)) {
+            return Cow::Borrowed(value);
+        }
+        let mut out = String::with_capacity(value.len() + int.len() / 3 + 1);
+        out.push_str(sign);
+        // Digits before the first separator, 1 to 3
+        let mut until_group = (int.len() + 2) % 3 + 1;
+        for digit in int.chars() {
+            if until_group == 0 {
+                out.extend(self.grouping);
+                until_group = 3;
+            }
+            out.push(digit);
+            until_group -= 1;
+        }
+        if let Some(frac) = frac {
+            out.push(self.decimal);
+            out.push_str(frac);
+        }
+        Cow::Owned(out)
+    }
+}
@@ -0,0 +1,294 @@
+// export/mod.rs
+// Output format selection and per-group export dispatch.
+
+pub mod avro;
+pub mod columns;
+pub mod derived;
+pub mod duckdb;
+pub mod geofence;
+pub mod influx;
+pub mod locale;
+pub mod ndjson;
+pub mod postgres;
+pub mod schema;
+pub mod spill;
+pub mod sql;
+pub mod writers;
+pub mod xlsx;
+
+use crate::errors::{Result, WallaceError};
+use crate::messages::registry::MessageRegistry;
+use crate::parser::{BytesFormat, ParsedMessage};
+use crate::report::provenance::{write_sidecar, Provenance};
+use crate::utils::export_to_csv;
+use columns::{add_metadata, ColumnSelection, Tags};
+use locale::NumberFormat;
+use sql::Flight;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Csv,
+    Ndjson,
+    Xlsx,
+    Influx,
+    Avro,
+    DuckDb,
+    Postgres,
+}
+
+impl OutputFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "csv" => Some(OutputFormat::Csv),
+            "ndjson" | "jsonl" => Some(OutputFormat::Ndjson),
+            "xlsx" => Some(OutputFormat::Xlsx),
+            "influx" => Some(OutputFormat::Influx),
+            "avro" => Some(OutputFormat::Avro),
+            "duckdb" => Some(OutputFormat::DuckDb),
+            "postgres" => Some(OutputFormat::Postgres),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Csv => "csv",
+            OutputFormat::Ndjson => "ndjson",
+            OutputFormat::Xlsx => "xlsx",
+            OutputFormat::Influx => "lp",
+            OutputFormat::Avro => "avro",
+            OutputFormat::DuckDb => "duckdb",
+            OutputFormat::Postgres => "sql",
+        }
+    }
+
+    /// Workbook-like formats put every message type into one file instead of one file per type
+    pub fn is_single_file(&self) -> bool {
+        matches!(
+            self,
+            OutputFormat::Xlsx | OutputFormat::Influx | OutputFormat::DuckDb
+        )
+    }
+
+    /// Formats written as one file per message type, which `--rows-per-file` can split further
+    pub fn splits_into_files(&self) -> bool {
+        matches!(
+            self,
+            OutputFormat::Csv | OutputFormat::Ndjson | OutputFormat::Avro
+        )
+    }
+}
+
+/// Settings shared by the exporters
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    /// Written in CSV and xlsx rows for fields a record lacks, e.g. after a truncated payload
+    pub missing_value: String,
+    /// Microseconds since the Unix epoch at which the log's clock started, anchoring line
+    /// protocol timestamps; required for `OutputFormat::Influx`
+    pub start_time: Option<u64>,
+    /// Written as a `.meta.json` sidecar next to every export file
+    pub provenance: Option<Provenance>,
+    /// Also puts the provenance in `#` comment lines above the header of CSV files
+    pub provenance_header: bool,
+    /// Writes CSV text cells that look like spreadsheet formulas as they are instead of escaping them
+    pub keep_formulas: bool,
+    /// Text form of byte-array values, for formats that store them as binary
+    pub bytes: BytesFormat,
+    /// Fields to export per message type, in order
+    pub columns: ColumnSelection,
+    /// Adds the record index, byte offset, payload length and timestamp as leading columns
+    pub metadata: bool,
+    /// Constant columns in front of every row
+    pub tags: Tags,
+    /// Per-type files written at once; 0 and 1 both write them one after the other
+    pub jobs: usize,
+    /// Splits larger message types into numbered files of this many rows, e.g. `IMU_0001.csv`
+    pub rows_per_file: Option<usize>,
+    /// Decimal separator and digit grouping of numbers in CSV and xlsx files
+    pub numbers: NumberFormat,
+    /// Adds the log to an existing DuckDB or Postgres database as this flight instead of
+    /// replacing it
+    pub append: Option<Flight>,
+}
+
+impl ExportOptions {
+    fn csv_comment(&self) -> Vec<String> {
+        match &self.provenance {
+            Some(provenance) if self.provenance_header => provenance.comment_lines(),
+            _ => Vec::new(),
+        }
+    }
+
+    // Records where `file` came from, if provenance is on
+    fn stamp(&self, file: &Path) -> Result<()> {
+        match &self.provenance {
+            Some(provenance) => write_sidecar(file, provenance),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Exports every group into `output_dir`, either as one file per message type or,
+/// for workbook formats, as a single `messages.<ext>` file.
+pub fn export_all(
+    output_dir: &Path,
+    grouped: &BTreeMap<String, Vec<ParsedMessage>>,
+    format: OutputFormat,
+    registry: &MessageRegistry,
+    options: &ExportOptions,
+) -> Result<()> {
+    if !options.columns.is_empty() || options.metadata || !options.tags.is_empty() {
+        let (mut grouped, mut registry) = options.columns.apply(grouped, registry);
+        if options.metadata {
+            add_metadata(grouped.values_mut().flatten(), &mut registry);
+        }
+        options
+            .tags
+            .apply(grouped.values_mut().flatten(), &mut registry);
+        let options = ExportOptions {
+            columns: ColumnSelection::default(),
+            metadata: false,
+            tags: Tags::default(),
+            ..options.clone()
+        };
+        return export_all(output_dir, &grouped, format, &registry, &options);
+    }
+    if format == OutputFormat::Postgres {
+        // A bundle of DDL, load script and COPY files rather than a single export file
+        postgres::export_to_postgres(output_dir, grouped, registry, options.append.as_ref())?;
+        options.stamp(&output_dir.join("schema.sql"))?;
+        for name in grouped.keys() {
+            options.stamp(&output_dir.join(format!("{}.csv", name)))?;
+        }
+        return Ok(());
+    }
+    if format.is_single_file() {
+        return export_group_set(output_dir, "messages", grouped, format, registry, options);
+    }
+    let mut files = split_files(grouped, format, options.rows_per_file);
+    if options.jobs > 1 && files.len() > 1 {
+        // Largest first, so that the longest writes start right away on threads of their own
+        files.sort_by_key(|(_, messages)| std::cmp::Reverse(messages.len()));
+        return writers::write_files(output_dir, format, registry, options, |writers| {
+            for (name, messages) in files {
+                writers.write(&name, Cow::Borrowed(messages))?;
+            }
+            Ok(())
+        });
+    }
+    for (name, messages) in files {
+        export_group(output_dir, &name, messages, format, registry, options)?;
+    }
+    Ok(())
+}
+
+// The file stem and rows of each per-type file. Types with more than `rows_per_file` rows are cut
+// into `<name>_0001`, `<name>_0002`, ..., in log order.
+fn split_files(
+    grouped: &BTreeMap<String, Vec<ParsedMessage>>,
+    format: OutputFormat,
+    rows_per_file: Option<usize>,
+) -> Vec<(String, &[ParsedMessage])> {
+    let mut files = Vec::new();
+    for (name, group) in grouped {
+        match rows_per_file {
+            Some(rows) if format.splits_into_files() && group.len() > rows => {
+                for (i, chunk) in group.chunks(rows).enumerate() {
+                    files.push((format!("{}_{:04}", name, i + 1), chunk));
+                }
+            }
+            _ => files.push((name.clone(), group.as_slice())),
+        }
+    }
+    files
+}
+
+fn export_group_set(
+    output_dir: &Path,
+    file_stem: &str,
+    grouped: &BTreeMap<String, Vec<ParsedMessage>>,
+    format: OutputFormat,
+    registry: &MessageRegistry,
+    options: &ExportOptions,
+) -> Result<()> {
+    let file_path = output_dir.join(format!("{}.{}", file_stem, format.extension()));
+    let file_path_str = file_path
+        .to_str()
+        .ok_or_else(|| WallaceError::PathConversionError {
+            path: file_path.clone(),
+        })?;
+    match format {
+        OutputFormat::Xlsx => {
+            xlsx::export_to_xlsx(
+                file_path_str,
+                grouped,
+                registry,
+                &options.missing_value,
+                options.numbers,
+            )?
+        }
+        OutputFormat::Influx => influx::export_to_line_protocol(
+            file_path_str,
+            grouped,
+            registry,
+            influx::require_start_time(options.start_time)?,
+        )?,
+        OutputFormat::DuckDb => duckdb::export_to_duckdb(
+            file_path_str,
+            grouped,
+            registry,
+            options.append.as_ref(),
+        )?,
+        // Per-type formats never reach this point
+        _ => return Ok(()),
+    }
+    options.stamp(&file_path)
+}
+
+/// Writes one message group to `<output_dir>/<name>.<ext>` in the requested format.
+pub fn export_group(
+    output_dir: &Path,
+    name: &str,
+    messages: &[ParsedMessage],
+    format: OutputFormat,
+    registry: &MessageRegistry,
+    options: &ExportOptions,
+) -> Result<()> {
+    let file_path = output_dir.join(format!("{}.{}", name, format.extension()));
+    // Handle potential path conversion error
+    let file_path_str = file_path
+        .to_str()
+        .ok_or_else(|| WallaceError::PathConversionError {
+            path: file_path.clone(),
+        })?;
+    match format {
+        OutputFormat::Csv => export_to_csv(
+            file_path_str,
+            messages,
+            registry,
+            &options.missing_value,
+            &options.csv_comment(),
+            options.keep_formulas,
+            options.numbers,
+        )?,
+        OutputFormat::Ndjson => ndjson::export_to_ndjson(file_path_str, messages, registry)?,
+        OutputFormat::Avro => {
+            avro::export_to_avro(file_path_str, messages, registry, options.bytes)?
+        }
+        OutputFormat::Postgres => {
+            let mut grouped = BTreeMap::new();
+            grouped.insert(name.to_string(), messages.to_vec());
+            return export_all(output_dir, &grouped, format, registry, options);
+        }
+        OutputFormat::Xlsx | OutputFormat::Influx | OutputFormat::DuckDb => {
+            let mut grouped = BTreeMap::new();
+            grouped.insert(name.to_string(), messages.to_vec());
+            return export_group_set(output_dir, name, &grouped, format, registry, options);
+        }
+    }
+    options.stamp(&file_path)
+}
@@ -0,0 +1,154 @@
+// export/ndjson.rs
+// Newline-delimited JSON, one object per message, suitable for streaming into jq.
+
+use crate::errors::Result;
+use crate::messages::registry::MessageRegistry;
+use crate::parser::{is_numeric_type, ParsedMessage, Scaled};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+pub fn export_to_ndjson(
+    path: &str,
+    messages: &[ParsedMessage],
+    registry: &MessageRegistry,
+) -> Result<()> {
+    if messages.is_empty() {
+        return Ok(());
+    }
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_ndjson(&mut writer, messages, registry)?;
+    writer.flush()?;
+    println!("✅ Wrote {} rows to '{}'", messages.len(), path);
+    Ok(())
+}
+
+/// Writes each message as a single JSON object line, in the order given.
+/// Numeric fields (per the registry type) are emitted as JSON numbers, booleans as JSON booleans
+/// and everything else as strings. Fields a truncated record lost are `null`.
+pub fn write_ndjson<W: Write>(
+    writer: &mut W,
+    messages: &[ParsedMessage],
+    registry: &MessageRegistry,
+) -> Result<()> {
+    let mut encoder = JsonEncoder::new(registry);
+    for msg in messages {
+        writeln!(writer, "{}", encoder.encode(msg))?;
+    }
+    Ok(())
+}
+
+/// Encodes messages as single-line JSON objects, caching the registry's numeric and boolean field
+/// names per message type so the registry is only consulted once per type.
+pub struct JsonEncoder<'a> {
+    registry: &'a MessageRegistry,
+    types: HashMap<u16, TypeFields<'a>>,
+}
+
+#[derive(Default)]
+struct TypeFields<'a> {
+    unquoted: Vec<(&'a str, JsonKind)>,
+    /// Every exported field, for the ones a truncated record lacks
+    exported: Vec<&'a str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum JsonKind {
+    /// Normalised through a JSON number
+    Number,
+    /// Already valid JSON as decoded and written as is: booleans and exact decimals
+    Literal,
+}
+
+impl<'a> JsonEncoder<'a> {
+    pub fn new(registry: &'a MessageRegistry) -> Self {
+        JsonEncoder {
+            registry,
+            types: HashMap::new(),
+        }
+    }
+
+    pub fn encode(&mut self, msg: &ParsedMessage) -> String {
+        let registry = self.registry;
+        let fields = self.types.entry(msg.log_type).or_insert_with(|| {
+            registry
+                .get(&msg.log_type.to_string())
+                .map(|def| TypeFields {
+                    unquoted: def
+                        .fields
+                        .iter()
+                        .filter_map(|f| {
+                            let t = f.r#type.as_str();
+                            if t == "?" || Scaled::parse(t).is_some() {
+                                Some((f.name.as_str(), JsonKind::Literal))
+                            } else if is_numeric_type(t) {
+                                Some((f.name.as_str(), JsonKind::Number))
+                            } else {
+                                None
+                            }
+                        })
+                        .collect(),
+                    exported: def
+                        .fields
+                        .iter()
+                        .filter(|f| !f.is_skipped())
+                        .map(|f| f.name.as_str())
+                        .collect(),
+                })
+                .unwrap_or_default()
+        });
+        let mut line = message_to_json(msg, &fields.unquoted);
+        if msg.partial.is_some() {
+            line.pop(); // The closing brace
+            for name in &fields.exported {
+                if !msg.fields.iter().any(|(field, _)| field == name) {
+                    line.push_str(&format!(",{}:null", Value::from(*name)));
+                }
+            }
+            line.push('}');
+        }
+        line
+    }
+}
+
+// Field order is preserved by building the object text directly instead of going through a map
+fn message_to_json(msg: &ParsedMessage, unquoted_fields: &[(&str, JsonKind)]) -> String {
+    let mut line = format!(
+        "{{\"message\":{},\"log_type\":{}",
+        Value::from(msg.name.as_str()),
+        msg.log_type
+    );
+    for (name, val) in &msg.fields {
+        let kind = unquoted_fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, kind)| *kind);
+        let json_val = match kind {
+            _ if msg.is_filled(val) => "null".to_string(),
+            Some(JsonKind::Literal) if !val.is_empty() => val.clone(),
+            Some(JsonKind::Number) => {
+                // Integers beyond 64 bits are written verbatim, going through a JSON value would round them
+                let wide_integer = val.parse::<i64>().is_err()
+                    && val.parse::<u64>().is_err()
+                    && (val.parse::<i128>().is_ok() || val.parse::<u128>().is_ok());
+                if wide_integer {
+                    val.clone()
+                } else {
+                    // Non-finite floats have no JSON representation, keep those as strings
+                    val.parse::<f64>()
+                        .ok()
+                        .filter(|v| v.is_finite())
+                        .and_then(|_| serde_json::from_str::<Value>(val).ok())
+                        .unwrap_or_else(|| Value::from(val.as_str()))
+                        .to_string()
+                }
+            }
+            _ => Value::from(val.as_str()).to_string(),
+        };
+        line.push_str(&format!(",{}:{}", Value::from(name.as_str()), json_val));
+    }
+    line.push('}');
+    line
+}
@@ -0,0 +1,126 @@
+// export/postgres.rs
+// PostgreSQL bulk-load bundle: `schema.sql` (DDL), one COPY-compatible CSV per message type,
+// and `load.sql` with the matching psql `\copy` commands. With a connection URL the bundle is
+// loaded right away through `psql` (override with WALLACE_PSQL). A bundle for a flight to
+// append starts `schema.sql` by recording the flight, and is loaded in one transaction.
+
+use super::columns::{columns_for, Column};
+use super::sql::{create_table, quote_ident, quote_literal, Flight, SqlDialect};
+use crate::errors::{Result, WallaceError};
+use crate::messages::registry::MessageRegistry;
+use crate::parser::{is_numeric_type, ParsedMessage};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+pub fn export_to_postgres(
+    output_dir: &Path,
+    grouped: &BTreeMap<String, Vec<ParsedMessage>>,
+    registry: &MessageRegistry,
+    append: Option<&Flight>,
+) -> Result<()> {
+    let mut schema = String::new();
+    if let Some(flight) = append {
+        schema.push_str(
+            "-- Load with 'psql --single-transaction' so a flight loaded before is not\n",
+        );
+        schema.push_str("-- loaded again\n");
+        let records = grouped.values().map(Vec::len).sum();
+        schema.push_str(&flight.register(records));
+        schema.push('\n');
+    }
+    let mut load = String::new();
+    for (name, messages) in grouped {
+        let Some(def) = messages
+            .first()
+            .and_then(|first| registry.get(&first.log_type.to_string()))
+        else {
+            continue;
+        };
+        let columns = columns_for(def, |n| n.to_string());
+        if columns.is_empty() {
+            continue; // Nothing but padding, no table to create
+        }
+        let csv_name = format!("{}.csv", name);
+        write_copy_csv(&output_dir.join(&csv_name), &columns, messages)?;
+
+        schema.push_str(&create_table(name, &columns, SqlDialect::Postgres));
+        schema.push_str("\n\n");
+        let column_list: Vec<String> = columns.iter().map(|c| quote_ident(&c.name)).collect();
+        load.push_str(&format!(
+            "\\copy {} ({}) FROM {} WITH (FORMAT csv, HEADER true, NULL '')\n",
+            quote_ident(name),
+            column_list.join(", "),
+            quote_literal(&csv_name)
+        ));
+    }
+
+    fs::write(output_dir.join("schema.sql"), schema)?;
+    fs::write(output_dir.join("load.sql"), load)?;
+    println!(
+        "✅ Wrote Postgres schema and {} COPY files to '{}'",
+        grouped.len(),
+        output_dir.display()
+    );
+    Ok(())
+}
+
+/// Runs `schema.sql` and `load.sql` from `output_dir` against the database at `url`, in one
+/// transaction for a flight to append, which is skipped if it was loaded before.
+pub fn load_into_postgres(output_dir: &Path, url: &str, append: Option<&Flight>) -> Result<()> {
+    let binary = std::env::var("WALLACE_PSQL").unwrap_or_else(|_| "psql".to_string());
+    let mut command = Command::new(&binary);
+    command
+        .current_dir(output_dir) // `\copy` paths in load.sql are relative
+        .args(["--quiet", "-v", "ON_ERROR_STOP=1", "-d", url]);
+    if append.is_some() {
+        command.arg("--single-transaction");
+    }
+    let output = command
+        .args(["-f", "schema.sql", "-f", "load.sql"])
+        .output()
+        .map_err(|e| {
+            WallaceError::ExternalTool(format!(
+                "could not run '{}' ({}); install the PostgreSQL client or set WALLACE_PSQL",
+                binary, e
+            ))
+        })?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if let Some(flight) =
+        append.filter(|_| !output.status.success() && Flight::is_duplicate(&stderr))
+    {
+        println!(
+            "⏭️  '{}' is already in Postgres, nothing appended",
+            flight.input
+        );
+        return Ok(());
+    }
+    if !output.status.success() {
+        return Err(WallaceError::ExternalTool(format!(
+            "{} failed: {}",
+            binary,
+            stderr.trim()
+        )));
+    }
+    println!("✅ Loaded '{}' into Postgres", output_dir.display());
+    Ok(())
+}
+
+fn write_copy_csv(path: &Path, columns: &[Column], messages: &[ParsedMessage]) -> Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record(columns.iter().map(|c| c.name.as_str()))?;
+    for msg in messages {
+        writer.write_record(columns.iter().map(|c| {
+            let val = c.value(msg).unwrap_or("");
+            if is_numeric_type(&c.type_str) {
+                val.to_string()
+            } else {
+                // Postgres text cannot hold NUL bytes
+                val.replace('\0', "")
+            }
+        }))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
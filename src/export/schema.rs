@@ -0,0 +1,172 @@
+// export/schema.rs
+// Schemas of the exports derived from the registry alone, for setting up tables and validators
+// before any log is parsed: JSON Schema for NDJSON records, Arrow schemas in the JSON form of
+// the Arrow integration format, and SQL DDL like the database exporters run.
+
+use super::columns::{columns_for, Column};
+use super::sql::{create_table, SqlDialect};
+use crate::messages::registry::{MessageDef, MessageRegistry};
+use crate::parser::{FixedPoint, Scaled};
+use serde_json::{json, Map, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaKind {
+    JsonSchema,
+    Arrow,
+    Sql(SqlDialect),
+}
+
+/// The schema of every message type in `registry`, in log_type order
+pub fn emit(registry: &MessageRegistry, kind: SchemaKind) -> String {
+    let mut defs: Vec<(u16, &MessageDef)> = registry
+        .iter()
+        .filter_map(|(log_type, def)| Some((log_type.parse().ok()?, def)))
+        .collect();
+    defs.sort_by_key(|(log_type, _)| *log_type);
+    match kind {
+        SchemaKind::JsonSchema => pretty(&json_schema(&defs)),
+        SchemaKind::Arrow => pretty(&arrow_schemas(&defs)),
+        SchemaKind::Sql(dialect) => defs
+            .iter()
+            .map(|(_, def)| (def, columns_for(def, |n| n.to_string())))
+            .filter(|(_, columns)| !columns.is_empty())
+            .map(|(def, columns)| format!("{}\n", create_table(&def.name, &columns, dialect)))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
+
+fn pretty(value: &Value) -> String {
+    format!(
+        "{}\n",
+        serde_json::to_string_pretty(value).expect("a JSON value always serializes")
+    )
+}
+
+// One definition per message type, matched on the `message` and `log_type` keys every NDJSON
+// record starts with. Fields are not required, since decoding can stop early.
+fn json_schema(defs: &[(u16, &MessageDef)]) -> Value {
+    let mut definitions = Map::new();
+    for (log_type, def) in defs {
+        let mut properties = Map::new();
+        properties.insert("message".to_string(), json!({ "const": def.name }));
+        properties.insert("log_type".to_string(), json!({ "const": log_type }));
+        for field in def.fields.iter().filter(|f| !f.is_skipped()) {
+            let mut property = json_type(&field.r#type);
+            if let Some(unit) = &field.unit {
+                property["description"] = json!(format!("in {}", unit));
+            }
+            properties.entry(field.name.clone()).or_insert(property);
+        }
+        definitions.insert(
+            def.name.clone(),
+            json!({
+                "type": "object",
+                "properties": properties,
+                "required": ["message", "log_type"],
+            }),
+        );
+    }
+    let variants: Vec<Value> = defs
+        .iter()
+        .map(|(_, def)| json!({ "$ref": format!("#/$defs/{}", def.name) }))
+        .collect();
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "wallace_rs NDJSON record",
+        "oneOf": variants,
+        "$defs": definitions,
+    })
+}
+
+// As `export::ndjson` writes the value
+fn json_type(type_str: &str) -> Value {
+    match type_str {
+        "?" => json!({ "type": "boolean" }),
+        "Q" => json!({ "type": "integer", "minimum": 0, "maximum": u64::MAX }),
+        "q" => json!({ "type": "integer", "minimum": i64::MIN, "maximum": i64::MAX }),
+        "I" => json!({ "type": "integer", "minimum": 0, "maximum": u32::MAX }),
+        "i" => json!({ "type": "integer", "minimum": i32::MIN, "maximum": i32::MAX }),
+        "H" => json!({ "type": "integer", "minimum": 0, "maximum": u16::MAX }),
+        "h" => json!({ "type": "integer", "minimum": i16::MIN, "maximum": i16::MAX }),
+        "B" => json!({ "type": "integer", "minimum": 0, "maximum": u8::MAX }),
+        "b" => json!({ "type": "integer", "minimum": i8::MIN, "maximum": i8::MAX }),
+        "u128" | "i128" => json!({ "type": "integer" }),
+        // NaN and the infinities have no JSON number and are written as strings
+        "f" | "d" => json!({ "type": ["number", "string"] }),
+        s if FixedPoint::parse(s).is_some() || Scaled::parse(s).is_some() => {
+            json!({ "type": "number" })
+        }
+        s if s.len() > 1 && (s.chars().all(|c| c == 'B') || s.chars().all(|c| c == 'b')) => {
+            json!({ "type": "string", "pattern": "^([0-9A-F]{2}( [0-9A-F]{2})*)?$" })
+        }
+        _ => json!({ "type": "string" }),
+    }
+}
+
+// A schema per message type, keyed by name, with the columns the typed exporters write
+fn arrow_schemas(defs: &[(u16, &MessageDef)]) -> Value {
+    let mut schemas = Map::new();
+    for (log_type, def) in defs {
+        let fields: Vec<Value> = columns_for(def, |n| n.to_string())
+            .iter()
+            .map(|column| arrow_field(def, column))
+            .collect();
+        schemas.insert(
+            def.name.clone(),
+            json!({
+                "fields": fields,
+                "metadata": [{ "key": "log_type", "value": log_type.to_string() }],
+            }),
+        );
+    }
+    Value::Object(schemas)
+}
+
+fn arrow_field(def: &MessageDef, column: &Column) -> Value {
+    let field = &def.fields[column.field_index];
+    let mut metadata = vec![json!({ "key": "wallace_type", "value": column.type_str })];
+    if let Some(unit) = &field.unit {
+        metadata.push(json!({ "key": "unit", "value": unit }));
+    }
+    json!({
+        "name": column.name,
+        "nullable": true,
+        "type": arrow_type(&column.type_str),
+        "children": [],
+        "metadata": metadata,
+    })
+}
+
+fn arrow_type(type_str: &str) -> Value {
+    let int =
+        |bits: u32, signed: bool| json!({ "name": "int", "bitWidth": bits, "isSigned": signed });
+    match type_str {
+        "Q" => int(64, false),
+        "q" => int(64, true),
+        "I" => int(32, false),
+        "i" => int(32, true),
+        "H" => int(16, false),
+        "h" => int(16, true),
+        "B" => int(8, false),
+        "b" => int(8, true),
+        "f" => json!({ "name": "floatingpoint", "precision": "SINGLE" }),
+        "d" => json!({ "name": "floatingpoint", "precision": "DOUBLE" }),
+        "?" => json!({ "name": "bool" }),
+        // 39 digits hold every 128-bit integer, beyond what a 128-bit decimal can
+        "u128" | "i128" => {
+            json!({ "name": "decimal", "precision": 39, "scale": 0, "bitWidth": 256 })
+        }
+        s if Scaled::parse(s).is_some() => {
+            let scaled = Scaled::parse(s).unwrap(); // Checked by the guard
+            json!({ "name": "decimal", "precision": 10, "scale": scaled.decimals, "bitWidth": 128 })
+        }
+        s if FixedPoint::parse(s).is_some() => {
+            json!({ "name": "floatingpoint", "precision": "DOUBLE" })
+        }
+        s if s.len() > 1 && (s.chars().all(|c| c == 'B') || s.chars().all(|c| c == 'b')) => {
+            json!({ "name": "fixedsizebinary", "byteWidth": s.len() })
+        }
+        _ => json!({ "name": "utf8" }),
+    }
+}
@@ -0,0 +1,192 @@
+// export/spill.rs
+// Per-type exports of a log too large to hold in memory: each chunk of messages is exported into
+// a spill directory next to the exports, and the chunks of every type are merged into its one
+// file at the end.
+
+use super::avro::merge_avro;
+use super::columns::add_to_union;
+use super::{export_all, ExportOptions, OutputFormat};
+use crate::errors::{Result, WallaceError};
+use crate::messages::registry::MessageRegistry;
+use crate::parser::ParsedMessage;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Created in the output directory, on the same disk as the exports rather than in a temporary
+/// directory that may live in memory
+pub const SPILL_DIR: &str = ".wallace-spill";
+
+/// The chunks exported so far. The spill directory is removed when this is dropped, merged or not.
+pub struct Spill {
+    dir: PathBuf,
+    chunks: usize,
+}
+
+impl Spill {
+    pub fn new(output_dir: &Path) -> Result<Self> {
+        let dir = output_dir.join(SPILL_DIR);
+        // Left behind by a run that was killed
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        fs::create_dir_all(&dir)?;
+        Ok(Spill { dir, chunks: 0 })
+    }
+
+    pub fn chunks(&self) -> usize {
+        self.chunks
+    }
+
+    /// Exports one chunk like `export_all` into a directory of its own. Provenance is left to the
+    /// merged files.
+    pub fn write(
+        &mut self,
+        grouped: &BTreeMap<String, Vec<ParsedMessage>>,
+        format: OutputFormat,
+        registry: &MessageRegistry,
+        options: &ExportOptions,
+    ) -> Result<()> {
+        if !format.splits_into_files() || options.rows_per_file.is_some() {
+            return Err(WallaceError::InvalidArgument(format!(
+                "cannot export .{} files in chunks",
+                format.extension()
+            )));
+        }
+        self.chunks += 1;
+        let chunk_dir = self.dir.join(format!("{:06}", self.chunks));
+        fs::create_dir(&chunk_dir)?;
+        let options = ExportOptions {
+            provenance: None,
+            provenance_header: false,
+            ..options.clone()
+        };
+        export_all(&chunk_dir, grouped, format, registry, &options)
+    }
+
+    /// Joins the chunks of every type into `<output_dir>/<name>.<ext>`
+    pub fn merge(
+        self,
+        output_dir: &Path,
+        format: OutputFormat,
+        options: &ExportOptions,
+    ) -> Result<()> {
+        // Chunk directories are numbered, so the parts of each type come in log order
+        let mut parts: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+        for chunk in 1..=self.chunks {
+            for entry in fs::read_dir(self.dir.join(format!("{:06}", chunk)))? {
+                let path = entry?.path();
+                if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+                    parts.entry(name.to_string()).or_default().push(path);
+                }
+            }
+        }
+        for (name, parts) in parts {
+            let path = output_dir.join(format!("{}.{}", name, format.extension()));
+            let rows = match format {
+                OutputFormat::Csv => merge_csv(
+                    &parts,
+                    &path,
+                    &options.csv_comment(),
+                    &options.missing_value,
+                    options.numbers.csv_delimiter(),
+                )?,
+                OutputFormat::Ndjson => merge_lines(&parts, &path)?,
+                OutputFormat::Avro => merge_avro(&parts, &path)?,
+                // Refused by `write`
+                _ => continue,
+            };
+            println!("✅ Wrote {} rows to '{}'", rows, path.display());
+            options.stamp(&path)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Rows of every part under the union of their headers, `missing_value` where a part lacks a
+/// column. Returns the rows written.
+pub(super) fn merge_csv(
+    parts: &[PathBuf],
+    path: &Path,
+    comment: &[String],
+    missing_value: &str,
+    delimiter: u8,
+) -> Result<usize> {
+    let reader = |part: &Path| {
+        csv::ReaderBuilder::new()
+            .delimiter(delimiter)
+            .from_path(part)
+    };
+    let mut headers: Vec<(String, usize)> = Vec::new();
+    let mut part_headers = Vec::new();
+    for part in parts {
+        let names: Vec<String> = reader(part)?.headers()?.iter().map(String::from).collect();
+        add_to_union(&mut headers, names.iter().map(String::as_str));
+        part_headers.push(names);
+    }
+
+    let mut file = BufWriter::new(File::create(path)?);
+    for line in comment {
+        writeln!(file, "{}", line)?;
+    }
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(file);
+    if !headers.is_empty() {
+        writer.write_record(headers.iter().map(|(name, _)| name))?;
+    }
+    let mut rows = 0;
+    for (part, names) in parts.iter().zip(&part_headers) {
+        // Where each merged column is in this part's rows
+        let positions: Vec<Option<usize>> = headers
+            .iter()
+            .map(|(name, occurrence)| {
+                names
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, n)| *n == name)
+                    .nth(*occurrence)
+                    .map(|(i, _)| i)
+            })
+            .collect();
+        for record in reader(part)?.byte_records() {
+            let record = record?;
+            for position in &positions {
+                let value = position.and_then(|i| record.get(i));
+                writer.write_field(value.unwrap_or(missing_value.as_bytes()))?;
+            }
+            writer.write_record(None::<&[u8]>)?;
+            rows += 1;
+        }
+    }
+    writer.flush()?;
+    Ok(rows)
+}
+
+// Line-based parts, joined as they are
+fn merge_lines(parts: &[PathBuf], path: &Path) -> Result<usize> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    let mut rows = 0;
+    for part in parts {
+        let mut reader = BufReader::new(File::open(part)?);
+        loop {
+            let buffer = reader.fill_buf()?;
+            if buffer.is_empty() {
+                break;
+            }
+            rows += buffer.iter().filter(|b| **b == b'\n').count();
+            writer.write_all(buffer)?;
+            let consumed = buffer.len();
+            reader.consume(consumed);
+        }
+    }
+    writer.flush()?;
+    Ok(rows)
+}
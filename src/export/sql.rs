@@ -0,0 +1,107 @@
+// export/sql.rs
+// Registry type to SQL column type mapping and DDL generation shared by the database exporters.
+
+use super::columns::Column;
+use crate::parser::FixedPoint;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SqlDialect {
+    DuckDb,
+    Postgres,
+}
+
+pub fn column_type(type_str: &str, dialect: SqlDialect) -> &'static str {
+    match dialect {
+        SqlDialect::DuckDb => match type_str {
+            "Q" => "UBIGINT",
+            "q" => "BIGINT",
+            "I" => "UINTEGER",
+            "i" => "INTEGER",
+            "H" => "USMALLINT",
+            "h" => "SMALLINT",
+            "B" => "UTINYINT",
+            "b" => "TINYINT",
+            "f" => "FLOAT",
+            "d" => "DOUBLE",
+            "?" => "BOOLEAN",
+            "lat_e7" | "lon_e7" => "DECIMAL(10, 7)",
+            "alt_mm" => "DECIMAL(10, 3)",
+            "u128" => "UHUGEINT",
+            "i128" => "HUGEINT",
+            s if FixedPoint::parse(s).is_some() => "DOUBLE",
+            _ => "VARCHAR",
+        },
+        // Postgres has no unsigned integers, so each type widens to the next signed size
+        SqlDialect::Postgres => match type_str {
+            "Q" => "NUMERIC(20, 0)",
+            "q" | "I" => "BIGINT",
+            "i" | "H" => "INTEGER",
+            "h" | "B" | "b" => "SMALLINT",
+            "f" => "REAL",
+            "d" => "DOUBLE PRECISION",
+            s if FixedPoint::parse(s).is_some() => "DOUBLE PRECISION",
+            "?" => "BOOLEAN",
+            "lat_e7" | "lon_e7" => "NUMERIC(10, 7)",
+            "alt_mm" => "NUMERIC(10, 3)",
+            "u128" | "i128" => "NUMERIC(39, 0)",
+            _ => "TEXT",
+        },
+    }
+}
+
+pub fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+pub fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+pub fn create_table(table: &str, columns: &[Column], dialect: SqlDialect) -> String {
+    let column_defs: Vec<String> = columns
+        .iter()
+        .map(|c| {
+            format!(
+                "    {} {}",
+                quote_ident(&c.name),
+                column_type(&c.type_str, dialect)
+            )
+        })
+        .collect();
+    format!(
+        "CREATE TABLE IF NOT EXISTS {} (\n{}\n);",
+        quote_ident(table),
+        column_defs.join(",\n")
+    )
+}
+
+/// Table of a fleet database with one row per log appended to it
+pub const FLIGHTS_TABLE: &str = "wallace_flights";
+/// Column of every table of a fleet database with the hash of the log the row came from
+pub const FLIGHT_COLUMN: &str = "flight_sha256";
+
+/// A log added with `--append` to the database of earlier ones
+#[derive(Debug, Clone)]
+pub struct Flight {
+    pub input: String,
+    pub sha256: String,
+}
+
+impl Flight {
+    /// Creates the flights table if needed and records the flight in it. The hash is its key, so
+    /// a flight recorded before fails the statement, and the transaction loading it with it.
+    pub fn register(&self, records: usize) -> String {
+        format!(
+            "CREATE TABLE IF NOT EXISTS {table} (\n    input_sha256 TEXT PRIMARY KEY,\n    input TEXT NOT NULL,\n    records BIGINT NOT NULL,\n    loaded_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP\n);\nINSERT INTO {table} (input_sha256, input, records) VALUES ({}, {}, {});\n",
+            quote_literal(&self.sha256),
+            quote_literal(&self.input),
+            records,
+            table = quote_ident(FLIGHTS_TABLE),
+        )
+    }
+
+    /// Whether a database tool failed on `register` because the flight was loaded before
+    pub fn is_duplicate(error: &str) -> bool {
+        error.to_lowercase().contains("duplicate key")
+    }
+}
@@ -0,0 +1,434 @@
+// export/writers.rs
+// Per-type files written on `--jobs` threads while their rows are still coming in: each file has
+// a writer of its own on one of the threads, fed over a channel, so that a log can be written out
+// as it is decoded instead of once decoding is done.
+
+use super::avro::AvroWriter;
+use super::columns::{add_to_union, numeric_columns};
+use super::ndjson::JsonEncoder;
+use super::spill::{merge_csv, SPILL_DIR};
+use super::{ExportOptions, OutputFormat};
+use crate::errors::{Result, WallaceError};
+use crate::messages::registry::MessageRegistry;
+use crate::parser::ParsedMessage;
+use crate::utils::CsvRow;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread;
+
+// Batches of rows waiting for each thread before whoever feeds the writers has to wait too
+const QUEUED_BATCHES: usize = 64;
+
+/// Hands rows to the writer threads, see `write_files`
+pub struct FileWriters<'a> {
+    senders: Vec<SyncSender<Job<'a>>>,
+    /// Rows given to each thread so far, so that a new file goes to the least busy one
+    load: Vec<usize>,
+    /// The thread and number of each file, by file stem
+    files: HashMap<String, (usize, usize)>,
+}
+
+enum Job<'a> {
+    Open {
+        file: usize,
+        name: String,
+    },
+    Rows {
+        file: usize,
+        rows: Cow<'a, [ParsedMessage]>,
+    },
+}
+
+impl<'a> FileWriters<'a> {
+    /// Queues `rows` for `<name>.<ext>`, after the ones queued for it before
+    pub fn write(&mut self, name: &str, rows: Cow<'a, [ParsedMessage]>) -> Result<()> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let (thread, file) = match self.files.get(name) {
+            Some(&found) => found,
+            None => {
+                let thread = (0..self.load.len())
+                    .min_by_key(|&t| self.load[t])
+                    .unwrap_or(0);
+                let file = self.files.len();
+                self.files.insert(name.to_string(), (thread, file));
+                let name = name.to_string();
+                self.send(thread, Job::Open { file, name })?;
+                (thread, file)
+            }
+        };
+        self.load[thread] += rows.len();
+        self.send(thread, Job::Rows { file, rows })
+    }
+
+    /// Queues every message for the file of its type, in the order given
+    pub fn write_messages(&mut self, messages: Vec<ParsedMessage>) -> Result<()> {
+        // One batch per type, so that the channels carry a few large jobs
+        let mut batches: BTreeMap<String, Vec<ParsedMessage>> = BTreeMap::new();
+        for msg in messages {
+            match batches.get_mut(msg.name.as_str()) {
+                Some(batch) => batch.push(msg),
+                None => {
+                    batches.insert(msg.name.clone(), vec![msg]);
+                }
+            }
+        }
+        for (name, batch) in batches {
+            self.write(&name, Cow::Owned(batch))?;
+        }
+        Ok(())
+    }
+
+    fn send(&self, thread: usize, job: Job<'a>) -> Result<()> {
+        // A writer only hangs up once it has failed, and its own error is reported when it is
+        // joined
+        self.senders[thread].send(job).map_err(|_| {
+            WallaceError::Io(std::io::Error::new(
+                std::io::ErrorKind::BrokenPipe,
+                "an export writer stopped",
+            ))
+        })
+    }
+}
+
+/// Writes the per-type files of `format` (csv, ndjson or avro) in `output_dir` on
+/// `options.jobs` threads, with the rows `feed` gives the writers. Each file is finished once
+/// `feed` returns.
+pub fn write_files<'a, T>(
+    output_dir: &Path,
+    format: OutputFormat,
+    registry: &MessageRegistry,
+    options: &ExportOptions,
+    feed: impl FnOnce(&mut FileWriters<'a>) -> Result<T>,
+) -> Result<T> {
+    // CSV columns can still grow after the first rows are written, see `CsvFile`
+    let parts_dir = output_dir.join(SPILL_DIR);
+    if format == OutputFormat::Csv {
+        fs::create_dir_all(&parts_dir)?;
+    }
+    let threads = options.jobs.max(1);
+    let written = thread::scope(|scope| {
+        let mut senders = Vec::with_capacity(threads);
+        let mut writers = Vec::with_capacity(threads);
+        for _ in 0..threads {
+            let (sender, receiver) = mpsc::sync_channel(QUEUED_BATCHES);
+            let parts_dir = &parts_dir;
+            senders.push(sender);
+            writers.push(scope.spawn(move || {
+                write_jobs(receiver, output_dir, parts_dir, format, registry, options)
+            }));
+        }
+        let mut files = FileWriters {
+            senders,
+            load: vec![0; threads],
+            files: HashMap::new(),
+        };
+        let fed = feed(&mut files);
+        // Hanging up tells the writers to finish their files
+        drop(files);
+        let finished = writers
+            .into_iter()
+            .try_for_each(|writer| writer.join().expect("export writer panicked"));
+        // A writer's own error explains a failed send better than the send does
+        finished.and(fed)
+    });
+    if format == OutputFormat::Csv {
+        fs::remove_dir_all(&parts_dir)?;
+    }
+    written
+}
+
+// The files of one thread, written as their rows come in and finished when the feed hangs up
+fn write_jobs(
+    receiver: Receiver<Job<'_>>,
+    output_dir: &Path,
+    parts_dir: &Path,
+    format: OutputFormat,
+    registry: &MessageRegistry,
+    options: &ExportOptions,
+) -> Result<()> {
+    let mut files: BTreeMap<usize, TypeFile> = BTreeMap::new();
+    for job in receiver {
+        match job {
+            Job::Open { file, name } => {
+                let path = output_dir.join(format!("{}.{}", name, format.extension()));
+                files.insert(
+                    file,
+                    TypeFile {
+                        name,
+                        path,
+                        rows: 0,
+                        output: None,
+                    },
+                );
+            }
+            Job::Rows { file, rows } => {
+                let file = files
+                    .get_mut(&file)
+                    .expect("files are opened before their rows");
+                file.write(&rows, parts_dir, format, registry, options)?;
+            }
+        }
+    }
+    for file in files.into_values() {
+        file.finish(options)?;
+    }
+    Ok(())
+}
+
+// One per-type file, created with its first rows
+struct TypeFile<'r> {
+    name: String,
+    path: PathBuf,
+    rows: usize,
+    output: Option<Output<'r>>,
+}
+
+enum Output<'r> {
+    Csv(Box<CsvFile>),
+    Ndjson(BufWriter<File>, JsonEncoder<'r>),
+    Avro(AvroWriter),
+    /// A type the registry lacks has no Avro schema, and is left out as `export_to_avro` does
+    Skipped,
+}
+
+impl<'r> TypeFile<'r> {
+    fn write(
+        &mut self,
+        rows: &[ParsedMessage],
+        parts_dir: &Path,
+        format: OutputFormat,
+        registry: &'r MessageRegistry,
+        options: &ExportOptions,
+    ) -> Result<()> {
+        if self.output.is_none() {
+            self.output = Some(match format {
+                OutputFormat::Csv => Output::Csv(Box::new(CsvFile::new(parts_dir, &self.name))),
+                OutputFormat::Ndjson => Output::Ndjson(
+                    BufWriter::new(File::create(&self.path)?),
+                    JsonEncoder::new(registry),
+                ),
+                OutputFormat::Avro => match registry.get(&rows[0].log_type.to_string()) {
+                    Some(def) => Output::Avro(AvroWriter::create(&self.path, def, options.bytes)?),
+                    None => Output::Skipped,
+                },
+                _ => {
+                    return Err(WallaceError::InvalidArgument(format!(
+                        ".{} files are not written per type",
+                        format.extension()
+                    )))
+                }
+            });
+        }
+        let output = self.output.as_mut().expect("created above");
+        for msg in rows {
+            match output {
+                Output::Csv(csv) => csv.write(msg, registry, options)?,
+                Output::Ndjson(writer, encoder) => writeln!(writer, "{}", encoder.encode(msg))?,
+                Output::Avro(writer) => writer.push(msg)?,
+                Output::Skipped => return Ok(()),
+            }
+        }
+        self.rows += rows.len();
+        Ok(())
+    }
+
+    fn finish(self, options: &ExportOptions) -> Result<()> {
+        match self.output {
+            Some(Output::Csv(csv)) => csv.finish(&self.path, options)?,
+            Some(Output::Ndjson(mut writer, _)) => writer.flush()?,
+            Some(Output::Avro(writer)) => {
+                writer.finish()?;
+            }
+            Some(Output::Skipped) | None => return Ok(()),
+        }
+        println!("✅ Wrote {} rows to '{}'", self.rows, self.path.display());
+        options.stamp(&self.path)
+    }
+}
+
+// A CSV file whose columns are only known once every row has been seen. Rows are written into a
+// part under the columns seen so far, a row with new ones starts another part, and the parts
+// are merged under all the columns at the end; types nearly always have a single part, which is
+// moved into place.
+struct CsvFile {
+    parts_dir: PathBuf,
+    stem: String,
+    parts: Vec<PathBuf>,
+    writer: Option<csv::Writer<BufWriter<File>>>,
+    headers: Vec<(String, usize)>,
+    numeric: Vec<bool>,
+    /// The field names of the last row, which the next nearly always shares
+    previous: Vec<String>,
+}
+
+impl CsvFile {
+    fn new(parts_dir: &Path, stem: &str) -> Self {
+        CsvFile {
+            parts_dir: parts_dir.to_path_buf(),
+            stem: stem.to_string(),
+            parts: Vec::new(),
+            writer: None,
+            headers: Vec::new(),
+            numeric: Vec::new(),
+            previous: Vec::new(),
+        }
+    }
+
+    fn write(
+        &mut self,
+        msg: &ParsedMessage,
+        registry: &MessageRegistry,
+        options: &ExportOptions,
+    ) -> Result<()> {
+        let same = self.writer.is_some()
+            && msg.fields.len() == self.previous.len()
+            && msg
+                .fields
+                .iter()
+                .zip(&self.previous)
+                .all(|((n, _), p)| n == p);
+        if !same {
+            let mut headers = self.headers.clone();
+            add_to_union(
+                &mut headers,
+                msg.fields.iter().map(|(name, _)| name.as_str()),
+            );
+            if self.writer.is_none() || headers != self.headers {
+                self.start_part(headers, msg, registry, options)?;
+            }
+            self.previous = msg.fields.iter().map(|(name, _)| name.clone()).collect();
+        }
+        // As `export_to_csv`, rows without any field are not written
+        let Some(writer) = self.writer.as_mut().filter(|_| !self.headers.is_empty()) else {
+            return Ok(());
+        };
+        let row = CsvRow {
+            headers: &self.headers,
+            numeric: &self.numeric,
+            missing_value: &options.missing_value,
+            keep_formulas: options.keep_formulas,
+            numbers: options.numbers,
+        };
+        row.write(writer, msg)
+    }
+
+    fn start_part(
+        &mut self,
+        headers: Vec<(String, usize)>,
+        msg: &ParsedMessage,
+        registry: &MessageRegistry,
+        options: &ExportOptions,
+    ) -> Result<()> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+        }
+        let path = self
+            .parts_dir
+            .join(format!("{}.{:04}.csv", self.stem, self.parts.len() + 1));
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(options.numbers.csv_delimiter())
+            .from_writer(BufWriter::new(File::create(&path)?));
+        if !headers.is_empty() {
+            writer.write_record(headers.iter().map(|(name, _)| name))?;
+        }
+        self.numeric = match options.numbers.is_default() {
+            true => Vec::new(),
+            false => numeric_columns(std::slice::from_ref(msg), &headers, registry),
+        };
+        self.headers = headers;
+        self.parts.push(path);
+        self.writer = Some(writer);
+        Ok(())
+    }
+
+    fn finish(mut self, path: &Path, options: &ExportOptions) -> Result<()> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+        }
+        let comment = options.csv_comment();
+        match self.parts.as_slice() {
+            [part] if comment.is_empty() => fs::rename(part, path)?,
+            parts => {
+                merge_csv(
+                    parts,
+                    path,
+                    &comment,
+                    &options.missing_value,
+                    options.numbers.csv_delimiter(),
+                )?;
+                for part in parts {
+                    fs::remove_file(part)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::export_to_csv;
+
+    fn message(index: u64, fields: &[(&str, &str)]) -> ParsedMessage {
+        ParsedMessage {
+            log_type: 1,
+            name: "Status".to_string(),
+            fields: fields
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            index,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn columns_added_midway_come_out_as_a_single_export() {
+        let dir = std::env::temp_dir().join(format!("wallace-writers-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let messages = vec![
+            message(0, &[("a", "1"), ("b", "2")]),
+            message(1, &[("a", "3"), ("b", "4")]),
+            message(2, &[("a", "5"), ("late", "x"), ("b", "6")]),
+            message(3, &[("a", "=7")]),
+        ];
+        let registry = MessageRegistry::new();
+        let options = ExportOptions {
+            missing_value: "NA".to_string(),
+            jobs: 2,
+            ..Default::default()
+        };
+
+        write_files(&dir, OutputFormat::Csv, &registry, &options, |writers| {
+            // Split as the decoding would hand them over
+            writers.write_messages(messages[..2].to_vec())?;
+            writers.write_messages(messages[2..].to_vec())
+        })
+        .unwrap();
+        let expected = dir.join("expected.csv");
+        let expected_path = expected.to_str().unwrap();
+        export_to_csv(
+            expected_path,
+            &messages,
+            &registry,
+            "NA",
+            &[],
+            false,
+            options.numbers,
+        )
+        .unwrap();
+
+        let written = fs::read_to_string(dir.join("Status.csv")).unwrap();
+        assert_eq!(written, fs::read_to_string(&expected).unwrap());
+        assert_eq!(written.lines().next(), Some("a,late,b"));
+        assert!(!dir.join(SPILL_DIR).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,113 @@
+// export/xlsx.rs
+// Excel workbook with one worksheet per message type.
+
+use super::columns::{numeric_columns, row_values, union_headers};
+use super::locale::NumberFormat;
+use crate::errors::Result;
+use crate::messages::registry::MessageRegistry;
+use crate::parser::ParsedMessage;
+use rust_xlsxwriter::{DocProperties, ExcelDateTime, Format, Workbook};
+use std::collections::{BTreeMap, HashSet};
+
+// Hard limits of the xlsx format
+const MAX_ROWS: usize = 1_048_576;
+const MAX_SHEET_NAME: usize = 31;
+const MAX_CELL_CHARS: usize = 32_767;
+
+/// Writes all groups into a single workbook. Sheets are ordered by message name,
+/// have a frozen bold header row, and numeric registry types are written as number cells.
+/// Excel shows those with the decimal separator of its own locale; whole numbers are grouped
+/// with its group separator if `numbers` has grouping.
+pub fn export_to_xlsx(
+    path: &str,
+    grouped: &BTreeMap<String, Vec<ParsedMessage>>,
+    registry: &MessageRegistry,
+    missing_value: &str,
+    numbers: NumberFormat,
+) -> Result<()> {
+    let mut workbook = Workbook::new();
+    // A fixed creation time instead of the current one, so the same log gives the same file
+    workbook.set_properties(
+        &DocProperties::new().set_creation_datetime(&ExcelDateTime::from_ymd(1980, 1, 1)?),
+    );
+    let header_format = Format::new().set_bold();
+    let grouped_format = Format::new().set_num_format("#,##0");
+    let mut used_names = HashSet::new();
+
+    for (name, messages) in grouped {
+        if messages.is_empty() {
+            continue;
+        }
+
+        let headers = union_headers(messages);
+        let numeric = numeric_columns(messages, &headers, registry);
+
+        // Constant memory mode streams rows to a temp file, which we can do since rows are written in order
+        let sheet = workbook.add_worksheet_with_constant_memory();
+        sheet.set_name(unique_sheet_name(name, &mut used_names))?;
+
+        for (col, (field_name, _)) in headers.iter().enumerate() {
+            sheet.write_string_with_format(0, col as u16, field_name, &header_format)?;
+        }
+        sheet.set_freeze_panes(1, 0)?;
+
+        if messages.len() >= MAX_ROWS {
+            println!(
+                "⚠️  '{}' has {} rows, only the first {} fit in a worksheet",
+                name,
+                messages.len(),
+                MAX_ROWS - 1
+            );
+        }
+
+        for (i, msg) in messages.iter().take(MAX_ROWS - 1).enumerate() {
+            let row = (i + 1) as u32;
+            for (col, val) in row_values(msg, &headers, missing_value)
+                .into_iter()
+                .enumerate()
+            {
+                let number = if numeric.get(col).copied().unwrap_or(false) {
+                    val.parse::<f64>().ok().filter(|v| v.is_finite())
+                } else {
+                    None
+                };
+                match number {
+                    Some(v) if numbers.grouping.is_some() && v.fract() == 0.0 => {
+                        sheet.write_number_with_format(row, col as u16, v, &grouped_format)?
+                    }
+                    Some(v) => sheet.write_number(row, col as u16, v)?,
+                    None => sheet.write_string(row, col as u16, truncate_cell(val))?,
+                };
+            }
+        }
+    }
+
+    workbook.save(path)?;
+    println!("✅ Wrote {} worksheets to '{}'", grouped.len(), path);
+    Ok(())
+}
+
+// Sheet names are limited to 31 characters, may not contain []:*?/\ and must be unique
+fn unique_sheet_name(name: &str, used: &mut HashSet<String>) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| if "[]:*?/\\".contains(c) { '_' } else { c })
+        .take(MAX_SHEET_NAME)
+        .collect();
+    let mut candidate = cleaned.clone();
+    let mut n = 1;
+    while !used.insert(candidate.to_lowercase()) {
+        let suffix = format!("~{}", n);
+        let keep = MAX_SHEET_NAME - suffix.len();
+        candidate = format!("{}{}", cleaned.chars().take(keep).collect::<String>(), suffix);
+        n += 1;
+    }
+    candidate
+}
+
+fn truncate_cell(val: &str) -> &str {
+    match val.char_indices().nth(MAX_CELL_CHARS) {
+        Some((idx, _)) => &val[..idx],
+        None => val,
+    }
+}
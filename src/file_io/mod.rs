@@ -1,17 +1,103 @@
 // file_io/mod.rs
-// Placeholder for file I/O utilities.
+// Opening logs for reading: files or stdin, bzip2 or gzip compressed or not, with every failure
+// reported as a `WallaceError`. Support for another compression or container format goes in
+// `sniff`, to recognise it, and in `open_file_with_jobs`, to unpack it.
+
+pub mod parallel_bz2;
+pub mod sniff;
 
 use crate::errors::Result; // Use custom Result
-use bzip2::read::BzDecoder;
-use std::fs::File;
-use std::io::Read; // Remove io import, use std::io::Read directly
-use std::path::Path;
+use crate::report::provenance::sha256_file;
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+use parallel_bz2::ParallelBzDecoder;
+use sniff::{peek, InputFormat};
+use std::fs::{self, File};
+use std::io::{self, Read}; // Remove io import, use std::io::Read directly
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+// Set once from `--cache-decompressed`, for every log the run opens
+static CACHE_DIR: OnceLock<PathBuf> = OnceLock::new();
 
+/// Makes `open_file` decompress a bzip2 log into `dir` the first time, named by the SHA-256 of
+/// the compressed file, and read that copy whenever the same file is opened again, by this run
+/// or a later one
+pub fn set_cache_dir(dir: PathBuf) {
+    let _ = CACHE_DIR.set(dir);
+}
+
+/// Opens a log for reading. `-` reads from stdin, e.g. a live feed piped in. Compressed logs are
+/// told apart by their first bytes, and so are files of other formats, which fail to open.
 pub fn open_file<P: AsRef<Path>>(path: P) -> Result<Box<dyn Read>> {
-    // Update return type
-    let file = File::open(&path)?; // io::Error automatically converted by #[from]
-    match path.as_ref().extension().and_then(|s| s.to_str()) {
-        Some("bz2") => Ok(Box::new(BzDecoder::new(file))),
-        _ => Ok(Box::new(file)),
+    open_file_with_jobs(path, 1)
+}
+
+/// Opens a log like `open_file`, decompressing bzip2 input on up to `jobs` threads
+pub fn open_file_with_jobs<P: AsRef<Path>>(path: P, jobs: usize) -> Result<Box<dyn Read>> {
+    let path = path.as_ref();
+    if path == Path::new("-") {
+        let (format, stdin) = peek(io::stdin())?;
+        return match format {
+            InputFormat::Native => Ok(Box::new(stdin)),
+            InputFormat::Bzip2 => unpacked(MultiBzDecoder::new(stdin), "stdin"),
+            InputFormat::Gzip => unpacked(MultiGzDecoder::new(stdin), "stdin"),
+            InputFormat::Unsupported { .. } => Err(format.unsupported("stdin")),
+        };
+    }
+    let name = format!("'{}'", path.display());
+    // The peeked reader is read on rather than the file opened again, which would lose the
+    // peeked bytes of a pipe or device
+    let (format, file) = peek(File::open(path)?)?; // io::Error automatically converted by #[from]
+    match format {
+        InputFormat::Native => Ok(Box::new(file)),
+        InputFormat::Bzip2 => match CACHE_DIR.get() {
+            // The copy is named by the file's hash, which takes reading the file twice
+            Some(dir) if path.is_file() => {
+                unpacked(File::open(cached_copy(path, dir, jobs)?)?, &name)
+            }
+            _ => unpacked(decompressor(file, jobs), &name),
+        },
+        InputFormat::Gzip => unpacked(MultiGzDecoder::new(file), &name),
+        InputFormat::Unsupported { .. } => Err(format.unsupported(&name)),
+    }
+}
+
+// What a compressed log decompresses to, which must be a log of ours
+fn unpacked<R: Read + 'static>(reader: R, name: &str) -> Result<Box<dyn Read>> {
+    let (format, reader) = peek(reader)?;
+    match format {
+        InputFormat::Native => Ok(Box::new(reader)),
+        _ => Err(format.unsupported(&format!("{} decompressed", name))),
+    }
+}
+
+fn decompressor<R: Read + Send + 'static>(reader: R, jobs: usize) -> Box<dyn Read> {
+    if jobs > 1 {
+        Box::new(ParallelBzDecoder::new(reader, jobs))
+    } else {
+        // Concatenated streams too, as written by pbzip2 and lbzip2
+        Box::new(MultiBzDecoder::new(reader))
+    }
+}
+
+// The decompressed copy of `path` in the cache, made first if there is none. It is written under
+// a temporary name and renamed when complete, so an interrupted run leaves no partial copy behind.
+fn cached_copy(path: &Path, dir: &Path, jobs: usize) -> Result<PathBuf> {
+    let cached = dir.join(format!("{}.dat", sha256_file(path)?));
+    if cached.is_file() {
+        return Ok(cached);
+    }
+    fs::create_dir_all(dir)?;
+    let partial = cached.with_extension(format!("dat.{}.partial", std::process::id()));
+    let written = File::create(&partial).and_then(|file| {
+        let mut file = io::BufWriter::new(file);
+        io::copy(&mut decompressor(File::open(path)?, jobs), &mut file)?;
+        io::Write::flush(&mut file)
+    });
+    if let Err(e) = written.and_then(|()| fs::rename(&partial, &cached)) {
+        let _ = fs::remove_file(&partial);
+        return Err(e.into());
     }
+    Ok(cached)
 }
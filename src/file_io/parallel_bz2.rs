@@ -0,0 +1,334 @@
+// file_io/parallel_bz2.rs
+// Parallel bzip2 decompression: the blocks of a .bz2 file are found by their bit-aligned magic
+// numbers, each is rewrapped as a stream of its own and decompressed on a thread of its own, and
+// the output is joined in order. This reads files from any compressor, not only pbzip2's. The
+// file is read as its blocks are needed, so only a round of them is held at a time.
+
+use bzip2::read::BzDecoder;
+use std::collections::VecDeque;
+use std::io::{self, Read};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::thread;
+
+const BLOCK_MAGIC: u64 = 0x3141_5926_5359;
+const END_MAGIC: u64 = 0x1772_4538_5090;
+const MAGIC_BITS: u64 = 48;
+// Compressed bytes read from the input at a time
+const READ_BYTES: usize = 1 << 20;
+
+/// Reads the decompressed log while blocks further on are decompressed on up to `jobs` threads
+pub struct ParallelBzDecoder {
+    pieces: Receiver<io::Result<Vec<u8>>>,
+    current: Vec<u8>,
+    pos: usize,
+}
+
+impl ParallelBzDecoder {
+    /// Reads the compressed file from `reader` as its blocks are needed, `jobs` blocks ahead
+    pub fn new<R: Read + Send + 'static>(reader: R, jobs: usize) -> Self {
+        // One round of pieces at most waits to be read while the next is decompressed
+        let (sender, pieces) = sync_channel(jobs);
+        thread::spawn(move || {
+            if let Err(e) = decompress_all(Blocks::new(reader), jobs, &sender) {
+                let _ = sender.send(Err(e));
+            }
+        });
+        ParallelBzDecoder {
+            pieces,
+            current: Vec::new(),
+            pos: 0,
+        }
+    }
+}
+
+impl Read for ParallelBzDecoder {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.current.len() {
+            match self.pieces.recv() {
+                Ok(piece) => {
+                    self.current = piece?;
+                    self.pos = 0;
+                }
+                // Every piece has been read
+                Err(_) => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.current.len() - self.pos);
+        buf[..n].copy_from_slice(&self.current[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+// Sends the decompressed blocks in order, `jobs` at a time. A block that does not decode may end
+// in a magic number that turned up by chance inside compressed data, so it is tried once more up
+// to the magic after that one; the error stands if it fails again.
+fn decompress_all<R: Read>(
+    mut input: Blocks<R>,
+    jobs: usize,
+    sender: &SyncSender<io::Result<Vec<u8>>>,
+) -> io::Result<()> {
+    let mut retried = false;
+    loop {
+        let round = input.next_round(jobs)?;
+        if round.is_empty() {
+            // The file ends inside a block, which fails to decompress as it would in one stream
+            if let Some((start, end)) = input.cut_block() {
+                let _ = sender.send(decompress_block(&input.data, start, end, false));
+            }
+            return Ok(());
+        }
+        let results: Vec<io::Result<Vec<u8>>> = thread::scope(|scope| {
+            let handles: Vec<_> = round
+                .iter()
+                .map(|&(start, end)| {
+                    let data = &input.data;
+                    scope.spawn(move || decompress_block(data, start, end, true))
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|_| Err(io::ErrorKind::Other.into()))
+                })
+                .collect()
+        });
+        let mut done = 0;
+        for result in results {
+            match result {
+                Ok(piece) => {
+                    retried = false;
+                    done += 1;
+                    if sender.send(Ok(piece)).is_err() {
+                        return Ok(()); // The reader is gone
+                    }
+                }
+                Err(_) if !retried => {
+                    retried = true;
+                    break;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        input.consume(done, retried && done < round.len());
+    }
+}
+
+// The compressed input read so far and not yet decompressed, with the magic numbers found in it
+struct Blocks<R> {
+    reader: R,
+    data: Vec<u8>,
+    // Byte offset of `data` in the file
+    base: u64,
+    // The last 48 bits read, to match the magic numbers against
+    window: u64,
+    // Bit offsets in the file of the magic numbers not yet passed, and whether each starts a
+    // block rather than ends a stream
+    marks: VecDeque<(u64, bool)>,
+    ended: bool,
+}
+
+impl<R: Read> Blocks<R> {
+    fn new(reader: R) -> Self {
+        Blocks {
+            reader,
+            data: Vec::new(),
+            base: 0,
+            window: 0,
+            marks: VecDeque::new(),
+            ended: false,
+        }
+    }
+
+    // Bit ranges in `data` of the next `jobs` whole blocks, reading on until there are as many
+    // or the input ends. A block runs from its magic to the magic that follows: the next block's,
+    // or the end of its stream's.
+    fn next_round(&mut self, jobs: usize) -> io::Result<Vec<(u64, u64)>> {
+        while !self.ended && self.whole_blocks() < jobs {
+            self.read_more()?;
+        }
+        let base = self.base * 8;
+        Ok(self
+            .marks
+            .iter()
+            .zip(self.marks.iter().skip(1))
+            .filter(|(start, _)| start.1)
+            .take(jobs)
+            .map(|(start, end)| (start.0 - base, end.0 - base))
+            .collect())
+    }
+
+    fn whole_blocks(&self) -> usize {
+        self.marks
+            .iter()
+            .rev()
+            .skip(1)
+            .filter(|mark| mark.1)
+            .count()
+    }
+
+    fn read_more(&mut self) -> io::Result<()> {
+        let start = self.data.len();
+        let n = (&mut self.reader)
+            .take(READ_BYTES as u64)
+            .read_to_end(&mut self.data)?;
+        self.ended = n == 0;
+        for (i, &byte) in self.data[start..].iter().enumerate() {
+            for bit in 0..8 {
+                self.window =
+                    (self.window << 1 | (byte >> (7 - bit) & 1) as u64) & ((1 << MAGIC_BITS) - 1);
+                let end = (self.base + (start + i) as u64) * 8 + bit + 1;
+                if self.window == BLOCK_MAGIC || self.window == END_MAGIC {
+                    self.marks
+                        .push_back((end - MAGIC_BITS, self.window == BLOCK_MAGIC));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Passes the first `done` blocks of the round and drops the input before the next. With
+    // `retry`, the magic ending the block after them is taken for one found by chance, so that
+    // the block runs on to the next magic.
+    fn consume(&mut self, done: usize, retry: bool) {
+        for _ in 0..done {
+            while self.marks.pop_front().is_some_and(|(_, block)| !block) {}
+        }
+        while self.marks.front().is_some_and(|(_, block)| !block) {
+            self.marks.pop_front();
+        }
+        if retry {
+            self.marks.remove(1);
+        }
+        // A magic found later may start up to 48 bits before the end of what was read
+        let keep = match self.marks.front() {
+            Some(&(bit, _)) => bit / 8,
+            None => (self.base + self.data.len() as u64)
+                .saturating_sub(MAGIC_BITS / 8)
+                .max(self.base),
+        };
+        self.data.drain(..(keep - self.base) as usize);
+        self.base = keep;
+    }
+
+    // The bit range in `data` of the block the input ends inside, if it does
+    fn cut_block(&self) -> Option<(u64, u64)> {
+        let &(start, _) = self.marks.iter().find(|(_, block)| *block)?;
+        Some((start - self.base * 8, self.data.len() as u64 * 8))
+    }
+}
+
+// A block rewrapped as a stream of its own: a header, the block, and, if it is `whole`, the
+// stream end, whose CRC for a single block is the block's own, the 32 bits after its magic
+fn decompress_block(data: &[u8], start: u64, end: u64, whole: bool) -> io::Result<Vec<u8>> {
+    let mut stream = BitWriter::default();
+    stream.bytes.extend_from_slice(b"BZh9");
+    let mut pos = start;
+    while pos < end {
+        let n = (end - pos).min(32) as u32;
+        stream.write(read_bits(data, pos, n), n);
+        pos += n as u64;
+    }
+    if whole {
+        stream.write(END_MAGIC >> 24, 24);
+        stream.write(END_MAGIC & 0xFF_FFFF, 24);
+        stream.write(read_bits(data, start + MAGIC_BITS, 32), 32);
+    }
+    let mut out = Vec::new();
+    BzDecoder::new(stream.finish().as_slice()).read_to_end(&mut out)?;
+    Ok(out)
+}
+
+// `n` bits, at most 32, from bit `pos` on, most significant first as bzip2 writes them
+fn read_bits(data: &[u8], pos: u64, n: u32) -> u64 {
+    let first = (pos / 8) as usize;
+    let shift = (pos % 8) as u32;
+    let loaded = (0..5).fold(0u64, |v, i| {
+        v << 8 | *data.get(first + i).unwrap_or(&0) as u64
+    });
+    (loaded >> (40 - shift - n)) & ((1 << n) - 1)
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    pending: u64,
+    pending_bits: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u64, n: u32) {
+        self.pending = self.pending << n | value;
+        self.pending_bits += n;
+        while self.pending_bits >= 8 {
+            self.pending_bits -= 8;
+            self.bytes.push((self.pending >> self.pending_bits) as u8);
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.pending_bits > 0 {
+            self.bytes
+                .push((self.pending << (8 - self.pending_bits)) as u8);
+        }
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bzip2::read::MultiBzDecoder;
+    use bzip2::write::BzEncoder;
+    use bzip2::Compression;
+    use std::io::Write;
+
+    // Text that compresses, long enough for several 100k blocks at level 1
+    fn log_text() -> Vec<u8> {
+        (0..60_000u32)
+            .flat_map(|i| {
+                format!("{},{},{}\n", i, i.wrapping_mul(2_654_435_761) % 977, i % 13).into_bytes()
+            })
+            .collect()
+    }
+
+    fn compressed(data: &[u8]) -> Vec<u8> {
+        let mut encoder = BzEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn parallel(compressed: Vec<u8>, jobs: usize) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        ParallelBzDecoder::new(io::Cursor::new(compressed), jobs).read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn blocks_decompress_in_order_across_streams() {
+        let text = log_text();
+        let mut file = compressed(&text);
+        file.extend(compressed(&text[..1000]));
+        let mut expected = Vec::new();
+        MultiBzDecoder::new(&file[..])
+            .read_to_end(&mut expected)
+            .unwrap();
+        assert!(expected.len() > 300_000);
+        for jobs in [2, 3] {
+            assert_eq!(parallel(file.clone(), jobs).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn input_cut_inside_a_block_fails_after_the_whole_blocks() {
+        let file = compressed(&log_text());
+        let cut = file[..file.len() * 2 / 3].to_vec();
+        let mut decoder = ParallelBzDecoder::new(io::Cursor::new(cut), 2);
+        let mut out = vec![0; 100_000];
+        decoder.read_exact(&mut out).unwrap();
+        assert_eq!(out, log_text()[..100_000]);
+        assert!(decoder.read_to_end(&mut Vec::new()).is_err());
+    }
+}
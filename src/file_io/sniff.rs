@@ -0,0 +1,163 @@
+// file_io/sniff.rs
+// What an input is, by its first bytes: a log of ours, bzip2 or gzip around one, or something
+// else handed in by mistake, such as a PX4 ULog, a MAVLink telemetry log or the registry JSON.
+// Our logs start with a plain header value and no magic number, so a log of ours is whatever is
+// not recognised as something else.
+
+use crate::errors::WallaceError;
+use std::io::{self, Cursor, Read};
+
+/// Bytes read to tell the formats apart
+const SNIFF_LEN: u64 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    /// A log of ours, as far as the first bytes tell
+    Native,
+    Bzip2,
+    Gzip,
+    /// Recognised but not read here: what it is and what to do instead
+    Unsupported {
+        what: &'static str,
+        hint: &'static str,
+    },
+}
+
+impl InputFormat {
+    pub fn sniff(head: &[u8]) -> Self {
+        let unsupported = |what, hint| InputFormat::Unsupported { what, hint };
+        let decompress_first = "decompress it first, or recompress it with bzip2 or gzip";
+        let text = head.trim_ascii_start();
+        if head.is_empty() {
+            unsupported("empty", "there is no log in it")
+        } else if head.len() >= 4 && head.starts_with(b"BZh") && head[3].is_ascii_digit() {
+            InputFormat::Bzip2
+        } else if head.starts_with(&[0x1f, 0x8b]) {
+            InputFormat::Gzip
+        } else if head.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            unsupported("zstd-compressed", decompress_first)
+        } else if head.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0]) {
+            unsupported("xz-compressed", decompress_first)
+        } else if head.starts_with(b"PK\x03\x04") {
+            unsupported("a zip archive", "extract the log from it first")
+        } else if head.starts_with(b"ULog\x01\x12\x35") {
+            unsupported(
+                "a PX4 ULog file",
+                "only logs of the format the registry describes can be read",
+            )
+        } else if head.starts_with(&[0xa3, 0x95]) {
+            unsupported(
+                "an ArduPilot DataFlash log",
+                "only logs of the format the registry describes can be read",
+            )
+        } else if head.len() > 8 && head[0] == 0 && matches!(head[8], 0xfe | 0xfd) {
+            // A big-endian microsecond timestamp, then the start of a MAVLink 1 or 2 packet
+            unsupported(
+                "a MAVLink telemetry log (tlog)",
+                "only logs of the format the registry describes can be read",
+            )
+        } else if text.starts_with(b"{") || text.starts_with(b"[") {
+            unsupported(
+                "JSON, e.g. a registry",
+                "give the registry with --registry and the binary log with --input",
+            )
+        } else if head.len() >= 8
+            && head
+                .iter()
+                .all(|b| b.is_ascii_graphic() || b.is_ascii_whitespace())
+        {
+            unsupported(
+                "text, e.g. a CSV or NDJSON export",
+                "give a directory of exports as --input to read them back, or turn them into a log with 'encode'",
+            )
+        } else {
+            InputFormat::Native
+        }
+    }
+
+    /// The error for `source`, e.g. a file name, being of this format
+    pub fn unsupported(&self, source: &str) -> WallaceError {
+        match self {
+            InputFormat::Unsupported { what, hint } => {
+                WallaceError::UnsupportedInput(format!("{} is {}; {}", source, what, hint))
+            }
+            _ => WallaceError::UnsupportedInput(format!("{} cannot be read", source)),
+        }
+    }
+}
+
+/// A reader with the bytes read from it to sniff put back in front
+pub type Peeked<R> = io::Chain<Cursor<Vec<u8>>, R>;
+
+/// The format of what `reader` reads, and a reader that still reads it from the start
+pub fn peek<R: Read>(mut reader: R) -> io::Result<(InputFormat, Peeked<R>)> {
+    let mut head = Vec::new();
+    (&mut reader).take(SNIFF_LEN).read_to_end(&mut head)?;
+    Ok((InputFormat::sniff(&head), Cursor::new(head).chain(reader)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The description of what `head` was taken for, or `None` if it can be read
+    fn unsupported(head: &[u8]) -> Option<&'static str> {
+        match InputFormat::sniff(head) {
+            InputFormat::Unsupported { what, .. } => Some(what),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn logs_and_their_compressions_are_read() {
+        // A zero header, then a record of log_type 1027 with a 12-byte payload
+        let log = [0, 0, 0, 0, 0x03, 0x04, 12, 0, 1, 2, 3, 4, 5, 6, 7, 8];
+        assert_eq!(InputFormat::sniff(&log), InputFormat::Native);
+        assert_eq!(InputFormat::sniff(b"BZh91AY&SY"), InputFormat::Bzip2);
+        assert_eq!(InputFormat::sniff(&[0x1f, 0x8b, 8, 0]), InputFormat::Gzip);
+        // Not a block size after "BZh", so not bzip2
+        assert_eq!(InputFormat::sniff(b"BZhx\0\0\0\0"), InputFormat::Native);
+    }
+
+    #[test]
+    fn other_formats_are_named() {
+        let cases: [(&[u8], &str); 10] = [
+            (b"", "empty"),
+            (&[0x28, 0xb5, 0x2f, 0xfd, 0, 0], "zstd-compressed"),
+            (&[0xfd, b'7', b'z', b'X', b'Z', 0, 0], "xz-compressed"),
+            (b"PK\x03\x04\x14\0", "a zip archive"),
+            (b"ULog\x01\x12\x35\x01\0", "a PX4 ULog file"),
+            (&[0xa3, 0x95, 0x80, 0x80], "an ArduPilot DataFlash log"),
+            (
+                &[0, 5, 0xe2, 0x1b, 0x33, 0x10, 0x40, 0x00, 0xfe, 9],
+                "a MAVLink telemetry log (tlog)",
+            ),
+            (b"  {\"1027\": {", "JSON, e.g. a registry"),
+            (b"[{\"log_type\"", "JSON, e.g. a registry"),
+            (
+                b"timestamp,roll,pitch\n",
+                "text, e.g. a CSV or NDJSON export",
+            ),
+        ];
+        for (head, what) in cases {
+            assert_eq!(unsupported(head), Some(what), "{:x?}", head);
+        }
+    }
+
+    #[test]
+    fn peeked_readers_read_from_the_start() {
+        let data: Vec<u8> = (0..100).collect();
+        let (format, mut reader) = peek(Cursor::new(data.clone())).unwrap();
+        assert_eq!(format, InputFormat::Native);
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).unwrap();
+        assert_eq!(read, data);
+
+        // Shorter than the bytes sniffed
+        let (format, mut reader) = peek(Cursor::new(b"BZh9".to_vec())).unwrap();
+        assert_eq!(format, InputFormat::Bzip2);
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).unwrap();
+        assert_eq!(read, b"BZh9");
+    }
+}
@@ -1 +0,0 @@
-// Placeholder for event or task handlers.
\ No newline at end of file
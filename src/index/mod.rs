@@ -0,0 +1,289 @@
+// index/mod.rs
+// Sidecar index of record offsets and timestamps, so a log can be read selectively by seeking.
+// Offsets point into the file on disk, so only uncompressed logs can be indexed.
+
+use crate::errors::{Result, WallaceError};
+use crate::file_io::sniff::{peek, InputFormat};
+use crate::messages::registry::MessageRegistry;
+use crate::parser::MessageStream;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+pub mod parse_cache;
+pub mod reader;
+pub use reader::LogReader;
+
+const MAGIC: &[u8; 4] = b"WLIX";
+const VERSION: u32 = 1;
+// Stored in place of a missing timestamp
+const NO_TIMESTAMP: u64 = u64::MAX;
+const RECORD_HEADER_LEN: u64 = 4;
+
+#[derive(Debug, Clone, Copy)]
+pub struct IndexEntry {
+    /// Byte offset of the record header in the log
+    pub offset: u64,
+    /// Payload length in bytes
+    pub length: u16,
+    /// The record's own timestamp, or the last one seen before it for records without one.
+    /// `None` for records ahead of the first timestamp.
+    pub timestamp: Option<u64>,
+}
+
+#[derive(Debug)]
+pub struct LogIndex {
+    /// The log's 4-byte file header
+    pub header: i32,
+    /// Size of the log when it was indexed, to detect a stale index
+    pub source_len: u64,
+    pub first_timestamp: Option<u64>,
+    /// Entries per log_type, in log order
+    pub types: BTreeMap<u16, Vec<IndexEntry>>,
+}
+
+/// Where the index of `log` is kept: next to it, with `.idx` appended
+pub fn index_path(log: &Path) -> PathBuf {
+    let mut path = log.as_os_str().to_owned();
+    path.push(".idx");
+    PathBuf::from(path)
+}
+
+impl LogIndex {
+    /// Scans the uncompressed log at `path` once, recording every record
+    pub fn build(path: &Path, registry: &MessageRegistry) -> Result<Self> {
+        match peek(File::open(path)?)?.0 {
+            InputFormat::Native => {}
+            InputFormat::Bzip2 | InputFormat::Gzip => {
+                return Err(WallaceError::InvalidArgument(format!(
+                    "'{}': compressed logs cannot be indexed, decompress it first",
+                    path.display()
+                )))
+            }
+            format => return Err(format.unsupported(&format!("'{}'", path.display()))),
+        }
+        let file = File::open(path)?;
+        let source_len = file.metadata()?.len();
+        let mut stream = MessageStream::new(BufReader::new(file), registry)?;
+        let mut index = LogIndex {
+            header: stream.header,
+            source_len,
+            first_timestamp: None,
+            types: BTreeMap::new(),
+        };
+        let mut last_ts = None;
+        loop {
+            let offset = stream.bytes_read;
+            let record = match stream.next_record()? {
+                Some(record) => record,
+                None => break,
+            };
+            if let Some(ts) = stream.decode(&record)?.and_then(|msg| msg.timestamp_us()) {
+                index.first_timestamp.get_or_insert(ts);
+                last_ts = Some(ts);
+            }
+            index
+                .types
+                .entry(record.log_type)
+                .or_default()
+                .push(IndexEntry {
+                    offset,
+                    length: record.payload.len() as u16,
+                    timestamp: last_ts,
+                });
+        }
+        Ok(index)
+    }
+
+    /// Loads the index of `log`, failing if it is missing or no longer matches the log
+    pub fn load_for(log: &Path) -> Result<Self> {
+        let path = index_path(log);
+        let file = File::open(&path).map_err(|e| {
+            WallaceError::InvalidArgument(format!(
+                "cannot open index '{}' ({}), run 'index' first",
+                path.display(),
+                e
+            ))
+        })?;
+        let index = Self::read_from(BufReader::new(file))?;
+        if std::fs::metadata(log)?.len() != index.source_len {
+            return Err(WallaceError::InvalidArgument(format!(
+                "index '{}' is stale, the log changed since it was built; run 'index' again",
+                path.display()
+            )));
+        }
+        Ok(index)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_u32::<LittleEndian>(VERSION)?;
+        writer.write_i32::<LittleEndian>(self.header)?;
+        writer.write_u64::<LittleEndian>(self.source_len)?;
+        writer.write_u64::<LittleEndian>(self.first_timestamp.unwrap_or(NO_TIMESTAMP))?;
+        writer.write_u32::<LittleEndian>(self.types.len() as u32)?;
+        for (log_type, entries) in &self.types {
+            writer.write_u16::<LittleEndian>(*log_type)?;
+            writer.write_u64::<LittleEndian>(entries.len() as u64)?;
+            for entry in entries {
+                writer.write_u64::<LittleEndian>(entry.offset)?;
+                writer.write_u16::<LittleEndian>(entry.length)?;
+                writer.write_u64::<LittleEndian>(entry.timestamp.unwrap_or(NO_TIMESTAMP))?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn read_from<R: Read>(mut reader: R) -> Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        let version = reader.read_u32::<LittleEndian>()?;
+        if &magic != MAGIC || version != VERSION {
+            return Err(WallaceError::InvalidArgument(
+                "not an index file of a supported version".to_string(),
+            ));
+        }
+        let header = reader.read_i32::<LittleEndian>()?;
+        let source_len = reader.read_u64::<LittleEndian>()?;
+        let first_timestamp = timestamp(reader.read_u64::<LittleEndian>()?);
+        let mut types = BTreeMap::new();
+        for _ in 0..reader.read_u32::<LittleEndian>()? {
+            let log_type = reader.read_u16::<LittleEndian>()?;
+            let count = reader.read_u64::<LittleEndian>()?;
+            let mut entries = Vec::new();
+            for _ in 0..count {
+                entries.push(IndexEntry {
+                    offset: reader.read_u64::<LittleEndian>()?,
+                    length: reader.read_u16::<LittleEndian>()?,
+                    timestamp: timestamp(reader.read_u64::<LittleEndian>()?),
+                });
+            }
+            types.insert(log_type, entries);
+        }
+        Ok(LogIndex {
+            header,
+            source_len,
+            first_timestamp,
+            types,
+        })
+    }
+
+    pub fn record_count(&self) -> usize {
+        self.types.values().map(Vec::len).sum()
+    }
+
+    /// Entries of the given types (all when `None`) whose timestamp lies in `[from, to)`, in log order.
+    /// Bounds are absolute timestamps; records ahead of the first timestamp only match an open `from`.
+    pub fn select(
+        &self,
+        log_types: Option<&[u16]>,
+        from: Option<u64>,
+        to: Option<u64>,
+    ) -> Vec<IndexEntry> {
+        let mut selected: Vec<IndexEntry> = self
+            .types
+            .iter()
+            .filter(|(log_type, _)| log_types.is_none_or(|wanted| wanted.contains(log_type)))
+            .flat_map(|(_, entries)| entries.iter().copied())
+            .filter(|entry| match entry.timestamp {
+                None => from.is_none(),
+                Some(ts) => from.is_none_or(|f| ts >= f) && to.is_none_or(|t| ts < t),
+            })
+            .collect();
+        selected.sort_by_key(|entry| entry.offset);
+        selected
+    }
+}
+
+fn timestamp(raw: u64) -> Option<u64> {
+    (raw != NO_TIMESTAMP).then_some(raw)
+}
+
+/// Presents the selected records of a log as a log of their own (file header, then the records
+/// in order), so it can be fed to anything that reads a log stream
+pub struct IndexedReader<R: Read + Seek> {
+    inner: R,
+    header: [u8; 4],
+    header_pos: usize,
+    entries: std::vec::IntoIter<IndexEntry>,
+    // Bytes of the current record still to be copied
+    remaining: u64,
+    // Position of `inner`, to skip the seek between adjacent records
+    position: Option<u64>,
+}
+
+impl<R: Read + Seek> IndexedReader<R> {
+    pub fn new(inner: R, header: i32, entries: Vec<IndexEntry>) -> Self {
+        IndexedReader {
+            inner,
+            header: header.to_le_bytes(),
+            header_pos: 0,
+            entries: entries.into_iter(),
+            remaining: 0,
+            position: None,
+        }
+    }
+}
+
+impl<R: Read + Seek> Read for IndexedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.header_pos < self.header.len() {
+            let n = (self.header.len() - self.header_pos).min(buf.len());
+            buf[..n].copy_from_slice(&self.header[self.header_pos..self.header_pos + n]);
+            self.header_pos += n;
+            return Ok(n);
+        }
+        if self.remaining == 0 {
+            match self.entries.next() {
+                Some(entry) => {
+                    if self.position != Some(entry.offset) {
+                        self.inner.seek(SeekFrom::Start(entry.offset))?;
+                        self.position = Some(entry.offset);
+                    }
+                    self.remaining = RECORD_HEADER_LEN + entry.length as u64;
+                }
+                None => return Ok(0),
+            }
+        }
+        let len = (buf.len() as u64).min(self.remaining) as usize;
+        let n = self.inner.read(&mut buf[..len])?;
+        if n == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "log is shorter than its index",
+            ));
+        }
+        self.remaining -= n as u64;
+        self.position = self.position.map(|p| p + n as u64);
+        Ok(n)
+    }
+}
+
+/// Resolves message names or numeric ids to log_types. A name shared by several ids selects all of them.
+pub fn resolve_types(names: &[&str], registry: &MessageRegistry) -> Result<Vec<u16>> {
+    let mut log_types = Vec::new();
+    for name in names {
+        if let Ok(id) = name.parse::<u16>() {
+            log_types.push(id);
+            continue;
+        }
+        let before = log_types.len();
+        log_types.extend(
+            registry
+                .iter()
+                .filter(|(_, def)| def.name == *name)
+                .filter_map(|(id, _)| id.parse::<u16>().ok()),
+        );
+        if log_types.len() == before {
+            return Err(WallaceError::InvalidArgument(format!(
+                "no message named '{}' in the registry",
+                name
+            )));
+        }
+    }
+    Ok(log_types)
+}
@@ -0,0 +1,458 @@
+// index/parse_cache.rs
+// Decoded messages of a run, kept next to its output directory with `--incremental` so that the
+// next run on the same log reads them back instead of decoding the log again. A cache is used
+// only if the log and registry hash as they did, like in the provenance sidecars, and the options
+// that change decoding are the same. Messages are stored compactly: the names of a message and
+// its fields once per shape, then numbers in binary wherever they read back as the same text.
+
+use crate::errors::Result;
+use crate::parser::{Extracted, ParsedMessage, PartialPolicy, RecordCount};
+use crate::report::provenance::sha256_file;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Extension of the cache, added to the name of the output directory
+pub const CACHE_EXTENSION: &str = "parse.cache";
+
+const MAGIC: &[u8; 4] = b"WLPC";
+const VERSION: u32 = 4;
+
+// How a field value is stored, as the tag before it
+const TEXT: u8 = 0;
+const INTEGER: u8 = 1;
+const FLOAT: u8 = 2;
+const DOUBLE: u8 = 3;
+
+// Bits of the flags stored with each message, under the partial policy in the low two
+const SALVAGED: u8 = 1 << 2;
+const HAS_TIMESTAMP: u8 = 1 << 3;
+
+/// What the cached messages were decoded from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheKey {
+    pub input_sha256: String,
+    pub registry_sha256: String,
+    /// Options that change decoding, as `name=value` lines
+    pub options: String,
+}
+
+impl CacheKey {
+    pub fn new(input: &str, registry: &str, options: &BTreeMap<String, String>) -> Result<Self> {
+        Ok(CacheKey {
+            input_sha256: sha256_file(input)?,
+            registry_sha256: sha256_file(registry)?,
+            options: options
+                .iter()
+                .map(|(name, value)| format!("{}={}\n", name, value))
+                .collect(),
+        })
+    }
+}
+
+/// Where the cache of an output directory is kept: next to it, as `<dir>.parse.cache`, so that
+/// it is neither taken for an export nor archived with the outputs
+pub fn path(output_dir: &Path) -> Result<PathBuf> {
+    let dir = std::path::absolute(output_dir)?;
+    let name = dir
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("output")
+        .to_string();
+    Ok(dir.with_file_name(format!("{}.{}", name, CACHE_EXTENSION)))
+}
+
+/// The messages, warnings, skipped fields and record counts cached for `output_dir`, if it has a
+/// cache made from `key`. A cache that cannot be read counts as missing, the log is decoded again.
+pub fn load(output_dir: &Path, key: &CacheKey) -> Option<Extracted> {
+    let data = fs::read(path(output_dir).ok()?).ok()?;
+    read_from(&data, key).ok().flatten()
+}
+
+/// Writes the cache for `key` of `output_dir`, replacing the one there. It is written under a
+/// temporary name first, so an interrupted run leaves the old cache or none.
+pub fn save(output_dir: &Path, key: &CacheKey, extracted: &Extracted) -> Result<()> {
+    let path = path(output_dir)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut partial = path.clone().into_os_string();
+    partial.push(".partial");
+    let mut writer = BufWriter::new(File::create(&partial)?);
+    write_to(&mut writer, key, extracted)?;
+    writer.flush()?;
+    drop(writer);
+    fs::rename(&partial, &path)?;
+    Ok(())
+}
+
+fn write_to<W: Write>(writer: &mut W, key: &CacheKey, extracted: &Extracted) -> Result<()> {
+    let (messages, warnings, skipped_fields, record_counts) = extracted;
+    writer.write_all(MAGIC)?;
+    writer.write_u32::<LittleEndian>(VERSION)?;
+    write_str(writer, &key.input_sha256)?;
+    write_str(writer, &key.registry_sha256)?;
+    write_str(writer, &key.options)?;
+    writer.write_u64::<LittleEndian>(warnings.len() as u64)?;
+    for warning in warnings {
+        write_str(writer, warning)?;
+    }
+    writer.write_u64::<LittleEndian>(*skipped_fields as u64)?;
+    writer.write_u32::<LittleEndian>(record_counts.len() as u32)?;
+    for (log_type, count) in record_counts {
+        writer.write_u16::<LittleEndian>(*log_type)?;
+        writer.write_u64::<LittleEndian>(count.records as u64)?;
+        writer.write_u64::<LittleEndian>(count.payload_bytes)?;
+        writer.write_u32::<LittleEndian>(count.lengths.len() as u32)?;
+        for (length, records) in &count.lengths {
+            writer.write_u64::<LittleEndian>(*length as u64)?;
+            writer.write_u64::<LittleEndian>(*records as u64)?;
+        }
+    }
+    writer.write_u64::<LittleEndian>(messages.len() as u64)?;
+    // The shapes written so far by log_type, each with its number and a message of its shape
+    let mut shapes: HashMap<u16, Vec<(u64, &ParsedMessage)>> = HashMap::new();
+    let mut shape_count = 0;
+    // Positions and timestamps are stored as the difference to the message before, mostly a
+    // byte or two
+    let (mut index, mut offset, mut timestamp) = (0u64, 0u64, 0u64);
+    let mut buf = Vec::new();
+    let mut text = String::new();
+    for msg in messages {
+        buf.clear();
+        let known = shapes.entry(msg.log_type).or_default();
+        match known.iter().find(|(_, seen)| same_shape(seen, msg)) {
+            Some(&(shape, _)) => write_varint(&mut buf, shape),
+            None => {
+                write_varint(&mut buf, shape_count);
+                buf.write_u16::<LittleEndian>(msg.log_type)?;
+                write_text(&mut buf, &msg.name);
+                write_varint(&mut buf, msg.fields.len() as u64);
+                for (name, _) in &msg.fields {
+                    write_text(&mut buf, name);
+                }
+                known.push((shape_count, msg));
+                shape_count += 1;
+            }
+        }
+        let partial = match msg.partial {
+            None => 0,
+            Some(PartialPolicy::Truncate) => 1,
+            Some(PartialPolicy::NullFill) => 2,
+            Some(PartialPolicy::Error) => 3,
+        };
+        let salvaged = if msg.salvaged { SALVAGED } else { 0 };
+        let timestamped = if msg.timestamp.is_some() {
+            HAS_TIMESTAMP
+        } else {
+            0
+        };
+        buf.push(partial | salvaged | timestamped);
+        write_signed(&mut buf, msg.index.wrapping_sub(index) as i64);
+        write_signed(&mut buf, msg.offset.wrapping_sub(offset) as i64);
+        write_varint(&mut buf, msg.payload_len as u64);
+        (index, offset) = (msg.index, msg.offset);
+        if let Some(next) = msg.timestamp {
+            write_signed(&mut buf, next.wrapping_sub(timestamp) as i64);
+            timestamp = next;
+        }
+        for (_, value) in &msg.fields {
+            write_value(&mut buf, value, &mut text);
+        }
+        writer.write_all(&buf)?;
+    }
+    Ok(())
+}
+
+// Whether `msg` has the type, name and field names of `seen`
+fn same_shape(seen: &ParsedMessage, msg: &ParsedMessage) -> bool {
+    seen.name == msg.name
+        && seen.fields.len() == msg.fields.len()
+        && seen
+            .fields
+            .iter()
+            .zip(&msg.fields)
+            .all(|((seen, _), (name, _))| seen == name)
+}
+
+// `value` as a number if it is written the way the number reads back, as text otherwise
+fn write_value(buf: &mut Vec<u8>, value: &str, text: &mut String) {
+    if let Some(integer) = canonical_integer(value) {
+        buf.push(INTEGER);
+        write_signed(buf, integer);
+        return;
+    }
+    if value.starts_with(|c: char| c.is_ascii_digit() || c == '-') {
+        if let Ok(float) = value.parse::<f32>() {
+            text.clear();
+            let _ = write!(text, "{}", float);
+            if text == value {
+                buf.push(FLOAT);
+                buf.extend(float.to_le_bytes());
+                return;
+            }
+        }
+        if let Ok(double) = value.parse::<f64>() {
+            text.clear();
+            let _ = write!(text, "{}", double);
+            if text == value {
+                buf.push(DOUBLE);
+                buf.extend(double.to_le_bytes());
+                return;
+            }
+        }
+    }
+    buf.push(TEXT);
+    write_text(buf, value);
+}
+
+// The integer `value` is, if `to_string` writes it that way: no sign but a minus, no leading zeros
+fn canonical_integer(value: &str) -> Option<i64> {
+    let digits = value.strip_prefix('-').unwrap_or(value);
+    let canonical = !digits.is_empty()
+        && digits.bytes().all(|b| b.is_ascii_digit())
+        && (!digits.starts_with('0') || digits == value && digits.len() == 1);
+    canonical.then(|| value.parse().ok()).flatten()
+}
+
+// `None` if the cache is of another version or was made from another key
+fn read_from(mut reader: &[u8], key: &CacheKey) -> Result<Option<Extracted>> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC || reader.read_u32::<LittleEndian>()? != VERSION {
+        return Ok(None);
+    }
+    let cached = CacheKey {
+        input_sha256: read_str(&mut reader)?,
+        registry_sha256: read_str(&mut reader)?,
+        options: read_str(&mut reader)?,
+    };
+    if &cached != key {
+        return Ok(None);
+    }
+    let mut warnings = Vec::new();
+    for _ in 0..reader.read_u64::<LittleEndian>()? {
+        warnings.push(read_str(&mut reader)?);
+    }
+    let skipped_fields = reader.read_u64::<LittleEndian>()? as usize;
+    let mut record_counts = BTreeMap::new();
+    for _ in 0..reader.read_u32::<LittleEndian>()? {
+        let log_type = reader.read_u16::<LittleEndian>()?;
+        let mut count = RecordCount {
+            records: reader.read_u64::<LittleEndian>()? as usize,
+            payload_bytes: reader.read_u64::<LittleEndian>()?,
+            ..Default::default()
+        };
+        for _ in 0..reader.read_u32::<LittleEndian>()? {
+            let length = reader.read_u64::<LittleEndian>()? as usize;
+            count
+                .lengths
+                .insert(length, reader.read_u64::<LittleEndian>()? as usize);
+        }
+        record_counts.insert(log_type, count);
+    }
+    let count = reader.read_u64::<LittleEndian>()? as usize;
+    let mut messages = Vec::with_capacity(count.min(reader.len()));
+    let mut shapes: Vec<(u16, String, Vec<String>)> = Vec::new();
+    let (mut index, mut offset, mut timestamp) = (0u64, 0u64, 0u64);
+    for _ in 0..count {
+        let shape = read_varint(&mut reader)? as usize;
+        if shape == shapes.len() {
+            let log_type = reader.read_u16::<LittleEndian>()?;
+            let name = read_text(&mut reader)?;
+            let names = (0..read_varint(&mut reader)?)
+                .map(|_| read_text(&mut reader))
+                .collect::<Result<_>>()?;
+            shapes.push((log_type, name, names));
+        }
+        let (log_type, name, names) = shapes.get(shape).ok_or_else(invalid)?;
+        let flags = reader.read_u8()?;
+        index = index.wrapping_add(read_signed(&mut reader)? as u64);
+        offset = offset.wrapping_add(read_signed(&mut reader)? as u64);
+        let payload_len = read_varint(&mut reader)? as usize;
+        let has_timestamp = flags & HAS_TIMESTAMP != 0;
+        if has_timestamp {
+            timestamp = timestamp.wrapping_add(read_signed(&mut reader)? as u64);
+        }
+        let mut fields = Vec::with_capacity(names.len());
+        for name in names {
+            fields.push((name.clone(), read_value(&mut reader)?));
+        }
+        messages.push(ParsedMessage {
+            log_type: *log_type,
+            name: name.clone(),
+            fields,
+            index,
+            offset,
+            payload_len,
+            timestamp: has_timestamp.then_some(timestamp),
+            partial: match flags & 3 {
+                1 => Some(PartialPolicy::Truncate),
+                2 => Some(PartialPolicy::NullFill),
+                3 => Some(PartialPolicy::Error),
+                _ => None,
+            },
+            salvaged: flags & SALVAGED != 0,
+            raw: None,
+        });
+    }
+    Ok(Some((messages, warnings, skipped_fields, record_counts)))
+}
+
+fn read_value(reader: &mut &[u8]) -> Result<String> {
+    Ok(match reader.read_u8()? {
+        TEXT => read_text(reader)?,
+        INTEGER => read_signed(reader)?.to_string(),
+        FLOAT => formatted(reader.read_f32::<LittleEndian>()?),
+        DOUBLE => formatted(reader.read_f64::<LittleEndian>()?),
+        _ => return Err(invalid()),
+    })
+}
+
+// A float as `to_string` writes it, into room made up front rather than grown while writing
+fn formatted(value: impl std::fmt::Display) -> String {
+    let mut text = String::with_capacity(24);
+    let _ = write!(text, "{}", value);
+    text
+}
+
+fn write_str<W: Write>(writer: &mut W, s: &str) -> Result<()> {
+    writer.write_u32::<LittleEndian>(s.len() as u32)?;
+    writer.write_all(s.as_bytes())?;
+    Ok(())
+}
+
+fn read_str<R: Read>(reader: &mut R) -> Result<String> {
+    let mut bytes = vec![0u8; reader.read_u32::<LittleEndian>()? as usize];
+    reader.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(|_| invalid())
+}
+
+fn write_text(buf: &mut Vec<u8>, s: &str) {
+    write_varint(buf, s.len() as u64);
+    buf.extend_from_slice(s.as_bytes());
+}
+
+fn read_text(reader: &mut &[u8]) -> Result<String> {
+    let len = read_varint(reader)? as usize;
+    if len > reader.len() {
+        return Err(invalid());
+    }
+    let (bytes, rest) = reader.split_at(len);
+    *reader = rest;
+    String::from_utf8(bytes.to_vec()).map_err(|_| invalid())
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        buf.push((v as u8 & 0x7F) | 0x80);
+        v >>= 7;
+    }
+    buf.push(v as u8);
+}
+
+fn read_varint(reader: &mut &[u8]) -> Result<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = reader.read_u8()?;
+        value |= ((byte & 0x7F) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid())
+}
+
+// Zig-zag encoded, so that small negative numbers take few bytes too
+fn write_signed(buf: &mut Vec<u8>, n: i64) {
+    write_varint(buf, ((n << 1) ^ (n >> 63)) as u64);
+}
+
+fn read_signed(reader: &mut &[u8]) -> Result<i64> {
+    let v = read_varint(reader)?;
+    Ok((v >> 1) as i64 ^ -((v & 1) as i64))
+}
+
+fn invalid() -> crate::errors::WallaceError {
+    std::io::Error::from(std::io::ErrorKind::InvalidData).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(index: u64, fields: &[(&str, &str)]) -> ParsedMessage {
+        ParsedMessage {
+            log_type: 7,
+            name: "Gps".to_string(),
+            fields: fields
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            index,
+            offset: 4 + index * 20,
+            payload_len: 16,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn messages_read_back_as_they_were_written() {
+        let mut messages = vec![
+            message(
+                0,
+                &[("Timestamp", "1000"), ("Lat", "-12"), ("Alt", "512.25")],
+            ),
+            // Text that only looks like numbers stays text
+            message(3, &[("Timestamp", "900"), ("Lat", "007"), ("Alt", "-0")]),
+            message(
+                4,
+                &[
+                    ("Timestamp", ""),
+                    ("Lat", "0.1"),
+                    ("Alt", "0.30000000000000004"),
+                ],
+            ),
+            message(9, &[("Timestamp", "18446744073709551615"), ("Fix", "NaN")]),
+        ];
+        messages[0].timestamp = Some(1000);
+        messages[1].timestamp = Some(900);
+        messages[2].partial = Some(PartialPolicy::NullFill);
+        messages[3].salvaged = true;
+        let key = CacheKey {
+            input_sha256: "in".to_string(),
+            registry_sha256: "reg".to_string(),
+            options: String::new(),
+        };
+        let mut counts = BTreeMap::new();
+        counts.insert(7, RecordCount::default());
+        let extracted = (messages, vec!["a warning".to_string()], 2, counts);
+
+        let mut data = Vec::new();
+        write_to(&mut data, &key, &extracted).unwrap();
+        let (read, warnings, skipped, counts) = read_from(&data, &key).unwrap().unwrap();
+        assert_eq!(warnings, extracted.1);
+        assert_eq!((skipped, counts.len()), (2, 1));
+        assert_eq!(read.len(), extracted.0.len());
+        for (read, written) in read.iter().zip(&extracted.0) {
+            assert_eq!(read.fields, written.fields);
+            assert_eq!(
+                (read.index, read.offset, read.timestamp),
+                (written.index, written.offset, written.timestamp)
+            );
+            assert_eq!(
+                (read.partial, read.salvaged),
+                (written.partial, written.salvaged)
+            );
+        }
+
+        let other = CacheKey {
+            options: "partial=error\n".to_string(),
+            ..key
+        };
+        assert!(read_from(&data, &other).unwrap().is_none());
+    }
+}
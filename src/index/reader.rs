@@ -0,0 +1,71 @@
+// index/reader.rs
+// Seekable access to a log through its sidecar index, for tools that query one large log repeatedly.
+
+use super::{resolve_types, IndexEntry, IndexedReader, LogIndex};
+use crate::errors::Result;
+use crate::messages::registry::MessageRegistry;
+use crate::parser::{MessageStream, ParsedMessage};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+pub struct LogReader<'a> {
+    file: File,
+    index: LogIndex,
+    registry: &'a MessageRegistry,
+    /// Warnings collected by the queries so far, prefixed with the message they belong to
+    pub warnings: Vec<String>,
+}
+
+impl<'a> LogReader<'a> {
+    /// Opens an uncompressed log together with the index written by `wallace index`
+    pub fn open_with_index<P: AsRef<Path>>(path: P, registry: &'a MessageRegistry) -> Result<Self> {
+        let index = LogIndex::load_for(path.as_ref())?;
+        Ok(LogReader {
+            file: File::open(path)?,
+            index,
+            registry,
+            warnings: Vec::new(),
+        })
+    }
+
+    pub fn index(&self) -> &LogIndex {
+        &self.index
+    }
+
+    /// All messages of a type, given by registry name or numeric id, in log order
+    pub fn messages_of_type(&mut self, name: &str) -> Result<Vec<ParsedMessage>> {
+        let log_types = resolve_types(&[name], self.registry)?;
+        let entries = self.index.select(Some(&log_types), None, None);
+        self.read(entries)
+    }
+
+    /// All messages whose timestamp lies in `[t0, t1)`, in microseconds as logged.
+    /// Records without a timestamp of their own count as logged at the last timestamp before them.
+    pub fn messages_between(&mut self, t0: u64, t1: u64) -> Result<Vec<ParsedMessage>> {
+        let entries = self.index.select(None, Some(t0), Some(t1));
+        self.read(entries)
+    }
+
+    /// Messages of the given types (all when `None`) within the optional time bounds
+    pub fn messages(
+        &mut self,
+        log_types: Option<&[u16]>,
+        from: Option<u64>,
+        to: Option<u64>,
+    ) -> Result<Vec<ParsedMessage>> {
+        let entries = self.index.select(log_types, from, to);
+        self.read(entries)
+    }
+
+    fn read(&mut self, entries: Vec<IndexEntry>) -> Result<Vec<ParsedMessage>> {
+        let records = IndexedReader::new(&mut self.file, self.index.header, entries);
+        let mut stream = MessageStream::new(BufReader::new(records), self.registry)?;
+        let mut messages = Vec::new();
+        while let Some(msg) = stream.next_message()? {
+            messages.push(msg);
+        }
+        self.warnings.append(&mut stream.warnings);
+        Ok(messages)
+    }
+}
@@ -1,7 +1,18 @@
+pub mod analysis;
+pub mod archive;
+pub mod config;
+pub mod encode;
 pub mod errors;
+pub mod export;
 pub mod file_io;
-pub mod handler;
+pub mod index;
 pub mod messages;
+pub mod net;
 pub mod parser;
+pub mod redact;
+pub mod repair;
+pub mod report;
+pub mod script;
+pub mod sink;
+pub mod units;
 pub mod utils;
-
@@ -1,80 +1,1306 @@
+mod analysis;
+mod archive;
+mod commands;
+mod config;
+mod encode;
 mod errors; // Add errors module
+mod export;
 mod file_io;
+#[allow(dead_code, unused_imports)] // LogReader is library API, unused by the binary
+mod index;
+mod net;
 mod parser;
+mod redact;
+mod repair;
+mod report;
+mod script;
+mod sink;
+mod units;
 mod utils;
 use std::io::Write;
 mod messages {
+    pub mod firmware;
+    pub mod manifest;
     pub mod registry;
-    pub use registry::{load_message_registry, FieldDef, MessageDef, MessageRegistry};
+    pub use registry::{load_message_registry, MessageRegistry};
 }
 
+use crate::analysis::altitude::{altitude_profile, write_altitude_report, AltitudeSpec};
+use crate::analysis::changes::{find_changes, write_changes};
+use crate::analysis::correlate::{correlate, write_correlations, CorrelationSpec};
+use crate::analysis::energy::{integrate, write_battery_report, BatterySpec};
+use crate::analysis::extrema::{find_events, write_extrema, ExtremaSpec};
+use crate::analysis::geo::{track_stats, PositionSpec};
+use crate::analysis::histogram::{write_histogram, Histogram, HistogramSpec};
+use crate::analysis::FieldRef;
+use crate::archive::{archive_directory, ArchiveFormat};
+use crate::config::Config;
+use crate::encode::input::{is_export, read_exports};
 use crate::errors::{Result, WallaceError};
-use clap::{App, Arg};
-use file_io::open_file;
+use crate::export::columns::{add_metadata, ColumnSelection, Tags};
+use crate::export::derived::DerivedColumns;
+use crate::export::geofence::{Geofilter, GeofilterMode};
+use crate::export::influx::{require_start_time, write_to_influx};
+use crate::export::locale::NumberFormat;
+use crate::export::spill::Spill;
+use crate::export::sql::{Flight, FLIGHT_COLUMN};
+use crate::export::writers::write_files;
+use crate::export::{
+    export_all, ndjson::write_ndjson, postgres::load_into_postgres, ExportOptions, OutputFormat,
+};
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, Command};
+use export::ndjson::JsonEncoder;
+use file_io::open_file_with_jobs;
+use index::parse_cache::{self, CacheKey};
+use index::{resolve_types, IndexedReader, LogIndex};
+use messages::firmware;
 use messages::load_message_registry;
-use parser::extract_messages;
+use messages::manifest::Manifest;
+use messages::registry::{
+    load_registry_for_firmware, set_skip_policy, Definitions, RegistryWatcher,
+};
+use parser::stream::decode_record;
+use parser::{
+    extract_messages, BytesFormat, DecodeOptions, MessageStream, ParseLimits, ParsedMessage,
+    PartialPolicy, RecordCount,
+};
+use redact::{extract_messages_redacted, Redactor};
+use report::provenance::{sha256_file, Provenance};
+use report::{write_counts, write_coverage, write_summary, Summary};
+use script::{ScriptLimits, ScriptTransform};
+use sink::dashboard::DashboardSink;
+use sink::metrics::{serve_metrics, LiveMetrics, MetricsSink};
+use sink::{publish_all, MessageSink};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
-use utils::{export_to_csv, group_by_type};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use units::{parse_targets, UnitConverter};
+use utils::dedup::drop_duplicates;
+use utils::group_by_type;
+use utils::memory::{format_size, message_size, messages_size, MemoryStats};
+use utils::sample::{extract_messages_sampled, Sampler, Sampling};
 
-fn main() -> Result<()> {
-    // Update return type
-    // --- Clap Argument Parsing ---
+/// The full command line, also used to generate shell completions
+fn cli() -> Command {
     // Define command-line arguments using Clap
-    let matches = App::new("Wallace Log Parser")
+    let cli = Command::new("wallace_rs")
         .version("0.1.0")
         .author("Cline")
-        .about("Parses binary flight logs based on a JSON definition")
+        .about("Wallace Log Parser: parses binary flight logs based on a JSON definition")
+        .subcommand_negates_reqs(true)
+        .subcommands(commands::subcommands())
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .value_name("TOML_FILE")
+                .help("Reads default options from this file [default: wallace.toml, if present]")
+                .value_parser(commands::existing_file)
+                .global(true),
+        )
+        .arg(
+            Arg::new("profile")
+                .long("profile")
+                .value_name("NAME")
+                .help("Applies the settings of [profile.NAME] in the config file, e.g. one per vehicle type")
+                .global(true),
+        )
         .arg(
-            Arg::with_name("input")
-                .short("i")
+            Arg::new("cache-decompressed")
+                .long("cache-decompressed")
+                .value_name("DIR")
+                .help("Decompresses a bzip2 log once into DIR, keyed by the hash of its contents, and reads that copy on later runs")
+                .global(true),
+        )
+        .arg(
+            Arg::new("input")
+                .short('i')
                 .long("input")
                 .value_name("FILE")
-                .help("Sets the input log file path (e.g., example.dat, log.bz2)")
-                .takes_value(true)
+                .help("Sets the input log file path (e.g., example.dat, log.bz2, log.gz; compression is detected from the contents), '-' for stdin, or earlier CSV/NDJSON exports (a file or an export directory) to export or analyse again")
+                .value_parser(commands::existing_path)
                 .required(true),
         )
         .arg(
-            Arg::with_name("registry")
-                .short("r")
+            Arg::new("registry")
+                .short('r')
                 .long("registry")
                 .value_name("JSON_FILE")
-                .help("Sets the message definition JSON file path")
-                .takes_value(true)
-                .default_value("messages.json"),
+                .help("Sets the message definition JSON file path [default: messages.json]")
+                .value_parser(commands::existing_file),
+        )
+        .arg(
+            Arg::new("registry-manifest")
+                .long("registry-manifest")
+                .value_name("JSON_FILE")
+                .help("Picks the registry from a manifest of rules matching the log's header and early records, for fleets with several firmware versions")
+                .value_parser(commands::existing_file)
+                .conflicts_with("registry"),
         )
         .arg(
-            Arg::with_name("output")
-                .short("o")
+            Arg::new("output")
+                .short('o')
                 .long("output")
                 .value_name("DIRECTORY")
-                .help("Sets the output directory for CSV files")
-                .takes_value(true)
+                .help("Sets the output directory for exported files, or '-' to stream to stdout")
                 .default_value("output"),
         )
-        .get_matches();
+        .arg(
+            Arg::new("format")
+                .short('f')
+                .long("format")
+                .value_name("FORMAT")
+                .help("Sets the export format")
+                .value_parser(commands::format_parser())
+                .default_value("csv"),
+        )
+        .arg(
+            Arg::new("influx-url")
+                .long("influx-url")
+                .value_name("URL")
+                .help("Also writes all messages to an InfluxDB write endpoint (e.g. http://host:8086/write?db=flights)"),
+        )
+        .arg(
+            Arg::new("influx-token")
+                .long("influx-token")
+                .value_name("TOKEN")
+                .help("API token sent with InfluxDB 2.x writes")
+                .requires("influx-url"),
+        )
+        .arg(
+            Arg::new("start-time")
+                .long("start-time")
+                .value_name("TIME")
+                .help("UTC time the log's clock started, as RFC 3339 (e.g. 2024-05-01T12:30:00Z) or Unix seconds; InfluxDB points are stamped with it plus each record's timestamp. Required for InfluxDB output")
+                .value_parser(commands::start_time),
+        )
+        .arg(
+            Arg::new("pg-url")
+                .long("pg-url")
+                .value_name("URL")
+                .help("Loads the '--format postgres' bundle into this database via psql"),
+        )
+        .arg(
+            Arg::new("append")
+                .long("append")
+                .help("Adds the log to an existing '--format duckdb' database or, with --pg-url, Postgres database as one more flight instead of replacing it: rows get a flight_sha256 column, the wallace_flights table lists the logs loaded, and a log loaded before is skipped")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("kafka-brokers")
+                .long("kafka-brokers")
+                .value_name("HOST:PORT,...")
+                .help("Publishes every message as JSON to Kafka (requires the 'kafka' feature)"),
+        )
+        .arg(
+            Arg::new("topic-template")
+                .long("topic-template")
+                .value_name("TEMPLATE")
+                .help("Kafka topic per message; {msg_name} and {log_type} are substituted")
+                .default_value("telemetry.{msg_name}"),
+        )
+        .arg(
+            Arg::new("mqtt-broker")
+                .long("mqtt-broker")
+                .value_name("[USER:PASS@]HOST[:PORT]")
+                .help("Publishes every message as JSON to an MQTT broker (QoS 0)"),
+        )
+        .arg(
+            Arg::new("mqtt-topic-template")
+                .long("mqtt-topic-template")
+                .value_name("TEMPLATE")
+                .help("MQTT topic per message; {vehicle}, {msg_name} and {log_type} are substituted")
+                .default_value("wallace/{vehicle}/{msg_name}"),
+        )
+        .arg(
+            Arg::new("vehicle")
+                .long("vehicle")
+                .value_name("ID")
+                .help("Vehicle identifier used in MQTT topics")
+                .default_value("default"),
+        )
+        .args(commands::redaction_args())
+        .arg(
+            Arg::new("archive")
+                .long("archive")
+                .value_name("FORMAT")
+                .help("Bundles the output directory into a single zip or tar.gz artifact")
+                .value_parser(["zip", "tar.gz"]),
+        )
+        .arg(
+            Arg::new("live")
+                .long("live")
+                .help("Decodes the input as a live feed, publishing each message to the sinks as it arrives instead of writing files")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("ws-listen")
+                .long("ws-listen")
+                .value_name("ADDR:PORT")
+                .help("Serves decoded messages as JSON over WebSocket; clients filter with '?types=GPS,IMU'"),
+        )
+        .arg(
+            Arg::new("metrics-listen")
+                .long("metrics-listen")
+                .value_name("ADDR:PORT")
+                .help("Exposes Prometheus decoding metrics on '/metrics' (requires '--live')")
+                .requires("live"),
+        )
+        .arg(
+            Arg::new("metrics-field")
+                .long("metrics-field")
+                .value_name("MESSAGE.FIELD")
+                .help("Reports the last value of this field as a gauge; may be repeated")
+                .action(ArgAction::Append)
+                .requires("metrics-listen"),
+        )
+        .arg(
+            Arg::new("dashboard")
+                .long("dashboard")
+                .value_name("MESSAGE.FIELD")
+                .help("Shows the latest value of this field in a table redrawn in place, e.g. BatteryStatus.voltage; may be repeated (requires '--live')")
+                .action(ArgAction::Append)
+                .requires("live"),
+        )
+        .arg(
+            Arg::new("dashboard-interval")
+                .long("dashboard-interval")
+                .value_name("MS")
+                .help("Least time between two redraws of the dashboard, in milliseconds")
+                .value_parser(clap::value_parser!(u64).range(50..))
+                .default_value("500")
+                .requires("dashboard"),
+        )
+        .arg(
+            Arg::new("skip-field")
+                .long("skip-field")
+                .value_name("NAME")
+                .help("Also skips fields with this name, like TRASH, PADDING and RESERVED; may be repeated")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("keep-skipped")
+                .long("keep-skipped")
+                .visible_alias("keep-padding")
+                .help("Decodes and exports padding fields too (TRASH, PADDING, RESERVED and any the registry skips), to debug field alignment")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("skip-field"),
+        )
+        .arg(
+            Arg::new("partial")
+                .long("partial")
+                .value_name("POLICY")
+                .help("What to do with records whose payload ends before their fields do: keep the fields that fit, fill the rest with empty values, or fail")
+                .value_parser(["truncate", "null-fill", "error"])
+                .default_value("truncate"),
+        )
+        .arg(
+            Arg::new("lenient")
+                .long("lenient")
+                .help("With '--partial error', keeps the fields decoded before the one a payload ends in, marks the record as salvaged and carries on, instead of stopping the run")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("bytes-format")
+                .long("bytes-format")
+                .value_name("FORMAT")
+                .help("Text form of byte-array fields: space separated hex pairs, base64, or printable ASCII with \\xNN escapes")
+                .value_parser(["hex", "base64", "escaped"])
+                .default_value("hex"),
+        )
+        .arg(
+            Arg::new("max-records")
+                .long("max-records")
+                .value_name("N")
+                .help("Fails the run once the log holds more than N records, for input that cannot be trusted")
+                .value_parser(clap::value_parser!(u64).range(1..)),
+        )
+        .arg(
+            Arg::new("parse-timeout")
+                .long("parse-timeout")
+                .value_name("SECONDS")
+                .help("Fails the run if decoding the log takes longer than this")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .conflicts_with("live"),
+        )
+        .arg(
+            Arg::new("max-empty-run")
+                .long("max-empty-run")
+                .value_name("N")
+                .help("Fails the run after more than N empty records in a row, as zeroed or garbage input reads as an endless run of them")
+                .value_parser(clap::value_parser!(u64)),
+        )
+        .arg(
+            Arg::new("dedup")
+                .long("dedup")
+                .help("Drops records identical (type, timestamp and payload) to the previous record of their type, as written twice after a brown-out")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("sample")
+                .long("sample")
+                .value_name("PERCENT")
+                .help("Decodes and exports only this share of the records of each type, the first and then evenly spaced, e.g. 1% for a quick look at a new log")
+                .value_parser(commands::percent)
+                .conflicts_with_all(["reservoir", "live"]),
+        )
+        .arg(
+            Arg::new("reservoir")
+                .long("reservoir")
+                .value_name("N")
+                .help("Decodes and exports N records of each type picked at random, all equally likely (repeatable with --seed), e.g. for a quick look at a new log")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .conflicts_with("live"),
+        )
+        .arg(
+            Arg::new("use-index")
+                .long("use-index")
+                .help("Reads only the selected records by seeking with the sidecar index written by 'index'")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("types")
+                .long("types")
+                .value_name("NAME|ID,...")
+                .help("Decodes only these message types (requires '--use-index')")
+                .value_delimiter(',')
+                .requires("use-index"),
+        )
+        .arg(
+            Arg::new("from")
+                .long("from")
+                .value_name("DURATION")
+                .help("Decodes only from this time on, relative to the first timestamp (requires '--use-index')")
+                .value_parser(commands::duration)
+                .requires("use-index"),
+        )
+        .arg(
+            Arg::new("to")
+                .long("to")
+                .value_name("DURATION")
+                .help("Decodes only up to this time (exclusive), relative to the first timestamp (requires '--use-index')")
+                .value_parser(commands::duration)
+                .requires("use-index"),
+        )
+        .arg(
+            Arg::new("geofilter")
+                .long("geofilter")
+                .value_name("FILE")
+                .help("Checks each record against the polygons of a GeoJSON file, by the last position fix at or before it")
+                .value_parser(commands::existing_file),
+        )
+        .arg(
+            Arg::new("geofilter-mode")
+                .long("geofilter-mode")
+                .value_name("MODE")
+                .help("Adds an in_geofence column (1 inside, 0 outside), or keeps only the records captured inside or outside; records before the first fix are always kept")
+                .value_parser(["mark", "inside", "outside"])
+                .default_value("mark")
+                .requires("geofilter"),
+        )
+        .arg(
+            Arg::new("geofilter-position")
+                .long("geofilter-position")
+                .value_name("LAT:LON[:SCALE]")
+                .help("Latitude and longitude fields of one message, times SCALE in degrees")
+                .default_value("GPSData.lat:GPSData.lon:1e-7")
+                .requires("geofilter"),
+        )
+        .arg(
+            Arg::new("missing-value")
+                .long("missing-value")
+                .value_name("TEXT")
+                .help("Written in CSV and xlsx cells for fields a record lacks or lost to a truncated payload, e.g. NA or null; NDJSON and Avro write null, SQL exports load NULL and line protocol leaves the field out")
+                .default_value(""),
+        )
+        .arg(
+            Arg::new("locale")
+                .long("locale")
+                .value_name("NAME")
+                .help("Writes numbers in CSV and xlsx files as spreadsheets in this locale read them, e.g. 'de' for 1.234,5 with ';' between CSV fields")
+                .value_parser(NumberFormat::locale_names().collect::<Vec<_>>()),
+        )
+        .arg(
+            Arg::new("decimal-separator")
+                .long("decimal-separator")
+                .value_name("CHAR")
+                .help("Decimal separator of numbers in CSV files, overriding the locale's; a comma also makes ';' the CSV delimiter")
+                .value_parser([".", ","]),
+        )
+        .arg(
+            Arg::new("digit-grouping")
+                .long("digit-grouping")
+                .value_name("CHAR")
+                .help("Separator between groups of three digits in CSV numbers, overriding the locale's; in xlsx any grouping turns on Excel's own for whole numbers")
+                .value_parser(["none", ".", ",", "'", "space"]),
+        )
+        .arg(
+            Arg::new("columns")
+                .long("columns")
+                .value_name("MESSAGE=FIELD,...")
+                .help("Exports only these fields of a message type, in this order, e.g. GPS=TimeUS,Lat,Lng,Alt; may be repeated")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("rate")
+                .long("rate")
+                .value_name("MESSAGE.FIELD,...")
+                .help("Adds a d(FIELD)/dt column after each field with its change per second between messages, e.g. GPS.Alt")
+                .value_delimiter(','),
+        )
+        .arg(
+            Arg::new("smooth")
+                .long("smooth")
+                .value_name("MESSAGE.FIELD:window=N|cutoff=HZ,...")
+                .help("Adds a smoothed column after each field: a trailing moving average of N values (FIELD_maN), or a low-pass filter (FIELD_lpHZHz), e.g. IMU.AccZ:window=50")
+                .value_delimiter(','),
+        )
+        .arg(
+            Arg::new("tag")
+                .long("tag")
+                .value_name("KEY=VALUE")
+                .help("Adds a column with this constant value to every exported row and records it in summary.json, e.g. vehicle=N123; may be repeated")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("metadata")
+                .long("metadata")
+                .help("Adds each record's index, byte offset, payload length and timestamp as leading columns")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("provenance-header")
+                .long("provenance-header")
+                .help("Also writes the provenance (tool version, input and registry hashes, options) as '#' lines above each CSV header; every export gets a .meta.json sidecar regardless")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("keep-formulas")
+                .long("keep-formulas")
+                .help("Writes CSV text cells starting with =, +, -, @, tab or carriage return as they are; by default they get a leading ' so spreadsheets do not run them as formulas")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("rows-per-file")
+                .long("rows-per-file")
+                .value_name("N")
+                .help("Splits message types with more rows into numbered files, e.g. IMU_0001.csv, IMU_0002.csv (CSV, NDJSON and Avro)")
+                .value_parser(clap::value_parser!(u64).range(1..)),
+        )
+        .arg(
+            Arg::new("jobs")
+                .short('j')
+                .long("jobs")
+                .value_name("N")
+                .help("Decompresses bzip2 input on N threads and writes the per-type export files on N threads, as the log is decoded unless an option needs every message first [default: one per CPU]")
+                .value_parser(clap::value_parser!(u64).range(1..)),
+        )
+        .arg(
+            Arg::new("max-memory")
+                .long("max-memory")
+                .value_name("SIZE")
+                .help("Keeps decoded messages of a binary log under about SIZE (e.g. 512M, 4G); larger logs are exported a chunk at a time into a spill directory and merged into one file per type at the end (CSV, NDJSON and Avro)")
+                .value_parser(commands::byte_size),
+        )
+        .arg(
+            Arg::new("incremental")
+                .long("incremental")
+                .help("Keeps the decoded messages next to the output directory, in <DIR>.parse.cache, and reads them back on later runs while the input, registry and decoding options are unchanged, e.g. to rerun analyses without decoding the log again")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["live", "max-memory"]),
+        )
+        .arg(
+            Arg::new("histogram")
+                .long("histogram")
+                .value_name("MESSAGE.FIELD[:Nbins],...")
+                .help("Writes a histogram CSV (bin edges and counts) of each field, 20 bins unless given")
+                .value_delimiter(','),
+        )
+        .arg(
+            Arg::new("extrema")
+                .long("extrema")
+                .value_name("MESSAGE.FIELD[:THRESHOLD...],...")
+                .help("Reports the time and value of each field's minimum, maximum and threshold crossings in extrema.csv")
+                .value_delimiter(','),
+        )
+        .arg(
+            Arg::new("changes")
+                .long("changes")
+                .value_name("MESSAGE.FIELD,...")
+                .help("Logs each time these fields take a new value, e.g. flight mode or arm state, in changes.csv")
+                .value_delimiter(','),
+        )
+        .arg(
+            Arg::new("correlate")
+                .long("correlate")
+                .value_name("MESSAGE.FIELD:MESSAGE.FIELD,...")
+                .help("Compares each pair of signals (reference:signal) for correlation, lag and RMS error in correlation.csv")
+                .value_delimiter(','),
+        )
+        .arg(
+            Arg::new("battery")
+                .long("battery")
+                .value_name("VOLTAGE:CURRENT[:IDLE_AMPS]")
+                .help("Integrates consumed mAh and Wh from these fields over time and per flight segment (current above IDLE_AMPS, default 1) into battery.csv and battery_segments.csv"),
+        )
+        .arg(
+            Arg::new("altitude")
+                .long("altitude")
+                .value_name("BARO[:GPS][:AIRBORNE_M]")
+                .help("Estimates height above the launch point from a barometric altitude, corrected by a GPS one if given, into altitude.csv with climb rates and altitude_segments.csv with the maxima per flight segment (above AIRBORNE_M, default 2)"),
+        )
+        .arg(
+            Arg::new("track")
+                .long("track")
+                .value_name("LAT:LON[:SCALE]")
+                .help("Adds the ground track distance, the farthest distance from the first fix and the bounding box to summary.json, from latitude and longitude fields times SCALE in degrees, e.g. GPSData.lat:GPSData.lon:1e-7"),
+        )
+        .arg(
+            Arg::new("convert")
+                .long("convert")
+                .value_name("PRESET|FROM=TO,...")
+                .help("Converts values to other units on export: 'imperial', 'aviation' or unit pairs like 'm/s=kn,Pa=hPa'. Per-field 'convert_to' in the registry applies regardless"),
+        )
+        .arg(
+            Arg::new("transform")
+                .long("transform")
+                .value_name("SCRIPT")
+                .help("Runs this Rhai script on every message after unit conversion; it can change the values in 'fields' and drops the message by ending in 'false'")
+                .value_parser(commands::existing_file)
+                .conflicts_with("live"),
+        )
+        .arg(
+            Arg::new("transform-max-operations")
+                .long("transform-max-operations")
+                .value_name("N")
+                .help("Fails the run if the transform script takes more than N operations on one message")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .default_value(script::DEFAULT_MAX_OPERATIONS.to_string())
+                .requires("transform"),
+        );
+    config::with_env(cli)
+}
+
+fn main() -> Result<()> {
+    let args: Vec<_> = std::env::args_os().collect();
+    let profile = config::profile(&args);
+    let config = match (config::locate(&args), &profile) {
+        (Some(path), profile) => Config::load(path, profile.as_deref())?,
+        (None, Some(name)) => {
+            return Err(WallaceError::InvalidArgument(format!(
+                "'--profile {}' needs a config file; none was given and there is no '{}'",
+                name,
+                config::CONFIG_FILE
+            )))
+        }
+        (None, None) => Config::default(),
+    };
+    let matches = config.apply(cli()).get_matches_from(args);
+    if let Some(dir) = matches.get_one::<String>("cache-decompressed") {
+        file_io::set_cache_dir(PathBuf::from(dir));
+    }
+    match matches.subcommand() {
+        Some(("completions", sub_matches)) => {
+            return commands::completions::run(sub_matches, &mut cli())
+        }
+        Some((name, sub_matches)) => return commands::run(name, sub_matches),
+        None => {}
+    }
 
     // Extract command-line arguments
-    let input_path = matches.value_of("input").unwrap(); // Required, so unwrap is safe
-    let registry_path = matches.value_of("registry").unwrap(); // Has default
-    let output_path = matches.value_of("output").unwrap(); // Has default
+    let input_path = matches
+        .get_one::<String>("input")
+        .map(String::as_str)
+        .unwrap(); // Required, so unwrap is safe
+
+    // Defaulted here rather than by clap, which would check that the default exists even when
+    // running a subcommand with its own registry
+    let from_exports = input_path != "-" && is_export(Path::new(input_path));
+
+    // A registry given on the command line or in the environment beats the manifest, which beats
+    // one from the config file
+    let manifest = matches
+        .get_one::<String>("registry-manifest")
+        .map(|path| Manifest::load(path))
+        .transpose()?;
+    let registry_given = matches
+        .value_source("registry")
+        .is_some_and(|source| source != ValueSource::DefaultValue);
+    let registry_path = match &manifest {
+        Some(_) if from_exports && !registry_given => {
+            return Err(WallaceError::InvalidArgument(
+                "a registry manifest needs the binary log; give exports a --registry".to_string(),
+            ))
+        }
+        Some(manifest) if !registry_given => {
+            let path = manifest.select(input_path)?;
+            println!("📚 Using registry '{}' from the manifest", path);
+            path
+        }
+        _ => matches
+            .get_one::<String>("registry")
+            .map_or(commands::DEFAULT_REGISTRY, String::as_str),
+    };
+    if !Path::new(registry_path).is_file() {
+        return Err(WallaceError::InvalidArgument(format!(
+            "registry '{}' does not exist (set one with --registry)",
+            registry_path
+        )));
+    }
+    let output_path = matches
+        .get_one::<String>("output")
+        .map(String::as_str)
+        .unwrap(); // Has default
+    let format = *matches.get_one::<OutputFormat>("format").unwrap(); // Has default
+    let archive_format = matches
+        .get_one::<String>("archive")
+        .map(String::as_str)
+        .and_then(ArchiveFormat::from_name);
+    let to_stdout = output_path == "-";
+    let pg_url = matches.get_one::<String>("pg-url").map(String::as_str);
+    if pg_url.is_some() && format != OutputFormat::Postgres {
+        return Err(WallaceError::InvalidArgument(
+            "'--pg-url' requires '--format postgres'".to_string(),
+        ));
+    }
+    // With --append the log is one more flight of a fleet database, known by its hash
+    let flight = if matches.get_flag("append") {
+        if !matches!(format, OutputFormat::DuckDb | OutputFormat::Postgres) {
+            return Err(WallaceError::InvalidArgument(
+                "'--append' requires '--format duckdb' or '--format postgres'".to_string(),
+            ));
+        }
+        if from_exports || input_path == "-" {
+            return Err(WallaceError::InvalidArgument(
+                "'--append' needs a binary log file, whose hash tells if it was appended before"
+                    .to_string(),
+            ));
+        }
+        Some(Flight {
+            input: input_path.to_string(),
+            sha256: sha256_file(input_path)?,
+        })
+    } else {
+        None
+    };
+    if to_stdout && format != OutputFormat::Ndjson {
+        return Err(WallaceError::InvalidArgument(
+            "writing to stdout ('-o -') requires '--format ndjson'".to_string(),
+        ));
+    }
+    let start_time = matches.get_one::<u64>("start-time").copied();
+    // Check before decoding so a missing anchor doesn't cost a full pass over the log
+    if format == OutputFormat::Influx || matches.contains_id("influx-url") {
+        require_start_time(start_time)?;
+    }
+
+    let live = matches.get_flag("live");
+    let partial = matches
+        .get_one::<String>("partial")
+        .and_then(|name| PartialPolicy::from_name(name))
+        .unwrap_or_default(); // Restricted to known names
+    let lenient = matches.get_flag("lenient");
+    // The other policies never fail a record, so there would be nothing to salvage
+    if lenient && partial != PartialPolicy::Error {
+        return Err(WallaceError::InvalidArgument(format!(
+            "'--lenient' only applies with '--partial error'; '--partial {}' already keeps short records",
+            partial.name()
+        )));
+    }
+    let decode_options = DecodeOptions {
+        partial,
+        lenient,
+        bytes: matches
+            .get_one::<String>("bytes-format")
+            .and_then(|name| BytesFormat::from_name(name))
+            .unwrap_or_default(), // Restricted to known names
+        limits: ParseLimits {
+            max_records: matches.get_one::<u64>("max-records").copied(),
+            timeout: matches
+                .get_one::<u64>("parse-timeout")
+                .map(|&secs| Duration::from_secs(secs)),
+            max_empty_run: matches.get_one::<u64>("max-empty-run").copied(),
+        },
+        ..Default::default()
+    };
+
+    let mut sinks = build_sinks(&matches)?;
+    let metrics = match matches
+        .get_one::<String>("metrics-listen")
+        .map(String::as_str)
+    {
+        Some(addr) => {
+            let metrics = Arc::new(Mutex::new(LiveMetrics::new()));
+            serve_metrics(addr, Arc::clone(&metrics))?;
+            let watched: Vec<&str> = matches
+                .get_many::<String>("metrics-field")
+                .map(|v| v.map(String::as_str).collect())
+                .unwrap_or_default();
+            sinks.push(Box::new(MetricsSink::new(Arc::clone(&metrics), &watched)));
+            Some(metrics)
+        }
+        None => None,
+    };
+    let dashboard = match matches.get_many::<String>("dashboard") {
+        Some(specs) => Some(DashboardSink::new(
+            specs
+                .map(|spec| FieldRef::parse(spec))
+                .collect::<Result<Vec<_>>>()?,
+            Duration::from_millis(*matches.get_one::<u64>("dashboard-interval").unwrap()), // Has default
+        )),
+        None => None,
+    };
+    if live && sinks.is_empty() && dashboard.is_none() {
+        return Err(WallaceError::InvalidArgument(
+            "'--live' needs at least one sink such as '--ws-listen', '--mqtt-broker', '--metrics-listen' or '--dashboard'".to_string(),
+        ));
+    }
 
     // --- End Argument Parsing ---
 
     // --- Load data and process messages ---
 
-    // Load message registry from JSON
-    let registry = load_message_registry(registry_path)?;
+    // Load message registry from JSON, with the log's firmware version read once up front: live
+    // mode reloads the registry without reading the log again
+    let firmware = if from_exports {
+        None
+    } else {
+        firmware::detect(input_path, &load_message_registry(registry_path)?)?
+    };
+    if let Some(version) = &firmware {
+        println!("🏷️  Firmware {}, using its field layout", version);
+    }
+    let registry_setup = RegistrySetup {
+        path: registry_path,
+        firmware,
+        skip_names: matches
+            .get_many::<String>("skip-field")
+            .map(|v| v.map(String::as_str).collect())
+            .unwrap_or_default(),
+        keep_skipped: matches.get_flag("keep-skipped"),
+        unit_targets: match matches.get_one::<String>("convert").map(String::as_str) {
+            Some(spec) => parse_targets(spec)?,
+            None => Vec::new(),
+        },
+    };
+    let (registry, converter) = registry_setup.load()?;
+    let histograms = match matches.get_many::<String>("histogram") {
+        Some(specs) => specs
+            .map(|spec| HistogramSpec::parse(spec))
+            .collect::<Result<Vec<_>>>()?,
+        None => Vec::new(),
+    };
+    let extrema = match matches.get_many::<String>("extrema") {
+        Some(specs) => specs
+            .map(|spec| ExtremaSpec::parse(spec))
+            .collect::<Result<Vec<_>>>()?,
+        None => Vec::new(),
+    };
+    let changes = match matches.get_many::<String>("changes") {
+        Some(specs) => specs
+            .map(|spec| FieldRef::parse(spec))
+            .collect::<Result<Vec<_>>>()?,
+        None => Vec::new(),
+    };
+    let correlations = match matches.get_many::<String>("correlate") {
+        Some(specs) => specs
+            .map(|spec| CorrelationSpec::parse(spec))
+            .collect::<Result<Vec<_>>>()?,
+        None => Vec::new(),
+    };
+    let battery = matches
+        .get_one::<String>("battery")
+        .map(|spec| BatterySpec::parse(spec))
+        .transpose()?;
+    let altitude = matches
+        .get_one::<String>("altitude")
+        .map(|spec| AltitudeSpec::parse(spec))
+        .transpose()?;
+    let track = matches
+        .get_one::<String>("track")
+        .map(|spec| PositionSpec::parse(spec))
+        .transpose()?;
+    let mut transform = match matches.get_one::<String>("transform") {
+        Some(path) => Some(ScriptTransform::load(
+            path,
+            ScriptLimits {
+                max_operations: *matches.get_one::<u64>("transform-max-operations").unwrap(), // Has default
+                ..Default::default()
+            },
+        )?),
+        None => None,
+    };
+    let geofilter = match matches.get_one::<String>("geofilter") {
+        Some(path) => Some(Geofilter::new(
+            path,
+            PositionSpec::parse(matches.get_one::<String>("geofilter-position").unwrap())?, // Has default
+            matches
+                .get_one::<String>("geofilter-mode")
+                .and_then(|name| GeofilterMode::from_name(name))
+                .unwrap_or(GeofilterMode::Mark), // Restricted to known names
+        )?),
+        None => None,
+    };
+    let mut derived = DerivedColumns::parse_rates(
+        matches
+            .get_many::<String>("rate")
+            .into_iter()
+            .flatten()
+            .map(String::as_str),
+    )?;
+    derived.parse_smoothing(
+        matches
+            .get_many::<String>("smooth")
+            .into_iter()
+            .flatten()
+            .map(String::as_str),
+    )?;
+    derived.check(&registry)?;
+    let columns = ColumnSelection::parse(
+        matches
+            .get_many::<String>("columns")
+            .into_iter()
+            .flatten()
+            .map(String::as_str),
+    )?;
+    if let Some(geofilter) = &geofilter {
+        geofilter.check(&registry)?;
+    }
+    if let Some(track) = &track {
+        track.check(&registry)?;
+    }
+    if derived.is_empty() && geofilter.is_none() {
+        columns.check(&registry)?;
+    } else {
+        // Derived columns can be selected like the fields they come from
+        let mut with_derived = registry.clone();
+        derived.add_fields(&mut with_derived);
+        if let Some(geofilter) = &geofilter {
+            geofilter.add_fields(&mut with_derived);
+        }
+        columns.check(&with_derived)?;
+    }
+    let mut tags = Tags::parse(
+        matches
+            .get_many::<String>("tag")
+            .into_iter()
+            .flatten()
+            .map(String::as_str),
+    )?;
+    if let Some(flight) = &flight {
+        tags.push(FLIGHT_COLUMN, &flight.sha256)?;
+    }
+    tags.check(&registry)?;
+    if let Some(dashboard) = dashboard {
+        dashboard.check(&registry)?;
+        sinks.push(Box::new(dashboard));
+    }
+    for field in histograms
+        .iter()
+        .map(|spec| &spec.field)
+        .chain(extrema.iter().map(|spec| &spec.field))
+        .chain(
+            correlations
+                .iter()
+                .flat_map(|spec| [&spec.reference, &spec.signal]),
+        )
+        .chain(altitude.iter().flat_map(AltitudeSpec::fields))
+    {
+        field.check(&registry)?;
+    }
+    for field in &changes {
+        field.check_exists(&registry)?;
+    }
+
+    let mut memory = MemoryStats::new(matches.get_one::<u64>("max-memory").copied());
+    // Options that need every message at once, so a log too large for `--max-memory` cannot be
+    // exported in chunks with them
+    let whole_log: Vec<&str> = [
+        ("'-o -'", to_stdout),
+        (
+            "a '--format' other than csv, ndjson or avro",
+            !format.splits_into_files(),
+        ),
+        ("'--rows-per-file'", matches.contains_id("rows-per-file")),
+        ("'--dedup'", matches.get_flag("dedup")),
+        ("'--geofilter'", geofilter.is_some()),
+        ("'--rate' or '--smooth'", !derived.is_empty()),
+        ("'--histogram'", !histograms.is_empty()),
+        ("'--extrema'", !extrema.is_empty()),
+        ("'--changes'", !changes.is_empty()),
+        ("'--correlate'", !correlations.is_empty()),
+        ("'--battery'", battery.is_some()),
+        ("'--altitude'", altitude.is_some()),
+        ("'--track'", track.is_some()),
+        ("'--influx-url'", matches.contains_id("influx-url")),
+    ]
+    .into_iter()
+    .filter_map(|(option, given)| given.then_some(option))
+    .collect();
+
+    let sampling = match (
+        matches.get_one::<f64>("sample"),
+        matches.get_one::<u64>("reservoir"),
+    ) {
+        (Some(&percent), _) => Some(Sampling::percent(percent)),
+        (_, Some(&size)) => Some(Sampling::Reservoir(size as usize)),
+        _ => None,
+    };
+
+    // Exports read back are already decoded, binary logs are decoded here
+    let redactor = commands::redactor_from(&matches, &registry)?;
+    if from_exports && (live || matches.get_flag("use-index") || redactor.is_some()) {
+        return Err(WallaceError::InvalidArgument(
+            "'--live', '--use-index' and '--redact' need a binary log, not exports".to_string(),
+        ));
+    }
+    if from_exports && sampling.is_some() {
+        return Err(WallaceError::InvalidArgument(
+            "'--sample' and '--reservoir' need a binary log, not exports".to_string(),
+        ));
+    }
+    // With --incremental, the messages an earlier run decoded into the same output directory are
+    // read back as long as the log, the registry and the decoding options are unchanged
+    let cache_key = if matches.get_flag("incremental") {
+        if from_exports || input_path == "-" || to_stdout {
+            return Err(WallaceError::InvalidArgument(
+                "'--incremental' needs a binary log file and an output directory".to_string(),
+            ));
+        }
+        Some(CacheKey::new(
+            input_path,
+            registry_path,
+            &decoding_options(&matches),
+        )?)
+    } else {
+        None
+    };
+    let cached = cache_key
+        .as_ref()
+        .and_then(|key| parse_cache::load(Path::new(output_path), key));
+    // With several jobs, the per-type files are written while the log is decoded, unless
+    // something needs all the messages first or reworks them on their way to the exports
+    let streamed = jobs(&matches) > 1
+        && whole_log.is_empty()
+        && cache_key.is_none()
+        && columns.is_empty()
+        && !matches.get_flag("metadata")
+        && tags.is_empty();
+    let (mut all_messages, warnings, skipped_fields, record_counts) = if from_exports {
+        let messages = read_exports(Path::new(input_path), &registry)?;
+        println!(
+            "📥 Read {} messages from the exports in '{}'",
+            messages.len(),
+            input_path
+        );
+        let mut record_counts: BTreeMap<u16, RecordCount> = BTreeMap::new();
+        // Exports rarely keep payload lengths, so their lengths are not counted
+        for msg in &messages {
+            let count = record_counts.entry(msg.log_type).or_default();
+            count.records += 1;
+            count.payload_bytes += msg.payload_len as u64;
+        }
+        (messages, Vec::new(), 0, record_counts)
+    } else if let Some(extracted) = cached {
+        println!(
+            "♻️  Read {} decoded messages back from '{}', the log, registry and decoding options are unchanged",
+            extracted.0.len(),
+            parse_cache::path(Path::new(output_path))?.display()
+        );
+        extracted
+    } else {
+        // Open the input file (handles bzip2 decompression), or only the records selected via the index
+        let mut reader = if matches.get_flag("use-index") {
+            open_indexed(&matches, input_path, &registry)?
+        } else {
+            open_file_with_jobs(input_path, jobs(&matches))?
+        };
+
+        // --- Live mode: publish each message as soon as its record is complete ---
+        if live {
+            return run_live(
+                reader,
+                &registry_setup,
+                registry,
+                converter,
+                decode_options,
+                &mut sinks,
+                metrics.as_deref(),
+            );
+        }
+
+        // Extract messages from the input file; with a memory limit, only until they reach it
+        let mut redactor = redactor;
+        let extracted = match (sampling, memory.chunk_budget()) {
+            // A sample is small enough to keep whole
+            (Some(sampling), _) => {
+                let sampler = Sampler::new(sampling, commands::seed(&matches));
+                let extracted = extract_messages_sampled(
+                    &mut reader,
+                    &registry,
+                    sampler,
+                    redactor.as_mut(),
+                    decode_options,
+                )?;
+                println!(
+                    "🎲 Sampled {} of {} records, {}",
+                    extracted.0.len(),
+                    extracted
+                        .3
+                        .values()
+                        .map(|count| count.records)
+                        .sum::<usize>(),
+                    sampling.describe()
+                );
+                extracted
+            }
+            (None, Some(budget)) => {
+                let mut stream = MessageStream::new(&mut reader, &registry)?;
+                stream.options = decode_options;
+                let (messages, complete) = read_chunk(&mut stream, redactor.as_mut(), budget)?;
+                if !complete {
+                    // --- Chunked mode: the log does not fit, export it as it is decoded ---
+                    if !whole_log.is_empty() {
+                        return Err(WallaceError::InvalidArgument(format!(
+                            "the decoded log does not fit in '--max-memory {}' and cannot be exported in chunks with {}",
+                            format_size(budget * 2),
+                            whole_log.join(", ")
+                        )));
+                    }
+                    println!(
+                        "🧮 Decoded messages passed {} (half of '--max-memory'), exporting in chunks",
+                        format_size(budget)
+                    );
+                    let output_dir = Path::new(output_path);
+                    fs::create_dir_all(output_dir)?;
+                    let export_options = export_options(
+                        &matches,
+                        input_path,
+                        registry_path,
+                        columns,
+                        tags.clone(),
+                        decode_options.bytes,
+                        None,
+                    )?;
+                    let mut summary = Summary::new(input_path, registry_path, &[], 0, 0);
+                    let mut spill = Spill::new(output_dir)?;
+                    let mut converted = 0;
+                    let (mut chunk, mut complete) = (messages, false);
+                    // The input can end right after a full chunk
+                    while !chunk.is_empty() {
+                        converted += prepare_chunk(
+                            &mut chunk,
+                            &converter,
+                            transform.as_mut(),
+                            &mut sinks,
+                            &registry,
+                            &mut summary,
+                        )?;
+                        let grouped = group_by_type(&chunk);
+                        memory.observe(2 * messages_size(&chunk));
+                        drop(chunk);
+                        spill.write(&grouped, format, &registry, &export_options)?;
+                        drop(grouped);
+                        if complete {
+                            break;
+                        }
+                        (chunk, complete) = read_chunk(&mut stream, redactor.as_mut(), budget)?;
+                    }
+                    memory.chunks = spill.chunks();
+                    spill.merge(output_dir, format, &export_options)?;
+                    if let Some(redactor) = &redactor {
+                        println!("🕶️  Redacted {} field values", redactor.fields_redacted);
+                    }
+                    if converted > 0 {
+                        println!("📐 Converted {} values to other units", converted);
+                    }
+                    if let Some(transform) = &transform {
+                        print_transform(transform);
+                    }
+                    println!(
+                        "🧮 Exported {} messages in {} chunks, about {} of them in memory at the peak",
+                        summary.total_messages,
+                        memory.chunks,
+                        format_size(memory.peak_bytes)
+                    );
+                    summary.warnings = stream.warnings.len();
+                    summary.skipped_fields = stream.skipped_fields;
+                    summary.tags = tags.to_map();
+                    summary.memory = memory;
+                    return finish_run(
+                        output_dir,
+                        &summary,
+                        &stream.warnings,
+                        &stream.record_counts,
+                        &registry,
+                        archive_format,
+                    );
+                }
+                (
+                    messages,
+                    stream.warnings,
+                    stream.skipped_fields,
+                    stream.record_counts,
+                )
+            }
+            // --- Streamed mode: the writers take each chunk of messages as it is decoded ---
+            (None, None) if streamed => {
+                let mut stream = MessageStream::new(&mut reader, &registry)?;
+                stream.options = decode_options;
+                let output_dir = Path::new(output_path);
+                fs::create_dir_all(output_dir)?;
+                let export_options = export_options(
+                    &matches,
+                    input_path,
+                    registry_path,
+                    columns,
+                    tags.clone(),
+                    decode_options.bytes,
+                    None,
+                )?;
+                let mut summary = Summary::new(input_path, registry_path, &[], 0, 0);
+                let mut converted = 0;
+                write_files(output_dir, format, &registry, &export_options, |writers| {
+                    loop {
+                        let (mut chunk, complete) =
+                            read_chunk(&mut stream, redactor.as_mut(), STREAMED_CHUNK_BYTES)?;
+                        converted += prepare_chunk(
+                            &mut chunk,
+                            &converter,
+                            transform.as_mut(),
+                            &mut sinks,
+                            &registry,
+                            &mut summary,
+                        )?;
+                        memory.observe(messages_size(&chunk));
+                        writers.write_messages(chunk)?;
+                        if complete {
+                            return Ok(());
+                        }
+                    }
+                })?;
+                if let Some(redactor) = &redactor {
+                    println!("🕶️  Redacted {} field values", redactor.fields_redacted);
+                }
+                if converted > 0 {
+                    println!("📐 Converted {} values to other units", converted);
+                }
+                if let Some(transform) = &transform {
+                    print_transform(transform);
+                }
+                summary.warnings = stream.warnings.len();
+                summary.skipped_fields = stream.skipped_fields;
+                summary.memory = memory;
+                return finish_run(
+                    output_dir,
+                    &summary,
+                    &stream.warnings,
+                    &stream.record_counts,
+                    &registry,
+                    archive_format,
+                );
+            }
+            (None, None) => match redactor.as_mut() {
+                Some(redactor) => {
+                    extract_messages_redacted(&mut reader, &registry, redactor, decode_options)?
+                }
+                None => extract_messages(&mut reader, &registry, decode_options)?,
+            },
+        };
+        if let Some(redactor) = &redactor {
+            println!("🕶️  Redacted {} field values", redactor.fields_redacted);
+        }
+        if let Some(key) = &cache_key {
+            parse_cache::save(Path::new(output_path), key, &extracted)?;
+        }
+        extracted
+    };
+
+    // Drop records written twice before they are counted, converted or exported
+    if matches.get_flag("dedup") {
+        let dropped = drop_duplicates(&mut all_messages);
+        println!("🧹 Dropped {} duplicate records", dropped);
+    }
+
+    // Positions are read before unit conversion, in the scale they were logged in
+    if let Some(geofilter) = &geofilter {
+        let stats = geofilter.apply(&mut all_messages);
+        println!(
+            "🗺️  {} records inside the geofence, {} outside, {} before the first fix",
+            stats.inside, stats.outside, stats.unknown
+        );
+        if stats.dropped > 0 {
+            println!("🧹 Dropped {} records by the geofence", stats.dropped);
+        }
+    }
+
+    // Convert values to the requested units before anything is written
+    if !converter.is_empty() {
+        let converted = converter.apply(&mut all_messages);
+        println!("📐 Converted {} values to other units", converted);
+    }
+
+    // The transform script sees the converted values, and its changes feed the derived columns
+    if let Some(transform) = transform.as_mut() {
+        transform.apply(&mut all_messages)?;
+        print_transform(transform);
+    }
 
-    // Open the input file (handles bzip2 decompression)
-    let mut reader = open_file(input_path)?;
+    // Derived columns come from the converted values and are exported like any other field
+    let mut registry = registry;
+    if let Some(geofilter) = &geofilter {
+        geofilter.add_fields(&mut registry);
+    }
+    if !derived.is_empty() {
+        derived.add_fields(&mut registry);
+        let computed = derived.apply(&mut all_messages);
+        println!("📉 Computed {} derived values", computed);
+    }
 
-    // Extract messages from the input file
-    let (all_messages, warnings, skipped_fields) =
-        extract_messages(&mut reader, &registry)?;
+    // --- Publish to message sinks (Kafka, ...) in log order ---
+    if !sinks.is_empty() {
+        publish_all(&mut sinks, &all_messages, &registry)?;
+    }
+
+    // --- Stream mode: every message in log order as one NDJSON stream on stdout ---
+    if to_stdout {
+        let mut registry = registry;
+        if matches.get_flag("metadata") {
+            add_metadata(&mut all_messages, &mut registry);
+        }
+        tags.apply(&mut all_messages, &mut registry);
+        let stdout = std::io::stdout();
+        let mut out = std::io::BufWriter::new(stdout.lock());
+        let written = write_ndjson(&mut out, &all_messages, &registry).and_then(|_| {
+            out.flush()?;
+            Ok(())
+        });
+        match written {
+            // The consumer (e.g. `head`) closing the pipe early is not an error
+            Err(WallaceError::Io(e)) if e.kind() == std::io::ErrorKind::BrokenPipe => {}
+            other => other?,
+        }
+        // Keep stdout clean for the pipeline, report on stderr instead
+        if !warnings.is_empty() {
+            eprintln!("⚠️  {} warnings while parsing", warnings.len());
+        }
+        return Ok(());
+    }
 
     // Group messages by type
     let grouped = group_by_type(&all_messages);
+    // Grouping copies the messages, and both are held until the analyses are done
+    memory.observe(2 * messages_size(&all_messages));
+    if let Some(limit) = memory.limit_bytes {
+        println!(
+            "🧮 About {} of decoded messages in memory at the peak, of '--max-memory {}'",
+            format_size(memory.peak_bytes),
+            format_size(limit)
+        );
+    }
 
     // Create the output directory if it doesn't exist
     let output_dir = Path::new(output_path);
@@ -83,26 +1309,212 @@ fn main() -> Result<()> {
         fs::create_dir_all(output_dir)?; // io::Error automatically converted by #[from]
     }
 
-    // Export each message group to a CSV file
-    for (name, group) in &grouped {
-        let file_path = output_dir.join(format!("{}.csv", name));
-        // Handle potential path conversion error
-        let file_path_str =
-            file_path
-                .to_str()
-                .ok_or_else(|| WallaceError::PathConversionError {
-                    path: file_path.clone(),
-                })?;
-        export_to_csv(file_path_str, group)?;
+    // Export the message groups in the requested format
+    let export_options = export_options(
+        &matches,
+        input_path,
+        registry_path,
+        columns,
+        tags.clone(),
+        decode_options.bytes,
+        flight.clone(),
+    )?;
+    export_all(output_dir, &grouped, format, &registry, &export_options)?;
+
+    // Load the generated bundle if a database was given
+    if let Some(url) = pg_url {
+        load_into_postgres(output_dir, url, flight.as_ref())?;
+    }
+
+    // Push the same data straight into InfluxDB if an endpoint was given
+    if let Some(url) = matches.get_one::<String>("influx-url").map(String::as_str) {
+        write_to_influx(
+            url,
+            matches
+                .get_one::<String>("influx-token")
+                .map(String::as_str),
+            &grouped,
+            &registry,
+            require_start_time(start_time)?,
+        )?;
+    }
+
+    // --- Analyses of selected fields ---
+    for spec in &histograms {
+        let values = spec.field.values(&all_messages);
+        match Histogram::compute(&values, spec.bins) {
+            Some(histogram) => {
+                let path = output_dir.join(format!("histogram_{}.csv", spec.field.file_stem()));
+                write_histogram(&path, &histogram)?;
+                println!(
+                    "📊 Wrote histogram of {} ({} values) to '{}'",
+                    spec.field,
+                    values.len(),
+                    path.display()
+                );
+            }
+            None => println!("⚠️  No values of {}, no histogram written", spec.field),
+        }
+    }
+
+    if !extrema.is_empty() {
+        let reports: Vec<_> = extrema
+            .iter()
+            .map(|spec| {
+                let series = spec.field.series(&all_messages);
+                (&spec.field, find_events(&series, &spec.thresholds))
+            })
+            .collect();
+        let path = output_dir.join("extrema.csv");
+        write_extrema(&path, &reports)?;
+        println!(
+            "📈 Wrote {} extrema and threshold crossings to '{}'",
+            reports
+                .iter()
+                .map(|(_, events)| events.len())
+                .sum::<usize>(),
+            path.display()
+        );
+    }
+
+    if !changes.is_empty() {
+        let found = find_changes(&changes, &all_messages);
+        let path = output_dir.join("changes.csv");
+        write_changes(&path, &found)?;
+        println!(
+            "🔁 Wrote {} value changes of {} fields to '{}'",
+            found.len(),
+            changes.len(),
+            path.display()
+        );
+    }
+
+    if !correlations.is_empty() {
+        let results: Vec<_> = correlations
+            .iter()
+            .map(|spec| {
+                let reference = spec.reference.series(&all_messages);
+                let signal = spec.signal.series(&all_messages);
+                let result = correlate(&reference, &signal);
+                if result.is_none() {
+                    println!(
+                        "⚠️  {} and {} do not overlap in time, not compared",
+                        spec.reference, spec.signal
+                    );
+                }
+                (spec, result)
+            })
+            .collect();
+        let path = output_dir.join("correlation.csv");
+        write_correlations(&path, &results)?;
+        println!(
+            "🔗 Wrote {} signal comparisons to '{}'",
+            results.len(),
+            path.display()
+        );
     }
 
+    if let Some(spec) = &battery {
+        let voltage = spec.voltage.series(&all_messages);
+        let current = spec.current.series(&all_messages);
+        match integrate(&voltage, &current, spec.idle_current) {
+            Some(report) => {
+                write_battery_report(
+                    output_dir.join("battery.csv"),
+                    output_dir.join("battery_segments.csv"),
+                    &report,
+                )?;
+                println!(
+                    "🔋 Consumed {:.1} mAh / {:.2} Wh, {} flight segments, written to '{}'",
+                    report.total_mah,
+                    report.total_wh,
+                    report.segments.len(),
+                    output_dir.join("battery.csv").display()
+                );
+            }
+            None => println!(
+                "⚠️  No values of {} or {}, no battery report written",
+                spec.voltage, spec.current
+            ),
+        }
+    }
+
+    if let Some(spec) = &altitude {
+        let baro = spec.baro.series(&all_messages);
+        let gps = spec.gps.as_ref().map(|field| field.series(&all_messages));
+        match altitude_profile(&baro, gps.as_deref(), spec.airborne) {
+            Some(report) => {
+                write_altitude_report(
+                    output_dir.join("altitude.csv"),
+                    output_dir.join("altitude_segments.csv"),
+                    &report,
+                )?;
+                println!(
+                    "⛰️  Reached {:.1} m above the launch point, {} flight segments, written to '{}'",
+                    report.max_agl,
+                    report.segments.len(),
+                    output_dir.join("altitude.csv").display()
+                );
+            }
+            None => println!(
+                "⚠️  No values of {}, no altitude profile written",
+                spec.baro
+            ),
+        }
+    }
+
+    // --- Write run summary ---
+    let mut summary = Summary::new(
+        input_path,
+        registry_path,
+        &all_messages,
+        warnings.len(),
+        skipped_fields,
+    );
+    summary.tags = tags.to_map();
+    summary.memory = memory;
+    if let Some(spec) = &track {
+        summary.track = track_stats(&spec.fixes(&all_messages));
+        match &summary.track {
+            Some(track) => println!(
+                "🛰️  Covered {:.2} km over {} fixes, at most {:.0} m from the first fix",
+                track.distance_m / 1000.0,
+                track.fixes,
+                track.max_distance_from_launch_m
+            ),
+            None => println!(
+                "⚠️  No position fixes in {} and {}, no track in the summary",
+                spec.lat, spec.lon
+            ),
+        }
+    }
+    finish_run(
+        output_dir,
+        &summary,
+        &warnings,
+        &record_counts,
+        &registry,
+        archive_format,
+    )
+}
+
+// Reports that close every run exporting to a directory: the warnings, what decoding lost or
+// skipped, the summary and record counts, and the archive if requested
+fn finish_run(
+    output_dir: &Path,
+    summary: &Summary,
+    warnings: &[String],
+    record_counts: &BTreeMap<u16, RecordCount>,
+    registry: &messages::MessageRegistry,
+    archive_format: Option<ArchiveFormat>,
+) -> Result<()> {
     // --- Handle warnings ---
     // Check if there are any warnings
     if !warnings.is_empty() {
         // Ensure warnings log is also placed in the specified output directory
         let warnings_path = output_dir.join("warnings.log");
         let mut log_file = std::fs::File::create(&warnings_path)?; // io::Error automatically converted
-        for line in &warnings {
+        for line in warnings {
             writeln!(log_file, "{}", line)?; // io::Error automatically converted
         }
         println!(
@@ -112,14 +1524,436 @@ fn main() -> Result<()> {
         );
     }
 
+    for (policy, partial) in &summary.partial_records {
+        println!(
+            "✂️  {} records ended before their fields did ('--partial {}')",
+            partial, policy
+        );
+    }
+
+    if summary.salvaged_records > 0 {
+        println!(
+            "🩹 Salvaged the leading fields of {} records that failed to decode",
+            summary.salvaged_records
+        );
+    }
+
     // --- Print summary of skipped fields ---
     // Check if any ignorable fields were skipped
-    if skipped_fields > 0 {
+    if summary.skipped_fields > 0 {
         println!(
             "⏭️  Skipped {} ignorable fields like TRASH, PADDING, RESERVED",
-            skipped_fields
+            summary.skipped_fields
         );
     }
 
+    write_summary(output_dir.join("summary.json"), summary)?;
+    let counts_path = output_dir.join("counts.csv");
+    let misframed = write_counts(&counts_path, record_counts, registry)?;
+    if !misframed.is_empty() {
+        println!(
+            "⚠️  {} message types have records of payload lengths the registry does not give them ({}), the sign of a framing or registry bug, see '{}'",
+            misframed.len(),
+            misframed.join(", "),
+            counts_path.display()
+        );
+    }
+    let coverage_path = output_dir.join("coverage.csv");
+    let coverage = write_coverage(&coverage_path, record_counts, registry)?;
+    if coverage.missing > 0 || coverage.unregistered > 0 {
+        println!(
+            "🧭 {} registry messages never appear in the log and {} log types are not in the registry, see '{}'",
+            coverage.missing,
+            coverage.unregistered,
+            coverage_path.display()
+        );
+    }
+
+    // --- Bundle outputs into a single artifact if requested ---
+    if let Some(format) = archive_format {
+        let archive_path = archive_directory(output_dir, format)?;
+        println!("📦 Archived outputs to '{}'", archive_path.display());
+    }
+
+    Ok(())
+}
+
+// Decoded messages handed to the writers at a time in streamed mode
+const STREAMED_CHUNK_BYTES: u64 = 4 << 20;
+
+// What chunked and streamed runs do to each chunk of messages before exporting it: unit
+// conversion, the transform script, publishing to the sinks and counting. Returns the values
+// converted.
+fn prepare_chunk(
+    chunk: &mut Vec<ParsedMessage>,
+    converter: &UnitConverter,
+    transform: Option<&mut ScriptTransform>,
+    sinks: &mut [Box<dyn MessageSink>],
+    registry: &messages::MessageRegistry,
+    summary: &mut Summary,
+) -> Result<usize> {
+    let converted = match converter.is_empty() {
+        true => 0,
+        false => converter.apply(chunk),
+    };
+    if let Some(transform) = transform {
+        transform.apply(chunk)?;
+    }
+    if !sinks.is_empty() {
+        publish_all(sinks, chunk, registry)?;
+    }
+    summary.count(chunk);
+    Ok(converted)
+}
+
+// Decodes messages until the input ends (true) or their estimated size reaches `budget` (false),
+// at least one unless the input ends
+fn read_chunk<R: std::io::Read>(
+    stream: &mut MessageStream<R>,
+    mut redactor: Option<&mut Redactor>,
+    budget: u64,
+) -> Result<(Vec<ParsedMessage>, bool)> {
+    let mut messages = Vec::new();
+    let mut size = 0;
+    while size < budget || messages.is_empty() {
+        let Some(mut record) = stream.next_record()? else {
+            return Ok((messages, true));
+        };
+        if let Some(redactor) = redactor.as_deref_mut() {
+            redactor.apply(&mut record);
+        }
+        if let Some(msg) = stream.decode(&record)? {
+            size += message_size(&msg);
+            messages.push(msg);
+        }
+    }
+    Ok((messages, false))
+}
+
+fn export_options(
+    matches: &clap::ArgMatches,
+    input_path: &str,
+    registry_path: &str,
+    columns: ColumnSelection,
+    tags: Tags,
+    bytes: BytesFormat,
+    append: Option<Flight>,
+) -> Result<ExportOptions> {
+    Ok(ExportOptions {
+        missing_value: matches
+            .get_one::<String>("missing-value")
+            .cloned()
+            .unwrap_or_default(),
+        start_time: matches.get_one::<u64>("start-time").copied(),
+        provenance: Some(Provenance::new(
+            input_path,
+            registry_path,
+            provenance_options(matches),
+        )?),
+        provenance_header: matches.get_flag("provenance-header"),
+        keep_formulas: matches.get_flag("keep-formulas"),
+        bytes,
+        columns,
+        metadata: matches.get_flag("metadata"),
+        tags,
+        jobs: jobs(matches),
+        rows_per_file: matches
+            .get_one::<u64>("rows-per-file")
+            .map(|&rows| rows as usize),
+        numbers: number_format(matches)?,
+        append,
+    })
+}
+
+// The locale's number format with the separators given on their own replacing its own
+fn number_format(matches: &clap::ArgMatches) -> Result<NumberFormat> {
+    let mut numbers = matches
+        .get_one::<String>("locale")
+        .and_then(|name| NumberFormat::from_locale(name))
+        .unwrap_or_default(); // Restricted to known names
+    if let Some(decimal) = matches.get_one::<String>("decimal-separator") {
+        numbers.decimal = decimal.chars().next().unwrap_or('.');
+    }
+    if let Some(grouping) = matches.get_one::<String>("digit-grouping") {
+        numbers.grouping = NumberFormat::parse_separator(grouping);
+    }
+    numbers.check()?;
+    Ok(numbers)
+}
+
+// Threads for decompression and exports: `--jobs`, or one per CPU
+fn jobs(matches: &clap::ArgMatches) -> usize {
+    matches
+        .get_one::<u64>("jobs")
+        .map(|&jobs| jobs as usize)
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()))
+}
+
+// Options that change what ends up in the exports, as given, for the provenance sidecars.
+// Destinations and credentials are left out.
+fn provenance_options(matches: &clap::ArgMatches) -> BTreeMap<String, String> {
+    const RECORDED: &[&str] = &[
+        "config",
+        "profile",
+        "registry-manifest",
+        "format",
+        "missing-value",
+        "locale",
+        "decimal-separator",
+        "digit-grouping",
+        "convert",
+        "transform",
+        "columns",
+        "rate",
+        "smooth",
+        "tag",
+        "metadata",
+        "keep-formulas",
+        "append",
+        "rows-per-file",
+        "max-memory",
+        "skip-field",
+        "keep-skipped",
+        "partial",
+        "lenient",
+        "bytes-format",
+        "dedup",
+        "sample",
+        "reservoir",
+        "use-index",
+        "types",
+        "from",
+        "to",
+        "geofilter",
+        "geofilter-mode",
+        "geofilter-position",
+        "redact",
+        "redact-rules",
+        "seed",
+    ];
+    RECORDED
+        .iter()
+        .filter_map(|id| {
+            let values: Vec<String> = matches
+                .get_raw(id)?
+                .map(|v| v.to_string_lossy().into_owned())
+                .collect();
+            Some((id.to_string(), values.join(",")))
+        })
+        .collect()
+}
+
+fn print_transform(transform: &ScriptTransform) {
+    println!(
+        "🧩 The transform script changed {} values and dropped {} messages",
+        transform.changed, transform.dropped
+    );
+}
+
+// The recorded options that change how the log is decoded, which a parse cache must match
+fn decoding_options(matches: &clap::ArgMatches) -> BTreeMap<String, String> {
+    const DECODING: &[&str] = &[
+        "skip-field",
+        "keep-skipped",
+        "partial",
+        "lenient",
+        "bytes-format",
+        "sample",
+        "reservoir",
+        "use-index",
+        "types",
+        "from",
+        "to",
+        "redact",
+        "redact-rules",
+        "seed",
+    ];
+    provenance_options(matches)
+        .into_iter()
+        .filter(|(id, _)| DECODING.contains(&id.as_str()))
+        .collect()
+}
+
+// Selects records with the sidecar index and reads just those, as if they were a log of their own
+fn open_indexed(
+    matches: &clap::ArgMatches,
+    input_path: &str,
+    registry: &messages::MessageRegistry,
+) -> Result<Box<dyn std::io::Read>> {
+    let index = LogIndex::load_for(Path::new(input_path))?;
+    let log_types = match matches.get_many::<String>("types") {
+        Some(names) => Some(resolve_types(
+            &names.map(String::as_str).collect::<Vec<_>>(),
+            registry,
+        )?),
+        None => None,
+    };
+    let start = index.first_timestamp.unwrap_or(0);
+    let from = matches.get_one::<u64>("from").copied();
+    let to = matches.get_one::<u64>("to").copied();
+    let entries = index.select(
+        log_types.as_deref(),
+        from.map(|f| start + f),
+        to.map(|t| start + t),
+    );
+    println!(
+        "🔎 Index selected {} of {} records",
+        entries.len(),
+        index.record_count()
+    );
+    let file = fs::File::open(input_path)?;
+    Ok(Box::new(std::io::BufReader::new(IndexedReader::new(
+        file,
+        index.header,
+        entries,
+    ))))
+}
+
+// How the registry is read and prepared, kept so that live mode can redo it when the file changes
+struct RegistrySetup<'a> {
+    path: &'a str,
+    /// The log's firmware version; `None` when reading exports or when it has no version record
+    firmware: Option<firmware::FirmwareVersion>,
+    skip_names: Vec<&'a str>,
+    keep_skipped: bool,
+    unit_targets: Vec<(String, String)>,
+}
+
+impl RegistrySetup<'_> {
+    fn load(&self) -> Result<(messages::MessageRegistry, UnitConverter)> {
+        let mut registry = load_registry_for_firmware(self.path, self.firmware.as_ref())?;
+        set_skip_policy(&mut registry, &self.skip_names, self.keep_skipped);
+        let converter = UnitConverter::new(&mut registry, &self.unit_targets)?;
+        Ok((registry, converter))
+    }
+}
+
+// Feeds messages to the sinks one record at a time until the input ends. When the registry file
+// changes it is reloaded between two records; if it fails to load, the old one stays in use.
+fn run_live(
+    reader: Box<dyn std::io::Read>,
+    registry_setup: &RegistrySetup,
+    mut registry: messages::MessageRegistry,
+    mut converter: UnitConverter,
+    decode_options: DecodeOptions,
+    sinks: &mut [Box<dyn MessageSink>],
+    metrics: Option<&Mutex<LiveMetrics>>,
+) -> Result<()> {
+    // The stream only frames records here; they are decoded against the current registry
+    let framing = messages::MessageRegistry::new();
+    let mut stream = MessageStream::new(std::io::BufReader::new(reader), &framing)?;
+    stream.options = decode_options;
+    let mut watcher = RegistryWatcher::new(registry_setup.path);
+    let mut count = 0usize;
+    loop {
+        let mut encoder = JsonEncoder::new(&registry);
+        let defs = Definitions::new(&registry);
+        let mut reloaded = None;
+        loop {
+            if watcher.changed() {
+                match registry_setup.load() {
+                    Ok(setup) => {
+                        reloaded = Some(setup);
+                        break;
+                    }
+                    Err(e) => println!(
+                        "⚠️  Keeping the previous registry, '{}' failed to load: {}",
+                        registry_setup.path, e
+                    ),
+                }
+            }
+            let Some(record) = stream.next_record()? else {
+                break;
+            };
+            let decoded = decode_record(
+                &record,
+                &defs,
+                &mut stream.warnings,
+                &mut stream.skipped_fields,
+                &mut stream.unknown_records,
+                stream.options,
+            )?;
+            if let Some(mut msg) = decoded {
+                converter.apply(std::slice::from_mut(&mut msg));
+                let json = encoder.encode(&msg);
+                for sink in sinks.iter_mut() {
+                    sink.send(&msg, &json)?;
+                }
+                count += 1;
+            }
+            if let Some(metrics) = metrics {
+                metrics.lock().unwrap().set_progress(
+                    stream.bytes_read,
+                    stream.warnings.len(),
+                    stream.unknown_records,
+                );
+            }
+            // Flush whenever the feed has gone quiet, so a burst is delivered as soon as it is decoded
+            if stream.get_ref().buffer().is_empty() {
+                for sink in sinks.iter_mut() {
+                    sink.flush()?;
+                }
+            }
+        }
+        // Swap in the new definitions between two records, or stop at the end of the input
+        let Some((new_registry, new_converter)) = reloaded else {
+            break;
+        };
+        drop(encoder);
+        registry = new_registry;
+        converter = new_converter;
+        println!("🔄 Reloaded registry '{}'", registry_setup.path);
+    }
+    for sink in sinks.iter_mut() {
+        sink.flush()?;
+    }
+    println!("✅ Streamed {} messages", count);
+    if !stream.warnings.is_empty() {
+        println!("⚠️  {} warnings while parsing", stream.warnings.len());
+    }
     Ok(())
 }
+
+// Creates the publishing sinks requested on the command line
+fn build_sinks(matches: &clap::ArgMatches) -> Result<Vec<Box<dyn MessageSink>>> {
+    let mut sinks: Vec<Box<dyn MessageSink>> = Vec::new();
+
+    if let Some(brokers) = matches
+        .get_one::<String>("kafka-brokers")
+        .map(String::as_str)
+    {
+        #[cfg(feature = "kafka")]
+        sinks.push(Box::new(sink::kafka::KafkaSink::new(
+            brokers,
+            matches
+                .get_one::<String>("topic-template")
+                .map(String::as_str)
+                .unwrap(), // Has default
+        )?));
+        #[cfg(not(feature = "kafka"))]
+        return Err(WallaceError::InvalidArgument(format!(
+            "cannot publish to '{}': built without Kafka support (enable the 'kafka' feature)",
+            brokers
+        )));
+    }
+
+    if let Some(broker) = matches.get_one::<String>("mqtt-broker").map(String::as_str) {
+        sinks.push(Box::new(sink::mqtt::MqttSink::new(
+            broker,
+            matches
+                .get_one::<String>("mqtt-topic-template")
+                .map(String::as_str)
+                .unwrap(), // Has default
+            matches
+                .get_one::<String>("vehicle")
+                .map(String::as_str)
+                .unwrap(), // Has default
+        )?));
+    }
+
+    if let Some(addr) = matches.get_one::<String>("ws-listen").map(String::as_str) {
+        sinks.push(Box::new(sink::websocket::WebSocketSink::bind(addr)?));
+    }
+
+    Ok(sinks)
+}
@@ -0,0 +1,112 @@
+// messages/firmware.rs
+// Firmware versions, for fields that only exist in some of them. A field with `since`/`until`
+// is kept only for versions in `since..until`; the version comes from the first record of the
+// message whose definition lists `firmware_version` fields, so one registry covers all layouts.
+
+use super::registry::MessageRegistry;
+use crate::errors::{Result, WallaceError};
+use crate::file_io::open_file;
+use crate::parser::MessageStream;
+use serde::Deserialize;
+use std::fmt;
+use std::io::BufReader;
+
+/// Records read from the start of a log looking for the version message
+pub const PROBE_RECORDS: usize = 100_000;
+
+/// Dotted version such as `2.14.1`, compared component by component
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(try_from = "String")]
+pub struct FirmwareVersion(Vec<u64>);
+
+impl FirmwareVersion {
+    pub fn parse(text: &str) -> Result<Self> {
+        text.split('.')
+            .map(|part| part.trim().parse::<u64>())
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map(FirmwareVersion)
+            .map_err(|_| {
+                WallaceError::InvalidArgument(format!(
+                    "invalid firmware version '{}': expected numbers separated by dots, e.g. 2.14",
+                    text
+                ))
+            })
+    }
+}
+
+impl TryFrom<String> for FirmwareVersion {
+    type Error = WallaceError;
+
+    fn try_from(text: String) -> Result<Self> {
+        FirmwareVersion::parse(&text)
+    }
+}
+
+impl fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = self.0.iter().map(u64::to_string).collect();
+        write!(f, "{}", parts.join("."))
+    }
+}
+
+/// Drops the fields that do not exist in `version`. Without a version, a log is taken to predate
+/// every `since`.
+pub fn apply_gates(registry: &mut MessageRegistry, version: Option<&FirmwareVersion>) {
+    for def in registry.values_mut() {
+        def.fields.retain(|field| {
+            let after_since = match (&field.since, version) {
+                (None, _) => true,
+                (Some(since), Some(version)) => version >= since,
+                (Some(_), None) => false,
+            };
+            let before_until = match (&field.until, version) {
+                (Some(until), Some(version)) => version < until,
+                _ => true,
+            };
+            after_since && before_until
+        });
+    }
+}
+
+/// The firmware version `input` was written by, read from the first record of the registry's
+/// version message. `None` if the registry has no version message, the log has no such record
+/// near its start, or the input is not a regular file: stdin, a pipe or a device cannot be read
+/// twice.
+pub fn detect(input: &str, registry: &MessageRegistry) -> Result<Option<FirmwareVersion>> {
+    let Some((log_type, def)) = registry
+        .iter()
+        .find(|(_, def)| !def.firmware_version.is_empty())
+    else {
+        return Ok(None);
+    };
+    if input == "-" || !std::fs::metadata(input).is_ok_and(|m| m.is_file()) {
+        return Ok(None);
+    }
+    let mut stream = MessageStream::new(BufReader::new(open_file(input)?), registry)?;
+    let mut records = 0;
+    while let Some(record) = stream.next_record()? {
+        records += 1;
+        if records > PROBE_RECORDS {
+            break;
+        }
+        if record.log_type.to_string() != *log_type {
+            continue;
+        }
+        let Some(msg) = stream.decode(&record)? else {
+            continue;
+        };
+        let parts: Option<Vec<&str>> = def
+            .firmware_version
+            .iter()
+            .map(|field| msg.field(field))
+            .collect();
+        let Some(parts) = parts else {
+            return Err(WallaceError::InvalidArgument(format!(
+                "firmware version fields {:?} are not all fields of '{}'",
+                def.firmware_version, def.name
+            )));
+        };
+        return FirmwareVersion::parse(&parts.join(".")).map(Some);
+    }
+    Ok(None)
+}
@@ -0,0 +1,139 @@
+// messages/manifest.rs
+// Picks the registry for a log from a manifest of rules, so that a mixed fleet's logs can be read
+// without naming the right definitions each time. Rules match on the 4-byte file header and on
+// field values of early records, e.g. a firmware version message, decoded with the rule's own
+// registry.
+
+use super::registry::{load_message_registry, MessageRegistry};
+use crate::errors::{Result, WallaceError};
+use crate::file_io::open_file;
+use crate::parser::MessageStream;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// Records read from the start of a log looking for the messages a rule checks
+pub const PROBE_RECORDS: usize = 100_000;
+
+/// ```json
+/// {
+///   "registries": [
+///     { "registry": "fw3.json", "header": 10, "when": { "FwVer.version_major": 3 } },
+///     { "registry": "fw2.json", "header": 10 }
+///   ],
+///   "default": "messages.json"
+/// }
+/// ```
+/// Registry paths are relative to the manifest.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub registries: Vec<Rule>,
+    /// Used when no rule matches
+    #[serde(default)]
+    pub default: Option<String>,
+}
+
+/// A registry and what a log must look like to use it; every condition given must hold
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub registry: String,
+    /// The file header, as the little-endian i32 it is read as
+    #[serde(default)]
+    pub header: Option<i32>,
+    /// `MESSAGE.FIELD` and the value it must have in the first such record of the log
+    #[serde(default)]
+    pub when: BTreeMap<String, serde_json::Value>,
+}
+
+impl Manifest {
+    pub fn load(path: &str) -> Result<Self> {
+        let manifest: Manifest = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        let base = Path::new(path).parent().unwrap_or(Path::new(""));
+        let resolve = |p: &str| base.join(p).to_string_lossy().into_owned();
+        Ok(Manifest {
+            registries: manifest
+                .registries
+                .into_iter()
+                .map(|rule| Rule {
+                    registry: resolve(&rule.registry),
+                    ..rule
+                })
+                .collect(),
+            default: manifest.default.as_deref().map(resolve),
+        })
+    }
+
+    /// The registry of the first rule that `input` matches, else the default
+    pub fn select(&self, input: &str) -> Result<&str> {
+        // Probing reads the start of the log once per rule, which a pipe or device can't replay
+        let regular = input != "-" && std::fs::metadata(input).is_ok_and(|m| m.is_file());
+        if !regular {
+            return Err(WallaceError::InvalidArgument(format!(
+                "a registry manifest needs a regular log file to probe, not '{}' (use --registry)",
+                input
+            )));
+        }
+        let header = MessageStream::new(open_file(input)?, &MessageRegistry::new())?.header;
+        for rule in &self.registries {
+            if rule.header.is_some_and(|h| h != header) {
+                continue;
+            }
+            if rule.when.is_empty() || rule.matches_records(input)? {
+                return Ok(&rule.registry);
+            }
+        }
+        self.default.as_deref().ok_or_else(|| {
+            WallaceError::InvalidArgument(format!(
+                "no registry in the manifest matches '{}' (header {}) and there is no default",
+                input, header
+            ))
+        })
+    }
+}
+
+impl Rule {
+    // Decodes the start of the log with this rule's registry and checks the first record of each
+    // message named in `when`
+    fn matches_records(&self, input: &str) -> Result<bool> {
+        let registry = load_message_registry(&self.registry)?;
+        let mut pending: BTreeMap<&str, Vec<(&str, &serde_json::Value)>> = BTreeMap::new();
+        for (key, expected) in &self.when {
+            let (message, field) = key.split_once('.').ok_or_else(|| {
+                WallaceError::InvalidArgument(format!(
+                    "manifest condition '{}' must name a MESSAGE.FIELD",
+                    key
+                ))
+            })?;
+            pending.entry(message).or_default().push((field, expected));
+        }
+        let mut stream = MessageStream::new(BufReader::new(open_file(input)?), &registry)?;
+        let mut records = 0;
+        while let Some(record) = stream.next_record()? {
+            records += 1;
+            if pending.is_empty() || records > PROBE_RECORDS {
+                break;
+            }
+            let Some(msg) = stream.decode(&record)? else {
+                continue;
+            };
+            let Some(conditions) = pending.remove(msg.name.as_str()) else {
+                continue;
+            };
+            let holds = conditions.iter().all(|(field, expected)| {
+                let wanted = match expected {
+                    serde_json::Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                };
+                msg.field(field) == Some(wanted.as_str())
+            });
+            if !holds {
+                return Ok(false);
+            }
+        }
+        Ok(pending.is_empty())
+    }
+}
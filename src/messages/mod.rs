@@ -1,8 +1,5 @@
+pub mod firmware;
+pub mod manifest;
 pub mod registry;
 
-pub use registry::{
-    FieldDef,
-    MessageDef,
-    MessageRegistry,
-    load_message_registry,
-};
+pub use registry::{load_message_registry, FieldDef, MessageDef, MessageRegistry};
@@ -1,29 +1,440 @@
 // messages/registry.rs
+use super::firmware::{apply_gates, FirmwareVersion};
+use crate::parser::DecodePlan;
 use serde::Deserialize;
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Clone, Deserialize)]
 pub struct FieldDef {
     pub name: String,
     pub r#type: String,
+    /// Physical unit of the decoded value, e.g. "m/s" (metadata only)
+    #[serde(default)]
+    pub unit: Option<String>,
+    /// Unit the value is converted to on export, overriding any `--convert` target for `unit`
+    #[serde(default)]
+    pub convert_to: Option<String>,
+    /// Labels for enumerated integer values, keyed by the raw value (metadata only)
+    #[serde(default, rename = "enum")]
+    pub enum_labels: Option<BTreeMap<String, String>>,
+    /// Whether the field is skipped while decoding; unset means skipped if named like padding
+    #[serde(default)]
+    pub skip: Option<bool>,
+    /// Character encoding of string fields
+    #[serde(default)]
+    pub encoding: StringEncoding,
+    /// Report strings that are not valid in their encoding and export their bytes as hex,
+    /// instead of substituting replacement characters
+    #[serde(default)]
+    pub strict: bool,
+    /// What happens to the bytes of a string field after its first NUL
+    #[serde(default)]
+    pub nul: NulPolicy,
+    /// First firmware version that logs the field
+    #[serde(default)]
+    pub since: Option<FirmwareVersion>,
+    /// First firmware version that no longer logs the field
+    #[serde(default)]
+    pub until: Option<FirmwareVersion>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NulPolicy {
+    /// Only trailing NULs are dropped, anything between the first NUL and them is kept
+    #[default]
+    Trim,
+    /// The first NUL terminates the string, whatever follows is dropped
+    Discard,
+    /// All bytes are kept, NULs included
+    Preserve,
+    /// The string ends at the first NUL; non-zero bytes after it are appended as `[hex]`
+    Hex,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StringEncoding {
+    #[default]
+    Utf8,
+    Utf16le,
+    Latin1,
+}
+
+/// Field names treated as padding and skipped unless the registry says otherwise
+pub const DEFAULT_SKIP_NAMES: &[&str] = &["TRASH", "PADDING", "RESERVED"];
+
+impl FieldDef {
+    /// True if the field is skipped while decoding and left out of exports
+    pub fn is_skipped(&self) -> bool {
+        self.skip
+            .unwrap_or_else(|| DEFAULT_SKIP_NAMES.contains(&self.name.as_str()))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct MessageDef {
     pub name: String,
     pub fields: Vec<FieldDef>,
+    /// How the payload struct was laid out by the logger
+    #[serde(default)]
+    pub packing: Packing,
+    /// Fields that together give the firmware version, e.g. major and minor, if this message
+    /// reports it. They must not be gated by version themselves.
+    #[serde(default)]
+    pub firmware_version: Vec<String>,
+    /// Field holding free text, e.g. status or event messages, which `messages` shows as a
+    /// timeline
+    #[serde(default)]
+    pub text: Option<String>,
+    /// Field giving the severity of `text`, as MAVLink numbers from 0 (emergency) to 7 (debug)
+    /// or as names such as WARNING, directly or through its `enum` labels
+    #[serde(default)]
+    pub severity: Option<String>,
+    /// Fields of a parameter record, which `params` collates into a parameter dump
+    #[serde(default)]
+    pub param: Option<ParamFields>,
+    /// Other layouts of the message, e.g. of firmware versions that logged fewer fields, each
+    /// used for the records whose payload has its size and not the size of `fields`
+    #[serde(default)]
+    pub variants: Vec<LayoutVariant>,
+    /// `fields` compiled for decoding when the registry is loaded
+    #[serde(skip)]
+    pub plan: DecodePlan,
+}
+
+/// A layout of a message at another payload size. Its fields are exported in the columns of the
+/// fields of the same name in the definition.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LayoutVariant {
+    pub fields: Vec<FieldDef>,
+    #[serde(skip)]
+    pub plan: DecodePlan,
+}
+
+impl MessageDef {
+    /// The plan compiled at load, or a new one if fields were added to the definition since
+    pub fn decode_plan(&self) -> Cow<'_, DecodePlan> {
+        compiled(&self.plan, &self.fields)
+    }
+
+    /// The fields and plan to decode a payload of `len` bytes with: those of the definition
+    /// unless its size is fixed and not `len`, and a variant's size is
+    pub fn layout(&self, len: usize) -> (&[FieldDef], Cow<'_, DecodePlan>) {
+        let plan = self.decode_plan();
+        if plan.payload_size().is_none_or(|size| size == len) {
+            return (&self.fields, plan);
+        }
+        self.variants
+            .iter()
+            .map(|variant| (variant, compiled(&variant.plan, &variant.fields)))
+            .find(|(_, plan)| plan.payload_size() == Some(len))
+            .map_or((&self.fields, plan), |(variant, plan)| {
+                (&variant.fields, plan)
+            })
+    }
+
+    /// The payload sizes of the definition and its variants, `None` if any of them varies
+    pub fn payload_sizes(&self) -> Option<Vec<usize>> {
+        std::iter::once(self.decode_plan().payload_size())
+            .chain(
+                self.variants
+                    .iter()
+                    .map(|variant| compiled(&variant.plan, &variant.fields).payload_size()),
+            )
+            .collect()
+    }
+}
+
+fn compiled<'a>(plan: &'a DecodePlan, fields: &[FieldDef]) -> Cow<'a, DecodePlan> {
+    if plan.steps().len() == fields.len() {
+        Cow::Borrowed(plan)
+    } else {
+        Cow::Owned(DecodePlan::compile(fields))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ParamFields {
+    /// Field holding the parameter's name or numeric id
+    pub name: String,
+    pub value: String,
+    /// Field holding the value before this record, for records only logged on a change
+    #[serde(default)]
+    pub previous: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Packing {
+    /// Fields follow each other without gaps, any padding is listed explicitly
+    #[default]
+    Packed,
+    /// Fields are aligned to their size as a C compiler would, with implicit padding in between
+    /// and at the end
+    Natural,
 }
 
 pub type MessageRegistry = HashMap<String, MessageDef>;
 
-use crate::errors::Result; // Use custom Result
+/// The definitions of a registry by log_type, looked up once per stream so that decoding a
+/// record does not format its type as a registry key
+#[derive(Debug, Clone, Default)]
+pub struct Definitions<'a>(HashMap<u16, &'a MessageDef>);
+
+impl<'a> Definitions<'a> {
+    pub fn new(registry: &'a MessageRegistry) -> Self {
+        Definitions(
+            registry
+                .iter()
+                .filter_map(|(id, def)| Some((id.parse().ok()?, def)))
+                .collect(),
+        )
+    }
+
+    pub fn get(&self, log_type: u16) -> Option<&'a MessageDef> {
+        self.0.get(&log_type).copied()
+    }
+}
+
+use crate::errors::{Result, WallaceError}; // Use custom Result
 use std::fs::File;
 use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Key of the optional alias table in a registry file, next to the message ids
+pub const ALIASES_KEY: &str = "aliases";
 
 pub fn load_message_registry(path: &str) -> Result<MessageRegistry> {
-    // Update return type
+    load_registry_for_firmware(path, None)
+}
+
+/// Loads the registry with the field layout of a firmware version, see `messages::firmware`
+pub fn load_registry_for_firmware(
+    path: &str,
+    firmware: Option<&FirmwareVersion>,
+) -> Result<MessageRegistry> {
     let file = File::open(path)?; // io::Error automatically converted by #[from] in WallaceError
     let reader = BufReader::new(file);
-    let registry: MessageRegistry = serde_json::from_reader(reader)?; // serde_json::Error automatically converted
+    let mut entries: serde_json::Map<String, serde_json::Value> = serde_json::from_reader(reader)?; // serde_json::Error automatically converted
+    let aliases: BTreeMap<String, String> = match entries.remove(ALIASES_KEY) {
+        Some(table) => serde_json::from_value(table)?,
+        None => BTreeMap::new(),
+    };
+    let mut registry: MessageRegistry = serde_json::from_value(entries.into())?;
+    apply_gates(&mut registry, firmware);
+    for def in registry.values_mut() {
+        if def.packing == Packing::Natural {
+            insert_natural_padding(&mut def.fields);
+            for variant in &mut def.variants {
+                insert_natural_padding(&mut variant.fields);
+            }
+        }
+    }
+    apply_aliases(&mut registry, &aliases)?;
+    compile_plans(&mut registry);
     Ok(registry)
 }
+
+// (Re)compiles the decode plans, which hold the field layout and skip flags as they are now
+fn compile_plans(registry: &mut MessageRegistry) {
+    for def in registry.values_mut() {
+        def.plan = DecodePlan::compile(&def.fields);
+        for variant in &mut def.variants {
+            variant.plan = DecodePlan::compile(&variant.fields);
+        }
+    }
+}
+
+/// Renames messages and fields as the alias table says, so that exports keep stable names across
+/// firmware renames. Keys are the names in the registry, `MESSAGE` or `MESSAGE.FIELD`; values
+/// are the new message or field name, which is then used everywhere, from filters to exports.
+pub fn apply_aliases(
+    registry: &mut MessageRegistry,
+    aliases: &BTreeMap<String, String>,
+) -> Result<()> {
+    for (from, to) in aliases {
+        let (message, field) = match from.split_once('.') {
+            Some((message, field)) => (message, Some(field)),
+            None => (from.as_str(), None),
+        };
+        let mut found = false;
+        for def in registry.values_mut().filter(|def| def.name == message) {
+            match field {
+                Some(field) => {
+                    let variant_fields = def.variants.iter_mut().flat_map(|v| v.fields.iter_mut());
+                    for f in def
+                        .fields
+                        .iter_mut()
+                        .chain(variant_fields)
+                        .filter(|f| f.name == field)
+                    {
+                        f.name = to.clone();
+                        found = true;
+                    }
+                }
+                None => found = true,
+            }
+        }
+        if !found {
+            return Err(WallaceError::InvalidArgument(format!(
+                "alias '{}': no such {} in the registry",
+                from,
+                if field.is_some() { "field" } else { "message" }
+            )));
+        }
+    }
+    // Messages last, so field keys can use the registry's own message names
+    for def in registry.values_mut() {
+        if let Some(to) = aliases.get(&def.name) {
+            def.name = to.clone();
+        }
+    }
+
+    // A new name must not clash with another message or field
+    let targets: HashSet<&str> = aliases.values().map(String::as_str).collect();
+    let mut names = HashMap::new();
+    for (id, def) in registry.iter() {
+        if let Some(other) = names.insert(def.name.as_str(), id) {
+            if targets.contains(def.name.as_str()) {
+                return Err(WallaceError::InvalidArgument(format!(
+                    "alias: messages {} and {} are both named '{}'",
+                    other, id, def.name
+                )));
+            }
+        }
+        for name in def
+            .fields
+            .iter()
+            .map(|f| f.name.as_str())
+            .filter(|n| targets.contains(n))
+        {
+            if def.fields.iter().filter(|f| f.name == name).count() > 1 {
+                return Err(WallaceError::InvalidArgument(format!(
+                    "alias: message '{}' has two fields named '{}'",
+                    def.name, name
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// How often `RegistryWatcher` looks at the file
+pub const RELOAD_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Notices when a registry file has been rewritten, by its modification time, so long-running
+/// modes can reload it
+pub struct RegistryWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    checked: Instant,
+}
+
+impl RegistryWatcher {
+    pub fn new(path: &str) -> Self {
+        let path = PathBuf::from(path);
+        RegistryWatcher {
+            modified: modified_time(&path),
+            path,
+            checked: Instant::now(),
+        }
+    }
+
+    /// Whether the file changed since the last call that returned true, looking at most once
+    /// per `RELOAD_CHECK_INTERVAL`
+    pub fn changed(&mut self) -> bool {
+        if self.checked.elapsed() < RELOAD_CHECK_INTERVAL {
+            return false;
+        }
+        self.checked = Instant::now();
+        let modified = modified_time(&self.path);
+        if modified.is_none() || modified == self.modified {
+            return false; // Missing while being replaced, or unchanged
+        }
+        self.modified = modified;
+        true
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Adjusts which fields are skipped: fields named in `extra_names` are skipped as well, unless
+/// `keep_all` is set, in which case nothing is skipped (to debug alignment against the raw bytes)
+pub fn set_skip_policy(registry: &mut MessageRegistry, extra_names: &[&str], keep_all: bool) {
+    let fields = registry.values_mut().flat_map(|def| {
+        def.fields
+            .iter_mut()
+            .chain(def.variants.iter_mut().flat_map(|v| v.fields.iter_mut()))
+    });
+    for field in fields {
+        if keep_all {
+            field.skip = Some(false);
+        } else if extra_names.contains(&field.name.as_str()) {
+            field.skip = Some(true);
+        }
+    }
+    compile_plans(registry);
+}
+
+// Alignment of a type under C rules: scalars align to their size, strings and byte arrays to 1
+fn natural_alignment(type_str: &str) -> usize {
+    match type_str {
+        "u128" | "i128" => 16,
+        "Q" | "q" | "d" => 8,
+        "I" | "i" | "f" | "lat_e7" | "lon_e7" | "alt_mm" => 4,
+        "H" | "h" => 2,
+        // Fixed-point values are stored in a plain integer of their width
+        s => match crate::parser::FixedPoint::parse(s).map(|fp| fp.size()) {
+            Some(size) if size.is_power_of_two() => size,
+            _ => 1,
+        },
+    }
+}
+
+/// The payload size of `fields` aligned as a C compiler would, as with `"packing": "natural"`,
+/// or `None` if a field has no known size
+pub fn natural_size(fields: &[FieldDef]) -> Option<usize> {
+    let mut fields = fields.to_vec();
+    insert_natural_padding(&mut fields);
+    fields.iter().map(crate::parser::field_size).sum()
+}
+
+// Makes the implicit padding of a naturally aligned struct explicit, as PADDING byte arrays,
+// so that decoding and encoding only ever see packed layouts
+fn insert_natural_padding(def_fields: &mut Vec<FieldDef>) {
+    let mut fields = Vec::with_capacity(def_fields.len());
+    let mut offset = 0;
+    let mut max_align = 1;
+    for field in def_fields.drain(..) {
+        let align = natural_alignment(&field.r#type);
+        max_align = max_align.max(align);
+        push_padding(&mut fields, (align - offset % align) % align);
+        offset += (align - offset % align) % align;
+        match crate::parser::field_size(&field) {
+            Some(size) => offset += size,
+            // Variable length contents end the payload, there is nothing to align after them
+            None => max_align = 1,
+        }
+        fields.push(field);
+    }
+    push_padding(&mut fields, (max_align - offset % max_align) % max_align);
+    *def_fields = fields;
+}
+
+fn push_padding(fields: &mut Vec<FieldDef>, len: usize) {
+    if len > 0 {
+        fields.push(FieldDef {
+            name: "PADDING".to_string(),
+            r#type: "B".repeat(len),
+            skip: Some(true),
+            ..Default::default()
+        });
+    }
+}
@@ -0,0 +1,91 @@
+// net/http.rs
+// Minimal blocking HTTP/1.1 client, enough for POSTing export payloads to plain-http endpoints.
+
+use crate::errors::{Result, WallaceError};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+
+pub struct HttpUrl {
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+impl HttpUrl {
+    /// Parses `http://host[:port][/path][?query]`. TLS endpoints are not supported.
+    pub fn parse(url: &str) -> Result<Self> {
+        let rest = url.strip_prefix("http://").ok_or_else(|| {
+            WallaceError::InvalidArgument(format!(
+                "only plain http:// URLs are supported, got '{}'",
+                url
+            ))
+        })?;
+        let (authority, path) = match rest.find('/') {
+            Some(idx) => (&rest[..idx], &rest[idx..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse().map_err(|_| {
+                    WallaceError::InvalidArgument(format!("invalid port in URL '{}'", url))
+                })?,
+            ),
+            None => (authority, 80),
+        };
+        Ok(HttpUrl {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+}
+
+/// Sends a POST request and returns the response body. Non-2xx responses become `WallaceError::Http`.
+pub fn post(url: &HttpUrl, headers: &[(&str, &str)], body: &[u8]) -> Result<String> {
+    let mut stream = TcpStream::connect((url.host.as_str(), url.port))?;
+    let mut request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        url.path,
+        url.host,
+        url.port,
+        body.len()
+    );
+    for (name, value) in headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes())?;
+    stream.write_all(body)?;
+    stream.flush()?;
+
+    let mut reader = BufReader::new(stream);
+    let mut status_line = String::new();
+    reader.read_line(&mut status_line)?;
+    let status: u16 = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| WallaceError::Http(format!("malformed response '{}'", status_line.trim())))?;
+
+    // Skip headers, the connection is closed by the server after the body
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line == "\r\n" {
+            break;
+        }
+    }
+    let mut response_body = String::new();
+    reader.read_to_string(&mut response_body)?;
+
+    if !(200..300).contains(&status) {
+        return Err(WallaceError::Http(format!(
+            "{} responded {}: {}",
+            url.host,
+            status,
+            response_body.trim()
+        )));
+    }
+    Ok(response_body)
+}
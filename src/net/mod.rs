@@ -0,0 +1,5 @@
+// net/mod.rs
+// Network clients used by the exporters and sinks.
+
+pub mod http;
+pub mod mqtt;
@@ -0,0 +1,136 @@
+// net/mqtt.rs
+// Minimal MQTT 3.1.1 client: connect, QoS 0 publish and disconnect over plain TCP.
+
+use crate::errors::{Result, WallaceError};
+use std::io::{BufWriter, Read, Write};
+use std::net::TcpStream;
+
+pub struct MqttClient {
+    stream: BufWriter<TcpStream>,
+}
+
+pub struct MqttOptions {
+    pub host: String,
+    pub port: u16,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl MqttOptions {
+    /// Parses `[mqtt://][user[:password]@]host[:port]`
+    pub fn parse(broker: &str, client_id: &str) -> Result<Self> {
+        let rest = broker.strip_prefix("mqtt://").unwrap_or(broker);
+        let (credentials, address) = match rest.rsplit_once('@') {
+            Some((credentials, address)) => (Some(credentials), address),
+            None => (None, rest),
+        };
+        let (username, password) = match credentials {
+            Some(c) => match c.split_once(':') {
+                Some((user, pass)) => (Some(user.to_string()), Some(pass.to_string())),
+                None => (Some(c.to_string()), None),
+            },
+            None => (None, None),
+        };
+        let (host, port) = match address.rsplit_once(':') {
+            Some((host, port)) => (
+                host.to_string(),
+                port.parse().map_err(|_| {
+                    WallaceError::InvalidArgument(format!("invalid MQTT port in '{}'", broker))
+                })?,
+            ),
+            None => (address.to_string(), 1883),
+        };
+        Ok(MqttOptions {
+            host,
+            port,
+            client_id: client_id.to_string(),
+            username,
+            password,
+        })
+    }
+}
+
+impl MqttClient {
+    pub fn connect(options: &MqttOptions) -> Result<Self> {
+        let mut stream = TcpStream::connect((options.host.as_str(), options.port))?;
+        stream.set_nodelay(true)?;
+
+        let mut flags = 0x02; // clean session
+        let mut payload = Vec::new();
+        put_string(&mut payload, options.client_id.as_bytes());
+        if let Some(username) = &options.username {
+            flags |= 0x80;
+            put_string(&mut payload, username.as_bytes());
+        }
+        if let Some(password) = &options.password {
+            flags |= 0x40;
+            put_string(&mut payload, password.as_bytes());
+        }
+
+        let mut body = Vec::new();
+        put_string(&mut body, b"MQTT");
+        body.push(4); // protocol level 3.1.1
+        body.push(flags);
+        body.extend_from_slice(&0u16.to_be_bytes()); // keep alive disabled, no pings needed
+        body.extend_from_slice(&payload);
+        write_packet(&mut stream, 0x10, &body)?;
+
+        // CONNACK: 0x20, length 2, session present, return code
+        let mut connack = [0u8; 4];
+        stream.read_exact(&mut connack)?;
+        if connack[0] != 0x20 || connack[3] != 0 {
+            return Err(WallaceError::Sink(format!(
+                "MQTT broker {}:{} refused the connection (return code {})",
+                options.host, options.port, connack[3]
+            )));
+        }
+
+        Ok(MqttClient {
+            stream: BufWriter::new(stream),
+        })
+    }
+
+    /// Publishes with QoS 0 (fire and forget)
+    pub fn publish(&mut self, topic: &str, payload: &[u8]) -> Result<()> {
+        let mut body = Vec::with_capacity(topic.len() + payload.len() + 2);
+        put_string(&mut body, topic.as_bytes());
+        body.extend_from_slice(payload);
+        write_packet(&mut self.stream, 0x30, &body)
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.stream.flush()?;
+        Ok(())
+    }
+
+    pub fn disconnect(&mut self) -> Result<()> {
+        write_packet(&mut self.stream, 0xE0, &[])?;
+        self.flush()
+    }
+}
+
+fn put_string(out: &mut Vec<u8>, s: &[u8]) {
+    out.extend_from_slice(&(s.len() as u16).to_be_bytes());
+    out.extend_from_slice(s);
+}
+
+fn write_packet<W: Write>(writer: &mut W, packet_type: u8, body: &[u8]) -> Result<()> {
+    // Remaining length uses 7 bits per byte, high bit set when more bytes follow
+    let mut header = vec![packet_type];
+    let mut len = body.len();
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        header.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+    writer.write_all(&header)?;
+    writer.write_all(body)?;
+    Ok(())
+}
@@ -0,0 +1,112 @@
+// parser/async_stream.rs
+// Async counterpart of `MessageStream` for tokio sources (sockets, pipes), behind the `async` feature.
+
+use super::stream::{decode_record, ended, in_record, RawRecord, Watchdog};
+use super::{DecodeOptions, ParsedMessage};
+use crate::errors::{Result, WallaceError};
+use crate::messages::registry::{Definitions, MessageRegistry};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+pub struct AsyncMessageStream<'a, R: AsyncRead + Unpin> {
+    reader: R,
+    registry: &'a MessageRegistry,
+    defs: Definitions<'a>,
+    /// The 4-byte file header, kept so rewritten logs can reproduce it
+    pub header: i32,
+    /// Warnings collected so far, prefixed with the message they belong to
+    pub warnings: Vec<String>,
+    /// Number of padding fields (TRASH/PADDING/RESERVED by default) skipped so far
+    pub skipped_fields: usize,
+    /// Records read so far, known type or not
+    pub records_read: u64,
+    /// Records skipped because their type is not in the registry
+    pub unknown_records: usize,
+    /// How records are decoded, e.g. whether raw payloads are kept
+    pub options: DecodeOptions,
+    /// Bytes consumed from the input, including the file header
+    pub bytes_read: u64,
+    watchdog: Watchdog,
+}
+
+impl<'a, R: AsyncRead + Unpin> AsyncMessageStream<'a, R> {
+    /// Waits for the file header and prepares to decode records
+    pub async fn new(mut reader: R, registry: &'a MessageRegistry) -> Result<Self> {
+        let header = reader.read_i32_le().await?;
+        Ok(AsyncMessageStream {
+            reader,
+            registry,
+            defs: Definitions::new(registry),
+            header,
+            warnings: Vec::new(),
+            skipped_fields: 0,
+            records_read: 0,
+            unknown_records: 0,
+            options: DecodeOptions::default(),
+            bytes_read: 4,
+            watchdog: Watchdog::new(),
+        })
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Returns the next message of a known type once it has fully arrived.
+    /// Records whose type is not in the registry are skipped. `None` means a clean end of input.
+    pub async fn next_message(&mut self) -> Result<Option<ParsedMessage>> {
+        while let Some(record) = self.next_record().await? {
+            if let Some(msg) = self.decode(&record)? {
+                return Ok(Some(msg));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the next record undecoded, whatever its type
+    pub async fn next_record(&mut self) -> Result<Option<RawRecord>> {
+        let (index, offset, registry) = (self.records_read, self.bytes_read, self.registry);
+        let failed =
+            |e, log_type| in_record(WallaceError::Io(e), index, offset, log_type, registry);
+        let log_type = match self.reader.read_u16_le().await {
+            Ok(v) => v,
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(failed(e, None)),
+        };
+        let length = self
+            .reader
+            .read_u16_le()
+            .await
+            .map_err(|e| failed(ended(e, "its length"), Some(log_type)))?;
+        self.watchdog.check(
+            &self.options.limits,
+            length,
+            self.records_read,
+            self.bytes_read,
+        )?;
+        let mut payload = vec![0u8; length as usize];
+        if let Err(e) = self.reader.read_exact(&mut payload).await {
+            let inside = format!("its {}-byte payload", length);
+            return Err(failed(ended(e, &inside), Some(log_type)));
+        }
+        self.bytes_read += 4 + length as u64;
+        self.records_read += 1;
+        Ok(Some(RawRecord {
+            log_type,
+            payload,
+            index: self.records_read - 1,
+            offset,
+        }))
+    }
+
+    /// Decodes a record read with `next_record`, or returns `None` if its type is not in the registry
+    pub fn decode(&mut self, record: &RawRecord) -> Result<Option<ParsedMessage>> {
+        decode_record(
+            record,
+            &self.defs,
+            &mut self.warnings,
+            &mut self.skipped_fields,
+            &mut self.unknown_records,
+            self.options,
+        )
+    }
+}
@@ -0,0 +1,347 @@
+// parser/borrowed.rs
+// Zero-copy decoding of a log that is already in memory, e.g. memory-mapped: records are framed
+// in place and field values borrow from their payload, so reading a record allocates nothing.
+
+use super::stream::{ended, in_record};
+use super::{get_type_size, FixedPoint, ParsedMessage, Scaled, TIMESTAMP_FIELDS};
+use crate::errors::{Result, WallaceError};
+use crate::messages::registry::{FieldDef, MessageDef, MessageRegistry, NulPolicy, StringEncoding};
+use byteorder::{ByteOrder, LittleEndian};
+use std::fmt;
+
+const HEADER_LEN: usize = 4;
+const RECORD_HEADER_LEN: usize = 4;
+
+/// A field value: numbers decoded, text and byte arrays left in the payload
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldValue<'a> {
+    Unsigned(u64),
+    Signed(i64),
+    U128(u128),
+    I128(i128),
+    F32(f32),
+    /// `d` and fixed-point fields
+    F64(f64),
+    Bool(bool),
+    /// A raw integer with a decimal scale, such as `lat_e7`
+    Scaled(i32, Scaled),
+    /// A string field, up to where its NUL policy ends it
+    Text(&'a str),
+    /// A string field that needs decoding to read as text: UTF-16, Latin-1 beyond ASCII,
+    /// invalid UTF-8, or bytes kept after a NUL (`nul: hex`). `ParsedMessageRef::to_message`
+    /// decodes it the way exports do.
+    RawText(&'a [u8]),
+    /// A byte array (`BB...`)
+    Bytes(&'a [u8]),
+}
+
+impl FieldValue<'_> {
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            FieldValue::Unsigned(v) => Some(v as f64),
+            FieldValue::Signed(v) => Some(v as f64),
+            FieldValue::U128(v) => Some(v as f64),
+            FieldValue::I128(v) => Some(v as f64),
+            FieldValue::F32(v) => Some(v as f64),
+            FieldValue::F64(v) => Some(v),
+            FieldValue::Bool(v) => Some(v as u8 as f64),
+            FieldValue::Scaled(raw, scaled) => {
+                Some(raw as f64 / 10f64.powi(scaled.decimals as i32))
+            }
+            FieldValue::Text(_) | FieldValue::RawText(_) | FieldValue::Bytes(_) => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            FieldValue::Unsigned(v) => Some(v),
+            FieldValue::Signed(v) => u64::try_from(v).ok(),
+            _ => None,
+        }
+    }
+}
+
+/// Writes numbers and text as the exports do; byte arrays and raw text as hex pairs
+impl fmt::Display for FieldValue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldValue::Unsigned(v) => write!(f, "{}", v),
+            FieldValue::Signed(v) => write!(f, "{}", v),
+            FieldValue::U128(v) => write!(f, "{}", v),
+            FieldValue::I128(v) => write!(f, "{}", v),
+            FieldValue::F32(v) => write!(f, "{}", v),
+            FieldValue::F64(v) => write!(f, "{}", v),
+            FieldValue::Bool(v) => write!(f, "{}", v),
+            FieldValue::Scaled(raw, scaled) => f.write_str(&scaled.format(*raw)),
+            FieldValue::Text(text) => f.write_str(text),
+            FieldValue::RawText(bytes) | FieldValue::Bytes(bytes) => {
+                for (i, b) in bytes.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" ")?;
+                    }
+                    write!(f, "{:02X}", b)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A record of a known type, borrowing its payload and definition
+#[derive(Debug, Clone, Copy)]
+pub struct ParsedMessageRef<'a> {
+    pub log_type: u16,
+    pub def: &'a MessageDef,
+    pub payload: &'a [u8],
+    /// Position of the record among all records of the log, unknown types included
+    pub index: u64,
+    /// Byte offset of the record in the log, counting the file header
+    pub offset: u64,
+}
+
+impl<'a> ParsedMessageRef<'a> {
+    pub fn name(&self) -> &'a str {
+        &self.def.name
+    }
+
+    /// The fields in order, decoded as they are iterated. Padding fields are left out, and the
+    /// iteration ends early at a field that does not fit the payload.
+    pub fn fields(&self) -> FieldIter<'a> {
+        FieldIter {
+            defs: self.def.layout(self.payload.len()).0.iter(),
+            payload: self.payload,
+            pos: 0,
+        }
+    }
+
+    /// Returns the value of a field by name, if present
+    pub fn field(&self, name: &str) -> Option<FieldValue<'a>> {
+        self.fields()
+            .find(|(field, _)| *field == name)
+            .map(|(_, value)| value)
+    }
+
+    /// Returns the record timestamp in microseconds, if the message carries one
+    pub fn timestamp_us(&self) -> Option<u64> {
+        TIMESTAMP_FIELDS
+            .iter()
+            .find_map(|name| self.field(name))
+            .and_then(|value| value.as_u64())
+    }
+
+    /// Whether the payload ends before the fields of the definition do
+    pub fn is_truncated(&self) -> bool {
+        let mut fields = self.fields();
+        fields.by_ref().for_each(drop);
+        !fields.defs.as_slice().is_empty()
+    }
+
+    /// Decodes the record into an owned message, exactly as the streams do
+    pub fn to_message(self, options: super::DecodeOptions) -> Result<ParsedMessage> {
+        let (fields, plan) = self.def.layout(self.payload.len());
+        let parsed = super::parse_with_plan(self.payload, fields, &plan, options).map_err(|e| {
+            WallaceError::InRecord {
+                index: self.index,
+                offset: self.offset,
+                log_type: Some(self.log_type),
+                name: Some(self.def.name.clone()),
+                source: Box::new(WallaceError::ParsingError {
+                    log_type: self.log_type,
+                    name: self.def.name.clone(),
+                    reason: e.to_string(),
+                }),
+            }
+        })?;
+        let mut msg = ParsedMessage {
+            log_type: self.log_type,
+            name: self.def.name.clone(),
+            fields: parsed.fields,
+            index: self.index,
+            offset: self.offset,
+            payload_len: self.payload.len(),
+            raw: options.keep_raw.then(|| self.payload.to_vec()),
+            partial: parsed.truncated.then_some(options.partial),
+            salvaged: parsed.salvaged,
+            ..Default::default()
+        };
+        msg.timestamp = msg.timestamp_us();
+        Ok(msg)
+    }
+}
+
+/// The fields of a `ParsedMessageRef`, as (name, value)
+pub struct FieldIter<'a> {
+    defs: std::slice::Iter<'a, FieldDef>,
+    payload: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Iterator for FieldIter<'a> {
+    type Item = (&'a str, FieldValue<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let field = self.defs.as_slice().first()?;
+            let rest = &self.payload[self.pos..];
+            // FILE_CONTENTS runs to the end of the payload
+            let size = if field.r#type == "c" && field.name == "FILE_CONTENTS" {
+                rest.len()
+            } else {
+                get_type_size(&field.r#type)?
+            };
+            // Padding cut short is skipped to the end, like the streams do
+            if field.is_skipped() {
+                self.defs.next();
+                self.pos += size.min(rest.len());
+                continue;
+            }
+            if size > rest.len() {
+                return None;
+            }
+            self.defs.next();
+            self.pos += size;
+            return Some((&field.name, value(field, &rest[..size])));
+        }
+    }
+}
+
+// The value of a field from exactly its bytes
+fn value<'a>(field: &FieldDef, bytes: &'a [u8]) -> FieldValue<'a> {
+    match field.r#type.as_str() {
+        "Q" => FieldValue::Unsigned(LittleEndian::read_u64(bytes)),
+        "q" => FieldValue::Signed(LittleEndian::read_i64(bytes)),
+        "I" => FieldValue::Unsigned(LittleEndian::read_u32(bytes) as u64),
+        "H" => FieldValue::Unsigned(LittleEndian::read_u16(bytes) as u64),
+        "B" => FieldValue::Unsigned(bytes[0] as u64),
+        "b" => FieldValue::Signed(bytes[0] as i8 as i64),
+        "i" => FieldValue::Signed(LittleEndian::read_i32(bytes) as i64),
+        "h" => FieldValue::Signed(LittleEndian::read_i16(bytes) as i64),
+        "f" => FieldValue::F32(LittleEndian::read_f32(bytes)),
+        "d" => FieldValue::F64(LittleEndian::read_f64(bytes)),
+        "?" => FieldValue::Bool(bytes[0] != 0),
+        "u128" => FieldValue::U128(LittleEndian::read_u128(bytes)),
+        "i128" => FieldValue::I128(LittleEndian::read_i128(bytes)),
+        s if FixedPoint::parse(s).is_some() => {
+            FieldValue::F64(FixedPoint::parse(s).unwrap().decode(bytes)) // Checked by the guard
+        }
+        s if Scaled::parse(s).is_some() => FieldValue::Scaled(
+            LittleEndian::read_i32(bytes),
+            Scaled::parse(s).unwrap(), // Checked by the guard
+        ),
+        s if s.len() > 1 && (s.chars().all(|c| c == 'B') || s.chars().all(|c| c == 'b')) => {
+            FieldValue::Bytes(bytes)
+        }
+        // Characters and strings, fixed length or to the end of the payload
+        _ => text(field, bytes),
+    }
+}
+
+// A string field as `Text` where the bytes can be read as UTF-8 in place, `RawText` otherwise
+fn text<'a>(field: &FieldDef, bytes: &'a [u8]) -> FieldValue<'a> {
+    let first_nul = bytes.iter().position(|&b| b == 0);
+    let end = match (field.nul, first_nul) {
+        (_, None) | (NulPolicy::Preserve, _) => bytes.len(),
+        (NulPolicy::Trim, Some(_)) => bytes.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1),
+        (NulPolicy::Discard, Some(end)) => end,
+        (NulPolicy::Hex, Some(end)) if bytes[end..].iter().all(|&b| b == 0) => end,
+        (NulPolicy::Hex, Some(_)) => return FieldValue::RawText(bytes),
+    };
+    let kept = &bytes[..end];
+    let utf8 = match field.encoding {
+        StringEncoding::Utf8 => true,
+        StringEncoding::Latin1 => kept.is_ascii(),
+        StringEncoding::Utf16le => false,
+    };
+    match std::str::from_utf8(kept) {
+        Ok(text) if utf8 => FieldValue::Text(text),
+        _ => FieldValue::RawText(bytes),
+    }
+}
+
+/// The records of a whole log in memory, framed in place. Records of types not in the registry
+/// are skipped.
+pub struct MessageSlices<'a> {
+    data: &'a [u8],
+    pos: usize,
+    registry: &'a MessageRegistry,
+    /// The 4-byte file header
+    pub header: i32,
+    /// Records read so far, known type or not
+    pub records_read: u64,
+    /// Records skipped because their type is not in the registry
+    pub unknown_records: usize,
+}
+
+impl<'a> MessageSlices<'a> {
+    pub fn new(data: &'a [u8], registry: &'a MessageRegistry) -> Result<Self> {
+        if data.len() < HEADER_LEN {
+            return Err(WallaceError::Io(std::io::ErrorKind::UnexpectedEof.into()));
+        }
+        Ok(MessageSlices {
+            data,
+            pos: HEADER_LEN,
+            registry,
+            header: LittleEndian::read_i32(data),
+            records_read: 0,
+            unknown_records: 0,
+        })
+    }
+}
+
+impl<'a> Iterator for MessageSlices<'a> {
+    type Item = Result<ParsedMessageRef<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let rest = &self.data[self.pos..];
+            if rest.is_empty() {
+                return None;
+            }
+            let length = match rest.get(2..RECORD_HEADER_LEN) {
+                Some(length) => LittleEndian::read_u16(length) as usize,
+                None => return Some(Err(self.cut_short())),
+            };
+            let Some(payload) = rest.get(RECORD_HEADER_LEN..RECORD_HEADER_LEN + length) else {
+                return Some(Err(self.cut_short()));
+            };
+            let log_type = LittleEndian::read_u16(rest);
+            let offset = self.pos as u64;
+            self.pos += RECORD_HEADER_LEN + length;
+            self.records_read += 1;
+            match self.registry.get(&log_type.to_string()) {
+                Some(def) => {
+                    return Some(Ok(ParsedMessageRef {
+                        log_type,
+                        def,
+                        payload,
+                        index: self.records_read - 1,
+                        offset,
+                    }))
+                }
+                None => self.unknown_records += 1,
+            }
+        }
+    }
+}
+
+impl MessageSlices<'_> {
+    // The last record is cut short; the error ends the iteration like a failed read would
+    fn cut_short(&mut self) -> WallaceError {
+        let rest = &self.data[self.pos..];
+        let log_type = rest.get(..2).map(LittleEndian::read_u16);
+        let inside = match rest.get(2..RECORD_HEADER_LEN) {
+            Some(length) => format!("its {}-byte payload", LittleEndian::read_u16(length)),
+            None => "its length".to_string(),
+        };
+        let error = WallaceError::Io(ended(std::io::ErrorKind::UnexpectedEof.into(), &inside));
+        let error = in_record(
+            error,
+            self.records_read,
+            self.pos as u64,
+            log_type,
+            self.registry,
+        );
+        self.pos = self.data.len();
+        error
+    }
+}
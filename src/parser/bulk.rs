@@ -0,0 +1,164 @@
+// parser/bulk.rs
+// Bulk decoding of many records of a message whose fields are all fixed-width scalars: each field
+// is read across every payload at its offset in the decode plan into a typed column, one tight
+// loop per field instead of a decoder match per field per record.
+
+use super::{FieldDecoder, Scaled};
+use crate::messages::registry::MessageDef;
+
+/// The values of one field across records
+#[derive(Debug, Clone, PartialEq)]
+pub enum Column {
+    U8(Vec<u8>),
+    I8(Vec<i8>),
+    U16(Vec<u16>),
+    I16(Vec<i16>),
+    U32(Vec<u32>),
+    I32(Vec<i32>),
+    U64(Vec<u64>),
+    I64(Vec<i64>),
+    U128(Vec<u128>),
+    I128(Vec<i128>),
+    F32(Vec<f32>),
+    /// `d` and fixed-point fields
+    F64(Vec<f64>),
+    Bool(Vec<bool>),
+    /// Raw integers with their decimal scale, such as `lat_e7`
+    Scaled(Vec<i32>, Scaled),
+    /// Characters, strings and byte arrays in their text form; never produced by `decode_columns`
+    Text(Vec<String>),
+}
+
+impl Column {
+    pub fn len(&self) -> usize {
+        match self {
+            Column::U8(v) => v.len(),
+            Column::I8(v) => v.len(),
+            Column::U16(v) => v.len(),
+            Column::I16(v) => v.len(),
+            Column::U32(v) => v.len(),
+            Column::I32(v) => v.len(),
+            Column::U64(v) => v.len(),
+            Column::I64(v) => v.len(),
+            Column::U128(v) => v.len(),
+            Column::I128(v) => v.len(),
+            Column::F32(v) => v.len(),
+            Column::F64(v) => v.len(),
+            Column::Bool(v) => v.len(),
+            Column::Scaled(v, _) => v.len(),
+            Column::Text(v) => v.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The value of a row as the streams decode it to text
+    pub fn text(&self, row: usize) -> String {
+        match self {
+            Column::U8(v) => v[row].to_string(),
+            Column::I8(v) => v[row].to_string(),
+            Column::U16(v) => v[row].to_string(),
+            Column::I16(v) => v[row].to_string(),
+            Column::U32(v) => v[row].to_string(),
+            Column::I32(v) => v[row].to_string(),
+            Column::U64(v) => v[row].to_string(),
+            Column::I64(v) => v[row].to_string(),
+            Column::U128(v) => v[row].to_string(),
+            Column::I128(v) => v[row].to_string(),
+            Column::F32(v) => v[row].to_string(),
+            Column::F64(v) => v[row].to_string(),
+            Column::Bool(v) => v[row].to_string(),
+            Column::Scaled(v, scaled) => scaled.format(v[row]),
+            Column::Text(v) => v[row].clone(),
+        }
+    }
+}
+
+/// Whether every field of `def` is a fixed-width scalar, so its records can be decoded in bulk
+pub fn is_bulk_decodable(def: &MessageDef) -> bool {
+    let plan = def.decode_plan();
+    plan.payload_size().is_some()
+        && def
+            .fields
+            .iter()
+            .zip(plan.steps())
+            .all(|(_, step)| step.skip || is_scalar(step.decoder))
+}
+
+fn is_scalar(decoder: FieldDecoder) -> bool {
+    !matches!(
+        decoder,
+        FieldDecoder::Char
+            | FieldDecoder::Text
+            | FieldDecoder::Bytes
+            | FieldDecoder::FileContents
+            | FieldDecoder::Unsupported
+    )
+}
+
+/// Decodes records of `def`'s type into one column per field, in field order with padding left
+/// out. `None` if the message is not bulk decodable or a payload is not exactly the size of the
+/// definition; such records, e.g. truncated ones or length variants, go through `parse_fields`.
+pub fn decode_columns<'a>(
+    def: &'a MessageDef,
+    payloads: &[&[u8]],
+) -> Option<Vec<(&'a str, Column)>> {
+    let plan = def.decode_plan();
+    let size = plan.payload_size()?;
+    if payloads.iter().any(|payload| payload.len() != size) {
+        return None;
+    }
+    let mut columns = Vec::new();
+    for (field, step) in def.fields.iter().zip(plan.steps()) {
+        if step.skip {
+            continue;
+        }
+        // Every offset is known in a plan of fixed size
+        let offset = step.offset?;
+        columns.push((field.name.as_str(), column(step.decoder, payloads, offset)?));
+    }
+    Some(columns)
+}
+
+fn column(decoder: FieldDecoder, payloads: &[&[u8]], offset: usize) -> Option<Column> {
+    Some(match decoder {
+        FieldDecoder::U8 => Column::U8(gather(payloads, offset, u8::from_le_bytes)),
+        FieldDecoder::I8 => Column::I8(gather(payloads, offset, i8::from_le_bytes)),
+        FieldDecoder::U16 => Column::U16(gather(payloads, offset, u16::from_le_bytes)),
+        FieldDecoder::I16 => Column::I16(gather(payloads, offset, i16::from_le_bytes)),
+        FieldDecoder::U32 => Column::U32(gather(payloads, offset, u32::from_le_bytes)),
+        FieldDecoder::I32 => Column::I32(gather(payloads, offset, i32::from_le_bytes)),
+        FieldDecoder::U64 => Column::U64(gather(payloads, offset, u64::from_le_bytes)),
+        FieldDecoder::I64 => Column::I64(gather(payloads, offset, i64::from_le_bytes)),
+        FieldDecoder::U128 => Column::U128(gather(payloads, offset, u128::from_le_bytes)),
+        FieldDecoder::I128 => Column::I128(gather(payloads, offset, i128::from_le_bytes)),
+        FieldDecoder::F32 => Column::F32(gather(payloads, offset, f32::from_le_bytes)),
+        FieldDecoder::F64 => Column::F64(gather(payloads, offset, f64::from_le_bytes)),
+        FieldDecoder::Bool => Column::Bool(gather(payloads, offset, |[b]: [u8; 1]| b != 0)),
+        FieldDecoder::Fixed(fixed) => Column::F64(
+            payloads
+                .iter()
+                .map(|payload| fixed.decode(&payload[offset..offset + fixed.size()]))
+                .collect(),
+        ),
+        FieldDecoder::Scaled(scaled) => {
+            Column::Scaled(gather(payloads, offset, i32::from_le_bytes), scaled)
+        }
+        _ => return None,
+    })
+}
+
+// Reads the N little-endian bytes at `offset` of every payload; the fixed width lets the loop
+// compile to plain loads, vectorised where the target allows
+fn gather<T, const N: usize>(payloads: &[&[u8]], offset: usize, read: fn([u8; N]) -> T) -> Vec<T> {
+    payloads
+        .iter()
+        .map(|payload| {
+            let mut bytes = [0u8; N];
+            bytes.copy_from_slice(&payload[offset..offset + N]);
+            read(bytes)
+        })
+        .collect()
+}
@@ -0,0 +1,122 @@
+// parser/bytes.rs
+// Text forms of byte-array fields (`BB...` types): space separated hex pairs, base64, or printable
+// ASCII with `\xNN` escapes for everything else.
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BytesFormat {
+    /// `01 A0 FF`, three characters per byte
+    #[default]
+    Hex,
+    /// Standard padded base64, `AaD/`
+    Base64,
+    /// `\x01\xa0\xff`, with printable ASCII as is and `\\` for a backslash
+    Escaped,
+}
+
+impl BytesFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "hex" => Some(BytesFormat::Hex),
+            "base64" => Some(BytesFormat::Base64),
+            "escaped" => Some(BytesFormat::Escaped),
+            _ => None,
+        }
+    }
+
+    pub fn format(&self, bytes: &[u8]) -> String {
+        match self {
+            BytesFormat::Hex => super::text::hex(bytes),
+            BytesFormat::Base64 => {
+                let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+                for chunk in bytes.chunks(3) {
+                    let n = chunk
+                        .iter()
+                        .enumerate()
+                        .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+                    for i in 0..4 {
+                        if i <= chunk.len() {
+                            out.push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+                        } else {
+                            out.push('=');
+                        }
+                    }
+                }
+                out
+            }
+            BytesFormat::Escaped => {
+                let mut out = String::with_capacity(bytes.len());
+                for &b in bytes {
+                    match b {
+                        b'\\' => out.push_str("\\\\"),
+                        0x20..=0x7e => out.push(b as char),
+                        _ => out.push_str(&format!("\\x{:02x}", b)),
+                    }
+                }
+                out
+            }
+        }
+    }
+
+    /// Reads `format`'s output back, `None` if it is not valid in this form
+    pub fn parse(&self, text: &str) -> Option<Vec<u8>> {
+        match self {
+            BytesFormat::Hex => text
+                .split_whitespace()
+                .map(|b| u8::from_str_radix(b, 16).ok())
+                .collect(),
+            BytesFormat::Base64 => {
+                let text = text.trim().as_bytes();
+                if !text.len().is_multiple_of(4) {
+                    return None;
+                }
+                let mut out = Vec::with_capacity(text.len() / 4 * 3);
+                for chunk in text.chunks(4) {
+                    let padding = chunk.iter().rev().take_while(|c| **c == b'=').count();
+                    if padding > 2 {
+                        return None;
+                    }
+                    let mut n = 0u32;
+                    for (i, c) in chunk[..4 - padding].iter().enumerate() {
+                        let value = BASE64.iter().position(|b| b == c)? as u32;
+                        n |= value << (18 - 6 * i);
+                    }
+                    out.extend_from_slice(&n.to_be_bytes()[1..4 - padding]);
+                }
+                Some(out)
+            }
+            BytesFormat::Escaped => {
+                let mut out = Vec::with_capacity(text.len());
+                let mut rest = text.as_bytes();
+                while let Some((&b, tail)) = rest.split_first() {
+                    rest = tail;
+                    if b != b'\\' {
+                        out.push(b);
+                        continue;
+                    }
+                    match rest {
+                        [b'\\', tail @ ..] => {
+                            out.push(b'\\');
+                            rest = tail;
+                        }
+                        [b'x', hi, lo, tail @ ..] => {
+                            let pair = [*hi, *lo];
+                            out.push(
+                                u8::from_str_radix(std::str::from_utf8(&pair).ok()?, 16).ok()?,
+                            );
+                            rest = tail;
+                        }
+                        _ => return None,
+                    }
+                }
+                Some(out)
+            }
+        }
+    }
+}
+
+/// Whether a field type is a byte array (`BB...` or `bb...`) rather than a single byte
+pub fn is_byte_array(r#type: &str) -> bool {
+    r#type.len() > 1 && (r#type.chars().all(|c| c == 'B') || r#type.chars().all(|c| c == 'b'))
+}
@@ -0,0 +1,376 @@
+// parser/columnar.rs
+// Decoded records held column by column, one typed vector per field and message type, instead of
+// a `ParsedMessage` of strings per record. Numbers are kept in their logged width, which takes a
+// fraction of the memory, and columnar exports can take the vectors as they are.
+
+use super::bulk::Column;
+use super::plan::{self, FieldDecoder};
+use super::{DecodeOptions, MessageStream, ParsedMessage, PartialPolicy, TIMESTAMP_FIELDS};
+use crate::errors::Result;
+use crate::messages::registry::{FieldDef, MessageDef, MessageRegistry};
+use byteorder::{ByteOrder, LittleEndian};
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::mem::size_of;
+
+/// The values of one field
+#[derive(Debug, Clone)]
+pub struct BatchColumn {
+    pub name: String,
+    pub decoder: FieldDecoder,
+    pub values: Column,
+    /// Rows without a value, in order, because the payload ended before the field; `values`
+    /// holds zero or an empty string for them
+    pub missing: Vec<usize>,
+}
+
+/// The records of one message type, one column per field of the definition, padding left out
+#[derive(Debug, Clone)]
+pub struct ColumnarBatch {
+    pub log_type: u16,
+    pub name: String,
+    /// Position of each record among all records of the log
+    pub index: Vec<u64>,
+    /// Byte offset of each record in the input, counting the file header
+    pub offset: Vec<u64>,
+    pub columns: Vec<BatchColumn>,
+    // Column of each field of the definition, `None` for padding
+    field_columns: Vec<Option<usize>>,
+}
+
+impl ColumnarBatch {
+    pub fn new(log_type: u16, def: &MessageDef) -> Self {
+        let plan = def.decode_plan();
+        let mut columns = Vec::new();
+        let field_columns = def
+            .fields
+            .iter()
+            .zip(plan.steps())
+            .map(|(field, step)| {
+                if field.is_skipped() {
+                    return None;
+                }
+                columns.push(BatchColumn {
+                    name: field.name.clone(),
+                    decoder: step.decoder,
+                    values: empty_column(step.decoder),
+                    missing: Vec::new(),
+                });
+                Some(columns.len() - 1)
+            })
+            .collect();
+        ColumnarBatch {
+            log_type,
+            name: def.name.clone(),
+            index: Vec::new(),
+            offset: Vec::new(),
+            columns,
+            field_columns,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Decodes a payload of `def`, the definition the batch was made for, into a new row. Fields
+    /// the payload ends before are missing, as under `PartialPolicy::NullFill`; a length variant
+    /// fills the columns of its fields by name.
+    pub fn push(
+        &mut self,
+        def: &MessageDef,
+        payload: &[u8],
+        index: u64,
+        offset: u64,
+        options: DecodeOptions,
+        warnings: &mut Vec<String>,
+    ) {
+        let row = self.len();
+        let (fields, plan) = def.layout(payload.len());
+        let own_layout = std::ptr::eq(fields, def.fields.as_slice());
+        let mut filled = vec![false; self.columns.len()];
+        let mut pos = 0;
+        for (i, (field, step)) in fields.iter().zip(plan.steps()).enumerate() {
+            // Decoded as far as `parse_fields` would: padding cut short ends at the payload end,
+            // a field of unknown size ends the record
+            let Some(size) = step.size else {
+                if field.is_skipped() {
+                    continue;
+                }
+                break;
+            };
+            if field.is_skipped() {
+                pos = (pos + size).min(payload.len());
+                continue;
+            }
+            if pos + size > payload.len() {
+                break;
+            }
+            let end = match step.decoder {
+                FieldDecoder::FileContents => payload.len(),
+                _ => pos + size,
+            };
+            let bytes = &payload[pos..end];
+            pos = end;
+            let column = if own_layout {
+                self.field_columns[i]
+            } else {
+                self.columns.iter().position(|c| c.name == field.name)
+            };
+            let Some(c) = column.filter(|&c| !filled[c]) else {
+                continue;
+            };
+            let column = &mut self.columns[c];
+            filled[c] = if column.decoder == step.decoder {
+                push_bytes(column, field, bytes, options, warnings);
+                true
+            } else {
+                // A variant logging the field in another type
+                let text = plan::decode(step.decoder, field, bytes, options.bytes, warnings);
+                push_text(&mut column.values, &text)
+            };
+        }
+        for (column, filled) in self.columns.iter_mut().zip(filled) {
+            if !filled {
+                if column.values.len() == row {
+                    push_default(&mut column.values);
+                }
+                column.missing.push(row);
+            }
+        }
+        self.index.push(index);
+        self.offset.push(offset);
+    }
+
+    /// The row as the streams decode it, with `PartialPolicy::Truncate`: the fields after the
+    /// first missing one are left out
+    pub fn message(&self, row: usize) -> ParsedMessage {
+        let mut fields = Vec::with_capacity(self.columns.len());
+        let mut partial = None;
+        for column in &self.columns {
+            if column.missing.binary_search(&row).is_ok() {
+                partial = Some(PartialPolicy::Truncate);
+                break;
+            }
+            fields.push((column.name.clone(), column.values.text(row)));
+        }
+        let mut msg = ParsedMessage {
+            log_type: self.log_type,
+            name: self.name.clone(),
+            fields,
+            index: self.index[row],
+            offset: self.offset[row],
+            partial,
+            ..Default::default()
+        };
+        msg.timestamp = msg.timestamp_us();
+        msg
+    }
+
+    /// The record timestamps in microseconds, if the message carries them
+    pub fn timestamps(&self) -> Option<&[u64]> {
+        self.columns
+            .iter()
+            .find(|column| TIMESTAMP_FIELDS.contains(&column.name.as_str()))
+            .and_then(|column| match &column.values {
+                Column::U64(values) => Some(values.as_slice()),
+                _ => None,
+            })
+    }
+
+    /// Estimated bytes the batch takes in memory, comparable to `utils::memory::messages_size`
+    pub fn memory_size(&self) -> u64 {
+        let columns: usize = self
+            .columns
+            .iter()
+            .map(|column| {
+                size_of::<BatchColumn>()
+                    + column.name.capacity()
+                    + column.missing.capacity() * size_of::<usize>()
+                    + column_size(&column.values)
+            })
+            .sum();
+        (size_of::<ColumnarBatch>()
+            + self.name.capacity()
+            + (self.index.capacity() + self.offset.capacity()) * size_of::<u64>()
+            + self.field_columns.capacity() * size_of::<Option<usize>>()
+            + columns) as u64
+    }
+}
+
+/// Reads a whole log into one batch per message type, keyed by name like `group_by_type`.
+/// Returns the warnings of the decode along with them.
+pub fn extract_batches<R: Read>(
+    reader: &mut R,
+    registry: &MessageRegistry,
+    options: DecodeOptions,
+) -> Result<(BTreeMap<String, ColumnarBatch>, Vec<String>)> {
+    let mut stream = MessageStream::new(reader, registry)?;
+    let mut batches: BTreeMap<u16, ColumnarBatch> = BTreeMap::new();
+    let mut warnings = Vec::new();
+    while let Some(record) = stream.next_record()? {
+        let Some(def) = registry.get(&record.log_type.to_string()) else {
+            continue;
+        };
+        batches
+            .entry(record.log_type)
+            .or_insert_with(|| ColumnarBatch::new(record.log_type, def))
+            .push(
+                def,
+                &record.payload,
+                record.index,
+                record.offset,
+                options,
+                &mut warnings,
+            );
+    }
+    let batches = batches
+        .into_values()
+        .map(|batch| (batch.name.clone(), batch))
+        .collect();
+    Ok((batches, warnings))
+}
+
+fn empty_column(decoder: FieldDecoder) -> Column {
+    match decoder {
+        FieldDecoder::U8 => Column::U8(Vec::new()),
+        FieldDecoder::I8 => Column::I8(Vec::new()),
+        FieldDecoder::U16 => Column::U16(Vec::new()),
+        FieldDecoder::I16 => Column::I16(Vec::new()),
+        FieldDecoder::U32 => Column::U32(Vec::new()),
+        FieldDecoder::I32 => Column::I32(Vec::new()),
+        FieldDecoder::U64 => Column::U64(Vec::new()),
+        FieldDecoder::I64 => Column::I64(Vec::new()),
+        FieldDecoder::U128 => Column::U128(Vec::new()),
+        FieldDecoder::I128 => Column::I128(Vec::new()),
+        FieldDecoder::F32 => Column::F32(Vec::new()),
+        FieldDecoder::F64 | FieldDecoder::Fixed(_) => Column::F64(Vec::new()),
+        FieldDecoder::Bool => Column::Bool(Vec::new()),
+        FieldDecoder::Scaled(scaled) => Column::Scaled(Vec::new(), scaled),
+        FieldDecoder::Char
+        | FieldDecoder::Text
+        | FieldDecoder::Bytes
+        | FieldDecoder::FileContents
+        | FieldDecoder::Unsupported => Column::Text(Vec::new()),
+    }
+}
+
+// The bytes of a field of the column's own type
+fn push_bytes(
+    column: &mut BatchColumn,
+    field: &FieldDef,
+    bytes: &[u8],
+    options: DecodeOptions,
+    warnings: &mut Vec<String>,
+) {
+    match (&mut column.values, column.decoder) {
+        (Column::U8(v), _) => v.push(bytes[0]),
+        (Column::I8(v), _) => v.push(bytes[0] as i8),
+        (Column::U16(v), _) => v.push(LittleEndian::read_u16(bytes)),
+        (Column::I16(v), _) => v.push(LittleEndian::read_i16(bytes)),
+        (Column::U32(v), _) => v.push(LittleEndian::read_u32(bytes)),
+        (Column::I32(v), _) => v.push(LittleEndian::read_i32(bytes)),
+        (Column::U64(v), _) => v.push(LittleEndian::read_u64(bytes)),
+        (Column::I64(v), _) => v.push(LittleEndian::read_i64(bytes)),
+        (Column::U128(v), _) => v.push(LittleEndian::read_u128(bytes)),
+        (Column::I128(v), _) => v.push(LittleEndian::read_i128(bytes)),
+        (Column::F32(v), _) => v.push(LittleEndian::read_f32(bytes)),
+        (Column::F64(v), FieldDecoder::Fixed(fixed)) => v.push(fixed.decode(bytes)),
+        (Column::F64(v), _) => v.push(LittleEndian::read_f64(bytes)),
+        (Column::Bool(v), _) => {
+            if bytes[0] > 1 {
+                warnings.push(format!(
+                    "Boolean field '{}' holds {}, read as true",
+                    field.name, bytes[0]
+                ));
+            }
+            v.push(bytes[0] != 0)
+        }
+        (Column::Scaled(v, _), _) => v.push(LittleEndian::read_i32(bytes)),
+        (Column::Text(v), decoder) => {
+            v.push(plan::decode(decoder, field, bytes, options.bytes, warnings))
+        }
+    }
+}
+
+// A value decoded as another type, read back from its text; false if it does not fit the column
+fn push_text(column: &mut Column, text: &str) -> bool {
+    let parsed = match column {
+        Column::U8(v) => text.parse().map(|x| v.push(x)).is_ok(),
+        Column::I8(v) => text.parse().map(|x| v.push(x)).is_ok(),
+        Column::U16(v) => text.parse().map(|x| v.push(x)).is_ok(),
+        Column::I16(v) => text.parse().map(|x| v.push(x)).is_ok(),
+        Column::U32(v) => text.parse().map(|x| v.push(x)).is_ok(),
+        Column::I32(v) => text.parse().map(|x| v.push(x)).is_ok(),
+        Column::U64(v) => text.parse().map(|x| v.push(x)).is_ok(),
+        Column::I64(v) => text.parse().map(|x| v.push(x)).is_ok(),
+        Column::U128(v) => text.parse().map(|x| v.push(x)).is_ok(),
+        Column::I128(v) => text.parse().map(|x| v.push(x)).is_ok(),
+        Column::F32(v) => text.parse().map(|x| v.push(x)).is_ok(),
+        Column::F64(v) => text.parse().map(|x| v.push(x)).is_ok(),
+        Column::Bool(v) => text.parse().map(|x| v.push(x)).is_ok(),
+        Column::Scaled(v, scaled) => {
+            // The exact decimal, e.g. 47.1234567, is the raw integer with a point in it
+            let raw = text.split_once('.').and_then(|(int, frac)| {
+                (frac.len() == scaled.decimals as usize)
+                    .then(|| format!("{}{}", int, frac).parse().ok())
+                    .flatten()
+            });
+            raw.map(|x| v.push(x)).is_some()
+        }
+        Column::Text(v) => {
+            v.push(text.to_string());
+            true
+        }
+    };
+    if !parsed {
+        push_default(column);
+    }
+    parsed
+}
+
+fn push_default(column: &mut Column) {
+    match column {
+        Column::U8(v) => v.push(0),
+        Column::I8(v) => v.push(0),
+        Column::U16(v) => v.push(0),
+        Column::I16(v) => v.push(0),
+        Column::U32(v) => v.push(0),
+        Column::I32(v) => v.push(0),
+        Column::U64(v) => v.push(0),
+        Column::I64(v) => v.push(0),
+        Column::U128(v) => v.push(0),
+        Column::I128(v) => v.push(0),
+        Column::F32(v) => v.push(0.0),
+        Column::F64(v) => v.push(0.0),
+        Column::Bool(v) => v.push(false),
+        Column::Scaled(v, _) => v.push(0),
+        Column::Text(v) => v.push(String::new()),
+    }
+}
+
+fn column_size(column: &Column) -> usize {
+    match column {
+        Column::U8(v) => v.capacity(),
+        Column::I8(v) => v.capacity(),
+        Column::U16(v) => v.capacity() * 2,
+        Column::I16(v) => v.capacity() * 2,
+        Column::U32(v) => v.capacity() * 4,
+        Column::I32(v) => v.capacity() * 4,
+        Column::U64(v) => v.capacity() * 8,
+        Column::I64(v) => v.capacity() * 8,
+        Column::U128(v) => v.capacity() * 16,
+        Column::I128(v) => v.capacity() * 16,
+        Column::F32(v) => v.capacity() * 4,
+        Column::F64(v) => v.capacity() * 8,
+        Column::Bool(v) => v.capacity(),
+        Column::Scaled(v, _) => v.capacity() * 4,
+        Column::Text(v) => {
+            v.capacity() * size_of::<String>() + v.iter().map(String::capacity).sum::<usize>()
+        }
+    }
+}
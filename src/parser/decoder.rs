@@ -0,0 +1,131 @@
+// parser/decoder.rs
+// Push-style decoding for event-driven receivers: bytes are fed in whatever chunks arrive and
+// complete messages come out, with partial frames buffered in between.
+
+use super::stream::{decode_record, in_record, RawRecord};
+use super::{DecodeOptions, ParsedMessage};
+use crate::errors::{Result, WallaceError};
+use crate::messages::registry::{Definitions, MessageRegistry};
+use byteorder::{ByteOrder, LittleEndian};
+
+const HEADER_LEN: usize = 4;
+const RECORD_HEADER_LEN: usize = 4;
+
+pub struct Decoder<'a> {
+    registry: &'a MessageRegistry,
+    defs: Definitions<'a>,
+    buf: Vec<u8>,
+    /// The 4-byte file header, once it has arrived
+    pub header: Option<i32>,
+    /// Warnings collected so far, prefixed with the message they belong to
+    pub warnings: Vec<String>,
+    /// Number of padding fields (TRASH/PADDING/RESERVED by default) skipped so far
+    pub skipped_fields: usize,
+    /// Records read so far, known type or not
+    pub records_read: u64,
+    /// Records skipped because their type is not in the registry
+    pub unknown_records: usize,
+    /// How records are decoded, e.g. whether raw payloads are kept
+    pub options: DecodeOptions,
+    /// Bytes consumed as complete frames, including the file header
+    pub bytes_read: u64,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(registry: &'a MessageRegistry) -> Self {
+        Decoder {
+            registry,
+            defs: Definitions::new(registry),
+            buf: Vec::new(),
+            header: None,
+            warnings: Vec::new(),
+            skipped_fields: 0,
+            records_read: 0,
+            unknown_records: 0,
+            options: DecodeOptions::default(),
+            bytes_read: 0,
+        }
+    }
+
+    /// Buffers `chunk` and returns every message it completed, in log order.
+    /// Records whose type is not in the registry are skipped.
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<Vec<ParsedMessage>> {
+        let mut messages = Vec::new();
+        for record in self.feed_records(chunk) {
+            if let Some(msg) = decode_record(
+                &record,
+                &self.defs,
+                &mut self.warnings,
+                &mut self.skipped_fields,
+                &mut self.unknown_records,
+                self.options,
+            )? {
+                messages.push(msg);
+            }
+        }
+        Ok(messages)
+    }
+
+    /// Like `feed`, but returns the completed records undecoded, whatever their type
+    pub fn feed_records(&mut self, chunk: &[u8]) -> Vec<RawRecord> {
+        self.buf.extend_from_slice(chunk);
+        let mut pos = 0;
+        if self.header.is_none() {
+            if self.buf.len() < HEADER_LEN {
+                return Vec::new();
+            }
+            self.header = Some(LittleEndian::read_i32(&self.buf[..HEADER_LEN]));
+            pos = HEADER_LEN;
+        }
+        let mut records = Vec::new();
+        while self.buf.len() - pos >= RECORD_HEADER_LEN {
+            let log_type = LittleEndian::read_u16(&self.buf[pos..pos + 2]);
+            let length = LittleEndian::read_u16(&self.buf[pos + 2..pos + 4]) as usize;
+            let end = pos + RECORD_HEADER_LEN + length;
+            if self.buf.len() < end {
+                break;
+            }
+            records.push(RawRecord {
+                log_type,
+                payload: self.buf[pos + RECORD_HEADER_LEN..end].to_vec(),
+                index: self.records_read,
+                offset: self.bytes_read + pos as u64,
+            });
+            self.records_read += 1;
+            pos = end;
+        }
+        self.buf.drain(..pos);
+        self.bytes_read += pos as u64;
+        records
+    }
+
+    /// Bytes of an incomplete frame waiting for more input
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Ends the input, failing if it stopped in the middle of a frame
+    pub fn finish(&self) -> Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let error = WallaceError::Io(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            format!(
+                "input ended inside a frame, {} bytes left over",
+                self.buf.len()
+            ),
+        ));
+        if self.header.is_none() {
+            return Err(error);
+        }
+        let log_type = (self.buf.len() >= 2).then(|| LittleEndian::read_u16(&self.buf));
+        Err(in_record(
+            error,
+            self.records_read,
+            self.bytes_read,
+            log_type,
+            self.registry,
+        ))
+    }
+}
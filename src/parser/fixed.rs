@@ -0,0 +1,94 @@
+// parser/fixed.rs
+// Fixed-point field types: `qM.N` (signed) and `uqM.N` (unsigned), exported as floats.
+
+/// A binary fixed-point number with `int_bits` integer bits (including the sign bit when signed)
+/// and `frac_bits` fractional bits, stored little-endian in `(int_bits + frac_bits) / 8` bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedPoint {
+    pub signed: bool,
+    pub int_bits: u32,
+    pub frac_bits: u32,
+}
+
+impl FixedPoint {
+    /// Parses a type string like `q32.16` or `uq16.16`. The total width must be a whole number
+    /// of bytes, at most 64 bits.
+    pub fn parse(type_str: &str) -> Option<Self> {
+        let (signed, rest) = match type_str.strip_prefix("uq") {
+            Some(rest) => (false, rest),
+            None => (true, type_str.strip_prefix('q')?),
+        };
+        let (int_bits, frac_bits) = rest.split_once('.')?;
+        let fixed = FixedPoint {
+            signed,
+            int_bits: int_bits.parse().ok()?,
+            frac_bits: frac_bits.parse().ok()?,
+        };
+        let bits = fixed.int_bits + fixed.frac_bits;
+        (bits > 0 && bits <= 64 && bits.is_multiple_of(8)).then_some(fixed)
+    }
+
+    pub fn size(&self) -> usize {
+        ((self.int_bits + self.frac_bits) / 8) as usize
+    }
+
+    fn scale(&self) -> f64 {
+        (self.frac_bits as f64).exp2()
+    }
+
+    /// Converts the raw little-endian bytes (exactly `size()` of them) to its value
+    pub fn decode(&self, bytes: &[u8]) -> f64 {
+        let mut raw = [0u8; 8];
+        raw[..bytes.len()].copy_from_slice(bytes);
+        let raw = u64::from_le_bytes(raw);
+        let bits = (self.int_bits + self.frac_bits) as u64;
+        if self.signed {
+            // Sign-extend from the stored width
+            let shift = 64 - bits;
+            ((raw << shift) as i64 >> shift) as f64 / self.scale()
+        } else {
+            raw as f64 / self.scale()
+        }
+    }
+
+    // Smallest and largest raw values
+    fn raw_range(&self) -> (f64, f64) {
+        let bits = (self.int_bits + self.frac_bits) as i32;
+        if self.signed {
+            (-(2f64.powi(bits - 1)), 2f64.powi(bits - 1) - 1.0)
+        } else {
+            (0.0, 2f64.powi(bits) - 1.0)
+        }
+    }
+
+    /// Converts a value to its raw little-endian bytes, rounding to the nearest step.
+    /// `None` if the value is not finite or out of range.
+    pub fn encode(&self, value: f64) -> Option<Vec<u8>> {
+        let scaled = (value * self.scale()).round();
+        let (min, max) = self.raw_range();
+        if !scaled.is_finite() || scaled < min || scaled > max {
+            return None;
+        }
+        Some(self.raw_bytes(scaled))
+    }
+
+    /// Like `encode`, but clamps out of range values to the nearest bound (NaN becomes zero)
+    pub fn encode_saturating(&self, value: f64) -> Vec<u8> {
+        let (min, max) = self.raw_range();
+        let scaled = (value * self.scale()).round();
+        self.raw_bytes(if scaled.is_nan() {
+            0.0
+        } else {
+            scaled.clamp(min, max)
+        })
+    }
+
+    fn raw_bytes(&self, scaled: f64) -> Vec<u8> {
+        let raw = if self.signed {
+            scaled as i64 as u64
+        } else {
+            scaled as u64
+        };
+        raw.to_le_bytes()[..self.size()].to_vec()
+    }
+}
@@ -0,0 +1,64 @@
+// parser/layout.rs
+// Where each field of a definition lies in its payload, taken from the decode plan that decoding
+// uses, for tools that match a record's bytes with its fields, such as a hex view.
+
+use super::plan::{DecodePlan, FieldDecoder};
+use crate::messages::registry::{FieldDef, MessageDef};
+use std::ops::Range;
+
+/// One field of a definition and the bytes it takes
+#[derive(Debug, Clone, Copy)]
+pub struct FieldSpan<'a> {
+    pub field: &'a FieldDef,
+    pub decoder: FieldDecoder,
+    /// Where the field starts in the payload, `None` after a field of unknown or variable size
+    pub offset: Option<usize>,
+    /// Bytes the field takes, `None` for unsupported types and, without a payload length,
+    /// FILE_CONTENTS
+    pub size: Option<usize>,
+}
+
+impl FieldSpan<'_> {
+    /// The payload bytes of the field, if both its offset and size are known
+    pub fn range(&self) -> Option<Range<usize>> {
+        Some(self.offset?..self.offset? + self.size?)
+    }
+
+    /// Padding the decoder skips rather than decodes
+    pub fn is_skipped(&self) -> bool {
+        self.field.is_skipped()
+    }
+}
+
+/// The fields of `def` in payload order, padding included, with their offsets, sizes and
+/// decoders. With `"packing": "natural"` the implicit padding shows as PADDING fields.
+pub fn field_layout(def: &MessageDef) -> Vec<FieldSpan<'_>> {
+    spans(&def.fields, &def.decode_plan(), None)
+}
+
+/// The layout decoding uses for a payload of `len` bytes: that of the layout variant of this
+/// size if there is one, with FILE_CONTENTS taking the rest of the payload. Fields of a payload
+/// cut short may end past `len`.
+pub fn field_layout_for(def: &MessageDef, len: usize) -> Vec<FieldSpan<'_>> {
+    let (fields, plan) = def.layout(len);
+    spans(fields, &plan, Some(len))
+}
+
+fn spans<'a>(fields: &'a [FieldDef], plan: &DecodePlan, len: Option<usize>) -> Vec<FieldSpan<'a>> {
+    fields
+        .iter()
+        .zip(plan.steps())
+        .map(|(field, step)| FieldSpan {
+            field,
+            decoder: step.decoder,
+            offset: step.offset,
+            size: match step.decoder {
+                FieldDecoder::FileContents => step
+                    .offset
+                    .zip(len)
+                    .map(|(offset, len)| len.saturating_sub(offset)),
+                _ => step.size,
+            },
+        })
+        .collect()
+}
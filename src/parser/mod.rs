@@ -1,14 +1,143 @@
-use crate::errors::{Result, WallaceError}; // Use custom Result and Error
+use crate::errors::Result; // Use custom Result
 use crate::messages::registry::{FieldDef, MessageDef, MessageRegistry};
-use byteorder::{LittleEndian, ReadBytesExt};
-use std::collections::HashMap;
-use std::io::{Read, Seek, SeekFrom}; // Import Seek and SeekFrom
+use std::collections::BTreeMap;
+use std::io::Read;
 
-#[derive(Debug, Clone)]
+// Library API for embedding servers and receivers, the binary itself reads blocking streams
+#[cfg(feature = "async")]
+#[allow(dead_code)]
+pub mod async_stream;
+// Library API for consumers reading whole logs from memory, the binary decodes into owned messages
+#[allow(dead_code)]
+pub mod borrowed;
+// Library API for columnar consumers, the binary only measures it in `bench`
+#[allow(dead_code)]
+pub mod bulk;
+pub mod bytes;
+// Library API for columnar consumers, the binary only measures it in `bench`
+#[allow(dead_code)]
+pub mod columnar;
+#[allow(dead_code)]
+pub mod decoder;
+pub mod fixed;
+// Library API for hex viewers and other tools that map bytes to fields
+#[allow(dead_code)]
+pub mod layout;
+pub mod plan;
+pub mod semantic;
+pub mod stream;
+pub mod text;
+#[cfg(feature = "async")]
+#[allow(unused_imports)]
+pub use async_stream::AsyncMessageStream;
+#[allow(unused_imports)]
+pub use borrowed::{FieldValue, MessageSlices, ParsedMessageRef};
+pub use bytes::BytesFormat;
+#[allow(unused_imports)]
+pub use columnar::{extract_batches, ColumnarBatch};
+#[allow(unused_imports)]
+pub use decoder::Decoder;
+pub use fixed::FixedPoint;
+#[allow(unused_imports)]
+pub use layout::{field_layout, field_layout_for, FieldSpan};
+pub use plan::{DecodePlan, FieldDecoder};
+pub use semantic::Scaled;
+pub use stream::{MessageStream, ParseLimits, RecordCount};
+
+#[derive(Debug, Clone, Default)]
 pub struct ParsedMessage {
     pub log_type: u16,
     pub name: String,
     pub fields: Vec<(String, String)>,
+    /// Position of the record among all records of the log, unknown types included
+    pub index: u64,
+    /// Byte offset of the record in the input, counting the file header
+    pub offset: u64,
+    /// Length of the payload in bytes
+    pub payload_len: usize,
+    /// The record timestamp in microseconds, if the message carries one
+    pub timestamp: Option<u64>,
+    /// The policy applied because the payload ended before the fields did, if it did
+    pub partial: Option<PartialPolicy>,
+    /// A field failed to decode and only the ones before it were kept (lenient mode)
+    pub salvaged: bool,
+    /// The payload exactly as logged, if the stream was set to keep it (`DecodeOptions::keep_raw`)
+    #[allow(dead_code)] // Library API, the binary decodes every field itself
+    pub raw: Option<Vec<u8>>,
+}
+
+/// What happens when a payload ends before the fields of its definition do
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PartialPolicy {
+    /// The fields that fit are kept, the rest left out
+    #[default]
+    Truncate,
+    /// The fields that do not fit are kept with empty values, so every row has every column
+    NullFill,
+    /// The record fails to decode
+    Error,
+}
+
+impl PartialPolicy {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "truncate" => Some(PartialPolicy::Truncate),
+            "null-fill" => Some(PartialPolicy::NullFill),
+            "error" => Some(PartialPolicy::Error),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PartialPolicy::Truncate => "truncate",
+            PartialPolicy::NullFill => "null-fill",
+            PartialPolicy::Error => "error",
+        }
+    }
+}
+
+/// How the streams decode records
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DecodeOptions {
+    /// Keep each record's payload on its message as `raw`, for custom decoding or forwarding
+    /// the original frames; off by default to save the memory
+    pub keep_raw: bool,
+    pub partial: PartialPolicy,
+    /// With `PartialPolicy::Error`, keep the fields decoded before the one a payload ends in and
+    /// carry on, instead of failing the whole run
+    pub lenient: bool,
+    /// Text form of byte-array fields
+    pub bytes: BytesFormat,
+    /// Record count, time and empty-record bounds on the streams
+    pub limits: ParseLimits,
+}
+
+/// Field names recognised as the record timestamp, in microseconds since boot
+pub const TIMESTAMP_FIELDS: &[&str] = &["Timestamp", "TimeUS"];
+
+impl ParsedMessage {
+    /// Returns the value of a field by name, if present
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field_name, _)| field_name == name)
+            .map(|(_, val)| val.as_str())
+    }
+
+    /// Whether `value` of this message stands for a field lost to truncation: the empty values
+    /// `PartialPolicy::NullFill` puts in place of the fields that did not fit
+    pub fn is_filled(&self, value: &str) -> bool {
+        self.partial == Some(PartialPolicy::NullFill) && value.is_empty()
+    }
+
+    /// Returns the record timestamp in microseconds, if the message carries one
+    pub fn timestamp_us(&self) -> Option<u64> {
+        TIMESTAMP_FIELDS
+            .iter()
+            .find_map(|name| self.field(name))
+            .and_then(|val| val.parse().ok())
+    }
 }
 
 // Helper function to get byte size of a type string
@@ -18,7 +147,10 @@ fn get_type_size(type_str: &str) -> Option<usize> {
         "Q" | "q" | "d" => Some(8),
         "I" | "i" | "f" => Some(4),
         "H" | "h" => Some(2),
-        "B" | "b" => Some(1),
+        "B" | "b" | "?" => Some(1),
+        "u128" | "i128" => Some(16),
+        s if Scaled::parse(s).is_some() => Scaled::parse(s).map(|scaled| scaled.size()),
+        s if FixedPoint::parse(s).is_some() => FixedPoint::parse(s).map(|fp| fp.size()),
         s if s.chars().all(|c| c == 'c') => Some(s.len()),
         s if s.ends_with("s") => s[..s.len() - 1].parse::<usize>().ok(),
         s if s.chars().all(|c| c == 'B') => Some(s.len()),
@@ -27,221 +159,223 @@ fn get_type_size(type_str: &str) -> Option<usize> {
     }
 }
 
+/// Byte size of a field, or `None` for the variable length FILE_CONTENTS and unknown types
+pub fn field_size(field: &FieldDef) -> Option<usize> {
+    match field.r#type.as_str() {
+        "c" if field.name == "FILE_CONTENTS" => None,
+        t => get_type_size(t),
+    }
+}
+
+/// Byte offset of the named field within the payload, if every field before it has a known size
+pub fn field_offset(def: &MessageDef, name: &str) -> Option<usize> {
+    let index = def.fields.iter().position(|field| field.name == name)?;
+    def.decode_plan().steps()[index].offset
+}
+
+/// Smallest payload that holds every field of `def`; variable length FILE_CONTENTS counts as empty.
+/// `None` if a field type has no known size.
+pub fn min_payload_size(def: &MessageDef) -> Option<usize> {
+    def.fields
+        .iter()
+        .map(|f| match f.r#type.as_str() {
+            "c" if f.name == "FILE_CONTENTS" => Some(0),
+            t => get_type_size(t),
+        })
+        .sum()
+}
+
+/// Returns true for scalar type codes whose decoded value is a plain number
+pub fn is_numeric_type(type_str: &str) -> bool {
+    matches!(
+        type_str,
+        "Q" | "q" | "I" | "i" | "H" | "h" | "B" | "b" | "f" | "d" | "u128" | "i128"
+    ) || FixedPoint::parse(type_str).is_some()
+        || Scaled::parse(type_str).is_some()
+}
+
+/// Messages in log order, warnings, skipped padding fields and the records read per log_type
+pub type Extracted = (
+    Vec<ParsedMessage>,
+    Vec<String>,
+    usize,
+    BTreeMap<u16, RecordCount>,
+);
+
 pub fn extract_messages<R: Read>(
     reader: &mut R,
     registry: &MessageRegistry,
-) -> Result<(Vec<ParsedMessage>, Vec<String>, usize)> {
-    // Update return type
-
+    options: DecodeOptions,
+) -> Result<Extracted> {
+    let mut stream = MessageStream::new(reader, registry)?;
+    stream.options = options;
     let mut messages = Vec::new();
-    let mut warnings = Vec::new();
-    let mut total_skipped_fields = 0;
-    // Read header, convert potential io::Error to WallaceError::Io
-    let _header = reader.read_i32::<LittleEndian>()?;
-
-    loop {
-        let log_type = match reader.read_u16::<LittleEndian>() {
-            Ok(v) => v,
-            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break, // End of file is expected
-            Err(e) => return Err(WallaceError::Io(e)),                        // Other IO errors
-        };
-
-        // Read length, convert potential io::Error
-        let length = reader.read_u16::<LittleEndian>()?;
-        let mut payload = vec![0u8; length as usize];
-        // Read payload, convert potential io::Error
-        reader.read_exact(&mut payload)?;
-
-        let log_type_key = log_type.to_string();
-        if let Some(def) = registry.get(&log_type_key) {
-            // parse_fields now returns Result<(...), WallaceError>
-            match parse_fields(&payload, &def.fields) {
-                Ok((fields, field_warnings, skipped_fields)) => {
-                    total_skipped_fields += skipped_fields;
-                    messages.push(ParsedMessage {
-                        log_type,
-                        name: def.name.clone(),
-                        fields,
-                    });
-                    for warn in field_warnings {
-                        warnings.push(format!("log_type {} ({}): {}", log_type, def.name, warn));
-                    }
-                }
-                Err(e) => {
-                    // Propagate parsing errors, adding context
-                    return Err(WallaceError::ParsingError {
-                        log_type,
-                        name: def.name.clone(),
-                        reason: e.to_string(),
-                    });
-                }
-            }
-        } else {
-            // Optionally add a warning for unknown message types if desired
-            // warnings.push(format!("Unknown message type ID: {}", log_type));
-            // Or return an error:
-            // return Err(WallaceError::UnknownMessageType(log_type));
-            // Current behavior is to silently skip, which we'll keep for now.
-        }
+    while let Some(msg) = stream.next_message()? {
+        messages.push(msg);
     }
+    Ok((
+        messages,
+        stream.warnings,
+        stream.skipped_fields,
+        stream.record_counts,
+    ))
+}
 
-    Ok((messages, warnings, total_skipped_fields))
+/// What `parse_fields` made of a payload
+#[derive(Debug, Default)]
+pub struct ParsedFields {
+    pub fields: Vec<(String, String)>,
+    pub warnings: Vec<String>,
+    /// Padding fields skipped
+    pub skipped: usize,
+    /// The payload ended before the fields did
+    pub truncated: bool,
+    /// A field failed to decode and only the ones before it were kept (`DecodeOptions::lenient`)
+    pub salvaged: bool,
 }
 
+/// Decodes a payload with a plan compiled for the occasion; decoding many records of the same
+/// definition is quicker with `parse_with_plan` and the plan kept by the registry
 pub fn parse_fields(
     payload: &[u8],
     field_defs: &[FieldDef],
-) -> Result<(Vec<(String, String)>, Vec<String>, usize)> {
-    // Update return type
+    options: DecodeOptions,
+) -> Result<ParsedFields> {
+    parse_with_plan(
+        payload,
+        field_defs,
+        &DecodePlan::compile(field_defs),
+        options,
+    )
+}
+
+/// Decodes a payload following `plan`, compiled from `field_defs`
+pub fn parse_with_plan(
+    payload: &[u8],
+    field_defs: &[FieldDef],
+    plan: &DecodePlan,
+    options: DecodeOptions,
+) -> Result<ParsedFields> {
     let mut skip_count = 0;
-    let mut cursor = std::io::Cursor::new(payload);
-    let mut parsed = Vec::new();
+    let mut pos = 0;
+    let mut parsed = Vec::with_capacity(field_defs.len());
     let mut warnings = Vec::new();
+    let mut truncated = false;
+    let mut salvaged = false;
 
-    for field in field_defs {
-        let current_pos = cursor.position(); // Get position before read/skip
+    // A payload of another size than the definition's is reported before any field is read
+    let mismatch = plan
+        .payload_size()
+        .filter(|&expected| expected != payload.len());
+    if let Some(expected) = mismatch {
+        warnings.push(format!(
+            "Payload length {} does not match the {} bytes of the definition.",
+            payload.len(),
+            expected
+        ));
+    }
 
-        // --- Refactored Skipping Logic ---
-        if matches!(field.name.as_str(), "TRASH" | "PADDING" | "RESERVED") {
-            if let Some(size_to_skip) = get_type_size(&field.r#type) {
-                // Check if skipping exceeds payload bounds
-                if current_pos + size_to_skip as u64 > payload.len() as u64 {
+    for (i, (field, step)) in field_defs.iter().zip(plan.steps()).enumerate() {
+        if step.skip {
+            if let Some(size_to_skip) = step.size {
+                if pos + size_to_skip > payload.len() {
                     warnings.push(format!(
                         "Attempted to skip field '{}' ({}) of size {}, but it exceeds payload length {}. Skipping remaining {} bytes.",
-                        field.name, field.r#type, size_to_skip, payload.len(), payload.len() as u64 - current_pos
+                        field.name, field.r#type, size_to_skip, payload.len(), payload.len() - pos
                     ));
-                    cursor.seek(SeekFrom::End(0))?; // Seek to end
+                    pos = payload.len();
                 } else {
-                    cursor.seek(SeekFrom::Current(size_to_skip as i64))?;
+                    pos += size_to_skip;
                 }
                 skip_count += 1;
-                continue; // Move to the next field
             } else {
-                // If size cannot be determined for a TRASH field, add warning and stop parsing this message?
+                // Continuing risks misaligned reads, but the warning says so
                 warnings.push(format!(
                     "Cannot determine size for skippable field '{}' with unknown type '{}'. Parsing may be incorrect.",
                     field.name, field.r#type
                 ));
-                // Depending on desired strictness, could return an error here:
-                // return Err(WallaceError::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, "Unknown type for skippable field")));
-                continue; // Or just continue, risking misaligned reads
             }
+            continue;
         }
-        // --- End Refactored Skipping Logic ---
 
-        // Check if reading the next field would exceed bounds *before* attempting to read
-        // This requires knowing the size beforehand.
-        let field_size = get_type_size(&field.r#type);
-
-        if let Some(size) = field_size {
-            if current_pos + size as u64 > payload.len() as u64 {
-                warnings.push(format!(
-                    "Attempted to read field '{}' ({}) of size {}, but it exceeds payload length {}. Stopping parse for this message.",
-                    field.name, field.r#type, size, payload.len()
-                 ));
-                // Return potentially partial results and let caller decide? Or error out?
-                // For now, let's stop parsing this message's fields.
-                break;
-            }
-        } else if !matches!(field.r#type.as_str(), "c" if field.name == "FILE_CONTENTS") {
-            // If size is unknown (and not the special FILE_CONTENTS case), we can't safely proceed.
+        let Some(size) = step.size else {
             warnings.push(format!(
                 "Cannot determine size for field '{}' with unknown type '{}'. Stopping parse for this message.",
                 field.name, field.r#type
              ));
             break;
-        }
-
-        // Proceed with reading the field value
-        let val = match field.r#type.as_str() {
-            "Q" => cursor.read_u64::<LittleEndian>()?.to_string(),
-            "q" => cursor.read_i64::<LittleEndian>()?.to_string(),
-            "I" => cursor.read_u32::<LittleEndian>()?.to_string(),
-            "H" => cursor.read_u16::<LittleEndian>()?.to_string(),
-            "B" => cursor.read_u8()?.to_string(),
-            "b" => cursor.read_i8()?.to_string(),
-            "i" => cursor.read_i32::<LittleEndian>()?.to_string(),
-            "h" => cursor.read_i16::<LittleEndian>()?.to_string(),
-            "f" => cursor.read_f32::<LittleEndian>()?.to_string(),
-            "d" => cursor.read_f64::<LittleEndian>()?.to_string(),
-            // Handle variable length 'c' type (assumes it reads to end of payload)
-            // This is potentially fragile if other fields follow FILE_CONTENTS.
-            // The JSON definition should ideally only use this for the *last* field.
-            "c" if field.name == "FILE_CONTENTS" => {
-                let mut buf = Vec::new();
-                cursor.read_to_end(&mut buf)?;
-                String::from_utf8_lossy(&buf)
-                    .trim_end_matches('\0')
-                    .to_string()
-            }
-            // Fixed length string
-            s if s.chars().all(|c| c == 'c') => {
-                let len = s.len(); // Size already checked above
-                let mut buf = vec![0u8; len];
-                cursor.read_exact(&mut buf)?;
-                String::from_utf8_lossy(&buf)
-                    .trim_end_matches('\0')
-                    .to_string()
-            }
-            // String with explicit length (e.g., "10s") - less common, maybe remove?
-            // Size check was done above if get_type_size supports it.
-            s if s.ends_with("s") => {
-                if let Some(len) = get_type_size(s) {
-                    let mut buf = vec![0u8; len];
-                    cursor.read_exact(&mut buf)?;
-                    String::from_utf8_lossy(&buf)
-                        .trim_end_matches('\0')
-                        .to_string()
-                } else {
-                    // Should not happen if get_type_size is consistent
+        };
+        if pos + size > payload.len() {
+            let problem = format!(
+                "Attempted to read field '{}' ({}) of size {}, but it exceeds payload length {}.",
+                field.name,
+                field.r#type,
+                size,
+                payload.len()
+            );
+            truncated = true;
+            match options.partial {
+                PartialPolicy::Truncate => {
+                    warnings.push(format!("{} Stopping parse for this message.", problem))
+                }
+                PartialPolicy::NullFill => {
                     warnings.push(format!(
-                        "Internal error: Could not get size for type '{}' in field '{}'",
-                        s, field.name
+                        "{} Leaving it and the remaining fields empty.",
+                        problem
                     ));
-                    "[error]".to_string()
+                    parsed.extend(
+                        field_defs[i..]
+                            .iter()
+                            .zip(&plan.steps()[i..])
+                            .filter(|(_, step)| !step.skip)
+                            .map(|(f, _)| (f.name.clone(), String::new())),
+                    );
+                }
+                PartialPolicy::Error if options.lenient => {
+                    warnings.push(format!("{} Keeping the fields before it.", problem));
+                    salvaged = true;
+                }
+                PartialPolicy::Error => {
+                    return Err(
+                        std::io::Error::new(std::io::ErrorKind::UnexpectedEof, problem).into(),
+                    )
                 }
             }
-            // Fixed length byte array (hex output)
-            s if s.chars().all(|c| c == 'B') || s.chars().all(|c| c == 'b') => {
-                let count = s.len(); // Size already checked above
-                let mut buf = vec![0u8; count];
-                cursor.read_exact(&mut buf)?;
-                buf.iter()
-                    .map(|b| format!("{:02X}", b))
-                    .collect::<Vec<_>>()
-                    .join(" ")
-            }
-            // Unknown type (size check failed earlier or wasn't possible)
-            unknown => {
-                // This branch might be less likely now due to earlier size checks
-                warnings.push(format!(
-                    "Unsupported type '{}' encountered for field '{}'",
-                    unknown, field.name
-                ));
-                "[unsupported]".to_string()
-            }
+            break;
+        }
+
+        // FILE_CONTENTS takes the rest of the payload, every other field exactly its size
+        let end = match step.decoder {
+            FieldDecoder::FileContents => payload.len(),
+            _ => pos + size,
         };
+        let val = plan::decode(
+            step.decoder,
+            field,
+            &payload[pos..end],
+            options.bytes,
+            &mut warnings,
+        );
+        pos = end;
         parsed.push((field.name.clone(), val));
     }
 
-    // Check if cursor consumed the whole payload (optional, for strictness)
-    if cursor.position() < payload.len() as u64 {
+    if pos < payload.len() && mismatch.is_none() {
         warnings.push(format!(
             "Payload not fully consumed. Expected length {}, read {}. Remaining {} bytes.",
             payload.len(),
-            cursor.position(),
-            payload.len() as u64 - cursor.position()
-        ));
-    } else if cursor.position() > payload.len() as u64 {
-        // This shouldn't happen with the bounds checks, but as a safeguard:
-        warnings.push(format!(
-            "Read past payload end. Expected length {}, read {}.",
-            payload.len(),
-            cursor.position()
+            pos,
+            payload.len() - pos
         ));
     }
 
-    Ok((parsed, warnings, skip_count)) // Ensure this is the last statement before the closing brace
-} // <-- Ensure this closing brace matches the function definition
+    Ok(ParsedFields {
+        fields: parsed,
+        warnings,
+        skipped: skip_count,
+        truncated,
+        salvaged,
+    })
+}
 
 // Placeholder for parsing-related logic.
@@ -0,0 +1,481 @@
+// parser/plan.rs
+// Decode plans: the field types of a message definition resolved once, when the registry is
+// loaded, into decoders with their sizes and offsets, so that decoding a record matches no type
+// strings.
+
+use super::{get_type_size, BytesFormat, FixedPoint, Scaled};
+use crate::messages::registry::FieldDef;
+use byteorder::{ByteOrder, LittleEndian};
+
+/// How the bytes of a field are turned into its value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldDecoder {
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    U64,
+    I64,
+    U128,
+    I128,
+    F32,
+    F64,
+    Bool,
+    /// A single character, `c`
+    Char,
+    Fixed(FixedPoint),
+    Scaled(Scaled),
+    /// Fixed length string, `cc...` or `<N>s`
+    Text,
+    /// Fixed length byte array, `BB...` or `bb...`
+    Bytes,
+    /// `c` named FILE_CONTENTS, which runs to the end of the payload
+    FileContents,
+    /// A type with no known size
+    Unsupported,
+}
+
+/// One field of a plan
+#[derive(Debug, Clone, Copy)]
+pub struct FieldStep {
+    pub decoder: FieldDecoder,
+    /// Bytes the field takes, `None` for unsupported types. FILE_CONTENTS counts the one byte it
+    /// needs at least.
+    pub size: Option<usize>,
+    /// Where the field starts in the payload, if every field before it has a fixed size
+    pub offset: Option<usize>,
+    /// The field is padding, skipped rather than decoded (`FieldDef::is_skipped` at compile time)
+    pub skip: bool,
+}
+
+/// The fields of a definition compiled for decoding, one step per field in the same order
+#[derive(Debug, Clone, Default)]
+pub struct DecodePlan {
+    steps: Vec<FieldStep>,
+    payload_size: Option<usize>,
+}
+
+impl DecodePlan {
+    pub fn compile(fields: &[FieldDef]) -> Self {
+        let mut offset = Some(0);
+        let steps = fields
+            .iter()
+            .map(|field| {
+                let decoder = decoder(field);
+                let size = get_type_size(&field.r#type);
+                let step = FieldStep {
+                    decoder,
+                    size,
+                    offset,
+                    skip: field.is_skipped(),
+                };
+                offset = match decoder {
+                    FieldDecoder::FileContents => None,
+                    _ => offset.zip(size).map(|(offset, size)| offset + size),
+                };
+                step
+            })
+            .collect();
+        DecodePlan {
+            steps,
+            payload_size: offset,
+        }
+    }
+
+    pub fn steps(&self) -> &[FieldStep] {
+        &self.steps
+    }
+
+    /// The size every payload of the definition has, `None` if it varies (FILE_CONTENTS) or a
+    /// type has no known size
+    pub fn payload_size(&self) -> Option<usize> {
+        self.payload_size
+    }
+}
+
+// Resolved in the order `get_type_size` sizes the types
+fn decoder(field: &FieldDef) -> FieldDecoder {
+    match field.r#type.as_str() {
+        "Q" => FieldDecoder::U64,
+        "q" => FieldDecoder::I64,
+        "I" => FieldDecoder::U32,
+        "i" => FieldDecoder::I32,
+        "H" => FieldDecoder::U16,
+        "h" => FieldDecoder::I16,
+        "B" => FieldDecoder::U8,
+        "b" => FieldDecoder::I8,
+        "f" => FieldDecoder::F32,
+        "d" => FieldDecoder::F64,
+        "?" => FieldDecoder::Bool,
+        "u128" => FieldDecoder::U128,
+        "i128" => FieldDecoder::I128,
+        "c" if field.name == "FILE_CONTENTS" => FieldDecoder::FileContents,
+        "c" => FieldDecoder::Char,
+        s => {
+            if let Some(scaled) = Scaled::parse(s) {
+                FieldDecoder::Scaled(scaled)
+            } else if let Some(fixed) = FixedPoint::parse(s) {
+                FieldDecoder::Fixed(fixed)
+            } else if s.chars().all(|c| c == 'c') || s.ends_with('s') && get_type_size(s).is_some()
+            {
+                FieldDecoder::Text
+            } else if s.chars().all(|c| c == 'B') || s.chars().all(|c| c == 'b') {
+                FieldDecoder::Bytes
+            } else {
+                FieldDecoder::Unsupported
+            }
+        }
+    }
+}
+
+/// The text value of a field from its bytes: exactly `size` of them, or the rest of the payload
+/// for FILE_CONTENTS
+pub fn decode(
+    decoder: FieldDecoder,
+    field: &FieldDef,
+    bytes: &[u8],
+    format: BytesFormat,
+    warnings: &mut Vec<String>,
+) -> String {
+    match decoder {
+        FieldDecoder::U8 => bytes[0].to_string(),
+        FieldDecoder::I8 => (bytes[0] as i8).to_string(),
+        FieldDecoder::U16 => LittleEndian::read_u16(bytes).to_string(),
+        FieldDecoder::I16 => LittleEndian::read_i16(bytes).to_string(),
+        FieldDecoder::U32 => LittleEndian::read_u32(bytes).to_string(),
+        FieldDecoder::I32 => LittleEndian::read_i32(bytes).to_string(),
+        FieldDecoder::U64 => LittleEndian::read_u64(bytes).to_string(),
+        FieldDecoder::I64 => LittleEndian::read_i64(bytes).to_string(),
+        FieldDecoder::U128 => LittleEndian::read_u128(bytes).to_string(),
+        FieldDecoder::I128 => LittleEndian::read_i128(bytes).to_string(),
+        FieldDecoder::F32 => LittleEndian::read_f32(bytes).to_string(),
+        FieldDecoder::F64 => LittleEndian::read_f64(bytes).to_string(),
+        FieldDecoder::Bool => match bytes[0] {
+            0 => "false".to_string(),
+            1 => "true".to_string(),
+            other => {
+                warnings.push(format!(
+                    "Boolean field '{}' holds {}, read as true",
+                    field.name, other
+                ));
+                "true".to_string()
+            }
+        },
+        // Byte values map to Latin-1 so none is lost; NUL means unset
+        FieldDecoder::Char => match bytes[0] {
+            0 => String::new(),
+            byte => char::from(byte).to_string(),
+        },
+        // Fixed-point is converted to its float value
+        FieldDecoder::Fixed(fixed) => fixed.decode(bytes).to_string(),
+        // Scaled integers keep their exact decimal value
+        FieldDecoder::Scaled(scaled) => scaled.format(LittleEndian::read_i32(bytes)),
+        FieldDecoder::Text | FieldDecoder::FileContents => {
+            super::text::decode_string(bytes, field, warnings)
+        }
+        // As hex unless another form was asked for
+        FieldDecoder::Bytes => format.format(bytes),
+        FieldDecoder::Unsupported => {
+            warnings.push(format!(
+                "Unsupported type '{}' encountered for field '{}'",
+                field.r#type, field.name
+            ));
+            "[unsupported]".to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::registry::{set_skip_policy, MessageRegistry};
+    use crate::parser::text::decode_string;
+    use crate::parser::{parse_with_plan, DecodeOptions, PartialPolicy};
+    use byteorder::ReadBytesExt;
+    use std::io::{Cursor, Read};
+
+    const REGISTRY: &str = r#"{
+        "1": {"name": "Everything", "fields": [
+            {"name": "Timestamp", "type": "Q"},
+            {"name": "Q1", "type": "q"},
+            {"name": "U32", "type": "I"},
+            {"name": "I32", "type": "i"},
+            {"name": "U16", "type": "H"},
+            {"name": "I16", "type": "h"},
+            {"name": "U8", "type": "B"},
+            {"name": "I8", "type": "b"},
+            {"name": "F32", "type": "f"},
+            {"name": "F64", "type": "d"},
+            {"name": "Flag", "type": "?"},
+            {"name": "Wide", "type": "u128"},
+            {"name": "SignedWide", "type": "i128"},
+            {"name": "Letter", "type": "c"},
+            {"name": "TRASH", "type": "BB"},
+            {"name": "Ratio", "type": "q16.16"},
+            {"name": "Gain", "type": "uq8.8"},
+            {"name": "Lat", "type": "lat_e7"},
+            {"name": "Alt", "type": "alt_mm"},
+            {"name": "Code", "type": "cccc"},
+            {"name": "Label", "type": "6s"},
+            {"name": "RESERVED", "type": "I"},
+            {"name": "Raw", "type": "BBB"},
+            {"name": "Signed", "type": "bb"},
+            {"name": "FILE_CONTENTS", "type": "c"}
+        ]}
+    }"#;
+
+    // The decoder plans replaced, which matched each field's type string as it read it
+    fn decode_by_type(
+        cursor: &mut Cursor<&[u8]>,
+        field: &FieldDef,
+        warnings: &mut Vec<String>,
+    ) -> String {
+        let mut take = |len: usize| {
+            let mut buf = vec![0; len];
+            cursor.read_exact(&mut buf).unwrap();
+            buf
+        };
+        match field.r#type.as_str() {
+            "Q" => (&take(8)[..])
+                .read_u64::<LittleEndian>()
+                .unwrap()
+                .to_string(),
+            "q" => (&take(8)[..])
+                .read_i64::<LittleEndian>()
+                .unwrap()
+                .to_string(),
+            "I" => (&take(4)[..])
+                .read_u32::<LittleEndian>()
+                .unwrap()
+                .to_string(),
+            "i" => (&take(4)[..])
+                .read_i32::<LittleEndian>()
+                .unwrap()
+                .to_string(),
+            "H" => (&take(2)[..])
+                .read_u16::<LittleEndian>()
+                .unwrap()
+                .to_string(),
+            "h" => (&take(2)[..])
+                .read_i16::<LittleEndian>()
+                .unwrap()
+                .to_string(),
+            "B" => take(1)[0].to_string(),
+            "b" => (take(1)[0] as i8).to_string(),
+            "f" => (&take(4)[..])
+                .read_f32::<LittleEndian>()
+                .unwrap()
+                .to_string(),
+            "d" => (&take(8)[..])
+                .read_f64::<LittleEndian>()
+                .unwrap()
+                .to_string(),
+            "?" => match take(1)[0] {
+                0 => "false".to_string(),
+                1 => "true".to_string(),
+                other => {
+                    warnings.push(format!(
+                        "Boolean field '{}' holds {}, read as true",
+                        field.name, other
+                    ));
+                    "true".to_string()
+                }
+            },
+            "u128" => (&take(16)[..])
+                .read_u128::<LittleEndian>()
+                .unwrap()
+                .to_string(),
+            "i128" => (&take(16)[..])
+                .read_i128::<LittleEndian>()
+                .unwrap()
+                .to_string(),
+            "c" if field.name == "FILE_CONTENTS" => {
+                let mut buf = Vec::new();
+                cursor.read_to_end(&mut buf).unwrap();
+                decode_string(&buf, field, warnings)
+            }
+            "c" => match take(1)[0] {
+                0 => String::new(),
+                byte => char::from(byte).to_string(),
+            },
+            s if FixedPoint::parse(s).is_some() => {
+                let fixed = FixedPoint::parse(s).unwrap();
+                fixed.decode(&take(fixed.size())).to_string()
+            }
+            s if Scaled::parse(s).is_some() => {
+                let raw = (&take(4)[..]).read_i32::<LittleEndian>().unwrap();
+                Scaled::parse(s).unwrap().format(raw)
+            }
+            s if s.chars().all(|c| c == 'c') || s.ends_with('s') => {
+                decode_string(&take(get_type_size(s).unwrap()), field, warnings)
+            }
+            s => BytesFormat::default().format(&take(s.len())),
+        }
+    }
+
+    // Payloads of the definition's size: zeroed, all ones, and noise
+    fn payloads(len: usize) -> Vec<Vec<u8>> {
+        let mut state = 0x2545_f491_u32;
+        let noise = (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (state >> 16) as u8
+            })
+            .collect();
+        vec![vec![0; len], vec![0xff; len], noise]
+    }
+
+    #[test]
+    fn plan_decoding_matches_decoding_by_type_string() {
+        let registry: MessageRegistry = serde_json::from_str(REGISTRY).unwrap();
+        let def = &registry["1"];
+        let plan = DecodePlan::compile(&def.fields);
+        // FILE_CONTENTS gets a few bytes of its own
+        let fixed_size: usize = plan.steps().iter().filter_map(|step| step.size).sum();
+        for payload in payloads(fixed_size + 4) {
+            let mut cursor = Cursor::new(&payload[..]);
+            let mut warnings = Vec::new();
+            let mut expected = Vec::new();
+            for field in &def.fields {
+                if field.is_skipped() {
+                    cursor.set_position(
+                        cursor.position() + get_type_size(&field.r#type).unwrap() as u64,
+                    );
+                    continue;
+                }
+                let value = decode_by_type(&mut cursor, field, &mut warnings);
+                expected.push((field.name.clone(), value));
+            }
+
+            let parsed =
+                parse_with_plan(&payload, &def.fields, &plan, DecodeOptions::default()).unwrap();
+            assert_eq!(parsed.fields, expected);
+            assert_eq!(parsed.warnings, warnings);
+            assert_eq!(parsed.skipped, 2);
+        }
+    }
+
+    #[test]
+    fn skip_policy_recompiles_the_skip_flags() {
+        let mut registry: MessageRegistry = serde_json::from_str(REGISTRY).unwrap();
+        set_skip_policy(&mut registry, &["Letter"], false);
+        let skipped = |registry: &MessageRegistry| -> Vec<String> {
+            let def = &registry["1"];
+            def.fields
+                .iter()
+                .zip(def.plan.steps())
+                .filter(|(_, step)| step.skip)
+                .map(|(field, _)| field.name.clone())
+                .collect()
+        };
+        assert_eq!(skipped(&registry), ["Letter", "TRASH", "RESERVED"]);
+        set_skip_policy(&mut registry, &[], true);
+        assert!(skipped(&registry).is_empty());
+    }
+
+    const VARIANTS: &str = r#"{
+        "2": {"name": "Mag", "fields": [
+            {"name": "Timestamp", "type": "Q"},
+            {"name": "X", "type": "h"},
+            {"name": "Y", "type": "h"},
+            {"name": "Temperature", "type": "h"}
+        ], "variants": [
+            {"fields": [
+                {"name": "Timestamp", "type": "Q"},
+                {"name": "X", "type": "h"}
+            ]},
+            {"fields": [
+                {"name": "Timestamp", "type": "I"},
+                {"name": "X", "type": "h"},
+                {"name": "Y", "type": "h"}
+            ]}
+        ]}
+    }"#;
+
+    fn mag() -> MessageRegistry {
+        let mut registry: MessageRegistry = serde_json::from_str(VARIANTS).unwrap();
+        set_skip_policy(&mut registry, &[], false);
+        registry
+    }
+
+    fn names(fields: &[FieldDef]) -> Vec<&str> {
+        fields.iter().map(|field| field.name.as_str()).collect()
+    }
+
+    fn names_of(fields: &[(String, String)]) -> Vec<&str> {
+        fields.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    #[test]
+    fn layout_picks_the_variant_of_the_payload_size() {
+        let registry = mag();
+        let def = &registry["2"];
+        assert_eq!(def.payload_sizes(), Some(vec![14, 10, 8]));
+
+        let (fields, plan) = def.layout(14);
+        assert_eq!(names(fields), ["Timestamp", "X", "Y", "Temperature"]);
+        assert_eq!(plan.payload_size(), Some(14));
+        let (fields, plan) = def.layout(10);
+        assert_eq!(names(fields), ["Timestamp", "X"]);
+        assert_eq!(plan.payload_size(), Some(10));
+        let (fields, _) = def.layout(8);
+        assert_eq!(fields[0].r#type, "I");
+        // A size no layout has is decoded with the definition's own
+        let (fields, plan) = def.layout(12);
+        assert_eq!(fields.len(), 4);
+        assert_eq!(plan.payload_size(), Some(14));
+    }
+
+    #[test]
+    fn short_payloads_follow_the_partial_policy() {
+        let registry = mag();
+        let def = &registry["2"];
+        let payload = [1, 0, 0, 0, 0, 0, 0, 0, 2, 0, 3];
+        let decode = |partial| {
+            let options = DecodeOptions {
+                partial,
+                ..Default::default()
+            };
+            parse_with_plan(&payload, &def.fields, &def.plan, options)
+        };
+
+        let truncated = decode(PartialPolicy::Truncate).unwrap();
+        assert_eq!(
+            truncated.fields,
+            [("Timestamp".into(), "1".into()), ("X".into(), "2".into())]
+        );
+        assert!(truncated.truncated);
+        assert!(truncated.warnings[0].contains("does not match the 14 bytes"));
+        assert!(truncated.warnings[1].contains("Attempted to read field 'Y'"));
+
+        let filled = decode(PartialPolicy::NullFill).unwrap();
+        assert_eq!(
+            names_of(&filled.fields),
+            ["Timestamp", "X", "Y", "Temperature"]
+        );
+        assert_eq!(filled.fields[3].1, "");
+
+        assert!(decode(PartialPolicy::Error).is_err());
+    }
+
+    #[test]
+    fn oversize_payloads_decode_every_field_and_say_so() {
+        let registry = mag();
+        let def = &registry["2"];
+        let mut payload = vec![0; 14];
+        payload[8] = 7;
+        payload.extend([0xaa; 3]);
+        let parsed =
+            parse_with_plan(&payload, &def.fields, &def.plan, DecodeOptions::default()).unwrap();
+        assert_eq!(parsed.fields.len(), 4);
+        assert_eq!(parsed.fields[1].1, "7");
+        assert!(!parsed.truncated);
+        // The size mismatch is reported once, not again as bytes left over
+        assert_eq!(
+            parsed.warnings,
+            ["Payload length 17 does not match the 14 bytes of the definition."]
+        );
+    }
+}
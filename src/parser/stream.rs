@@ -0,0 +1,461 @@
+// parser/stream.rs
+// Record-at-a-time decoding, for live feeds where the log never "ends".
+
+use super::{parse_with_plan, DecodeOptions, ParsedMessage};
+use crate::errors::{Result, WallaceError};
+use crate::messages::registry::{Definitions, MessageRegistry};
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::time::{Duration, Instant};
+
+/// A record exactly as framed in the log
+#[derive(Debug, Clone)]
+pub struct RawRecord {
+    pub log_type: u16,
+    pub payload: Vec<u8>,
+    /// Position among all records of the log, from 0
+    pub index: u64,
+    /// Byte offset of the record in the input, counting the file header
+    pub offset: u64,
+}
+
+/// Records read and their payload bytes, per log_type
+#[derive(Debug, Default, Clone)]
+pub struct RecordCount {
+    pub records: usize,
+    pub payload_bytes: u64,
+    /// Records per payload length
+    pub lengths: BTreeMap<usize, usize>,
+}
+
+impl RecordCount {
+    /// Counts a record with a payload of `length` bytes
+    pub fn add(&mut self, length: usize) {
+        self.records += 1;
+        self.payload_bytes += length as u64;
+        *self.lengths.entry(length).or_default() += 1;
+    }
+
+    /// The most common payload length, the shortest of those tied
+    pub fn mode_length(&self) -> Option<usize> {
+        self.lengths
+            .iter()
+            .rev()
+            .max_by_key(|(_, records)| **records)
+            .map(|(length, _)| *length)
+    }
+}
+
+/// Bounds on one parse, for input that cannot be trusted to be a sane log, such as an upload.
+/// A stream that reaches one fails with `WallaceError::LimitExceeded`; none is set by default.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ParseLimits {
+    /// Records to read at most, known type or not
+    pub max_records: Option<u64>,
+    /// Time the parse may take from the moment the stream was opened
+    pub timeout: Option<Duration>,
+    /// Empty records to accept in a row; zeroed or garbage input reads as an endless run of them
+    pub max_empty_run: Option<u64>,
+}
+
+// Holds a stream to its `ParseLimits`, checked before each record is read
+#[derive(Debug)]
+pub(crate) struct Watchdog {
+    started: Instant,
+    empty_run: u64,
+}
+
+impl Watchdog {
+    pub(crate) fn new() -> Self {
+        Watchdog {
+            started: Instant::now(),
+            empty_run: 0,
+        }
+    }
+
+    /// Fails if reading a record of `length` bytes, after `records` of them and `bytes` of input,
+    /// would break a limit
+    pub(crate) fn check(
+        &mut self,
+        limits: &ParseLimits,
+        length: u16,
+        records: u64,
+        bytes: u64,
+    ) -> Result<()> {
+        self.empty_run = if length == 0 { self.empty_run + 1 } else { 0 };
+        let reason = if limits.max_records.is_some_and(|max| records >= max) {
+            format!("more than {} records", records)
+        } else if limits.max_empty_run.is_some_and(|max| self.empty_run > max) {
+            format!("{} empty records in a row", self.empty_run)
+        } else if let Some(timeout) = limits.timeout.filter(|&t| self.started.elapsed() > t) {
+            format!("parsing took longer than {:?}", timeout)
+        } else {
+            return Ok(());
+        };
+        Err(WallaceError::LimitExceeded {
+            reason,
+            records,
+            bytes,
+        })
+    }
+}
+
+pub struct MessageStream<'a, R: Read> {
+    reader: R,
+    registry: &'a MessageRegistry,
+    defs: Definitions<'a>,
+    /// The 4-byte file header, kept so rewritten logs can reproduce it
+    pub header: i32,
+    /// Warnings collected so far, prefixed with the message they belong to
+    pub warnings: Vec<String>,
+    /// Number of padding fields (TRASH/PADDING/RESERVED by default) skipped so far
+    pub skipped_fields: usize,
+    /// Records read so far, known type or not
+    pub records_read: u64,
+    /// Records skipped because their type is not in the registry
+    pub unknown_records: usize,
+    /// How records are decoded, e.g. whether raw payloads are kept
+    pub options: DecodeOptions,
+    /// Bytes consumed from the input, including the file header
+    pub bytes_read: u64,
+    /// Every record read so far, known type or not
+    pub record_counts: BTreeMap<u16, RecordCount>,
+    watchdog: Watchdog,
+}
+
+impl<'a, R: Read> MessageStream<'a, R> {
+    /// Consumes the file header and prepares to decode records
+    pub fn new(mut reader: R, registry: &'a MessageRegistry) -> Result<Self> {
+        let header = reader.read_i32::<LittleEndian>()?;
+        Ok(MessageStream {
+            reader,
+            registry,
+            defs: Definitions::new(registry),
+            header,
+            warnings: Vec::new(),
+            skipped_fields: 0,
+            records_read: 0,
+            unknown_records: 0,
+            options: DecodeOptions::default(),
+            bytes_read: 4,
+            record_counts: BTreeMap::new(),
+            watchdog: Watchdog::new(),
+        })
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Returns the next message of a known type, blocking until it has fully arrived.
+    /// Records whose type is not in the registry are skipped. `None` means a clean end of input.
+    pub fn next_message(&mut self) -> Result<Option<ParsedMessage>> {
+        while let Some(record) = self.next_record()? {
+            if let Some(msg) = self.decode(&record)? {
+                return Ok(Some(msg));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the next record undecoded, whatever its type
+    pub fn next_record(&mut self) -> Result<Option<RawRecord>> {
+        let (index, offset, registry) = (self.records_read, self.bytes_read, self.registry);
+        let failed =
+            |e, log_type| in_record(WallaceError::Io(e), index, offset, log_type, registry);
+        let log_type = match self.reader.read_u16::<LittleEndian>() {
+            Ok(v) => v,
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(failed(e, None)),
+        };
+        let length = self
+            .reader
+            .read_u16::<LittleEndian>()
+            .map_err(|e| failed(ended(e, "its length"), Some(log_type)))?;
+        self.watchdog.check(
+            &self.options.limits,
+            length,
+            self.records_read,
+            self.bytes_read,
+        )?;
+        let mut payload = vec![0u8; length as usize];
+        self.reader.read_exact(&mut payload).map_err(|e| {
+            let inside = format!("its {}-byte payload", length);
+            failed(ended(e, &inside), Some(log_type))
+        })?;
+        self.bytes_read += 4 + length as u64;
+        self.record_counts
+            .entry(log_type)
+            .or_default()
+            .add(length as usize);
+        self.records_read += 1;
+        Ok(Some(RawRecord {
+            log_type,
+            payload,
+            index: self.records_read - 1,
+            offset,
+        }))
+    }
+
+    /// Decodes a record read with `next_record`, or returns `None` if its type is not in the registry
+    pub fn decode(&mut self, record: &RawRecord) -> Result<Option<ParsedMessage>> {
+        decode_record(
+            record,
+            &self.defs,
+            &mut self.warnings,
+            &mut self.skipped_fields,
+            &mut self.unknown_records,
+            self.options,
+        )
+    }
+}
+
+// Shared by the blocking and async streams, which keep the same running totals
+pub(crate) fn decode_record(
+    record: &RawRecord,
+    defs: &Definitions,
+    warnings: &mut Vec<String>,
+    skipped_fields: &mut usize,
+    unknown_records: &mut usize,
+    options: DecodeOptions,
+) -> Result<Option<ParsedMessage>> {
+    let log_type = record.log_type;
+    let def = match defs.get(log_type) {
+        Some(def) => def,
+        None => {
+            *unknown_records += 1;
+            return Ok(None);
+        }
+    };
+    // The definition's own layout, or a variant of the payload's size
+    let (fields, plan) = def.layout(record.payload.len());
+    let parsed = parse_with_plan(&record.payload, fields, &plan, options).map_err(|e| {
+        let error = WallaceError::ParsingError {
+            log_type,
+            name: def.name.clone(),
+            reason: e.to_string(),
+        };
+        WallaceError::InRecord {
+            index: record.index,
+            offset: record.offset,
+            log_type: Some(log_type),
+            name: Some(def.name.clone()),
+            source: Box::new(error),
+        }
+    })?;
+    *skipped_fields += parsed.skipped;
+    for warn in parsed.warnings {
+        warnings.push(format!("log_type {} ({}): {}", log_type, def.name, warn));
+    }
+    let mut msg = ParsedMessage {
+        log_type,
+        name: def.name.clone(),
+        fields: parsed.fields,
+        index: record.index,
+        offset: record.offset,
+        payload_len: record.payload.len(),
+        raw: options.keep_raw.then(|| record.payload.clone()),
+        partial: parsed.truncated.then_some(options.partial),
+        salvaged: parsed.salvaged,
+        ..Default::default()
+    };
+    msg.timestamp = msg.timestamp_us();
+    Ok(Some(msg))
+}
+
+/// `error` as a failure of the `index`th record of the log, which starts at `offset`, named by
+/// `registry` if its `log_type` was read
+pub(crate) fn in_record(
+    error: WallaceError,
+    index: u64,
+    offset: u64,
+    log_type: Option<u16>,
+    registry: &MessageRegistry,
+) -> WallaceError {
+    WallaceError::InRecord {
+        index,
+        offset,
+        log_type,
+        name: log_type
+            .and_then(|log_type| registry.get(&log_type.to_string()))
+            .map(|def| def.name.clone()),
+        source: Box::new(error),
+    }
+}
+
+/// The end of input inside a record said as such, rather than as a read that came up short
+pub(crate) fn ended(e: std::io::Error, inside: &str) -> std::io::Error {
+    match e.kind() {
+        std::io::ErrorKind::UnexpectedEof => std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            format!("the log ends inside {}", inside),
+        ),
+        _ => e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::registry::set_skip_policy;
+    use crate::parser::PartialPolicy;
+
+    const REGISTRY: &str = r#"{
+        "2": {"name": "Mag", "fields": [
+            {"name": "Timestamp", "type": "Q"},
+            {"name": "X", "type": "h"},
+            {"name": "Y", "type": "h"}
+        ], "variants": [
+            {"fields": [
+                {"name": "Timestamp", "type": "Q"},
+                {"name": "X", "type": "h"}
+            ]}
+        ]}
+    }"#;
+
+    fn registry() -> MessageRegistry {
+        let mut registry: MessageRegistry = serde_json::from_str(REGISTRY).unwrap();
+        set_skip_policy(&mut registry, &[], false);
+        registry
+    }
+
+    fn log(payloads: &[&[u8]]) -> Vec<u8> {
+        let mut log = 0_i32.to_le_bytes().to_vec();
+        for payload in payloads {
+            log.extend(2_u16.to_le_bytes());
+            log.extend((payload.len() as u16).to_le_bytes());
+            log.extend(*payload);
+        }
+        log
+    }
+
+    fn mag(timestamp: u64, rest: &[i16]) -> Vec<u8> {
+        let mut payload = timestamp.to_le_bytes().to_vec();
+        payload.extend(rest.iter().flat_map(|value| value.to_le_bytes()));
+        payload
+    }
+
+    #[test]
+    fn records_are_decoded_with_the_layout_of_their_size() {
+        let registry = registry();
+        let (full, short) = (mag(10, &[1, 2]), mag(20, &[3]));
+        let log = log(&[&full, &short]);
+        let mut stream = MessageStream::new(&log[..], &registry).unwrap();
+
+        let first = stream.next_message().unwrap().unwrap();
+        assert_eq!(first.field("Y"), Some("2"));
+        let second = stream.next_message().unwrap().unwrap();
+        assert_eq!(second.fields.len(), 2);
+        assert_eq!(second.field("X"), Some("3"));
+        assert_eq!(second.partial, None);
+        assert!(stream.next_message().unwrap().is_none());
+        assert!(stream.warnings.is_empty());
+        assert_eq!(stream.record_counts[&2].lengths.len(), 2);
+    }
+
+    #[test]
+    fn short_records_are_decoded_as_far_as_they_go() {
+        let registry = registry();
+        let cut = mag(10, &[1]);
+        let log = log(&[&cut[..9]]);
+        let mut stream = MessageStream::new(&log[..], &registry).unwrap();
+        let msg = stream.next_message().unwrap().unwrap();
+        assert_eq!(msg.fields, [("Timestamp".to_string(), "10".to_string())]);
+        assert_eq!(msg.partial, Some(PartialPolicy::Truncate));
+        assert_eq!(stream.warnings.len(), 2);
+        assert!(stream.warnings[0].starts_with("log_type 2 (Mag): Payload length 9"));
+
+        let mut stream = MessageStream::new(&log[..], &registry).unwrap();
+        stream.options.partial = PartialPolicy::Error;
+        assert!(stream.next_message().is_err());
+    }
+
+    #[test]
+    fn oversize_records_keep_every_field_with_a_warning() {
+        let registry = registry();
+        let long = mag(10, &[1, 2, 3, 4]);
+        let log = log(&[&long]);
+        let mut stream = MessageStream::new(&log[..], &registry).unwrap();
+        let msg = stream.next_message().unwrap().unwrap();
+        assert_eq!(msg.fields.len(), 3);
+        assert_eq!(msg.field("Y"), Some("2"));
+        assert_eq!(msg.payload_len, 16);
+        assert_eq!(
+            stream.warnings,
+            ["log_type 2 (Mag): Payload length 16 does not match the 12 bytes of the definition."]
+        );
+    }
+
+    #[test]
+    fn read_failures_say_which_record_they_are_in() {
+        let registry = registry();
+        let full = mag(10, &[1, 2]);
+        // A second Mag record cut inside its payload
+        let mut cut = log(&[&full]);
+        cut.extend([2, 0, 12, 0, 1, 2, 3, 4, 5]);
+        let mut stream = MessageStream::new(&cut[..], &registry).unwrap();
+        assert!(stream.next_message().unwrap().is_some());
+        let error = stream.next_message().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Record 1 at byte 20 (0x14), Mag (log_type 2): I/O Error: the log ends inside its 12-byte payload"
+        );
+        let WallaceError::InRecord {
+            index,
+            offset,
+            log_type,
+            name,
+            source,
+        } = error
+        else {
+            panic!("not in a record: {:?}", error);
+        };
+        assert_eq!((index, offset, log_type), (1, 20, Some(2)));
+        assert_eq!(name.as_deref(), Some("Mag"));
+        assert!(
+            matches!(*source, WallaceError::Io(ref e) if e.kind() == std::io::ErrorKind::UnexpectedEof)
+        );
+
+        // A record of a type outside the registry, cut inside its length
+        let mut cut = log(&[&full]);
+        cut.extend([7, 0, 12]);
+        let mut stream = MessageStream::new(&cut[..], &registry).unwrap();
+        assert!(stream.next_message().unwrap().is_some());
+        assert_eq!(
+            stream.next_message().unwrap_err().to_string(),
+            "Record 1 at byte 20 (0x14), log_type 7 (not in the registry): I/O Error: the log ends inside its length"
+        );
+    }
+
+    #[test]
+    fn decode_failures_say_which_record_they_are_in() {
+        let registry = registry();
+        let cut = mag(10, &[1]);
+        let log = log(&[&cut[..9]]);
+        let mut stream = MessageStream::new(&log[..], &registry).unwrap();
+        stream.options.partial = PartialPolicy::Error;
+        let error = stream.next_message().unwrap_err();
+        // The parsing error names the type already, so it is not said twice
+        assert!(
+            error.to_string().starts_with("Record 0 at byte 4 (0x4): "),
+            "{}",
+            error
+        );
+        let WallaceError::InRecord {
+            index,
+            offset,
+            log_type,
+            source,
+            ..
+        } = error
+        else {
+            panic!("not in a record: {:?}", error);
+        };
+        assert_eq!((index, offset, log_type), (0, 4, Some(2)));
+        assert!(matches!(
+            *source,
+            WallaceError::ParsingError { log_type: 2, ref name, .. } if name == "Mag"
+        ));
+    }
+}
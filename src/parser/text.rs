@@ -0,0 +1,87 @@
+// parser/text.rs
+// Decoding and encoding of string fields according to their registry encoding.
+
+use crate::messages::registry::{FieldDef, NulPolicy, StringEncoding};
+
+/// Decodes the raw bytes of a string field, handling NULs according to the field's policy.
+/// Invalid sequences become replacement characters, or for `strict` fields a warning and a hex dump.
+pub fn decode_string(bytes: &[u8], field: &FieldDef, warnings: &mut Vec<String>) -> String {
+    // A NUL is one code unit, two bytes in UTF-16
+    let unit = match field.encoding {
+        StringEncoding::Utf16le => 2,
+        _ => 1,
+    };
+    let terminator = bytes
+        .chunks(unit)
+        .position(|c| c.iter().all(|&b| b == 0))
+        .map(|i| i * unit);
+    match (field.nul, terminator) {
+        (NulPolicy::Preserve, _) => decode_text(bytes, field, warnings),
+        (NulPolicy::Trim, _) | (_, None) => decode_text(bytes, field, warnings)
+            .trim_end_matches('\0')
+            .to_string(),
+        (NulPolicy::Discard, Some(end)) => decode_text(&bytes[..end], field, warnings),
+        (NulPolicy::Hex, Some(end)) => {
+            let text = decode_text(&bytes[..end], field, warnings);
+            let rest = bytes.get(end + unit..).unwrap_or_default();
+            let used = rest.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+            if used == 0 {
+                text
+            } else {
+                format!("{}[{}]", text, hex(&rest[..used]))
+            }
+        }
+    }
+}
+
+fn decode_text(bytes: &[u8], field: &FieldDef, warnings: &mut Vec<String>) -> String {
+    match field.encoding {
+        StringEncoding::Utf8 => match std::str::from_utf8(bytes) {
+            Ok(text) => text.to_string(),
+            Err(_) if field.strict => invalid(bytes, field, warnings),
+            Err(_) => String::from_utf8_lossy(bytes).into_owned(),
+        },
+        StringEncoding::Utf16le => {
+            if !bytes.len().is_multiple_of(2) && field.strict {
+                return invalid(bytes, field, warnings);
+            }
+            let units: Vec<u16> = bytes
+                .chunks_exact(2)
+                .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                .collect();
+            match String::from_utf16(&units) {
+                Ok(text) => text,
+                Err(_) if field.strict => invalid(bytes, field, warnings),
+                Err(_) => String::from_utf16_lossy(&units),
+            }
+        }
+        // Every byte is a valid Latin-1 character
+        StringEncoding::Latin1 => bytes.iter().map(|&b| char::from(b)).collect(),
+    }
+}
+
+fn invalid(bytes: &[u8], field: &FieldDef, warnings: &mut Vec<String>) -> String {
+    warnings.push(format!(
+        "String field '{}' is not valid {:?}, exported as hex",
+        field.name, field.encoding
+    ));
+    hex(bytes)
+}
+
+/// Space separated upper case hex pairs, as byte arrays are exported
+pub fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Encodes a string value in the field's encoding, or `None` if it cannot be represented
+pub fn encode_string(text: &str, field: &FieldDef) -> Option<Vec<u8>> {
+    match field.encoding {
+        StringEncoding::Utf8 => Some(text.as_bytes().to_vec()),
+        StringEncoding::Utf16le => Some(text.encode_utf16().flat_map(u16::to_le_bytes).collect()),
+        StringEncoding::Latin1 => text.chars().map(|c| u8::try_from(c).ok()).collect(),
+    }
+}
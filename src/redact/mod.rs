@@ -0,0 +1,411 @@
+// redact/mod.rs
+// Zeroes, fuzzes or scrubs selected fields in the raw payloads, so logs can be shared without
+// leaking locations, serial numbers or operator names. Payload lengths never change.
+
+use crate::errors::{Result, WallaceError};
+use crate::messages::registry::MessageRegistry;
+use crate::parser::stream::RawRecord;
+use crate::parser::{field_size, DecodeOptions, Extracted, FixedPoint, MessageStream, Scaled};
+use crate::utils::rng::SplitMix64;
+use byteorder::{ByteOrder, LittleEndian};
+use std::collections::HashMap;
+use std::io::Read;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// Every byte of the field becomes 0
+    Zero,
+    /// Numeric fields get fresh uniform noise in [-N, N] raw units on every record
+    Fuzz(f64),
+    /// Numeric fields are offset by one random amount in [-N, N] raw units, chosen once per field,
+    /// which hides the location but keeps the shape of a track
+    Shift(f64),
+    /// In text fields, masks the value of every `key=value` or `key: value` line whose key
+    /// contains one of these words (case-insensitive)
+    Scrub(Vec<String>),
+}
+
+/// `MESSAGE.FIELD=ACTION`, where MESSAGE and FIELD may use `*` wildcards
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub message: String,
+    pub field: String,
+    pub action: Action,
+}
+
+impl Rule {
+    pub fn parse(text: &str) -> Result<Self> {
+        let invalid = |why: &str| {
+            WallaceError::InvalidArgument(format!("invalid redaction rule '{}': {}", text, why))
+        };
+        let (target, action) = text
+            .split_once('=')
+            .ok_or_else(|| invalid("expected MESSAGE.FIELD=ACTION"))?;
+        let (message, field) = target
+            .trim()
+            .split_once('.')
+            .ok_or_else(|| invalid("expected MESSAGE.FIELD before '='"))?;
+        let (name, arg) = match action.trim().split_once(':') {
+            Some((name, arg)) => (name, Some(arg)),
+            None => (action.trim(), None),
+        };
+        let amount = || -> Result<f64> {
+            arg.and_then(|a| a.parse::<f64>().ok())
+                .filter(|a| a.is_finite() && *a >= 0.0)
+                .ok_or_else(|| invalid("expected a non-negative amount, e.g. fuzz:1000"))
+        };
+        let action = match name {
+            "zero" => Action::Zero,
+            "fuzz" => Action::Fuzz(amount()?),
+            "shift" => Action::Shift(amount()?),
+            "scrub" => Action::Scrub(
+                arg.unwrap_or_default()
+                    .split(',')
+                    .filter(|k| !k.is_empty())
+                    .map(str::to_lowercase)
+                    .collect(),
+            ),
+            _ => {
+                return Err(invalid(
+                    "action must be zero, fuzz:N, shift:N or scrub:KEY[,KEY]",
+                ))
+            }
+        };
+        if action == Action::Scrub(Vec::new()) {
+            return Err(invalid("scrub needs at least one key, e.g. scrub:operator"));
+        }
+        Ok(Rule {
+            message: message.to_string(),
+            field: field.to_string(),
+            action,
+        })
+    }
+}
+
+/// Reads rules from a file, one per line; blank lines and `#` comments are ignored
+pub fn load_rules(path: &str) -> Result<Vec<Rule>> {
+    std::fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(Rule::parse)
+        .collect()
+}
+
+/// Same as `parser::extract_messages`, redacting each record before it is decoded
+pub fn extract_messages_redacted<R: Read>(
+    reader: &mut R,
+    registry: &MessageRegistry,
+    redactor: &mut Redactor,
+    options: DecodeOptions,
+) -> Result<Extracted> {
+    let mut stream = MessageStream::new(reader, registry)?;
+    stream.options = options;
+    let mut messages = Vec::new();
+    while let Some(mut record) = stream.next_record()? {
+        redactor.apply(&mut record);
+        if let Some(msg) = stream.decode(&record)? {
+            messages.push(msg);
+        }
+    }
+    Ok((
+        messages,
+        stream.warnings,
+        stream.skipped_fields,
+        stream.record_counts,
+    ))
+}
+
+// A rule resolved against one message definition
+struct Patch {
+    offset: usize,
+    // None runs to the end of the payload
+    size: Option<usize>,
+    type_str: String,
+    action: Action,
+    // Index into the per-field shift offsets
+    key: (String, String),
+}
+
+pub struct Redactor<'a> {
+    rules: Vec<Rule>,
+    registry: &'a MessageRegistry,
+    patches: HashMap<u16, Vec<Patch>>,
+    shifts: HashMap<(String, String), f64>,
+    rng: SplitMix64,
+    /// Number of field values changed so far
+    pub fields_redacted: usize,
+}
+
+impl<'a> Redactor<'a> {
+    pub fn new(rules: Vec<Rule>, registry: &'a MessageRegistry, seed: u64) -> Self {
+        Redactor {
+            rules,
+            registry,
+            patches: HashMap::new(),
+            shifts: HashMap::new(),
+            rng: SplitMix64(seed),
+            fields_redacted: 0,
+        }
+    }
+
+    /// Applies the rules to a record in place. Unknown types are left untouched.
+    pub fn apply(&mut self, record: &mut RawRecord) {
+        if !self.patches.contains_key(&record.log_type) {
+            let patches = self.resolve(record.log_type);
+            self.patches.insert(record.log_type, patches);
+        }
+        let patches = &self.patches[&record.log_type];
+        for patch in patches {
+            let payload = &mut record.payload;
+            if patch.offset >= payload.len() {
+                continue;
+            }
+            let end = match patch.size {
+                Some(size) if patch.offset + size > payload.len() => continue,
+                Some(size) => patch.offset + size,
+                None => payload.len(),
+            };
+            let bytes = &mut payload[patch.offset..end];
+            let changed = match &patch.action {
+                Action::Zero => {
+                    bytes.fill(0);
+                    true
+                }
+                Action::Fuzz(amount) => {
+                    let noise = (self.rng.next_f64() * 2.0 - 1.0) * amount;
+                    add_to_number(bytes, &patch.type_str, noise)
+                }
+                Action::Shift(amount) => {
+                    let rng = &mut self.rng;
+                    let shift = *self
+                        .shifts
+                        .entry(patch.key.clone())
+                        .or_insert_with(|| (rng.next_f64() * 2.0 - 1.0) * amount);
+                    add_to_number(bytes, &patch.type_str, shift)
+                }
+                Action::Scrub(keys) => scrub_text(bytes, keys),
+            };
+            if changed {
+                self.fields_redacted += 1;
+            }
+        }
+    }
+
+    fn resolve(&self, log_type: u16) -> Vec<Patch> {
+        let def = match self.registry.get(&log_type.to_string()) {
+            Some(def) => def,
+            None => return Vec::new(),
+        };
+        let mut patches = Vec::new();
+        let mut offset = 0;
+        for field in &def.fields {
+            let size = field_size(field);
+            // The first matching rule wins
+            let rule = self
+                .rules
+                .iter()
+                .find(|r| glob_match(&r.message, &def.name) && glob_match(&r.field, &field.name));
+            if let Some(rule) = rule {
+                patches.push(Patch {
+                    offset,
+                    size,
+                    type_str: field.r#type.clone(),
+                    action: rule.action.clone(),
+                    key: (def.name.clone(), field.name.clone()),
+                });
+            }
+            match size {
+                Some(size) => offset += size,
+                // Nothing can be located after a variable length or unknown field
+                None => break,
+            }
+        }
+        patches
+    }
+}
+
+// Adds `delta` to a little-endian number in place, saturating at the type's range.
+// Returns false for non-numeric types, which are left alone.
+fn add_to_number(bytes: &mut [u8], type_str: &str, delta: f64) -> bool {
+    // Float to integer `as` casts saturate at the type's bounds
+    match type_str {
+        "Q" => LittleEndian::write_u64(
+            bytes,
+            (LittleEndian::read_u64(bytes) as f64 + delta).round() as u64,
+        ),
+        "q" => LittleEndian::write_i64(
+            bytes,
+            (LittleEndian::read_i64(bytes) as f64 + delta).round() as i64,
+        ),
+        "I" => LittleEndian::write_u32(
+            bytes,
+            (LittleEndian::read_u32(bytes) as f64 + delta).round() as u32,
+        ),
+        "i" => LittleEndian::write_i32(
+            bytes,
+            (LittleEndian::read_i32(bytes) as f64 + delta).round() as i32,
+        ),
+        "H" => LittleEndian::write_u16(
+            bytes,
+            (LittleEndian::read_u16(bytes) as f64 + delta).round() as u16,
+        ),
+        "h" => LittleEndian::write_i16(
+            bytes,
+            (LittleEndian::read_i16(bytes) as f64 + delta).round() as i16,
+        ),
+        "B" => bytes[0] = (bytes[0] as f64 + delta).round() as u8,
+        "b" => bytes[0] = ((bytes[0] as i8) as f64 + delta).round() as i8 as u8,
+        "f" => {
+            LittleEndian::write_f32(bytes, (LittleEndian::read_f32(bytes) as f64 + delta) as f32)
+        }
+        "d" => LittleEndian::write_f64(bytes, LittleEndian::read_f64(bytes) + delta),
+        "u128" => LittleEndian::write_u128(
+            bytes,
+            (LittleEndian::read_u128(bytes) as f64 + delta).round() as u128,
+        ),
+        "i128" => LittleEndian::write_i128(
+            bytes,
+            (LittleEndian::read_i128(bytes) as f64 + delta).round() as i128,
+        ),
+        // The delta is in decoded units, degrees or metres
+        s if Scaled::parse(s).is_some() => {
+            let scale = 10f64.powi(Scaled::parse(s).unwrap().decimals as i32); // Checked by the guard
+            LittleEndian::write_i32(
+                bytes,
+                (LittleEndian::read_i32(bytes) as f64 + delta * scale).round() as i32,
+            )
+        }
+        s => match FixedPoint::parse(s) {
+            Some(fixed) => {
+                let size = fixed.size();
+                let value = fixed.decode(&bytes[..size]) + delta;
+                bytes[..size].copy_from_slice(&fixed.encode_saturating(value));
+            }
+            None => return false,
+        },
+    }
+    true
+}
+
+// Masks values on lines like `operator=Jane Doe` or `Operator: Jane Doe` with '*', keeping lengths
+fn scrub_text(bytes: &mut [u8], keys: &[String]) -> bool {
+    let mut changed = false;
+    let mut line_start = 0;
+    while line_start < bytes.len() {
+        let line_end = bytes[line_start..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(bytes.len(), |p| line_start + p);
+        let line = &bytes[line_start..line_end];
+        if let Some(sep) = line.iter().position(|&b| b == b'=' || b == b':') {
+            let key = String::from_utf8_lossy(&line[..sep]).to_lowercase();
+            if keys.iter().any(|k| key.contains(k.as_str())) {
+                for b in &mut bytes[line_start + sep + 1..line_end] {
+                    if !matches!(*b, b' ' | b'\t' | b'\r' | 0) {
+                        *b = b'*';
+                        changed = true;
+                    }
+                }
+            }
+        }
+        line_start = line_end + 1;
+    }
+    changed
+}
+
+/// Matches `text` against a pattern where `*` stands for any run of characters
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == text;
+    }
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !text.starts_with(first) || text.len() < first.len() + last.len() || !text.ends_with(last) {
+        return false;
+    }
+    let mut rest = &text[first.len()..text.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Lat sits behind skipped TRASH and PADDING bytes and a string, at offset 16
+    const REGISTRY: &str = r#"{
+        "5": {"name": "Pos", "fields": [
+            {"name": "TimeUS", "type": "Q"},
+            {"name": "TRASH", "type": "BBB"},
+            {"name": "Name", "type": "4s"},
+            {"name": "PADDING", "type": "B"},
+            {"name": "Lat", "type": "i"},
+            {"name": "Lng", "type": "i"}
+        ]},
+        "6": {"name": "File", "fields": [
+            {"name": "FILE_CONTENTS", "type": "c"},
+            {"name": "Lat", "type": "i"}
+        ]}
+    }"#;
+
+    fn payload() -> Vec<u8> {
+        (1..=24).collect()
+    }
+
+    fn redact(rules: &[&str], log_type: u16, payload: &[u8]) -> Vec<u8> {
+        let registry: MessageRegistry = serde_json::from_str(REGISTRY).unwrap();
+        let rules = rules.iter().map(|r| Rule::parse(r).unwrap()).collect();
+        let mut redactor = Redactor::new(rules, &registry, 7);
+        let mut record = RawRecord {
+            log_type,
+            payload: payload.to_vec(),
+            index: 0,
+            offset: 0,
+        };
+        redactor.apply(&mut record);
+        record.payload
+    }
+
+    fn zeroed(range: std::ops::Range<usize>) -> Vec<u8> {
+        let mut expected = payload();
+        expected[range].fill(0);
+        expected
+    }
+
+    #[test]
+    fn zero_overwrites_only_the_target_bytes() {
+        assert_eq!(redact(&["Pos.Lat=zero"], 5, &payload()), zeroed(16..20));
+        assert_eq!(redact(&["Pos.Name=zero"], 5, &payload()), zeroed(11..15));
+        assert_eq!(redact(&["*.L*=zero"], 5, &payload()), zeroed(16..24));
+        assert_eq!(redact(&["Pos.TimeUS=zero"], 5, &payload()), zeroed(0..8));
+    }
+
+    #[test]
+    fn numeric_actions_rewrite_only_the_target_field() {
+        let before = payload();
+        for rule in ["Pos.Lat=fuzz:1000", "Pos.Lat=shift:1000"] {
+            let after = redact(&[rule], 5, &before);
+            assert_eq!(after[..16], before[..16], "{}", rule);
+            assert_eq!(after[20..], before[20..], "{}", rule);
+            let delta =
+                LittleEndian::read_i32(&after[16..20]) - LittleEndian::read_i32(&before[16..20]);
+            assert!(delta != 0 && delta.abs() <= 1000, "{}: {}", rule, delta);
+        }
+    }
+
+    #[test]
+    fn fields_that_cannot_be_located_are_left_alone() {
+        // Shorter than the definition, so Lat is cut off and Lng is missing
+        let short = &payload()[..18];
+        assert_eq!(redact(&["Pos.L*=zero"], 5, short), short);
+        // Nothing after a variable length field has a known offset
+        assert_eq!(redact(&["File.Lat=zero"], 6, &payload()), payload());
+        assert_eq!(redact(&["Pos.Lat=zero"], 9, &payload()), payload());
+    }
+}
@@ -0,0 +1,348 @@
+// repair/mod.rs
+// Copies a log while dropping records that fail sanity checks, resynchronizing on the framing.
+// The format has no checksums, so a record is judged by its type being in the registry and its
+// length covering the fields its definition declares.
+
+use crate::errors::Result;
+use crate::messages::registry::MessageRegistry;
+use crate::parser::min_payload_size;
+use byteorder::{ByteOrder, LittleEndian};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+const HEADER_LEN: usize = 4;
+const RECORD_HEADER_LEN: usize = 4;
+
+#[derive(Debug, Serialize)]
+pub struct RemovedRange {
+    /// Byte offset in the input where the removed range starts
+    pub start: u64,
+    /// Byte offset just past the removed range
+    pub end: u64,
+    pub reason: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct RepairReport {
+    pub records_kept: usize,
+    pub bytes_removed: u64,
+    pub removed: Vec<RemovedRange>,
+}
+
+impl RepairReport {
+    // Contiguous ranges are merged, keeping the reason of the first
+    fn remove(&mut self, start: u64, end: u64, reason: String) {
+        self.bytes_removed += end - start;
+        match self.removed.last_mut() {
+            Some(last) if last.end == start => last.end = end,
+            _ => self.removed.push(RemovedRange { start, end, reason }),
+        }
+    }
+}
+
+enum Verdict {
+    Keep,
+    // Well framed but unwanted
+    Drop(String),
+    // Framing cannot be trusted from here on
+    Corrupt(String),
+}
+
+/// Copies `reader` to `writer`, keeping the file header and every record that passes the checks.
+/// Records of unknown types are dropped unless `keep_unknown` is set.
+pub fn repair_log<R: Read, W: Write>(
+    reader: R,
+    mut writer: W,
+    registry: &MessageRegistry,
+    keep_unknown: bool,
+) -> Result<RepairReport> {
+    let min_sizes: HashMap<u16, usize> = registry
+        .iter()
+        .filter_map(|(id, def)| Some((id.parse().ok()?, min_payload_size(def)?)))
+        .collect();
+    let mut window = Window::new(reader);
+    let mut report = RepairReport::default();
+
+    if window.fill(HEADER_LEN)? < HEADER_LEN {
+        report.remove(
+            0,
+            window.available() as u64,
+            "file header truncated".to_string(),
+        );
+        return Ok(report);
+    }
+    writer.write_all(window.bytes(0, HEADER_LEN))?;
+    window.advance(HEADER_LEN);
+
+    loop {
+        let available = window.fill(RECORD_HEADER_LEN)?;
+        if available == 0 {
+            break;
+        }
+        if available < RECORD_HEADER_LEN {
+            let start = window.offset();
+            report.remove(
+                start,
+                start + available as u64,
+                "record header truncated".to_string(),
+            );
+            break;
+        }
+        let (log_type, length) = window.record_header(0);
+        let record_len = RECORD_HEADER_LEN + length;
+        let complete = window.fill(record_len)? >= record_len;
+
+        let verdict = match min_sizes.get(&log_type) {
+            Some(&min) if complete && length >= min => Verdict::Keep,
+            Some(&min) if length < min => Verdict::Corrupt(format!(
+                "log_type {} with length {} shorter than its {} byte definition",
+                log_type, length, min
+            )),
+            // An unknown type is only trusted when a sane record follows it,
+            // otherwise it is most likely garbage
+            None if complete && followed_by_record(&mut window, record_len, &min_sizes)? => {
+                if keep_unknown {
+                    Verdict::Keep
+                } else {
+                    Verdict::Drop(format!("unknown log_type {}", log_type))
+                }
+            }
+            None => Verdict::Corrupt(format!("unknown log_type {}", log_type)),
+            Some(_) => Verdict::Corrupt(format!(
+                "log_type {} with length {} runs past end of file",
+                log_type, length
+            )),
+        };
+        let start = window.offset();
+        match verdict {
+            Verdict::Keep => {
+                writer.write_all(window.bytes(0, record_len))?;
+                report.records_kept += 1;
+                window.advance(record_len);
+            }
+            Verdict::Drop(reason) => {
+                window.advance(record_len);
+                report.remove(start, window.offset(), reason);
+            }
+            Verdict::Corrupt(reason) => {
+                resync(&mut window, &min_sizes)?;
+                report.remove(
+                    start,
+                    window.offset(),
+                    format!("{}, resynchronized", reason),
+                );
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(report)
+}
+
+// True if the record of `record_len` bytes at the current position is followed by a plausible
+// record or ends exactly at the end of the input
+fn followed_by_record<R: Read>(
+    window: &mut Window<R>,
+    record_len: usize,
+    min_sizes: &HashMap<u16, usize>,
+) -> Result<bool> {
+    let available = window.fill(record_len + RECORD_HEADER_LEN)?;
+    Ok(available == record_len
+        || (available >= record_len + RECORD_HEADER_LEN
+            && plausible(window, record_len, min_sizes).is_some()))
+}
+
+// Length of the record at `at` if it is of a known type with a sane length
+fn plausible<R: Read>(
+    window: &Window<R>,
+    at: usize,
+    min_sizes: &HashMap<u16, usize>,
+) -> Option<usize> {
+    let (log_type, length) = window.record_header(at);
+    let min = *min_sizes.get(&log_type)?;
+    (length >= min).then_some(RECORD_HEADER_LEN + length)
+}
+
+// Steps one byte at a time until a known record is found whose successor is also a known record,
+// or which ends exactly at the end of the input. Stops at the end of input if none is found.
+fn resync<R: Read>(window: &mut Window<R>, min_sizes: &HashMap<u16, usize>) -> Result<()> {
+    loop {
+        window.advance(1);
+        let available = window.fill(RECORD_HEADER_LEN)?;
+        if available < RECORD_HEADER_LEN {
+            window.advance(available);
+            return Ok(());
+        }
+        let record_len = match plausible(window, 0, min_sizes) {
+            Some(len) => len,
+            None => continue,
+        };
+        if followed_by_record(window, record_len, min_sizes)? {
+            return Ok(());
+        }
+    }
+}
+
+/// Read-ahead buffer over the input, addressed relative to the current position
+struct Window<R> {
+    reader: R,
+    buf: Vec<u8>,
+    pos: usize,
+    // Input offset of buf[pos]
+    offset: u64,
+}
+
+impl<R: Read> Window<R> {
+    fn new(reader: R) -> Self {
+        Window {
+            reader,
+            buf: Vec::new(),
+            pos: 0,
+            offset: 0,
+        }
+    }
+
+    /// Reads until `n` bytes are available past the current position or the input ends.
+    /// Returns the number of bytes available, which may be more than `n`.
+    fn fill(&mut self, n: usize) -> Result<usize> {
+        if self.pos > 1 << 20 {
+            self.buf.drain(..self.pos);
+            self.pos = 0;
+        }
+        while self.available() < n {
+            let len = self.buf.len();
+            self.buf.resize(len + n.max(64 * 1024), 0);
+            let read = self.reader.read(&mut self.buf[len..])?;
+            self.buf.truncate(len + read);
+            if read == 0 {
+                break;
+            }
+        }
+        Ok(self.available())
+    }
+
+    fn available(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn bytes(&self, at: usize, len: usize) -> &[u8] {
+        &self.buf[self.pos + at..self.pos + at + len]
+    }
+
+    fn record_header(&self, at: usize) -> (u16, usize) {
+        let header = self.bytes(at, RECORD_HEADER_LEN);
+        (
+            LittleEndian::read_u16(&header[0..2]),
+            LittleEndian::read_u16(&header[2..4]) as usize,
+        )
+    }
+
+    fn advance(&mut self, n: usize) {
+        self.pos += n;
+        self.offset += n as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: [u8; 4] = [10, 0, 0, 0];
+
+    fn registry() -> MessageRegistry {
+        serde_json::from_str(
+            r#"{
+                "1": {"name": "Gps", "fields": [
+                    {"name": "TimeUS", "type": "Q"},
+                    {"name": "Lat", "type": "i"}
+                ]},
+                "2": {"name": "Msg", "fields": [{"name": "TimeUS", "type": "Q"}]}
+            }"#,
+        )
+        .unwrap()
+    }
+
+    fn record(log_type: u16, payload: &[u8]) -> Vec<u8> {
+        let mut record = log_type.to_le_bytes().to_vec();
+        record.extend((payload.len() as u16).to_le_bytes());
+        record.extend(payload);
+        record
+    }
+
+    fn gps(time: u8) -> Vec<u8> {
+        record(1, &[time; 12])
+    }
+
+    fn msg(time: u8) -> Vec<u8> {
+        record(2, &[time; 8])
+    }
+
+    fn repair(parts: &[&[u8]]) -> (Vec<u8>, RepairReport) {
+        let input: Vec<u8> = parts.concat();
+        let mut output = Vec::new();
+        let report = repair_log(&input[..], &mut output, &registry(), false).unwrap();
+        (output, report)
+    }
+
+    fn ranges(report: &RepairReport) -> Vec<(u64, u64)> {
+        report.removed.iter().map(|r| (r.start, r.end)).collect()
+    }
+
+    #[test]
+    fn corrupted_length_is_removed_up_to_the_next_record() {
+        let mut short = gps(2);
+        short[2] = 3;
+        let (output, report) = repair(&[&HEADER, &gps(1), &short, &msg(3), &gps(4)]);
+
+        assert_eq!(output, [&HEADER[..], &gps(1), &msg(3), &gps(4)].concat());
+        assert_eq!(report.records_kept, 3);
+        assert_eq!(ranges(&report), [(20, 36)]);
+        assert!(report.removed[0]
+            .reason
+            .contains("shorter than its 12 byte definition"));
+    }
+
+    #[test]
+    fn garbage_between_records_is_removed_exactly() {
+        let garbage = [0xff; 7];
+        let (output, report) = repair(&[&HEADER, &gps(1), &garbage, &gps(2), &msg(3)]);
+
+        assert_eq!(output, [&HEADER[..], &gps(1), &gps(2), &msg(3)].concat());
+        assert_eq!(report.records_kept, 3);
+        assert_eq!(ranges(&report), [(20, 27)]);
+        assert_eq!(report.bytes_removed, 7);
+    }
+
+    #[test]
+    fn truncated_tail_is_removed() {
+        let cut = &gps(2)[..9];
+        let (output, report) = repair(&[&HEADER, &gps(1), &msg(3), cut]);
+
+        assert_eq!(output, [&HEADER[..], &gps(1), &msg(3)].concat());
+        assert_eq!(ranges(&report), [(32, 41)]);
+        assert!(report.removed[0].reason.contains("runs past end of file"));
+
+        let (output, report) = repair(&[&HEADER, &gps(1), &[1, 0]]);
+        assert_eq!(output, [&HEADER[..], &gps(1)].concat());
+        assert_eq!(ranges(&report), [(20, 22)]);
+        assert_eq!(report.removed[0].reason, "record header truncated");
+    }
+
+    #[test]
+    fn false_sync_match_inside_a_payload_is_skipped() {
+        // An unknown record whose payload holds a Gps header and 12 bytes, followed by junk,
+        // so the embedded match has no plausible successor
+        let mut corrupt = vec![0xee, 0xee, 5, 0];
+        corrupt.extend(record(1, &[0xaa; 12]));
+        corrupt.extend([0xee; 3]);
+        let (output, report) = repair(&[&HEADER, &gps(1), &corrupt, &gps(2), &msg(3)]);
+
+        assert_eq!(output, [&HEADER[..], &gps(1), &gps(2), &msg(3)].concat());
+        assert_eq!(report.records_kept, 3);
+        assert_eq!(ranges(&report), [(20, 20 + corrupt.len() as u64)]);
+    }
+}
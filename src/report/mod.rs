@@ -0,0 +1,261 @@
+// report/mod.rs
+// Run summary, per-type record counts, registry coverage and provenance written alongside the
+// exports.
+
+pub mod png;
+pub mod provenance;
+
+use crate::analysis::geo::TrackStats;
+use crate::errors::Result;
+use crate::messages::registry::MessageRegistry;
+use crate::parser::{ParsedMessage, RecordCount};
+use crate::utils::memory::MemoryStats;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+/// CSV files the analyses write next to the exports, which are not per-message exports
+pub fn is_report_csv(file_name: &str) -> bool {
+    matches!(
+        file_name,
+        "counts.csv"
+            | "coverage.csv"
+            | "extrema.csv"
+            | "changes.csv"
+            | "correlation.csv"
+            | "battery.csv"
+            | "battery_segments.csv"
+            | "altitude.csv"
+            | "altitude_segments.csv"
+    ) || file_name.starts_with("histogram_")
+}
+
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    pub tool_version: String,
+    pub input: String,
+    pub registry: String,
+    pub total_messages: usize,
+    pub message_counts: BTreeMap<String, usize>,
+    pub warnings: usize,
+    pub skipped_fields: usize,
+    /// Records whose payload ended before their fields did, by the policy applied to them
+    pub partial_records: BTreeMap<String, usize>,
+    /// Records of which only the fields before a decode failure were kept
+    pub salvaged_records: usize,
+    /// `--tag` values, also written into every row
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+    /// Distance and extent of the ground track, with `--track`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub track: Option<TrackStats>,
+    /// Estimated memory held by decoded messages, and whether the log had to be chunked
+    pub memory: MemoryStats,
+}
+
+impl Summary {
+    pub fn new(
+        input: &str,
+        registry: &str,
+        messages: &[ParsedMessage],
+        warnings: usize,
+        skipped_fields: usize,
+    ) -> Self {
+        let mut summary = Summary {
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            input: input.to_string(),
+            registry: registry.to_string(),
+            total_messages: 0,
+            message_counts: BTreeMap::new(),
+            warnings,
+            skipped_fields,
+            partial_records: BTreeMap::new(),
+            salvaged_records: 0,
+            tags: BTreeMap::new(),
+            track: None,
+            memory: MemoryStats::default(),
+        };
+        summary.count(messages);
+        summary
+    }
+
+    /// Adds `messages` to the counts, for logs read a chunk at a time
+    pub fn count(&mut self, messages: &[ParsedMessage]) {
+        self.total_messages += messages.len();
+        for msg in messages {
+            *self.message_counts.entry(msg.name.clone()).or_insert(0) += 1;
+            if let Some(policy) = msg.partial {
+                *self
+                    .partial_records
+                    .entry(policy.name().to_string())
+                    .or_insert(0) += 1;
+            }
+            if msg.salvaged {
+                self.salvaged_records += 1;
+            }
+        }
+    }
+}
+
+pub fn write_summary<P: AsRef<Path>>(path: P, summary: &Summary) -> Result<()> {
+    let writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(writer, summary)?; // serde_json::Error automatically converted
+    Ok(())
+}
+
+/// Writes one row per log_type, largest payload share first: message name, log_type, records,
+/// payload bytes, the payload's share of the (decompressed) log in percent, the shortest, longest
+/// and most common payload lengths, the lengths the registry gives the type, and the records of
+/// other lengths, which a framing or registry bug makes. Returns the names of the types that have
+/// such records. Types whose registry layout varies in length or is empty are not checked, nor
+/// are counts without lengths, as of messages read back from exports.
+pub fn write_counts<P: AsRef<Path>>(
+    path: P,
+    counts: &BTreeMap<u16, RecordCount>,
+    registry: &MessageRegistry,
+) -> Result<Vec<String>> {
+    // File header, then a 4-byte header in front of every payload
+    let file_bytes: u64 = 4 + counts
+        .values()
+        .map(|c| c.records as u64 * 4 + c.payload_bytes)
+        .sum::<u64>();
+    let mut rows: Vec<(&u16, &RecordCount)> = counts.iter().collect();
+    rows.sort_by(|a, b| b.1.payload_bytes.cmp(&a.1.payload_bytes).then(a.0.cmp(b.0)));
+
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record([
+        "message",
+        "log_type",
+        "records",
+        "payload_bytes",
+        "percent_of_file",
+        "min_length",
+        "max_length",
+        "mode_length",
+        "registry_lengths",
+        "unexpected_length_records",
+    ])?;
+    let mut misframed = Vec::new();
+    for (log_type, count) in rows {
+        let def = registry.get(&log_type.to_string());
+        let name = def.map_or("[unknown]", |def| def.name.as_str());
+        // A definition without fields only names the type, it does not give it a layout
+        let sizes = def
+            .filter(|def| !def.fields.is_empty())
+            .and_then(|def| def.payload_sizes());
+        let unexpected = sizes
+            .as_ref()
+            .filter(|_| !count.lengths.is_empty())
+            .map(|sizes| {
+                count
+                    .lengths
+                    .iter()
+                    .filter(|(length, _)| !sizes.contains(length))
+                    .map(|(_, records)| records)
+                    .sum::<usize>()
+            });
+        if unexpected.is_some_and(|records| records > 0) {
+            misframed.push(name.to_string());
+        }
+        let length = |length: Option<&usize>| length.map(usize::to_string).unwrap_or_default();
+        writer.write_record([
+            name.to_string(),
+            log_type.to_string(),
+            count.records.to_string(),
+            count.payload_bytes.to_string(),
+            format!(
+                "{:.2}",
+                count.payload_bytes as f64 * 100.0 / file_bytes as f64
+            ),
+            length(count.lengths.keys().next()),
+            length(count.lengths.keys().next_back()),
+            length(count.mode_length().as_ref()),
+            sizes.map_or(String::new(), |sizes| {
+                let sizes: Vec<String> = sizes.iter().map(usize::to_string).collect();
+                sizes.join("|")
+            }),
+            unexpected
+                .map(|records| records.to_string())
+                .unwrap_or_default(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(misframed)
+}
+
+/// How the message types of the registry and of the log match up
+#[derive(Debug, Default)]
+pub struct Coverage {
+    /// Registry messages with no record in the log
+    pub missing: usize,
+    /// Log types with records but no registry message
+    pub unregistered: usize,
+}
+
+/// Writes one row per message type of the registry or the log: message name, log_type, records
+/// and status, which is `missing` for registry messages the log never had, `unregistered` for
+/// log types the registry lacks, and `logged` for the rest. Rows come in that order.
+pub fn write_coverage<P: AsRef<Path>>(
+    path: P,
+    counts: &BTreeMap<u16, RecordCount>,
+    registry: &MessageRegistry,
+) -> Result<Coverage> {
+    let mut rows: Vec<(CoverageStatus, u16, &str, usize)> = Vec::new();
+    for (id, def) in registry {
+        let Ok(log_type) = id.parse::<u16>() else {
+            continue;
+        };
+        match counts.get(&log_type) {
+            Some(count) => rows.push((CoverageStatus::Logged, log_type, &def.name, count.records)),
+            None => rows.push((CoverageStatus::Missing, log_type, &def.name, 0)),
+        }
+    }
+    for (&log_type, count) in counts {
+        if registry.get(&log_type.to_string()).is_none() {
+            rows.push((
+                CoverageStatus::Unregistered,
+                log_type,
+                "[unknown]",
+                count.records,
+            ));
+        }
+    }
+    rows.sort_by_key(|&(status, log_type, _, _)| (status, log_type));
+
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record(["message", "log_type", "records", "status"])?;
+    for (status, log_type, name, records) in &rows {
+        writer.write_record([
+            name.to_string(),
+            log_type.to_string(),
+            records.to_string(),
+            status.name().to_string(),
+        ])?;
+    }
+    writer.flush()?;
+    let count = |status| rows.iter().filter(|row| row.0 == status).count();
+    Ok(Coverage {
+        missing: count(CoverageStatus::Missing),
+        unregistered: count(CoverageStatus::Unregistered),
+    })
+}
+
+// In the order of the rows of coverage.csv
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum CoverageStatus {
+    Missing,
+    Unregistered,
+    Logged,
+}
+
+impl CoverageStatus {
+    fn name(self) -> &'static str {
+        match self {
+            CoverageStatus::Missing => "missing",
+            CoverageStatus::Unregistered => "unregistered",
+            CoverageStatus::Logged => "logged",
+        }
+    }
+}
@@ -0,0 +1,47 @@
+// report/png.rs
+// Just enough of PNG to write an 8-bit RGB image, for the charts written next to the reports.
+
+use crate::errors::Result;
+use flate2::write::ZlibEncoder;
+use flate2::{Compression, Crc};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// Writes `rgb`, `width * height` pixels of three bytes each from the top row down, as a PNG file
+pub fn write_rgb<P: AsRef<Path>>(path: P, width: u32, height: u32, rgb: &[u8]) -> Result<()> {
+    debug_assert_eq!(rgb.len(), width as usize * height as usize * 3);
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bits per channel, truecolor, deflate, adaptive filtering, no interlace
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    // Every scanline starts with its filter type; none is used
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    for row in rgb.chunks(width as usize * 3) {
+        encoder.write_all(&[0])?;
+        encoder.write_all(row)?;
+    }
+    let data = encoder.finish()?;
+
+    let mut png = SIGNATURE.to_vec();
+    push_chunk(&mut png, b"IHDR", &header);
+    push_chunk(&mut png, b"IDAT", &data);
+    push_chunk(&mut png, b"IEND", &[]);
+    fs::write(path, png)?;
+    Ok(())
+}
+
+// Length, type, data and the CRC of type and data
+fn push_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let mut crc = Crc::new();
+    crc.update(kind);
+    crc.update(data);
+    png.extend_from_slice(&crc.sum().to_be_bytes());
+}
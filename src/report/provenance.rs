@@ -0,0 +1,128 @@
+// report/provenance.rs
+// Where an export came from: tool version, input and registry with their hashes, and the options
+// used. Written as a `<file>.meta.json` sidecar next to each export file, and optionally as
+// `#` comment lines at the top of CSVs, so a stray file can be traced back to its run.
+
+use crate::errors::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Read};
+use std::path::Path;
+
+/// Appended to an export file's name to name its sidecar
+pub const SIDECAR_SUFFIX: &str = ".meta.json";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Provenance {
+    pub tool_version: String,
+    pub input: String,
+    /// Of the input file as stored (compressed or not); `None` for stdin, export directories and
+    /// pipes, which can only be read once
+    pub input_sha256: Option<String>,
+    pub registry: String,
+    pub registry_sha256: String,
+    /// Options that shape the output, by long name
+    pub options: BTreeMap<String, String>,
+}
+
+impl Provenance {
+    pub fn new(input: &str, registry: &str, options: BTreeMap<String, String>) -> Result<Self> {
+        let input_sha256 = match input {
+            "-" => None,
+            path if !Path::new(path).is_file() => None,
+            path => Some(sha256_file(path)?),
+        };
+        Ok(Provenance {
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            input: input.to_string(),
+            input_sha256,
+            registry: registry.to_string(),
+            registry_sha256: sha256_file(registry)?,
+            options,
+        })
+    }
+
+    /// The provenance as `# key: value` lines, for the top of a CSV file
+    pub fn comment_lines(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!("# wallace_rs {}", self.tool_version),
+            format!("# input: {}", self.input),
+        ];
+        if let Some(hash) = &self.input_sha256 {
+            lines.push(format!("# input_sha256: {}", hash));
+        }
+        lines.push(format!("# registry: {}", self.registry));
+        lines.push(format!("# registry_sha256: {}", self.registry_sha256));
+        for (name, value) in &self.options {
+            lines.push(format!("# --{}: {}", name, value));
+        }
+        lines
+    }
+}
+
+#[derive(Serialize)]
+struct Sidecar<'a> {
+    file: String,
+    sha256: String,
+    #[serde(flatten)]
+    provenance: &'a Provenance,
+}
+
+/// A sidecar as read back, with the hashes it recorded
+#[derive(Debug, Deserialize)]
+pub struct SidecarRecord {
+    pub file: String,
+    pub sha256: String,
+    pub input: String,
+    #[serde(default)]
+    pub input_sha256: Option<String>,
+    pub registry: String,
+    pub registry_sha256: String,
+}
+
+/// Writes `<file>.meta.json` with the provenance and the name and hash of `file` itself.
+/// Does nothing if `file` was not written, e.g. for a group without rows.
+pub fn write_sidecar(file: &Path, provenance: &Provenance) -> Result<()> {
+    if !file.is_file() {
+        return Ok(());
+    }
+    let name = file
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let sidecar = Sidecar {
+        sha256: sha256_file(file)?,
+        file: name.clone(),
+        provenance,
+    };
+    let writer = BufWriter::new(File::create(
+        file.with_file_name(format!("{}{}", name, SIDECAR_SUFFIX)),
+    )?);
+    serde_json::to_writer_pretty(writer, &sidecar)?;
+    Ok(())
+}
+
+/// Hex SHA-256 of a file's contents
+pub fn sha256_file<P: AsRef<Path>>(path: P) -> Result<String> {
+    sha256_reader(&mut File::open(path)?)
+}
+
+/// Hex SHA-256 of everything `reader` yields
+pub fn sha256_reader(reader: &mut dyn Read) -> Result<String> {
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
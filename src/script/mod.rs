@@ -0,0 +1,174 @@
+// script/mod.rs
+// User transform scripts in Rhai, run on every decoded message. The engine is sandboxed: no
+// modules or eval, and bounds on operations, call depth and the size of strings, arrays and
+// maps, so a bad script in a shared pipeline fails the run instead of hanging or exhausting it.
+//
+// A script sees the message as `name`, `log_type`, `index`, `timestamp` (`()` if none) and
+// `fields`, a map of field values as numbers, booleans or strings. Changes to `fields` are
+// written back; a script that ends in `false` drops the message.
+
+use crate::errors::{Result, WallaceError};
+use crate::parser::ParsedMessage;
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Dynamic, Engine, Map, Scope, AST};
+use std::fs;
+
+/// Default bound on the operations a script may take per message
+pub const DEFAULT_MAX_OPERATIONS: u64 = 100_000;
+
+/// What a script may use per message
+#[derive(Debug, Clone, Copy)]
+pub struct ScriptLimits {
+    pub max_operations: u64,
+    /// Bytes in one string
+    pub max_string_size: usize,
+    /// Items in one array or map
+    pub max_collection_size: usize,
+    pub max_call_levels: usize,
+}
+
+impl Default for ScriptLimits {
+    fn default() -> Self {
+        ScriptLimits {
+            max_operations: DEFAULT_MAX_OPERATIONS,
+            max_string_size: 64 * 1024,
+            max_collection_size: 10_000,
+            max_call_levels: 32,
+        }
+    }
+}
+
+pub struct ScriptTransform {
+    path: String,
+    engine: Engine,
+    ast: AST,
+    /// Field values the script changed so far
+    pub changed: usize,
+    /// Messages the script dropped so far
+    pub dropped: usize,
+}
+
+impl ScriptTransform {
+    /// Reads and compiles the script at `path`
+    pub fn load(path: &str, limits: ScriptLimits) -> Result<Self> {
+        let invalid = |e: &dyn std::fmt::Display| {
+            WallaceError::InvalidArgument(format!("script '{}': {}", path, e))
+        };
+        let source = fs::read_to_string(path).map_err(|e| invalid(&e))?;
+        let mut engine = Engine::new();
+        engine
+            .set_module_resolver(DummyModuleResolver::new())
+            .set_max_operations(limits.max_operations)
+            .set_max_string_size(limits.max_string_size)
+            .set_max_array_size(limits.max_collection_size)
+            .set_max_map_size(limits.max_collection_size)
+            .set_max_call_levels(limits.max_call_levels)
+            .set_max_expr_depths(64, 32);
+        engine.disable_symbol("eval");
+        let ast = engine.compile(&source).map_err(|e| invalid(&e))?;
+        Ok(ScriptTransform {
+            path: path.to_string(),
+            engine,
+            ast,
+            changed: 0,
+            dropped: 0,
+        })
+    }
+
+    /// Runs the script on every message, in order, and drops those it rejects
+    pub fn apply(&mut self, messages: &mut Vec<ParsedMessage>) -> Result<()> {
+        let mut failure = None;
+        messages.retain_mut(|msg| {
+            if failure.is_some() {
+                return true;
+            }
+            match self.run(msg) {
+                Ok(keep) => keep,
+                Err(e) => {
+                    failure = Some(e);
+                    true
+                }
+            }
+        });
+        match failure {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    // Whether to keep the message, after writing back the fields the script changed
+    fn run(&mut self, msg: &mut ParsedMessage) -> Result<bool> {
+        let fields: Map = msg
+            .fields
+            .iter()
+            .map(|(name, value)| (name.into(), to_dynamic(value)))
+            .collect();
+        let mut scope = Scope::new();
+        scope.push_constant("name", msg.name.clone());
+        scope.push_constant("log_type", msg.log_type as i64);
+        scope.push_constant("index", msg.index as i64);
+        scope.push_constant(
+            "timestamp",
+            msg.timestamp
+                .map_or(Dynamic::UNIT, |ts| Dynamic::from(ts as i64)),
+        );
+        scope.push("fields", fields);
+        let failed = |reason: &dyn std::fmt::Display| {
+            WallaceError::Script(format!(
+                "'{}' on record {} ({}): {}",
+                self.path, msg.index, msg.name, reason
+            ))
+        };
+        let result = self
+            .engine
+            .eval_ast_with_scope::<Dynamic>(&mut scope, &self.ast)
+            .map_err(|e| failed(&e))?;
+        let fields = scope
+            .get_value::<Map>("fields")
+            .ok_or_else(|| failed(&"'fields' is no longer a map"))?;
+        if let Some(name) = fields
+            .keys()
+            .find(|name| msg.field(name.as_str()).is_none())
+        {
+            return Err(failed(&format!(
+                "added field '{}', which the message does not have",
+                name
+            )));
+        }
+        for (name, value) in msg.fields.iter_mut() {
+            let Some(new) = fields.get(name.as_str()) else {
+                continue;
+            };
+            // Values are compared as the script saw them, so untouched ones keep their text
+            if new.to_string() != to_dynamic(value).to_string() {
+                *value = to_text(new);
+                self.changed += 1;
+            }
+        }
+        let keep = result.as_bool().unwrap_or(true);
+        if !keep {
+            self.dropped += 1;
+        }
+        Ok(keep)
+    }
+}
+
+fn to_dynamic(value: &str) -> Dynamic {
+    if let Ok(int) = value.parse::<i64>() {
+        Dynamic::from(int)
+    } else if let Ok(float) = value.parse::<f64>() {
+        Dynamic::from_float(float)
+    } else if let Ok(flag) = value.parse::<bool>() {
+        Dynamic::from_bool(flag)
+    } else {
+        Dynamic::from(value.to_string())
+    }
+}
+
+// Floats are written the way decoded floats are, e.g. `3` rather than Rhai's `3.0`
+fn to_text(value: &Dynamic) -> String {
+    match value.as_float() {
+        Ok(float) => float.to_string(),
+        Err(_) => value.to_string(),
+    }
+}
@@ -0,0 +1,114 @@
+// sink/dashboard.rs
+// A table of the latest value of chosen fields, redrawn in place on the terminal at a limited
+// rate, for watching a vehicle on the bench without a ground station.
+
+use super::MessageSink;
+use crate::analysis::FieldRef;
+use crate::errors::Result;
+use crate::messages::registry::MessageRegistry;
+use crate::parser::ParsedMessage;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+// A value not refreshed for this long is marked stale, e.g. when a sensor stops reporting
+const STALE_AFTER: Duration = Duration::from_secs(5);
+
+struct Row {
+    field: FieldRef,
+    value: Option<String>,
+    updated: Option<Instant>,
+}
+
+pub struct DashboardSink {
+    rows: Vec<Row>,
+    interval: Duration,
+    last_draw: Option<Instant>,
+    /// Lines of the previous table, moved back over before drawing the next one
+    drawn_lines: usize,
+    messages: u64,
+}
+
+impl DashboardSink {
+    pub fn new(fields: Vec<FieldRef>, interval: Duration) -> Self {
+        DashboardSink {
+            rows: fields
+                .into_iter()
+                .map(|field| Row {
+                    field,
+                    value: None,
+                    updated: None,
+                })
+                .collect(),
+            interval,
+            last_draw: None,
+            drawn_lines: 0,
+            messages: 0,
+        }
+    }
+
+    /// Fails unless every field is in the registry
+    pub fn check(&self, registry: &MessageRegistry) -> Result<()> {
+        self.rows
+            .iter()
+            .try_for_each(|row| row.field.check_exists(registry))
+    }
+
+    // Redraws unless the last table was drawn less than an interval ago
+    fn draw(&mut self) -> Result<()> {
+        let now = Instant::now();
+        if self
+            .last_draw
+            .is_some_and(|last| now.duration_since(last) < self.interval)
+        {
+            return Ok(());
+        }
+        self.last_draw = Some(now);
+
+        let labels: Vec<String> = self.rows.iter().map(|row| row.field.to_string()).collect();
+        let width = labels.iter().map(String::len).max().unwrap_or(0).max(5);
+        let mut table = String::new();
+        if self.drawn_lines > 0 {
+            // Back to the start of the previous table, and clear it
+            table.push_str(&format!("\x1b[{}F\x1b[J", self.drawn_lines));
+        }
+        table.push_str(&format!("📟 {} messages decoded\n", self.messages));
+        table.push_str(&format!("{:<width$}  {:>16}  AGE\n", "FIELD", "VALUE"));
+        for (row, label) in self.rows.iter().zip(&labels) {
+            let value = row.value.as_deref().unwrap_or("-");
+            let age = match row.updated {
+                Some(updated) if now.duration_since(updated) >= STALE_AFTER => {
+                    format!("{:.1}s (stale)", now.duration_since(updated).as_secs_f64())
+                }
+                Some(updated) => format!("{:.1}s", now.duration_since(updated).as_secs_f64()),
+                None => "never".to_string(),
+            };
+            table.push_str(&format!("{:<width$}  {:>16}  {}\n", label, value, age));
+        }
+        self.drawn_lines = self.rows.len() + 2;
+
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(table.as_bytes())?;
+        stdout.flush()?;
+        Ok(())
+    }
+}
+
+impl MessageSink for DashboardSink {
+    fn send(&mut self, msg: &ParsedMessage, _json: &str) -> Result<()> {
+        self.messages += 1;
+        for row in self.rows.iter_mut() {
+            if row.field.message != msg.name {
+                continue;
+            }
+            if let Some(value) = msg.field(&row.field.field) {
+                row.value = Some(value.to_string());
+                row.updated = Some(Instant::now());
+            }
+        }
+        self.draw()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.draw()
+    }
+}
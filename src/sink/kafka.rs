@@ -0,0 +1,70 @@
+// sink/kafka.rs
+// Publishes each message as a JSON record to a Kafka topic derived from the message name.
+
+use super::{render_topic, MessageSink};
+use crate::errors::{Result, WallaceError};
+use crate::parser::ParsedMessage;
+use rdkafka::config::ClientConfig;
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use rdkafka::producer::{BaseProducer, BaseRecord, Producer};
+use std::collections::HashMap;
+use std::time::Duration;
+
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct KafkaSink {
+    producer: BaseProducer,
+    topic_template: String,
+    // Rendered topic per message type, the template is only expanded once per type
+    topics: HashMap<u16, String>,
+}
+
+impl KafkaSink {
+    pub fn new(brokers: &str, topic_template: &str) -> Result<Self> {
+        let producer: BaseProducer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("linger.ms", "50")
+            .create()
+            .map_err(kafka_error)?;
+        Ok(KafkaSink {
+            producer,
+            topic_template: topic_template.to_string(),
+            topics: HashMap::new(),
+        })
+    }
+}
+
+impl MessageSink for KafkaSink {
+    fn send(&mut self, msg: &ParsedMessage, json: &str) -> Result<()> {
+        let topic = self
+            .topics
+            .entry(msg.log_type)
+            .or_insert_with(|| render_topic(&self.topic_template, msg));
+        // Log timestamps are relative to boot, so the broker assigns the record time
+        let mut record = BaseRecord::to(topic.as_str())
+            .key(msg.name.as_str())
+            .payload(json);
+
+        // When the local queue is full, serve delivery callbacks and retry
+        loop {
+            match self.producer.send(record) {
+                Ok(()) => break,
+                Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), rejected)) => {
+                    self.producer.poll(Duration::from_millis(100));
+                    record = rejected;
+                }
+                Err((e, _)) => return Err(kafka_error(e)),
+            }
+        }
+        self.producer.poll(Duration::ZERO);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.producer.flush(FLUSH_TIMEOUT).map_err(kafka_error)
+    }
+}
+
+fn kafka_error(e: KafkaError) -> WallaceError {
+    WallaceError::Sink(format!("Kafka: {}", e))
+}
@@ -0,0 +1,202 @@
+// sink/metrics.rs
+// Decoding health for live mode, served in the Prometheus text format on `/metrics`.
+
+use super::MessageSink;
+use crate::errors::Result;
+use crate::parser::ParsedMessage;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Scrapes are answered one at a time, so a client that stalls mid-request is dropped after this
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug)]
+pub struct LiveMetrics {
+    started: Instant,
+    messages: BTreeMap<String, u64>,
+    bytes_read: u64,
+    parse_warnings: u64,
+    unknown_records: u64,
+    // (message, field) -> last numeric value
+    last_values: BTreeMap<(String, String), f64>,
+}
+
+impl LiveMetrics {
+    pub fn new() -> Self {
+        LiveMetrics {
+            started: Instant::now(),
+            messages: BTreeMap::new(),
+            bytes_read: 0,
+            parse_warnings: 0,
+            unknown_records: 0,
+            last_values: BTreeMap::new(),
+        }
+    }
+
+    /// Updates the input-side counters, which the decoder tracks as running totals
+    pub fn set_progress(&mut self, bytes_read: u64, parse_warnings: usize, unknown_records: usize) {
+        self.bytes_read = bytes_read;
+        self.parse_warnings = parse_warnings as u64;
+        self.unknown_records = unknown_records as u64;
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        // Per-type messages/sec is `rate(wallace_messages_total[1m])` on the Prometheus side
+        out.push_str("# HELP wallace_messages_total Decoded messages by type.\n");
+        out.push_str("# TYPE wallace_messages_total counter\n");
+        for (name, count) in &self.messages {
+            let _ = writeln!(
+                out,
+                "wallace_messages_total{{type=\"{}\"}} {}",
+                escape_label(name),
+                count
+            );
+        }
+        let counters = [
+            (
+                "wallace_bytes_read_total",
+                "Bytes consumed from the input.",
+                self.bytes_read,
+            ),
+            (
+                "wallace_parse_warnings_total",
+                "Warnings raised while decoding fields.",
+                self.parse_warnings,
+            ),
+            (
+                "wallace_unknown_records_total",
+                "Records skipped because their type is not in the registry.",
+                self.unknown_records,
+            ),
+        ];
+        for (metric, help, value) in counters {
+            let _ = writeln!(
+                out,
+                "# HELP {} {}\n# TYPE {} counter\n{} {}",
+                metric, help, metric, metric, value
+            );
+        }
+        out.push_str("# HELP wallace_field_value Last decoded value of a watched field.\n");
+        out.push_str("# TYPE wallace_field_value gauge\n");
+        for ((message, field), value) in &self.last_values {
+            let _ = writeln!(
+                out,
+                "wallace_field_value{{message=\"{}\",field=\"{}\"}} {}",
+                escape_label(message),
+                escape_label(field),
+                value
+            );
+        }
+        let _ = writeln!(
+            out,
+            "# HELP wallace_uptime_seconds Seconds since decoding started.\n# TYPE wallace_uptime_seconds gauge\nwallace_uptime_seconds {}",
+            self.started.elapsed().as_secs_f64()
+        );
+        out
+    }
+}
+
+impl Default for LiveMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Counts messages and records the last value of the watched `Message.field` pairs
+pub struct MetricsSink {
+    metrics: Arc<Mutex<LiveMetrics>>,
+    watched: Vec<(String, String)>,
+}
+
+impl MetricsSink {
+    /// `watched` entries have the form `Message.field`
+    pub fn new(metrics: Arc<Mutex<LiveMetrics>>, watched: &[&str]) -> Self {
+        let watched = watched
+            .iter()
+            .filter_map(|w| w.split_once('.'))
+            .map(|(message, field)| (message.to_string(), field.to_string()))
+            .collect();
+        MetricsSink { metrics, watched }
+    }
+}
+
+impl MessageSink for MetricsSink {
+    fn send(&mut self, msg: &ParsedMessage, _json: &str) -> Result<()> {
+        let mut metrics = self.metrics.lock().unwrap();
+        *metrics.messages.entry(msg.name.clone()).or_insert(0) += 1;
+        for (message, field) in &self.watched {
+            if *message != msg.name {
+                continue;
+            }
+            if let Some(value) = msg.field(field).and_then(|v| v.parse::<f64>().ok()) {
+                metrics
+                    .last_values
+                    .insert((message.clone(), field.clone()), value);
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Serves `GET /metrics` on `addr` from a background thread
+pub fn serve_metrics(addr: &str, metrics: Arc<Mutex<LiveMetrics>>) -> Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    println!(
+        "📈 Metrics available at http://{}/metrics",
+        listener.local_addr()?
+    );
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // A failed or timed out request is dropped and the next one is served
+            let _ = respond(stream, &metrics);
+        }
+    });
+    Ok(())
+}
+
+fn respond(stream: TcpStream, metrics: &Mutex<LiveMetrics>) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Drain the headers, the request has no body we care about
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let path = request_line.split_whitespace().nth(1).unwrap_or("");
+    let (status, body) = if path == "/metrics" || path.starts_with("/metrics?") {
+        ("200 OK", metrics.lock().unwrap().render())
+    } else {
+        ("404 Not Found", "not found\n".to_string())
+    };
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
@@ -0,0 +1,49 @@
+// sink/mod.rs
+// Per-message publishing targets, fed in log order as messages are decoded.
+
+pub mod dashboard;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+pub mod metrics;
+pub mod mqtt;
+pub mod websocket;
+
+use crate::errors::Result;
+use crate::export::ndjson::JsonEncoder;
+use crate::messages::registry::MessageRegistry;
+use crate::parser::ParsedMessage;
+
+pub trait MessageSink {
+    /// Publishes a single decoded message. `json` is the message's NDJSON encoding,
+    /// computed once by the caller and shared by all sinks.
+    fn send(&mut self, msg: &ParsedMessage, json: &str) -> Result<()>;
+
+    /// Blocks until everything sent so far has been delivered
+    fn flush(&mut self) -> Result<()>;
+}
+
+/// Expands `{msg_name}` and `{log_type}` placeholders in a topic template
+pub fn render_topic(template: &str, msg: &ParsedMessage) -> String {
+    template
+        .replace("{msg_name}", &msg.name)
+        .replace("{log_type}", &msg.log_type.to_string())
+}
+
+/// Sends every message to every sink, then flushes them all
+pub fn publish_all(
+    sinks: &mut [Box<dyn MessageSink>],
+    messages: &[ParsedMessage],
+    registry: &MessageRegistry,
+) -> Result<()> {
+    let mut encoder = JsonEncoder::new(registry);
+    for msg in messages {
+        let json = encoder.encode(msg);
+        for sink in sinks.iter_mut() {
+            sink.send(msg, &json)?;
+        }
+    }
+    for sink in sinks.iter_mut() {
+        sink.flush()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,49 @@
+// sink/mqtt.rs
+// Publishes each message as JSON to an MQTT topic such as `wallace/{vehicle}/{msg_name}`.
+
+use super::{render_topic, MessageSink};
+use crate::errors::Result;
+use crate::net::mqtt::{MqttClient, MqttOptions};
+use crate::parser::ParsedMessage;
+use std::collections::HashMap;
+
+pub struct MqttSink {
+    client: MqttClient,
+    topic_template: String,
+    topics: HashMap<u16, String>,
+}
+
+impl MqttSink {
+    /// `{vehicle}` in the template is fixed at construction, the per-message
+    /// placeholders are expanded once per message type.
+    pub fn new(broker: &str, topic_template: &str, vehicle: &str) -> Result<Self> {
+        let client_id = format!("wallace-{}-{}", vehicle, std::process::id());
+        let client = MqttClient::connect(&MqttOptions::parse(broker, &client_id)?)?;
+        Ok(MqttSink {
+            client,
+            topic_template: topic_template.replace("{vehicle}", vehicle),
+            topics: HashMap::new(),
+        })
+    }
+}
+
+impl MessageSink for MqttSink {
+    fn send(&mut self, msg: &ParsedMessage, json: &str) -> Result<()> {
+        let topic = self
+            .topics
+            .entry(msg.log_type)
+            .or_insert_with(|| render_topic(&self.topic_template, msg));
+        self.client.publish(topic, json.as_bytes())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.client.flush()
+    }
+}
+
+impl Drop for MqttSink {
+    fn drop(&mut self) {
+        // Best effort, the broker cleans up the session either way
+        let _ = self.client.disconnect();
+    }
+}
@@ -0,0 +1,113 @@
+// sink/websocket.rs
+// Pushes each message as a JSON text frame to every connected WebSocket client.
+// Clients may subscribe to a subset of types with `ws://host:port/?types=GPS,IMU,1027`.
+
+use super::MessageSink;
+use crate::errors::Result;
+use crate::parser::ParsedMessage;
+use std::collections::HashSet;
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tungstenite::handshake::server::{Callback, ErrorResponse, Request, Response};
+use tungstenite::{Message, WebSocket};
+
+// A client that cannot take a frame within this long is disconnected rather than stalling the feed
+const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+
+struct Client {
+    socket: WebSocket<TcpStream>,
+    // Message names or numeric log types, None subscribes to everything
+    types: Option<HashSet<String>>,
+}
+
+impl Client {
+    fn wants(&self, msg: &ParsedMessage) -> bool {
+        match &self.types {
+            Some(types) => types.contains(&msg.name) || types.contains(&msg.log_type.to_string()),
+            None => true,
+        }
+    }
+}
+
+pub struct WebSocketSink {
+    clients: Arc<Mutex<Vec<Client>>>,
+}
+
+impl WebSocketSink {
+    /// Starts accepting clients on `addr` in the background
+    pub fn bind(addr: &str) -> Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        println!("🔌 WebSocket endpoint listening on ws://{}/", listener.local_addr()?);
+
+        let clients = Arc::new(Mutex::new(Vec::new()));
+        let accepted = Arc::clone(&clients);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                // A failed handshake only affects that client
+                if let Some(client) = handshake(stream) {
+                    accepted.lock().unwrap().push(client);
+                }
+            }
+        });
+        Ok(WebSocketSink { clients })
+    }
+}
+
+fn handshake(stream: TcpStream) -> Option<Client> {
+    stream.set_write_timeout(Some(WRITE_TIMEOUT)).ok()?;
+    stream.set_read_timeout(Some(WRITE_TIMEOUT)).ok()?;
+    let peer = stream.peer_addr().ok()?;
+    let mut subscription = Subscription(None);
+    let socket = tungstenite::accept_hdr(stream, &mut subscription).ok()?;
+    let types = subscription.0;
+    match &types {
+        Some(t) => println!("🔌 WebSocket client {} subscribed to {} types", peer, t.len()),
+        None => println!("🔌 WebSocket client {} subscribed to all messages", peer),
+    }
+    Some(Client { socket, types })
+}
+
+// The types a client asks for in the query of its handshake request. The callback's error type
+// is tungstenite's, and never returned: every client is accepted.
+struct Subscription(Option<HashSet<String>>);
+
+impl Callback for &mut Subscription {
+    fn on_request(
+        self,
+        request: &Request,
+        response: Response,
+    ) -> std::result::Result<Response, ErrorResponse> {
+        self.0 = request.uri().query().and_then(parse_types);
+        Ok(response)
+    }
+}
+
+fn parse_types(query: &str) -> Option<HashSet<String>> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.strip_prefix("types="))
+        .map(|list| {
+            list.split(',')
+                .filter(|t| !t.is_empty())
+                .map(str::to_string)
+                .collect::<HashSet<_>>()
+        })
+        .find(|types| !types.is_empty())
+}
+
+impl MessageSink for WebSocketSink {
+    fn send(&mut self, msg: &ParsedMessage, json: &str) -> Result<()> {
+        let mut clients = self.clients.lock().unwrap();
+        // Disconnected or stalled clients are dropped, the feed carries on for the rest
+        clients.retain_mut(|client| {
+            !client.wants(msg) || client.socket.send(Message::Text(json.to_string())).is_ok()
+        });
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
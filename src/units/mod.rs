@@ -0,0 +1,238 @@
+// units/mod.rs
+// Converts decoded values to other units at export time, e.g. m/s to knots or rad to deg, either
+// for every field of a unit (`--convert imperial`, `--convert m/s=kn`) or per field in the registry.
+
+use crate::errors::{Result, WallaceError};
+use crate::messages::registry::MessageRegistry;
+use crate::parser::{is_numeric_type, ParsedMessage};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Quantity {
+    Length,
+    Speed,
+    Pressure,
+    Angle,
+    AngularRate,
+    Mass,
+    Temperature,
+}
+
+// Unit, what it measures, and how to reach the base unit of that quantity: base = value * scale + offset
+const UNITS: &[(&str, Quantity, f64, f64)] = &[
+    ("m", Quantity::Length, 1.0, 0.0),
+    ("mm", Quantity::Length, 0.001, 0.0),
+    ("cm", Quantity::Length, 0.01, 0.0),
+    ("km", Quantity::Length, 1000.0, 0.0),
+    ("in", Quantity::Length, 0.0254, 0.0),
+    ("ft", Quantity::Length, 0.3048, 0.0),
+    ("mi", Quantity::Length, 1609.344, 0.0),
+    ("nmi", Quantity::Length, 1852.0, 0.0),
+    ("m/s", Quantity::Speed, 1.0, 0.0),
+    ("km/h", Quantity::Speed, 1.0 / 3.6, 0.0),
+    ("kn", Quantity::Speed, 1852.0 / 3600.0, 0.0),
+    ("mph", Quantity::Speed, 0.44704, 0.0),
+    ("ft/s", Quantity::Speed, 0.3048, 0.0),
+    ("ft/min", Quantity::Speed, 0.3048 / 60.0, 0.0),
+    ("Pa", Quantity::Pressure, 1.0, 0.0),
+    ("hPa", Quantity::Pressure, 100.0, 0.0),
+    ("mbar", Quantity::Pressure, 100.0, 0.0),
+    ("kPa", Quantity::Pressure, 1000.0, 0.0),
+    ("bar", Quantity::Pressure, 100_000.0, 0.0),
+    ("psi", Quantity::Pressure, 6_894.757_293_168, 0.0),
+    ("inHg", Quantity::Pressure, 3386.389, 0.0),
+    ("rad", Quantity::Angle, 1.0, 0.0),
+    ("deg", Quantity::Angle, std::f64::consts::PI / 180.0, 0.0),
+    ("cdeg", Quantity::Angle, std::f64::consts::PI / 18000.0, 0.0),
+    ("rad/s", Quantity::AngularRate, 1.0, 0.0),
+    (
+        "deg/s",
+        Quantity::AngularRate,
+        std::f64::consts::PI / 180.0,
+        0.0,
+    ),
+    (
+        "rpm",
+        Quantity::AngularRate,
+        std::f64::consts::PI / 30.0,
+        0.0,
+    ),
+    ("kg", Quantity::Mass, 1.0, 0.0),
+    ("g", Quantity::Mass, 0.001, 0.0),
+    ("lb", Quantity::Mass, 0.453_592_37, 0.0),
+    ("K", Quantity::Temperature, 1.0, 0.0),
+    ("degC", Quantity::Temperature, 1.0, 273.15),
+    (
+        "degF",
+        Quantity::Temperature,
+        5.0 / 9.0,
+        273.15 - 32.0 * 5.0 / 9.0,
+    ),
+];
+
+/// Named sets of conversions for `--convert`
+pub const PRESETS: &[(&str, &[(&str, &str)])] = &[
+    (
+        "imperial",
+        &[
+            ("m", "ft"),
+            ("mm", "in"),
+            ("km", "mi"),
+            ("m/s", "mph"),
+            ("km/h", "mph"),
+            ("kg", "lb"),
+            ("Pa", "psi"),
+            ("degC", "degF"),
+        ],
+    ),
+    (
+        "aviation",
+        &[
+            ("m", "ft"),
+            ("km", "nmi"),
+            ("m/s", "kn"),
+            ("km/h", "kn"),
+            ("Pa", "hPa"),
+            ("rad", "deg"),
+            ("rad/s", "deg/s"),
+        ],
+    ),
+];
+
+/// A linear (or, for temperatures, affine) conversion between two units of the same quantity
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Conversion {
+    scale: f64,
+    offset: f64,
+}
+
+impl Conversion {
+    /// The conversion from unit `from` to unit `to`, `None` if either is unknown or they measure
+    /// different quantities
+    pub fn between(from: &str, to: &str) -> Option<Self> {
+        let (_, from_qty, from_scale, from_offset) = UNITS.iter().find(|u| u.0 == from)?;
+        let (_, to_qty, to_scale, to_offset) = UNITS.iter().find(|u| u.0 == to)?;
+        (from_qty == to_qty).then(|| Conversion {
+            scale: from_scale / to_scale,
+            offset: (from_offset - to_offset) / to_scale,
+        })
+    }
+
+    pub fn apply(&self, value: f64) -> f64 {
+        value * self.scale + self.offset
+    }
+}
+
+/// Parses a `--convert` value: a preset name, or `FROM=TO` unit pairs separated by commas
+pub fn parse_targets(spec: &str) -> Result<Vec<(String, String)>> {
+    if let Some((_, pairs)) = PRESETS.iter().find(|(name, _)| *name == spec) {
+        return Ok(pairs
+            .iter()
+            .map(|(from, to)| (from.to_string(), to.to_string()))
+            .collect());
+    }
+    spec.split(',')
+        .map(|pair| {
+            let (from, to) = pair.split_once('=').ok_or_else(|| {
+                WallaceError::InvalidArgument(format!(
+                    "invalid conversion '{}': expected a preset ({}) or FROM=TO",
+                    pair,
+                    PRESETS
+                        .iter()
+                        .map(|(name, _)| *name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))
+            })?;
+            let (from, to) = (from.trim(), to.trim());
+            Conversion::between(from, to).ok_or_else(|| {
+                WallaceError::InvalidArgument(format!("cannot convert '{}' to '{}'", from, to))
+            })?;
+            Ok((from.to_string(), to.to_string()))
+        })
+        .collect()
+}
+
+/// The conversions to apply, resolved per message type
+pub struct UnitConverter {
+    // Per log_type: field name, conversion
+    plan: HashMap<u16, Vec<(String, Conversion)>>,
+}
+
+impl UnitConverter {
+    /// Resolves the conversions for every numeric field with a unit. A `convert_to` in the registry
+    /// takes precedence over `targets`; fields whose unit has no target keep their values.
+    /// On success the registry's unit labels are changed to the target units.
+    pub fn new(registry: &mut MessageRegistry, targets: &[(String, String)]) -> Result<Self> {
+        let mut plan: HashMap<u16, Vec<(String, Conversion)>> = HashMap::new();
+        for (id, def) in registry.iter_mut() {
+            let log_type = match id.parse::<u16>() {
+                Ok(log_type) => log_type,
+                Err(_) => continue,
+            };
+            for field in def.fields.iter_mut() {
+                if field.is_skipped() || !is_numeric_type(&field.r#type) {
+                    continue;
+                }
+                let from = match field.unit.as_deref() {
+                    Some(unit) => unit,
+                    None if field.convert_to.is_some() => {
+                        return Err(WallaceError::InvalidArgument(format!(
+                            "field '{}.{}' has 'convert_to' but no 'unit' to convert from",
+                            def.name, field.name
+                        )))
+                    }
+                    None => continue,
+                };
+                let to = match field.convert_to.as_deref() {
+                    Some(to) => to,
+                    None => match targets.iter().find(|(unit, _)| unit == from) {
+                        Some((_, to)) => to.as_str(),
+                        None => continue,
+                    },
+                };
+                if from == to {
+                    continue;
+                }
+                let conversion = Conversion::between(from, to).ok_or_else(|| {
+                    WallaceError::InvalidArgument(format!(
+                        "field '{}.{}': cannot convert '{}' to '{}'",
+                        def.name, field.name, from, to
+                    ))
+                })?;
+                plan.entry(log_type)
+                    .or_default()
+                    .push((field.name.clone(), conversion));
+                field.unit = Some(to.to_string());
+            }
+        }
+        Ok(UnitConverter { plan })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.plan.is_empty()
+    }
+
+    /// Converts the values of `messages` in place, returning how many were changed.
+    /// Empty or non-numeric values are left as they are.
+    pub fn apply(&self, messages: &mut [ParsedMessage]) -> usize {
+        let mut converted = 0;
+        for msg in messages {
+            let fields = match self.plan.get(&msg.log_type) {
+                Some(fields) => fields,
+                None => continue,
+            };
+            for (name, value) in msg.fields.iter_mut() {
+                let conversion = match fields.iter().find(|(field, _)| field == name) {
+                    Some((_, conversion)) => conversion,
+                    None => continue,
+                };
+                if let Ok(number) = value.parse::<f64>() {
+                    *value = conversion.apply(number).to_string();
+                    converted += 1;
+                }
+            }
+        }
+        converted
+    }
+}
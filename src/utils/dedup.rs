@@ -0,0 +1,26 @@
+// utils/dedup.rs
+// Drops records the logger wrote twice, e.g. after a brown-out.
+
+use crate::parser::ParsedMessage;
+use std::collections::HashMap;
+
+/// Removes every message identical (same type, timestamp and field values) to the previous
+/// message of its type, keeping the first. Messages without a timestamp are kept, since repeated
+/// text or status records can be genuine. Returns the number removed.
+pub fn drop_duplicates(messages: &mut Vec<ParsedMessage>) -> usize {
+    let before = messages.len();
+    let mut last: HashMap<u16, ParsedMessage> = HashMap::new();
+    messages.retain(|msg| {
+        if msg.timestamp_us().is_none() {
+            return true;
+        }
+        match last.get(&msg.log_type) {
+            Some(previous) if previous.fields == msg.fields => false,
+            _ => {
+                last.insert(msg.log_type, msg.clone());
+                true
+            }
+        }
+    });
+    before - messages.len()
+}
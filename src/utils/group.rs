@@ -1,8 +1,10 @@
 use crate::parser::ParsedMessage;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
-pub fn group_by_type<'a>(messages: &'a [ParsedMessage]) -> HashMap<String, Vec<ParsedMessage>> {
-    let mut grouped: HashMap<String, Vec<ParsedMessage>> = HashMap::new();
+/// Groups messages by name. Groups iterate in name order and keep the log order of their
+/// messages, so every export of the same log is identical.
+pub fn group_by_type(messages: &[ParsedMessage]) -> BTreeMap<String, Vec<ParsedMessage>> {
+    let mut grouped: BTreeMap<String, Vec<ParsedMessage>> = BTreeMap::new();
     for msg in messages {
         grouped
             .entry(msg.name.clone())
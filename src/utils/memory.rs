@@ -0,0 +1,108 @@
+// utils/memory.rs
+// Estimates of the memory held by decoded messages, for `--max-memory` and the run summary.
+
+use crate::errors::{Result, WallaceError};
+use crate::parser::ParsedMessage;
+use serde::Serialize;
+use std::mem::size_of;
+
+/// Parses a size into bytes. Accepted units are `K`, `M`, `G` and `T`, powers of 1024, optionally
+/// followed by `B` or `iB`; a bare number is taken as bytes.
+pub fn parse_size(text: &str) -> Result<u64> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let unit = unit.trim().to_ascii_uppercase();
+    let unit = unit
+        .strip_suffix("IB")
+        .or_else(|| unit.strip_suffix('B'))
+        .unwrap_or(&unit);
+    let scale: u64 = match unit {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return Err(invalid_size(text)),
+    };
+    let value: f64 = number.parse().map_err(|_| invalid_size(text))?;
+    Ok((value * scale as f64).round() as u64)
+}
+
+fn invalid_size(text: &str) -> WallaceError {
+    WallaceError::InvalidArgument(format!(
+        "invalid size '{}' (expected e.g. 512M, 4G, 1.5GiB)",
+        text
+    ))
+}
+
+/// A size for messages, e.g. `1.5 GiB`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = "B";
+    for next in UNITS {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next;
+    }
+    if unit == "B" {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, unit)
+    }
+}
+
+/// Estimated bytes a message takes in memory with the strings and buffers it owns, leaving out
+/// the allocator's own overhead
+pub fn message_size(msg: &ParsedMessage) -> u64 {
+    let fields: usize = msg
+        .fields
+        .iter()
+        .map(|(name, value)| name.capacity() + value.capacity())
+        .sum();
+    (size_of::<ParsedMessage>()
+        + msg.name.capacity()
+        + msg.fields.capacity() * size_of::<(String, String)>()
+        + fields
+        + msg.raw.as_ref().map_or(0, Vec::capacity)) as u64
+}
+
+pub fn messages_size(messages: &[ParsedMessage]) -> u64 {
+    messages.iter().map(message_size).sum()
+}
+
+/// How much memory the decoded messages took, as recorded in `summary.json`
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct MemoryStats {
+    /// The most held at once by the estimate of `message_size`, including the copy grouped by
+    /// type that the exports are written from
+    pub peak_bytes: u64,
+    /// `--max-memory`, if given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit_bytes: Option<u64>,
+    /// Chunks the log was decoded and exported in because it did not fit in the limit, 0 if it did
+    pub chunks: usize,
+}
+
+impl MemoryStats {
+    pub fn new(limit_bytes: Option<u64>) -> Self {
+        MemoryStats {
+            limit_bytes,
+            ..Default::default()
+        }
+    }
+
+    pub fn observe(&mut self, bytes: u64) {
+        self.peak_bytes = self.peak_bytes.max(bytes);
+    }
+
+    /// Messages decoded at most before grouping, which copies them: half the limit
+    pub fn chunk_budget(&self) -> Option<u64> {
+        self.limit_bytes.map(|limit| limit / 2)
+    }
+}
@@ -1,33 +1,70 @@
+pub mod dedup;
 pub mod group;
+pub mod memory;
+pub mod rng;
+pub mod sample;
+pub mod time;
 
 use crate::errors::Result; // Use custom Result
+use crate::export::columns::{numeric_columns, row_values, union_headers};
+use crate::export::locale::NumberFormat;
+use crate::messages::registry::MessageRegistry;
 pub use crate::parser::ParsedMessage;
 pub use group::group_by_type;
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufWriter, Write};
 
-pub fn export_to_csv(path: &str, messages: &[ParsedMessage]) -> Result<()> {
+/// Writes `messages` as CSV. The header is the union of the fields of all messages, and fields a
+/// record lacks (e.g. after a truncated payload) are written as `missing_value`. `comment` lines
+/// go above the header as they are, so they should start with `#`. Unless `keep_formulas` is set,
+/// text cells that a spreadsheet would run as a formula are escaped (see `escape_formula`).
+/// Values of numeric registry types are written in `numbers`, which also picks the delimiter.
+pub fn export_to_csv(
+    path: &str,
+    messages: &[ParsedMessage],
+    registry: &MessageRegistry,
+    missing_value: &str,
+    comment: &[String],
+    keep_formulas: bool,
+    numbers: NumberFormat,
+) -> Result<()> {
     // Update return type
     if messages.is_empty() {
         return Ok(());
     }
 
-    let mut writer = csv::Writer::from_path(path)?; // csv::Error automatically converted by #[from]
+    let mut file = BufWriter::new(File::create(path)?);
+    for line in comment {
+        writeln!(file, "{}", line)?;
+    }
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(numbers.csv_delimiter())
+        .from_writer(file);
 
-    // Handle case where message might have no fields (unlikely but possible)
-    let headers: Vec<String> = messages
-        .get(0)
-        .map(|msg| msg.fields.iter().map(|(name, _)| name.clone()).collect())
-        .unwrap_or_else(Vec::new);
+    // Handle case where messages might have no fields (unlikely but possible)
+    let headers = union_headers(messages);
+    let numeric = match numbers.is_default() {
+        true => Vec::new(),
+        false => numeric_columns(messages, &headers, registry),
+    };
 
     // Only write headers if there are any
     if !headers.is_empty() {
-        writer.write_record(&headers)?; // csv::Error automatically converted
+        writer.write_record(headers.iter().map(|(name, _)| name))?; // csv::Error automatically converted
     }
 
     for msg in messages {
-        let row: Vec<String> = msg.fields.iter().map(|(_, val)| val.clone()).collect();
         // Only write row if headers were written (i.e., fields exist)
         if !headers.is_empty() {
-            writer.write_record(&row)?; // csv::Error automatically converted
+            let row = CsvRow {
+                headers: &headers,
+                numeric: &numeric,
+                missing_value,
+                keep_formulas,
+                numbers,
+            };
+            row.write(&mut writer, msg)?;
         }
     }
 
@@ -35,3 +72,60 @@ pub fn export_to_csv(path: &str, messages: &[ParsedMessage]) -> Result<()> {
     println!("✅ Wrote {} rows to '{}'", messages.len(), path);
     Ok(())
 }
+
+/// How `export_to_csv` lays out a row, for writers that take the messages a few at a time
+pub struct CsvRow<'a> {
+    /// See `union_headers`
+    pub headers: &'a [(String, usize)],
+    /// Which headers to write in `numbers` (see `numeric_columns`); empty for none
+    pub numeric: &'a [bool],
+    pub missing_value: &'a str,
+    pub keep_formulas: bool,
+    pub numbers: NumberFormat,
+}
+
+impl CsvRow<'_> {
+    pub fn write<W: Write>(&self, writer: &mut csv::Writer<W>, msg: &ParsedMessage) -> Result<()> {
+        let values = row_values(msg, self.headers, self.missing_value);
+        if self.keep_formulas && self.numeric.is_empty() {
+            writer.write_record(values)?; // csv::Error automatically converted
+            return Ok(());
+        }
+        for (col, value) in values.into_iter().enumerate() {
+            if self.numeric.get(col).copied().unwrap_or(false) {
+                writer.write_field(self.numbers.format(value).as_bytes())?;
+            } else if self.keep_formulas || value == self.missing_value {
+                // The missing value is the user's choice and written as given
+                writer.write_field(value)?;
+            } else {
+                writer.write_field(escape_formula(value).as_bytes())?;
+            }
+        }
+        writer.write_record(None::<&[u8]>)?;
+        Ok(())
+    }
+}
+
+// Spreadsheets run cells starting with these as formulas
+const FORMULA_STARTS: [char; 6] = ['=', '+', '-', '@', '\t', '\r'];
+
+/// Prefixes text starting with `=`, `+`, `-`, `@`, a tab or a carriage return with `'`, which
+/// spreadsheets show but do not evaluate, so a logged string like `=HYPERLINK(...)` stays text.
+/// Numbers such as `-1.5` are left alone. Text already starting with `'` gets another one, so that
+/// `unescape_formula` can tell the escape apart.
+pub fn escape_formula(value: &str) -> Cow<'_, str> {
+    let risky = value.starts_with(FORMULA_STARTS) && value.parse::<f64>().is_err();
+    if risky || value.starts_with('\'') {
+        Cow::Owned(format!("'{}", value))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+/// Reverses `escape_formula` when reading a CSV export back
+pub fn unescape_formula(value: &str) -> &str {
+    match value.strip_prefix('\'') {
+        Some(rest) if rest.starts_with(FORMULA_STARTS) || rest.starts_with('\'') => rest,
+        _ => value,
+    }
+}
@@ -0,0 +1,118 @@
+// utils/sample.rs
+// A representative subset of a log for a quick look: every Nth record of each type, or a uniform
+// random reservoir of N records per type. Records are picked before they are decoded, so a
+// sampled run only decodes the records it keeps.
+
+use crate::errors::Result;
+use crate::messages::registry::MessageRegistry;
+use crate::parser::stream::RawRecord;
+use crate::parser::{DecodeOptions, Extracted, MessageStream};
+use crate::redact::Redactor;
+use crate::utils::rng::SplitMix64;
+use std::collections::HashMap;
+use std::io::Read;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sampling {
+    /// The first record of each type and every Nth after it
+    Stride(u64),
+    /// Up to N records of each type, each record as likely as any other
+    Reservoir(usize),
+}
+
+impl Sampling {
+    /// `--sample`: a percentage of the records, as a stride
+    pub fn percent(percent: f64) -> Self {
+        Sampling::Stride((100.0 / percent).round().max(1.0) as u64)
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Sampling::Stride(1) => "every record".to_string(),
+            Sampling::Stride(stride) => format!("1 in {} records of each type", stride),
+            Sampling::Reservoir(size) => format!("up to {} random records of each type", size),
+        }
+    }
+}
+
+pub struct Sampler {
+    sampling: Sampling,
+    rng: SplitMix64,
+    /// Records offered so far, per log_type
+    seen: HashMap<u16, u64>,
+    reservoirs: HashMap<u16, Vec<RawRecord>>,
+}
+
+impl Sampler {
+    pub fn new(sampling: Sampling, seed: u64) -> Self {
+        Sampler {
+            sampling,
+            rng: SplitMix64(seed),
+            seen: HashMap::new(),
+            reservoirs: HashMap::new(),
+        }
+    }
+
+    /// The record back if the stride keeps it. A reservoir keeps its records until `finish`.
+    pub fn offer(&mut self, record: RawRecord) -> Option<RawRecord> {
+        let seen = self.seen.entry(record.log_type).or_default();
+        *seen += 1;
+        match self.sampling {
+            Sampling::Stride(stride) => (*seen - 1).is_multiple_of(stride).then_some(record),
+            Sampling::Reservoir(size) => {
+                let reservoir = self.reservoirs.entry(record.log_type).or_default();
+                if reservoir.len() < size {
+                    reservoir.push(record);
+                } else {
+                    // The nth record replaces a kept one with probability size/n
+                    let slot = (self.rng.next_u64() % *seen) as usize;
+                    if slot < size {
+                        reservoir[slot] = record;
+                    }
+                }
+                None
+            }
+        }
+    }
+
+    /// The records kept in the reservoirs, in log order
+    pub fn finish(self) -> Vec<RawRecord> {
+        let mut records: Vec<RawRecord> = self.reservoirs.into_values().flatten().collect();
+        records.sort_by_key(|record| record.index);
+        records
+    }
+}
+
+/// Like `extract_messages`, but decodes only the records `sampler` keeps, redacted first if a
+/// redactor is given. Record counts still cover every record of the log.
+pub fn extract_messages_sampled<R: Read>(
+    reader: &mut R,
+    registry: &MessageRegistry,
+    mut sampler: Sampler,
+    mut redactor: Option<&mut Redactor>,
+    options: DecodeOptions,
+) -> Result<Extracted> {
+    let mut stream = MessageStream::new(reader, registry)?;
+    stream.options = options;
+    let mut messages = Vec::new();
+    while let Some(record) = stream.next_record()? {
+        if let Some(mut record) = sampler.offer(record) {
+            if let Some(redactor) = redactor.as_deref_mut() {
+                redactor.apply(&mut record);
+            }
+            messages.extend(stream.decode(&record)?);
+        }
+    }
+    for mut record in sampler.finish() {
+        if let Some(redactor) = redactor.as_deref_mut() {
+            redactor.apply(&mut record);
+        }
+        messages.extend(stream.decode(&record)?);
+    }
+    Ok((
+        messages,
+        stream.warnings,
+        stream.skipped_fields,
+        stream.record_counts,
+    ))
+}
@@ -0,0 +1,34 @@
+// utils/time.rs
+// Human-friendly durations for command line options, e.g. "120s", "10min", "1.5h".
+
+use crate::errors::{Result, WallaceError};
+
+/// Forward jumps in a log's timestamps larger than this are taken to be corruption, not elapsed time
+pub const MAX_TIMESTAMP_JUMP_US: u64 = 3_600_000_000;
+
+/// Parses a duration into microseconds. Accepted units are `us`, `ms`, `s`, `m`/`min` and `h`;
+/// a bare number is taken as seconds.
+pub fn parse_duration(text: &str) -> Result<u64> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let scale = match unit.trim() {
+        "us" => 1.0,
+        "ms" => 1e3,
+        "" | "s" => 1e6,
+        "m" | "min" => 60e6,
+        "h" => 3600e6,
+        _ => return Err(invalid_duration(text)),
+    };
+    let value: f64 = number.parse().map_err(|_| invalid_duration(text))?;
+    Ok((value * scale).round() as u64)
+}
+
+fn invalid_duration(text: &str) -> WallaceError {
+    WallaceError::InvalidArgument(format!(
+        "invalid duration '{}' (expected e.g. 500ms, 120s, 10min, 1h)",
+        text
+    ))
+}